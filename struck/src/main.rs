@@ -1,15 +1,45 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ffi::OsStr;
-use std::io::Read;
+use std::io::{BufRead, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
-use std::{fs, io};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::{fs, io, iter};
 
-use anyhow::Error;
+use anyhow::{anyhow, Context, Error};
 use argh::FromArgs;
 
-use serde::Deserialize;
-use struckdown::html::to_html;
-use struckdown::processors::BuiltinProcessor;
-use struckdown::{event::AnnotatedEvent, pipeline::Pipeline};
+use regex::{Regex, RegexBuilder};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use struckdown::event::{
+    AnnotatedEvent, CodeBlockEvent, DocumentStartEvent, EndTagEvent, Event, InlineCodeEvent,
+    InterpretedTextEvent, RawHtmlEvent, Severity, StartTagEvent, TextEvent,
+};
+use struckdown::explain::{diff_events, EventChange};
+use struckdown::html::{to_html, to_html_writer, HtmlRendererOptions};
+use struckdown::merge::{merge, MergeOptions};
+use struckdown::ndjson::{read_ndjson, write_ndjson};
+use struckdown::parser::Parser;
+use struckdown::pipeline::{Pipeline, PipelineConfig, ProcessorConfig, TimingReport};
+use struckdown::plain::{to_text, PlainTextOptions};
+use struckdown::processors::{AutoAnchors, HtmlSanitizer, Processor, Syntect, TableOfContents};
+use struckdown::project::{OutputPathFn, Project, ProjectOptions};
+use struckdown::value::{from_value, to_value, Value};
+
+use crate::query::Selector;
+use crate::report::{location_at, print_diagnostic, DocumentsDiffer, DocumentsFailed, SourceError};
+use crate::watch::{Change, Watcher};
+
+mod mdbook;
+mod query;
+mod report;
+mod serve;
+mod watch;
 
 fn read_file<P: AsRef<Path>>(path: &P) -> Result<String, Error> {
     let path = path.as_ref();
@@ -18,10 +48,44 @@ fn read_file<P: AsRef<Path>>(path: &P) -> Result<String, Error> {
         io::stdin().read_to_string(&mut rv)?;
         Ok(rv)
     } else {
-        Ok(fs::read_to_string(&path)?)
+        fs::read_to_string(path).with_context(|| format!("cannot read {}", path.display()))
+    }
+}
+
+fn open_file<P: AsRef<Path>>(path: &P) -> Result<Box<dyn BufRead>, Error> {
+    let path = path.as_ref();
+    if path.as_os_str() == OsStr::new("-") {
+        Ok(Box::new(BufReader::new(io::stdin())))
+    } else {
+        let file =
+            fs::File::open(path).with_context(|| format!("cannot read {}", path.display()))?;
+        Ok(Box::new(BufReader::new(file)))
     }
 }
 
+/// Reads an NDJSON event stream while it is iterated.
+///
+/// The stream ends at the first error which is stored for the caller.
+/// Readers sharing the error end once any of them failed.
+fn stream_ndjson<R: BufRead>(
+    reader: R,
+    error: Rc<RefCell<Option<Error>>>,
+) -> impl Iterator<Item = AnnotatedEvent<'static>> {
+    let mut events = read_ndjson(reader);
+    iter::from_fn(move || {
+        if error.borrow().is_some() {
+            return None;
+        }
+        match events.next()? {
+            Ok(event) => Some(event),
+            Err(err) => {
+                *error.borrow_mut() = Some(err.into());
+                None
+            }
+        }
+    })
+}
+
 #[derive(FromArgs, Debug)]
 /// Small utility to play around with struckdown.
 struct Cli {
@@ -35,85 +99,1648 @@ enum Command {
     Parse(ParseCommand),
     Render(RenderCommand),
     Process(ProcessCommand),
+    Html(HtmlCommand),
+    Build(BuildCommand),
+    Watch(WatchCommand),
+    Serve(ServeCommand),
+    Diff(DiffCommand),
+    Toc(TocCommand),
+    Highlight(HighlightCommand),
+    Text(TextCommand),
+    Sanitize(SanitizeCommand),
+    Query(QueryCommand),
+    Grep(GrepCommand),
+    MdBookPreprocessor(MdBookPreprocessorCommand),
 }
 
 /// Parses a markdown document.
 ///
 /// This parses a markdown document and emits a JSON event stream.
+/// Multiple documents are emitted one after another, each starting with its
+/// own `document_start` event, unless `--merge` is given.
 #[derive(FromArgs, Debug)]
 #[argh(subcommand, name = "parse")]
 struct ParseCommand {
-    /// path to the file to read.
-    #[argh(positional, default = "PathBuf::from(\"-\")")]
-    path: PathBuf,
+    /// paths to the files to read (defaults to stdin).
+    #[argh(positional)]
+    paths: Vec<PathBuf>,
+    /// the output format: ndjson (default), json, yaml, msgpack, tree or html.
+    #[argh(option, short = 'f', default = "OutputFormat::NdJson")]
+    format: OutputFormat,
+    /// merge the documents into one, demoting the headings of all but the
+    /// first.
+    #[argh(switch)]
+    merge: bool,
+    /// the number of levels headings are demoted by when merging.
+    #[argh(option, default = "1")]
+    demote_headings: usize,
+}
+
+/// The formats event streams can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum OutputFormat {
+    #[default]
+    NdJson,
+    Json,
+    Yaml,
+    MsgPack,
+    Tree,
+    Html,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<OutputFormat, String> {
+        Ok(match value {
+            "ndjson" => OutputFormat::NdJson,
+            "json" => OutputFormat::Json,
+            "yaml" => OutputFormat::Yaml,
+            "msgpack" => OutputFormat::MsgPack,
+            "tree" => OutputFormat::Tree,
+            "html" => OutputFormat::Html,
+            _ => return Err(format!("unknown format '{}'", value)),
+        })
+    }
 }
 
 /// Process according a config file.
 ///
-/// This processes a stream according to a config file.
+/// This processes a stream according to a config file.  Besides the
+/// pipeline (parser, processors and renderer) the config file sets the
+/// input format (ndjson or markdown) and the output format.  NDJSON input
+/// is processed line by line which allows the command to be used as an
+/// external processor.  Markdown documents can add processors in the
+/// `processors` key of their front matter.  Processors that run commands,
+/// load code or read files are only allowed there with
+/// `--trust-front-matter`.  Multiple documents are processed one after
+/// another or merged into one with `--merge`.
 #[derive(FromArgs, Debug)]
 #[argh(subcommand, name = "process")]
 struct ProcessCommand {
     /// path to the config file.
     #[argh(positional)]
     config: PathBuf,
+    /// paths to the files to read (defaults to stdin).
+    #[argh(positional)]
+    paths: Vec<PathBuf>,
+    /// merge the documents into one, demoting the headings of all but the
+    /// first.
+    #[argh(switch)]
+    merge: bool,
+    /// the number of levels headings are demoted by when merging.
+    #[argh(option, default = "1")]
+    demote_headings: usize,
+    /// print the time spent and events produced by every stage.
+    #[argh(switch)]
+    profile: bool,
+    /// ignore processors declared in the front matter of the document.
+    #[argh(switch)]
+    no_front_matter_processors: bool,
+    /// allow processors in the front matter that run commands, load code or
+    /// read files.
+    #[argh(switch)]
+    trust_front_matter: bool,
+}
+
+/// Converts a markdown document to HTML.
+///
+/// This parses a markdown document, applies the processors of a config
+/// file (if given) and renders the result to HTML (or with the renderer
+/// selected with `--to`).  Processors listed in the `processors` key of the
+/// front matter are added to the pipeline.  Processors that run commands,
+/// load code or read files are only allowed there with
+/// `--trust-front-matter`.
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "html")]
+struct HtmlCommand {
+    /// path to the file to read.
+    #[argh(positional, default = "PathBuf::from(\"-\")")]
+    path: PathBuf,
+    /// path to a config file.
+    #[argh(option, short = 'c')]
+    config: Option<PathBuf>,
+    /// path to the file to write (defaults to stdout).
+    #[argh(option, short = 'o')]
+    output: Option<PathBuf>,
+    /// print the time spent and events produced by every stage.
+    #[argh(switch)]
+    profile: bool,
+    /// ignore processors declared in the front matter of the document.
+    #[argh(switch)]
+    no_front_matter_processors: bool,
+    /// allow processors in the front matter that run commands, load code or
+    /// read files.
+    #[argh(switch)]
+    trust_front_matter: bool,
+    /// the renderer to use (html, text or json-tree).
+    #[argh(option, default = "Renderer::Html")]
+    to: Renderer,
+    /// html: render `_foo_` as underlines.
+    #[argh(switch)]
+    underlines: bool,
+    /// html: the level of the top-level headings.
+    #[argh(option)]
+    headline_level: Option<usize>,
+    /// text: wrap paragraphs at this width.
+    #[argh(option, short = 'w')]
+    width: Option<usize>,
+    /// text: emit the targets of links after the link text.
+    #[argh(switch)]
+    link_targets: bool,
+}
+
+/// Builds a directory of markdown documents.
+///
+/// All files matching the pattern are processed as a project through the
+/// processors of a config file (if given) and rendered to HTML files in the
+/// output directory, mirroring the directory structure.  Failing documents
+/// are reported without aborting the build.
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "build")]
+struct BuildCommand {
+    /// glob pattern of the documents (eg: docs/**/*.md).
+    #[argh(positional)]
+    pattern: String,
+    /// path to the output directory.
+    #[argh(positional)]
+    output: PathBuf,
+    /// path to a config file.
+    #[argh(option, short = 'c')]
+    config: Option<PathBuf>,
 }
 
-/// Renders a token stream to HTML.
+/// Rebuilds a directory of markdown documents on changes.
+///
+/// Like `build` but keeps running and watches the documents and the config
+/// file for changes.  Only changed documents are processed again and only
+/// documents with a changed output are written and reported.
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "watch")]
+struct WatchCommand {
+    /// glob pattern of the documents (eg: docs/**/*.md).
+    #[argh(positional)]
+    pattern: String,
+    /// path to the output directory.
+    #[argh(positional)]
+    output: PathBuf,
+    /// path to a config file.
+    #[argh(option, short = 'c')]
+    config: Option<PathBuf>,
+    /// how often to check for changes in milliseconds (defaults to 500).
+    #[argh(option, default = "500")]
+    interval: u64,
+}
+
+/// Serves a directory of markdown documents for previewing.
+///
+/// The documents are built to memory and served over HTTP.  Open pages are
+/// reloaded when the documents or the config file change.
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "serve")]
+struct ServeCommand {
+    /// glob pattern of the documents (eg: docs/**/*.md).
+    #[argh(positional)]
+    pattern: String,
+    /// path to a config file.
+    #[argh(option, short = 'c')]
+    config: Option<PathBuf>,
+    /// the address to listen on (defaults to 127.0.0.1:8000).
+    #[argh(option, default = "String::from(\"127.0.0.1:8000\")")]
+    addr: String,
+    /// how often to check for changes in milliseconds (defaults to 500).
+    #[argh(option, default = "500")]
+    interval: u64,
+}
+
+/// Compares two documents structurally.
+///
+/// Both documents are parsed (files ending in .json, .jsonl or .ndjson are
+/// read as NDJSON event streams) and the event streams are compared.
+/// Locations are ignored and text is normalized so that changes which only
+/// affect formatting (such as reflowed paragraphs) are not reported.  Exits
+/// with 1 if the documents differ.
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "diff")]
+struct DiffCommand {
+    /// the old document.
+    #[argh(positional)]
+    old: PathBuf,
+    /// the new document.
+    #[argh(positional)]
+    new: PathBuf,
+    /// path to a config file whose processors are applied to both documents.
+    #[argh(option, short = 'c')]
+    config: Option<PathBuf>,
+}
+
+/// Prints the outline of a document.
+///
+/// Lists the headings of a document with their levels and anchors as
+/// collected by the table of contents processor.  Headings without an
+/// explicit anchor get one generated.
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "toc")]
+struct TocCommand {
+    /// path to the file to read.
+    #[argh(positional, default = "PathBuf::from(\"-\")")]
+    path: PathBuf,
+    /// the minimum heading level to include (defaults to 1).
+    #[argh(option, default = "1")]
+    min_depth: usize,
+    /// the maximum heading level to include (defaults to 6).
+    #[argh(option, default = "6")]
+    max_depth: usize,
+    /// emit the outline as JSON.
+    #[argh(switch)]
+    json: bool,
+}
+
+/// Highlights source code.
+///
+/// Runs a file through the syntect processor as a code block and emits the
+/// highlighted HTML.
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "highlight")]
+struct HighlightCommand {
+    /// path to the file to read.
+    #[argh(positional, default = "PathBuf::from(\"-\")")]
+    path: PathBuf,
+    /// the language to highlight as (defaults to the file extension).
+    #[argh(option)]
+    lang: Option<String>,
+    /// the name of the theme.
+    #[argh(option)]
+    theme: Option<String>,
+    /// path to a theme file or a folder of themes.
+    #[argh(option)]
+    theme_path: Option<PathBuf>,
+    /// path to a folder with additional syntax definitions.
+    #[argh(option)]
+    syntax_path: Option<PathBuf>,
+    /// emit CSS classes instead of inline styles.
+    #[argh(switch)]
+    classes: bool,
+    /// print the stylesheet of the theme instead.
+    #[argh(switch)]
+    css: bool,
+    /// list the available themes.
+    #[argh(switch)]
+    list_themes: bool,
+    /// list the available languages.
+    #[argh(switch)]
+    list_languages: bool,
+}
+
+/// Renders a markdown document to plain text.
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "text")]
+struct TextCommand {
+    /// path to the file to read.
+    #[argh(positional, default = "PathBuf::from(\"-\")")]
+    path: PathBuf,
+    /// path to a config file.
+    #[argh(option, short = 'c')]
+    config: Option<PathBuf>,
+    /// wrap paragraphs at this width.
+    #[argh(option, short = 'w')]
+    width: Option<usize>,
+    /// emit the targets of links after the link text.
+    #[argh(switch)]
+    link_targets: bool,
+    /// ignore processors declared in the front matter of the document.
+    #[argh(switch)]
+    no_front_matter_processors: bool,
+    /// allow processors in the front matter that run commands, load code or
+    /// read files.
+    #[argh(switch)]
+    trust_front_matter: bool,
+}
+
+/// Sanitizes raw HTML.
+///
+/// Runs only the HTML sanitizer over an NDJSON event stream or, with
+/// `--html`, over an HTML fragment.  The options can be loaded from a YAML
+/// file and overridden with flags.
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "sanitize")]
+struct SanitizeCommand {
+    /// path to the file to read.
+    #[argh(positional, default = "PathBuf::from(\"-\")")]
+    path: PathBuf,
+    /// read and emit an HTML fragment instead of an event stream.
+    #[argh(switch)]
+    html: bool,
+    /// path to a YAML file with the sanitizer options.
+    #[argh(option, short = 'c')]
+    config: Option<PathBuf>,
+    /// permit the class attribute.
+    #[argh(switch)]
+    allow_class: bool,
+    /// permit the style attribute.
+    #[argh(switch)]
+    allow_style: bool,
+    /// remove comments.
+    #[argh(switch)]
+    strip_comments: bool,
+    /// additional tags to permit.
+    #[argh(option)]
+    allow_tag: Vec<String>,
+    /// tags to remove from the permitted tags.
+    #[argh(option)]
+    remove_tag: Vec<String>,
+    /// additional attributes to permit on all tags.
+    #[argh(option)]
+    allow_attribute: Vec<String>,
+    /// the rel to add to all links.
+    #[argh(option)]
+    link_rel: Option<String>,
+    /// rewrite http URLs to https.
+    #[argh(switch)]
+    force_https: bool,
+    /// a URL to proxy external images through ({url} is replaced).
+    #[argh(option)]
+    image_proxy: Option<String>,
+}
+
+/// Queries the event tree of a document.
+///
+/// Prints the subtrees matching a selector as JSON, one per line.  The
+/// selector matches nodes by type (`heading2`, `code_block`), `#id`,
+/// `.class`, `@directive` and `:contains(text)`.  Whitespace separated
+/// selectors match descendants, `>` matches children and commas separate
+/// alternatives (eg: `block_quote paragraph:contains("TODO"), list >
+/// list_item`).
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "query")]
+struct QueryCommand {
+    /// the selector.
+    #[argh(positional)]
+    selector: Selector,
+    /// path to the file to read.
+    #[argh(positional, default = "PathBuf::from(\"-\")")]
+    path: PathBuf,
+    /// path to a config file.
+    #[argh(option, short = 'c')]
+    config: Option<PathBuf>,
+    /// emit the matches as a pretty printed JSON array.
+    #[argh(switch)]
+    pretty: bool,
+    /// ignore processors declared in the front matter of the document.
+    #[argh(switch)]
+    no_front_matter_processors: bool,
+    /// allow processors in the front matter that run commands, load code or
+    /// read files.
+    #[argh(switch)]
+    trust_front_matter: bool,
+}
+
+/// Searches the text of documents.
+///
+/// Matches a regular expression against the text of documents and prints
+/// the hits as `path:line:column:` followed by the headings the hit is in
+/// and the line of the hit.  By default only prose is searched, code blocks
+/// and inline code are included with `--code`.  Exits with 1 if nothing was
+/// found.
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "grep")]
+struct GrepCommand {
+    /// the regular expression.
+    #[argh(positional)]
+    pattern: String,
+    /// paths to the files to search (defaults to stdin).
+    #[argh(positional)]
+    paths: Vec<PathBuf>,
+    /// match case insensitively.
+    #[argh(switch, short = 'i')]
+    ignore_case: bool,
+    /// also search code blocks and inline code.
+    #[argh(switch)]
+    code: bool,
+    /// only search code blocks and inline code.
+    #[argh(switch)]
+    only_code: bool,
+    /// do not print the heading context.
+    #[argh(switch)]
+    no_heading: bool,
+}
+
+/// Runs as an mdBook preprocessor.
+///
+/// Speaks the preprocessor protocol of mdBook: the book is read from stdin,
+/// every chapter is run through the pipeline and replaced with the rendered
+/// HTML and the book is written to stdout.  The config file is given with
+/// `-c` or in `book.toml` (relative to the book):
+///
+/// [preprocessor.struck]
+/// command = "struck mdbook-preprocessor"
+/// config = "struckdown.yml"
+///
+/// Processors that run commands, load code or read files are only allowed
+/// in the front matter of chapters with `--trust-front-matter`.
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "mdbook-preprocessor")]
+struct MdBookPreprocessorCommand {
+    /// invoked by mdBook as `supports <renderer>`.
+    #[argh(positional)]
+    args: Vec<String>,
+    /// path to a config file.
+    #[argh(option, short = 'c')]
+    config: Option<PathBuf>,
+    /// allow processors in the front matter that run commands, load code or
+    /// read files.
+    #[argh(switch)]
+    trust_front_matter: bool,
+}
+
+/// Renders a token stream.
+///
+/// Reads an NDJSON event stream and renders it to HTML or with another
+/// renderer selected with `--to`.
 #[derive(FromArgs, Debug)]
 #[argh(subcommand, name = "render")]
 struct RenderCommand {
     #[argh(positional, default = "PathBuf::from(\"-\")")]
     path: PathBuf,
+    /// the renderer to use (html, text or json-tree).
+    #[argh(option, default = "Renderer::Html")]
+    to: Renderer,
+    /// html: render `_foo_` as underlines.
+    #[argh(switch)]
+    underlines: bool,
+    /// html: the level of the top-level headings.
+    #[argh(option)]
+    headline_level: Option<usize>,
+    /// text: wrap paragraphs at this width.
+    #[argh(option, short = 'w')]
+    width: Option<usize>,
+    /// text: emit the targets of links after the link text.
+    #[argh(switch)]
+    link_targets: bool,
 }
 
-fn parse_cmd(cmd: ParseCommand) -> Result<(), Error> {
-    let source = read_file(&cmd.path)?;
-    for event in struckdown::parser::parse(&source, &Default::default()) {
-        let out = serde_json::to_string(&event)?;
-        println!("{}", out);
+/// The renderers event streams can be rendered with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Renderer {
+    Html,
+    Text,
+    JsonTree,
+}
+
+impl FromStr for Renderer {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Renderer, String> {
+        Ok(match value {
+            "html" => Renderer::Html,
+            "text" => Renderer::Text,
+            "json-tree" => Renderer::JsonTree,
+            _ => {
+                return Err(format!(
+                    "unknown renderer '{}' (available: html, text, json-tree)",
+                    value
+                ))
+            }
+        })
+    }
+}
+
+/// Describes an event on a single line.
+///
+/// Tags are written as `<tag>` and `</tag>`, all other events as their type
+/// followed by their fields.
+fn describe_event(event: &Event) -> Result<String, Error> {
+    let value = to_value(event)?;
+    let ty = value.get("type").and_then(|x| x.as_str()).unwrap_or("");
+    let mut line = match ty {
+        "start_tag" => format!("<{}>", value["tag"].as_str().unwrap_or("")),
+        "end_tag" => format!("</{}>", value["tag"].as_str().unwrap_or("")),
+        _ => ty.to_string(),
+    };
+    if let Some(map) = value.as_object() {
+        for (key, value) in map {
+            if key != "type" && key != "tag" {
+                line.push_str(&format!(" {}={}", key, value));
+            }
+        }
+    }
+    Ok(line)
+}
+
+/// Writes an indented tree of the events for debugging.
+///
+/// Start tags open a level that is closed by their end tag.
+fn write_tree<'data, W: Write, I: Iterator<Item = AnnotatedEvent<'data>>>(
+    mut w: W,
+    iter: I,
+) -> Result<(), Error> {
+    let mut depth = 0;
+    for event in iter {
+        if let Event::EndTag(_) = event.event {
+            depth -= 1;
+            continue;
+        }
+        let mut line = describe_event(&event.event)?;
+        if let Some(ref loc) = event.location {
+            line.push_str(&format!(" @{}:{}", loc.line, loc.column));
+        }
+        writeln!(
+            w,
+            "{:indent$}{}",
+            "",
+            line,
+            indent = depth.max(0) as usize * 2
+        )?;
+        if let Event::StartTag(_) = event.event {
+            depth += 1;
+        }
     }
     Ok(())
 }
 
-fn render_cmd(cmd: RenderCommand) -> Result<(), Error> {
-    let source = read_file(&cmd.path)?;
-    let events = source
-        .lines()
-        .map(|line| -> Result<AnnotatedEvent, Error> { Ok(serde_json::from_str(&line)?) })
+/// Writes an event stream in the given format.
+fn write_events<'data, I: Iterator<Item = AnnotatedEvent<'data>>>(
+    iter: I,
+    format: OutputFormat,
+    renderer_options: &HtmlRendererOptions,
+) -> Result<(), Error> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match format {
+        OutputFormat::NdJson => write_ndjson(&mut out, iter)?,
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, &iter.collect::<Vec<_>>())?;
+            writeln!(out)?;
+        }
+        OutputFormat::Yaml => serde_yaml::to_writer(&mut out, &iter.collect::<Vec<_>>())?,
+        OutputFormat::MsgPack => {
+            rmp_serde::encode::write_named(&mut out, &iter.collect::<Vec<_>>())?
+        }
+        OutputFormat::Tree => write_tree(&mut out, iter)?,
+        OutputFormat::Html => {
+            to_html_writer(iter, renderer_options, &mut out)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the paths of the inputs defaulting to stdin.
+fn input_paths(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    if paths.is_empty() {
+        vec![PathBuf::from("-")]
+    } else {
+        paths
+    }
+}
+
+/// Returns the options for `--merge`.
+fn merge_options(merge: bool, demote_headings: usize) -> Option<MergeOptions> {
+    if merge {
+        Some(MergeOptions {
+            demote_headings,
+            ..MergeOptions::default()
+        })
+    } else {
+        None
+    }
+}
+
+/// Writes documents one after another or merged into a single document.
+fn write_documents<'data, D, I>(
+    documents: D,
+    merge_options: Option<&MergeOptions>,
+    format: OutputFormat,
+    renderer_options: &HtmlRendererOptions,
+) -> Result<(), Error>
+where
+    D: IntoIterator<Item = I>,
+    I: Iterator<Item = AnnotatedEvent<'data>>,
+{
+    match merge_options {
+        Some(options) => write_events(
+            merge(documents, options).into_iter(),
+            format,
+            renderer_options,
+        ),
+        None => write_events(documents.into_iter().flatten(), format, renderer_options),
+    }
+}
+
+fn parse_cmd(cmd: ParseCommand) -> Result<(), Error> {
+    let sources = input_paths(cmd.paths)
+        .iter()
+        .map(read_file)
         .collect::<Result<Vec<_>, _>>()?;
-    println!("{}", to_html(events.into_iter(), &Default::default()));
+    write_documents(
+        sources
+            .iter()
+            .map(|source| struckdown::parser::parse(source, &Default::default())),
+        merge_options(cmd.merge, cmd.demote_headings).as_ref(),
+        cmd.format,
+        &Default::default(),
+    )
+}
+
+/// Converts an event stream into a JSON tree.
+///
+/// Start tags become objects with the events up to their end tag in
+/// `children`, all other events are serialized as is.
+fn to_json_tree<'data, I: Iterator<Item = AnnotatedEvent<'data>>>(iter: I) -> Result<Value, Error> {
+    fn close(stack: &mut Vec<Vec<Value>>) {
+        let mut children = stack.pop().unwrap();
+        let mut node = children.remove(0);
+        node["children"] = Value::Array(children);
+        stack.last_mut().unwrap().push(node);
+    }
+
+    let mut stack = vec![Vec::new()];
+    for annotated_event in iter {
+        match annotated_event.event {
+            Event::StartTag(_) => stack.push(vec![to_value(&annotated_event)?]),
+            Event::EndTag(_) if stack.len() > 1 => close(&mut stack),
+            Event::EndTag(_) => {}
+            _ => stack.last_mut().unwrap().push(to_value(&annotated_event)?),
+        }
+    }
+    // unclosed tags
+    while stack.len() > 1 {
+        close(&mut stack);
+    }
+    Ok(Value::Array(stack.pop().unwrap()))
+}
+
+/// Renders an event stream with a renderer.
+fn render_events<'data, I: Iterator<Item = AnnotatedEvent<'data>>>(
+    iter: I,
+    renderer: Renderer,
+    html_options: &HtmlRendererOptions,
+    text_options: &PlainTextOptions,
+) -> Result<String, Error> {
+    Ok(match renderer {
+        Renderer::Html => format!("{}\n", to_html(iter, html_options)),
+        Renderer::Text => to_text(iter, text_options),
+        Renderer::JsonTree => format!("{}\n", serde_json::to_string_pretty(&to_json_tree(iter)?)?),
+    })
+}
+
+/// Applies the renderer option flags to the configured options.
+fn renderer_options(
+    html_options: &HtmlRendererOptions,
+    underlines: bool,
+    headline_level: Option<usize>,
+) -> HtmlRendererOptions {
+    let mut rv = html_options.clone();
+    rv.render_underlines |= underlines;
+    if let Some(level) = headline_level {
+        rv.initial_headline_level = level;
+    }
+    rv
+}
+
+fn render_cmd(cmd: RenderCommand) -> Result<(), Error> {
+    let error = Rc::new(RefCell::new(None));
+    let events = stream_ndjson(open_file(&cmd.path)?, error.clone());
+    let html_options = renderer_options(&Default::default(), cmd.underlines, cmd.headline_level);
+    let text_options = PlainTextOptions {
+        width: cmd.width,
+        show_link_targets: cmd.link_targets,
+    };
+    let output = render_events(events, cmd.to, &html_options, &text_options)?;
+    if let Some(err) = error.borrow_mut().take() {
+        return Err(err);
+    }
+    print!("{}", output);
     Ok(())
 }
 
-#[derive(Debug, Deserialize)]
+/// The formats documents can be read in.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum InputFormat {
+    #[default]
+    NdJson,
+    Markdown,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
 pub struct ProcessConfig {
-    processors: Vec<BuiltinProcessor>,
+    #[serde(flatten)]
+    pipeline: PipelineConfig,
+    input: InputFormat,
+    output: OutputFormat,
+}
+
+/// Parses a YAML file pointing errors at their location in the file.
+fn parse_yaml<T: DeserializeOwned>(path: &Path, source: &str) -> Result<T, Error> {
+    serde_yaml::from_str(source).map_err(|err| match err.location() {
+        Some(loc) => SourceError {
+            path: path.display().to_string(),
+            source: source.to_string(),
+            location: location_at(source, loc.line(), loc.column()),
+            // the message repeats the location at the end
+            message: err
+                .to_string()
+                .split(" at line ")
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+        }
+        .into(),
+        None => Error::from(err).context(format!("invalid config {}", path.display())),
+    })
+}
+
+fn load_config<P: AsRef<Path>>(path: &P) -> Result<ProcessConfig, Error> {
+    let command_source = read_file(path)?;
+    parse_yaml(path.as_ref(), &command_source)
+}
+
+fn load_pipeline<P: AsRef<Path>>(path: &P) -> Result<Pipeline, Error> {
+    Ok(Pipeline::try_from_config(&load_config(path)?.pipeline)?)
+}
+
+/// Returns the name of the stage a configured processor runs as.
+fn stage_name(processor: &ProcessorConfig) -> Option<String> {
+    match processor.stage {
+        Some(ref stage) => Some(stage.clone()),
+        None => to_value(&processor.processor)
+            .ok()?
+            .get("processor")?
+            .as_str()
+            .map(|x| x.to_string()),
+    }
+}
+
+/// Processors that run commands, load code or read files.
+///
+/// Documents are not necessarily trusted so these are rejected in front
+/// matter unless `--trust-front-matter` is passed.
+const UNTRUSTED_FRONT_MATTER_PROCESSORS: &[&str] = &[
+    "external",
+    "native_plugin",
+    "wasm_processor",
+    "code_import",
+    "snippets",
+];
+
+/// Merges the processors in the `processors` key of the front matter of a
+/// document into a pipeline config.
+///
+/// A processor replaces the configured stage of the same name, all others
+/// run after the configured processors.  Unless `trusted` is set processors
+/// in [`UNTRUSTED_FRONT_MATTER_PROCESSORS`] are rejected.
+fn merge_front_matter_processors(
+    config: &mut PipelineConfig,
+    source: &str,
+    trusted: bool,
+) -> Result<(), Error> {
+    let front_matter = match Parser::new(&config.parser).parse(source).next() {
+        Some(AnnotatedEvent {
+            event:
+                Event::DocumentStart(DocumentStartEvent {
+                    front_matter: Some(front_matter),
+                    ..
+                }),
+            ..
+        }) => front_matter,
+        _ => return Ok(()),
+    };
+    let processors: Vec<ProcessorConfig> = match front_matter.get("processors") {
+        Some(processors) => {
+            from_value(processors.clone()).context("invalid processors in front matter")?
+        }
+        None => return Ok(()),
+    };
+    for processor in processors {
+        let kind = to_value(&processor.processor)?
+            .get("processor")
+            .and_then(|x| x.as_str())
+            .map(|x| x.to_string());
+        if let Some(kind) = kind {
+            if !trusted && UNTRUSTED_FRONT_MATTER_PROCESSORS.contains(&kind.as_str()) {
+                return Err(anyhow!(
+                    "processor '{}' in front matter requires --trust-front-matter",
+                    kind
+                ));
+            }
+        }
+        let name = stage_name(&processor);
+        match config
+            .processors
+            .iter_mut()
+            .find(|x| name.is_some() && stage_name(x) == name)
+        {
+            Some(existing) => *existing = processor,
+            None => config.processors.push(processor),
+        }
+    }
+    Ok(())
+}
+
+/// Loads the pipeline for a document from an optional config file.
+fn load_document_pipeline(
+    config: Option<&Path>,
+    source: &str,
+    front_matter_processors: bool,
+    trust_front_matter: bool,
+) -> Result<Pipeline, Error> {
+    let mut config = match config {
+        Some(path) => load_config(&path)?.pipeline,
+        None => PipelineConfig::default(),
+    };
+    if front_matter_processors {
+        merge_front_matter_processors(&mut config, source, trust_front_matter)?;
+    }
+    Ok(Pipeline::try_from_config(&config)?)
+}
+
+/// Collects the timings of a pipeline run for `--profile`.
+struct Profiler {
+    report: Arc<Mutex<Option<TimingReport>>>,
+    start: Instant,
+}
+
+impl Profiler {
+    /// Creates a profiler if enabled.
+    fn new(enabled: bool) -> Option<Profiler> {
+        if !enabled {
+            return None;
+        }
+        Some(Profiler {
+            report: Arc::new(Mutex::new(None)),
+            start: Instant::now(),
+        })
+    }
+
+    /// Attaches the profiler to a pipeline.
+    ///
+    /// The timings of stages with the same name are added up if multiple
+    /// pipelines are profiled.
+    fn attach(&self, pipeline: &mut Pipeline) {
+        let report = self.report.clone();
+        pipeline.set_timing_callback(move |new_report| {
+            let mut report = report.lock().unwrap();
+            let report = match *report {
+                Some(ref mut report) => report,
+                None => {
+                    *report = Some(new_report.clone());
+                    return;
+                }
+            };
+            for stage in &new_report.stages {
+                match report.stages.iter_mut().find(|x| x.name == stage.name) {
+                    Some(existing) => {
+                        existing.events += stage.events;
+                        existing.duration += stage.duration;
+                    }
+                    None => report.stages.push(stage.clone()),
+                }
+            }
+        });
+    }
+
+    /// Prints the timings to stderr.
+    ///
+    /// The first stage is reported as `input_name`.  Time not spent in the
+    /// pipeline is attributed to the `output_name` stage.
+    fn print(&self, input_name: &str, output_name: &str) {
+        let total = self.start.elapsed();
+        let report = self.report.lock().unwrap();
+        let stages = report.as_ref().map_or(&[][..], |x| &x.stages[..]);
+        let in_pipeline = stages.iter().map(|x| x.duration).sum::<Duration>();
+        let ms = |x: Duration| x.as_secs_f64() * 1000.0;
+        eprintln!("{:<24} {:>10} {:>12}", "stage", "events", "time");
+        for (idx, stage) in stages.iter().enumerate() {
+            let name = if idx == 0 { input_name } else { &stage.name };
+            eprintln!(
+                "{:<24} {:>10} {:>10.3}ms",
+                name,
+                stage.events,
+                ms(stage.duration)
+            );
+        }
+        eprintln!(
+            "{:<24} {:>10} {:>10.3}ms",
+            output_name,
+            "",
+            ms(total.checked_sub(in_pipeline).unwrap_or_default())
+        );
+        eprintln!("{:<24} {:>10} {:>10.3}ms", "total", "", ms(total));
+    }
 }
 
 fn process_cmd(cmd: ProcessCommand) -> Result<(), Error> {
-    let command_source = read_file(&cmd.config)?;
-    let config: ProcessConfig = serde_yaml::from_str(&command_source)?;
+    let config = load_config(&cmd.config)?;
+    let paths = input_paths(cmd.paths);
+    let merge_options = merge_options(cmd.merge, cmd.demote_headings);
+    let profiler = Profiler::new(cmd.profile);
+    let output_name = match config.output {
+        OutputFormat::Html => "render",
+        _ => "output",
+    };
+
+    match config.input {
+        // NDJSON streams are processed while they are read so that the
+        // command can be used as an external processor.
+        InputFormat::NdJson => {
+            let error = Rc::new(RefCell::new(None));
+            let mut documents = Vec::new();
+            for path in &paths {
+                let mut pipeline = Pipeline::try_from_config(&config.pipeline)?;
+                if let Some(ref profiler) = profiler {
+                    profiler.attach(&mut pipeline);
+                }
+                documents.push(pipeline.apply(stream_ndjson(open_file(path)?, error.clone())));
+            }
+            write_documents(
+                documents,
+                merge_options.as_ref(),
+                config.output,
+                &config.pipeline.renderer,
+            )?;
+            if let Some(ref profiler) = profiler {
+                profiler.print("input", output_name);
+            }
+            let rv = match error.borrow_mut().take() {
+                Some(err) => Err(err),
+                None => Ok(()),
+            };
+            rv
+        }
+        InputFormat::Markdown => {
+            let sources = paths.iter().map(read_file).collect::<Result<Vec<_>, _>>()?;
+            let mut pipelines = Vec::new();
+            for source in &sources {
+                let mut pipeline_config = config.pipeline.clone();
+                if !cmd.no_front_matter_processors {
+                    merge_front_matter_processors(
+                        &mut pipeline_config,
+                        source,
+                        cmd.trust_front_matter,
+                    )?;
+                }
+                let mut pipeline = Pipeline::try_from_config(&pipeline_config)?;
+                if let Some(ref profiler) = profiler {
+                    profiler.attach(&mut pipeline);
+                }
+                pipelines.push(pipeline);
+            }
+            write_documents(
+                pipelines
+                    .iter()
+                    .zip(&sources)
+                    .map(|(pipeline, source)| pipeline.process(source)),
+                merge_options.as_ref(),
+                config.output,
+                &config.pipeline.renderer,
+            )?;
+            if let Some(ref profiler) = profiler {
+                profiler.print("parse", output_name);
+            }
+            Ok(())
+        }
+    }
+}
+
+fn html_cmd(cmd: HtmlCommand) -> Result<(), Error> {
+    let source = read_file(&cmd.path)?;
+    let mut pipeline = load_document_pipeline(
+        cmd.config.as_deref(),
+        &source,
+        !cmd.no_front_matter_processors,
+        cmd.trust_front_matter,
+    )?;
+    let html_options = renderer_options(
+        pipeline.renderer_options(),
+        cmd.underlines,
+        cmd.headline_level,
+    );
+    let text_options = PlainTextOptions {
+        width: cmd.width,
+        show_link_targets: cmd.link_targets,
+    };
+    let profiler = Profiler::new(cmd.profile);
+    if let Some(ref profiler) = profiler {
+        profiler.attach(&mut pipeline);
+    }
+    let rendered = render_events(
+        pipeline.process(&source),
+        cmd.to,
+        &html_options,
+        &text_options,
+    )?;
+    if let Some(ref profiler) = profiler {
+        profiler.print("parse", "render");
+    }
+    match cmd.output {
+        Some(ref output) => fs::write(output, rendered)?,
+        None => print!("{}", rendered),
+    }
+    Ok(())
+}
+
+/// Returns the directory a glob pattern is relative to.
+fn glob_base(pattern: &str) -> PathBuf {
+    Path::new(pattern)
+        .components()
+        .take_while(|x| !x.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+        .collect()
+}
+
+/// Finds the documents matching a glob pattern.
+///
+/// Returns the base directory of the pattern and the paths of the documents
+/// relative to it.
+fn find_documents(pattern: &str) -> Result<(PathBuf, Vec<String>), Error> {
+    let base = glob_base(pattern);
+    let mut documents = Vec::new();
+    for path in glob::glob(pattern)? {
+        let path = path?;
+        if !path.is_file() {
+            continue;
+        }
+        let rel = path.strip_prefix(&base).unwrap_or(&path);
+        documents.push(
+            rel.components()
+                .map(|x| x.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/"),
+        );
+    }
+    Ok((base, documents))
+}
 
-    let mut pipeline = Pipeline::new();
-    for processor in config.processors {
-        pipeline.add_processor(processor);
+/// Prints the error events of a document returning `true` if it failed.
+fn report_errors(path: &str, source: Option<&str>, events: &[AnnotatedEvent]) -> bool {
+    let mut failed = false;
+    for annotated_event in events {
+        if let Event::Error(ref error) = annotated_event.event {
+            failed |= error.severity == Severity::Error;
+            print_diagnostic(
+                error.severity,
+                error.title.as_str(),
+                error.description.as_ref().map(|x| x.as_str()),
+                path,
+                source,
+                annotated_event.location,
+            );
+        }
     }
+    failed
+}
 
-    let source = read_file(&"-")?;
-    let events = source
+/// Returns the path of the HTML file a document is rendered to.
+fn html_path(path: &str) -> String {
+    match path.rfind('.') {
+        Some(idx) if !path[idx..].contains('/') => format!("{}.html", &path[..idx]),
+        _ => format!("{}.html", path),
+    }
+}
+
+/// Creates a project whose references point at the rendered HTML files.
+fn html_project(pipeline: Pipeline) -> Project {
+    Project::with_options(
+        pipeline,
+        ProjectOptions {
+            output_path: Some(OutputPathFn::new(html_path)),
+            ..Default::default()
+        },
+    )
+}
+
+/// Returns the path a document is rendered to.
+fn output_path(output: &Path, path: &str) -> PathBuf {
+    output.join(html_path(path))
+}
+
+/// Writes the HTML of a document returning `false` if it failed.
+fn write_html(output: &Path, path: &str, html: &str) -> bool {
+    let output = output_path(output, path);
+    match output
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&output, html))
+    {
+        Ok(()) => true,
+        Err(err) => {
+            eprintln!("{}: error: {}", output.display(), err);
+            false
+        }
+    }
+}
+
+fn build_cmd(cmd: BuildCommand) -> Result<(), Error> {
+    let pipeline = match cmd.config {
+        Some(ref config) => load_pipeline(config)?,
+        None => Pipeline::new(),
+    };
+    let renderer_options = pipeline.renderer_options().clone();
+    let (base, documents) = find_documents(&cmd.pattern)?;
+    let mut project = html_project(pipeline);
+    let mut sources = HashMap::new();
+    let mut failed = 0;
+    for path in &documents {
+        match fs::read_to_string(base.join(path)) {
+            Ok(source) => {
+                project.add_document(path, &source);
+                sources.insert(path.clone(), source);
+            }
+            Err(err) => {
+                eprintln!("{}: error: {}", path, err);
+                failed += 1;
+            }
+        }
+    }
+
+    for document in project.build().documents {
+        let source = sources.get(&document.path).map(|x| x.as_str());
+        let errors = report_errors(&document.path, source, &document.events);
+        let html = to_html(document.events.into_iter(), &renderer_options);
+        if !write_html(&cmd.output, &document.path, &html) || errors {
+            failed += 1;
+        }
+    }
+
+    eprintln!(
+        "built {} of {} documents",
+        documents.len() - failed,
+        documents.len()
+    );
+    if failed > 0 {
+        return Err(DocumentsFailed(failed).into());
+    }
+    Ok(())
+}
+
+fn watch_cmd(cmd: WatchCommand) -> Result<(), Error> {
+    let mut watcher = Watcher::new(&cmd.pattern, cmd.config.as_deref());
+    loop {
+        let mut written = 0;
+        for change in watcher.poll()? {
+            match change {
+                Change::Updated { path, html } => {
+                    if write_html(&cmd.output, &path, &html) {
+                        written += 1;
+                    }
+                }
+                Change::Removed { path } => {
+                    fs::remove_file(output_path(&cmd.output, &path)).ok();
+                }
+            }
+        }
+        if written > 0 {
+            eprintln!("wrote {} documents", written);
+        }
+        thread::sleep(Duration::from_millis(cmd.interval));
+    }
+}
+
+fn serve_cmd(cmd: ServeCommand) -> Result<(), Error> {
+    let watcher = Watcher::new(&cmd.pattern, cmd.config.as_deref());
+    serve::serve(watcher, &cmd.addr, Duration::from_millis(cmd.interval))
+}
+
+/// Parses NDJSON into an event stream.
+fn parse_ndjson(source: &str) -> Result<Vec<AnnotatedEvent<'_>>, Error> {
+    source
         .lines()
-        .map(|line| -> Result<AnnotatedEvent, Error> { Ok(serde_json::from_str(&line)?) })
-        .collect::<Result<Vec<_>, _>>()?;
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| -> Result<AnnotatedEvent, Error> { Ok(serde_json::from_str(line)?) })
+        .collect()
+}
+
+/// Loads the event stream of a document for comparison.
+///
+/// Soft breaks are turned into spaces and adjacent text events are merged.
+fn load_events_for_diff(
+    path: &Path,
+    pipeline: &Pipeline,
+) -> Result<Vec<AnnotatedEvent<'static>>, Error> {
+    let source = read_file(&path)?;
+    let is_ndjson = matches!(
+        path.extension().and_then(|x| x.to_str()),
+        Some("json") | Some("jsonl") | Some("ndjson")
+    );
+    let events: Vec<AnnotatedEvent<'static>> = if is_ndjson {
+        pipeline
+            .apply_ref(parse_ndjson(&source)?.into_iter())
+            .map(|x| x.into_static())
+            .collect()
+    } else {
+        pipeline.process(&source).map(|x| x.into_static()).collect()
+    };
+
+    let mut rv: Vec<AnnotatedEvent<'static>> = Vec::with_capacity(events.len());
+    for mut annotated_event in events {
+        if let Event::SoftBreak = annotated_event.event {
+            annotated_event.event = TextEvent { text: " ".into() }.into();
+        }
+        if let (
+            Event::Text(ref text),
+            Some(AnnotatedEvent {
+                event: Event::Text(ref mut prev),
+                ..
+            }),
+        ) = (&annotated_event.event, rv.last_mut())
+        {
+            prev.text = format!("{}{}", prev.text.as_str(), text.text.as_str()).into();
+            continue;
+        }
+        rv.push(annotated_event);
+    }
+    Ok(rv)
+}
 
-    for event in pipeline.apply(events.into_iter()) {
-        let out = serde_json::to_string(&event)?;
-        println!("{}", out);
+fn diff_cmd(cmd: DiffCommand) -> Result<(), Error> {
+    let pipeline = match cmd.config {
+        Some(ref config) => load_pipeline(config)?,
+        None => Pipeline::new(),
+    };
+    let old = load_events_for_diff(&cmd.old, &pipeline)?;
+    let new = load_events_for_diff(&cmd.new, &pipeline)?;
+    let strip = |events: &[AnnotatedEvent<'static>]| {
+        events
+            .iter()
+            .map(|x| AnnotatedEvent::new(x.event.clone(), None))
+            .collect::<Vec<_>>()
+    };
+    let changes = diff_events(&strip(&old), &strip(&new));
+    if changes.is_empty() {
+        return Ok(());
     }
 
+    let line = |path: &Path, event: &AnnotatedEvent| -> Result<String, Error> {
+        Ok(match event.location {
+            Some(loc) => format!(
+                "{}  ({}:{})",
+                describe_event(&event.event)?,
+                path.display(),
+                loc.line
+            ),
+            None => describe_event(&event.event)?,
+        })
+    };
+    println!("--- {}", cmd.old.display());
+    println!("+++ {}", cmd.new.display());
+    let count = changes.len();
+    for change in changes {
+        match change {
+            EventChange::Removed { index, .. } => {
+                println!("- {}", line(&cmd.old, &old[index])?);
+            }
+            EventChange::Added { index, .. } => {
+                println!("+ {}", line(&cmd.new, &new[index])?);
+            }
+            EventChange::Modified {
+                old_index,
+                new_index,
+                ..
+            } => {
+                println!("~ {}", line(&cmd.old, &old[old_index])?);
+                println!("  {}", line(&cmd.new, &new[new_index])?);
+            }
+        }
+    }
+    Err(DocumentsDiffer(count).into())
+}
+
+/// A heading in the outline of a document.
+#[derive(Debug, Serialize)]
+struct OutlineEntry {
+    level: usize,
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    anchor: Option<String>,
+}
+
+/// Flattens the `toc` metadata into a list of headings.
+fn flatten_toc(items: &Value, level: usize, min_depth: usize, out: &mut Vec<OutlineEntry>) {
+    for item in items.as_array().into_iter().flatten() {
+        // levels that are skipped by the document are filled with empty
+        // items which have no text
+        if let Some(text) = item.get("text").and_then(|x| x.as_str()) {
+            if level >= min_depth {
+                out.push(OutlineEntry {
+                    level,
+                    text: text.to_string(),
+                    anchor: item.get("anchor").and_then(|x| x.as_str()).map(Into::into),
+                });
+            }
+        }
+        if let Some(children) = item.get("children") {
+            flatten_toc(children, level + 1, min_depth, out);
+        }
+    }
+}
+
+fn toc_cmd(cmd: TocCommand) -> Result<(), Error> {
+    let source = read_file(&cmd.path)?;
+    let pipeline = Pipeline::builder()
+        .processor(AutoAnchors::default())
+        .processor(TableOfContents {
+            metadata_only: true,
+            max_depth: cmd.max_depth,
+            ..Default::default()
+        })
+        .build()?;
+    let toc = pipeline
+        .process(&source)
+        .find_map(|annotated_event| match annotated_event.event {
+            Event::MetaData(meta) if meta.key.as_str() == "toc" => Some(meta.value),
+            _ => None,
+        })
+        .unwrap_or(Value::Null);
+
+    let mut outline = Vec::new();
+    flatten_toc(&toc, 1, cmd.min_depth, &mut outline);
+    if cmd.json {
+        println!("{}", serde_json::to_string_pretty(&outline)?);
+        return Ok(());
+    }
+    for entry in outline {
+        let mut line = format!(
+            "{:indent$}{} {}",
+            "",
+            "#".repeat(entry.level),
+            entry.text,
+            indent = (entry.level - cmd.min_depth.min(entry.level)) * 2
+        );
+        if let Some(anchor) = entry.anchor {
+            line.push_str(&format!(" (#{})", anchor));
+        }
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+fn query_cmd(cmd: QueryCommand) -> Result<(), Error> {
+    let source = read_file(&cmd.path)?;
+    let pipeline = load_document_pipeline(
+        cmd.config.as_deref(),
+        &source,
+        !cmd.no_front_matter_processors,
+        cmd.trust_front_matter,
+    )?;
+    let tree = match to_json_tree(pipeline.process(&source))? {
+        Value::Array(nodes) => nodes,
+        _ => unreachable!(),
+    };
+    let matches = cmd.selector.query(&tree);
+    if cmd.pretty {
+        println!("{}", serde_json::to_string_pretty(&matches)?);
+        return Ok(());
+    }
+    for node in matches {
+        println!("{}", serde_json::to_string(node)?);
+    }
+    Ok(())
+}
+
+/// Returns the 1-based line and column of a byte offset.
+fn line_and_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |x| x + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// Returns the line of the source at a byte offset.
+fn line_at(source: &str, offset: usize) -> &str {
+    let start = source[..offset].rfind('\n').map_or(0, |x| x + 1);
+    let end = source[offset..]
+        .find('\n')
+        .map_or(source.len(), |x| x + offset);
+    source[start..end].trim_end_matches('\r')
+}
+
+/// A match of a regular expression in a document.
+#[derive(Debug, PartialEq)]
+struct GrepMatch {
+    /// The 1-based line and column of the match if the location is known.
+    position: Option<(usize, usize)>,
+    /// The headings the match is in, outermost first.
+    headings: Vec<String>,
+    /// The line of the match or the matched text if the location is unknown.
+    line: String,
+}
+
+/// Finds the matches of a regular expression in a document.
+///
+/// Only prose is searched unless `code` is set, `only_code` restricts the
+/// search to code blocks and inline code.
+fn grep_document(regex: &Regex, source: &str, code: bool, only_code: bool) -> Vec<GrepMatch> {
+    let mut rv = Vec::new();
+    let mut headings: Vec<(usize, String)> = Vec::new();
+    let mut heading_text = None::<String>;
+
+    for annotated_event in struckdown::parser::parse(source, &Default::default()) {
+        let (text, is_code) = match annotated_event.event {
+            Event::StartTag(StartTagEvent { tag, .. }) if tag.header_level().is_some() => {
+                heading_text = Some(String::new());
+                continue;
+            }
+            Event::EndTag(EndTagEvent { tag }) => {
+                if let (Some(level), Some(text)) = (tag.header_level(), heading_text.take()) {
+                    headings.retain(|(x, _)| *x < level);
+                    headings.push((level, text));
+                }
+                continue;
+            }
+            Event::Text(TextEvent { ref text }) => (text.as_str(), false),
+            Event::InterpretedText(InterpretedTextEvent { ref text, .. }) => (text.as_str(), false),
+            Event::InlineCode(InlineCodeEvent { ref code }) => (code.as_str(), true),
+            Event::CodeBlock(CodeBlockEvent { ref code, .. }) => (code.as_str(), true),
+            _ => continue,
+        };
+        if let Some(ref mut heading_text) = heading_text {
+            heading_text.push_str(text);
+        }
+        if (is_code && !(code || only_code)) || (!is_code && only_code) {
+            continue;
+        }
+
+        for m in regex.find_iter(text) {
+            // the text of an event is not necessarily verbatim in the source
+            // (escapes, code fences) so the match is located in the span of
+            // the event and falls back to the start of the event.
+            let offset = annotated_event.location.map(|loc| {
+                source
+                    .get(loc.offset..loc.offset + loc.len)
+                    .and_then(|span| span.find(m.as_str()))
+                    .map_or(loc.offset, |x| loc.offset + x)
+            });
+            rv.push(GrepMatch {
+                position: offset.map(|offset| line_and_column(source, offset)),
+                headings: headings.iter().map(|(_, text)| text.clone()).collect(),
+                line: match offset {
+                    Some(offset) => line_at(source, offset).trim().to_string(),
+                    None => m.as_str().to_string(),
+                },
+            });
+        }
+    }
+    rv
+}
+
+fn grep_cmd(cmd: GrepCommand) -> Result<(), Error> {
+    let regex = RegexBuilder::new(&cmd.pattern)
+        .case_insensitive(cmd.ignore_case)
+        .build()?;
+    let mut found = 0;
+    for path in input_paths(cmd.paths.clone()) {
+        let source = read_file(&path)?;
+        for m in grep_document(&regex, &source, cmd.code, cmd.only_code) {
+            let mut line = match m.position {
+                Some((line, column)) => format!("{}:{}:{}:", path.display(), line, column),
+                None => format!("{}:", path.display()),
+            };
+            if !cmd.no_heading && !m.headings.is_empty() {
+                line.push_str(&format!(" [{}]", m.headings.join(" > ")));
+            }
+            println!("{} {}", line, m.line);
+            found += 1;
+        }
+    }
+    if found == 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn mdbook_preprocessor_cmd(cmd: MdBookPreprocessorCommand) -> Result<(), Error> {
+    match cmd.args.first().map(|x| x.as_str()) {
+        Some("supports") => {
+            let renderer = cmd
+                .args
+                .get(1)
+                .ok_or_else(|| anyhow!("missing renderer for supports"))?;
+            if !mdbook::supports(renderer) {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Some(arg) => Err(anyhow!("unknown argument '{}'", arg)),
+        None => mdbook::preprocess(cmd.config.as_deref(), cmd.trust_front_matter),
+    }
+}
+
+fn highlight_cmd(cmd: HighlightCommand) -> Result<(), Error> {
+    let syntect = Syntect {
+        theme: cmd.theme.clone(),
+        theme_path: cmd.theme_path.clone(),
+        syntax_path: cmd.syntax_path.clone(),
+        use_classes: cmd.classes,
+        ..Default::default()
+    };
+
+    if cmd.list_themes {
+        let theme_set = syntect.try_load_theme_set()?;
+        let mut themes = theme_set.themes.keys().collect::<Vec<_>>();
+        themes.sort();
+        for theme in themes {
+            println!("{}", theme);
+        }
+        return Ok(());
+    }
+    if cmd.list_languages {
+        let syntax_set = syntect.try_load_syntax_set()?;
+        for syntax in syntax_set.syntaxes() {
+            println!("{} ({})", syntax.name, syntax.file_extensions.join(", "));
+        }
+        return Ok(());
+    }
+    syntect.check()?;
+    if cmd.css {
+        print!("{}", syntect.theme_css());
+        return Ok(());
+    }
+
+    let lang = cmd.lang.clone().or_else(|| {
+        cmd.path
+            .extension()
+            .and_then(|x| x.to_str())
+            .map(|x| x.to_string())
+    });
+    let code = read_file(&cmd.path)?;
+    let events = vec![AnnotatedEvent::new(
+        CodeBlockEvent {
+            language: lang.map(Into::into),
+            args: None,
+            code: code.into(),
+        },
+        None,
+    )];
+    let pipeline = Pipeline::builder().processor(syntect).build()?;
+    print!(
+        "{}",
+        to_html(pipeline.apply_ref(events.into_iter()), &Default::default())
+    );
+    Ok(())
+}
+
+fn text_cmd(cmd: TextCommand) -> Result<(), Error> {
+    let source = read_file(&cmd.path)?;
+    let pipeline = load_document_pipeline(
+        cmd.config.as_deref(),
+        &source,
+        !cmd.no_front_matter_processors,
+        cmd.trust_front_matter,
+    )?;
+    let options = PlainTextOptions {
+        width: cmd.width,
+        show_link_targets: cmd.link_targets,
+    };
+    print!("{}", to_text(pipeline.process(&source), &options));
     Ok(())
 }
 
+fn sanitize_cmd(cmd: SanitizeCommand) -> Result<(), Error> {
+    let mut sanitizer: HtmlSanitizer = match cmd.config {
+        Some(ref config) => parse_yaml(config, &read_file(config)?)?,
+        None => HtmlSanitizer::default(),
+    };
+    sanitizer.allow_class |= cmd.allow_class;
+    sanitizer.allow_style |= cmd.allow_style;
+    sanitizer.allow_comments &= !cmd.strip_comments;
+    sanitizer.allowed_tags.extend(cmd.allow_tag);
+    sanitizer.removed_tags.extend(cmd.remove_tag);
+    sanitizer.allowed_attributes.extend(cmd.allow_attribute);
+    sanitizer.force_https |= cmd.force_https;
+    if cmd.link_rel.is_some() {
+        sanitizer.link_rel = cmd.link_rel;
+    }
+    if cmd.image_proxy.is_some() {
+        sanitizer.image_proxy = cmd.image_proxy;
+    }
+    let pipeline = Pipeline::builder().processor(sanitizer).build()?;
+
+    let source = read_file(&cmd.path)?;
+    if cmd.html {
+        let events = vec![AnnotatedEvent::new(
+            RawHtmlEvent {
+                html: source.as_str().into(),
+            },
+            None,
+        )];
+        print!(
+            "{}",
+            to_html(pipeline.apply_ref(events.into_iter()), &Default::default())
+        );
+        Ok(())
+    } else {
+        write_events(
+            pipeline.apply_ref(parse_ndjson(&source)?.into_iter()),
+            OutputFormat::NdJson,
+            &Default::default(),
+        )
+    }
+}
+
 fn run() -> Result<(), Error> {
     let cli: Cli = argh::from_env();
 
@@ -121,11 +1748,177 @@ fn run() -> Result<(), Error> {
         Command::Parse(args) => parse_cmd(args)?,
         Command::Render(args) => render_cmd(args)?,
         Command::Process(args) => process_cmd(args)?,
+        Command::Html(args) => html_cmd(args)?,
+        Command::Build(args) => build_cmd(args)?,
+        Command::Watch(args) => watch_cmd(args)?,
+        Command::Serve(args) => serve_cmd(args)?,
+        Command::Diff(args) => diff_cmd(args)?,
+        Command::Toc(args) => toc_cmd(args)?,
+        Command::Highlight(args) => highlight_cmd(args)?,
+        Command::Text(args) => text_cmd(args)?,
+        Command::Sanitize(args) => sanitize_cmd(args)?,
+        Command::Query(args) => query_cmd(args)?,
+        Command::Grep(args) => grep_cmd(args)?,
+        Command::MdBookPreprocessor(args) => mdbook_preprocessor_cmd(args)?,
     }
 
     Ok(())
 }
 
 fn main() {
-    run().unwrap();
+    if let Err(err) = run() {
+        std::process::exit(report::report_error(&err));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("struck-test-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn texts(events: &[AnnotatedEvent<'_>]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|x| match x.event {
+                Event::Text(ref text) => Some(text.text.as_str().to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_load_events_for_diff() {
+        let pipeline = Pipeline::new();
+        let path = temp_file("diff.md", "Hello\nreflowed\nworld *x*");
+        let events = load_events_for_diff(&path, &pipeline).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(texts(&events), vec!["Hello reflowed world ", "x"]);
+        assert!(!events.iter().any(|x| matches!(x.event, Event::SoftBreak)));
+
+        // event streams are normalized the same way
+        let path = temp_file(
+            "diff.ndjson",
+            concat!(
+                "{\"type\":\"text\",\"text\":\"Hello\"}\n",
+                "{\"type\":\"soft_break\"}\n",
+                "\n",
+                "{\"type\":\"text\",\"text\":\"world\"}\n",
+            ),
+        );
+        let events = load_events_for_diff(&path, &pipeline).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(texts(&events), vec!["Hello world"]);
+        assert_eq!(events.len(), 1);
+    }
+
+    fn grep(pattern: &str, source: &str, code: bool, only_code: bool) -> Vec<GrepMatch> {
+        grep_document(&Regex::new(pattern).unwrap(), source, code, only_code)
+    }
+
+    const GREP_DOCUMENT: &str = "\
+# Guide
+
+Intro with a needle.
+
+## Setup
+
+Use `needle` here.
+
+```
+let needle = 1;
+```
+
+# Other
+
+A \\*needle\\* with escapes.
+";
+
+    #[test]
+    fn test_grep_document() {
+        let matches = grep("needle", GREP_DOCUMENT, false, false);
+        assert_eq!(
+            matches,
+            vec![
+                GrepMatch {
+                    position: Some((3, 14)),
+                    headings: vec!["Guide".into()],
+                    line: "Intro with a needle.".into(),
+                },
+                GrepMatch {
+                    position: Some((15, 5)),
+                    headings: vec!["Other".into()],
+                    line: "A \\*needle\\* with escapes.".into(),
+                },
+            ]
+        );
+
+        let positions = |matches: Vec<GrepMatch>| {
+            matches
+                .into_iter()
+                .map(|m| (m.position, m.headings.join(" > ")))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            positions(grep("needle", GREP_DOCUMENT, true, false)),
+            vec![
+                (Some((3, 14)), "Guide".into()),
+                (Some((7, 6)), "Guide > Setup".into()),
+                (Some((10, 5)), "Guide > Setup".into()),
+                (Some((15, 5)), "Other".into()),
+            ]
+        );
+        assert_eq!(
+            positions(grep("needle", GREP_DOCUMENT, false, true)),
+            vec![
+                (Some((7, 6)), "Guide > Setup".into()),
+                (Some((10, 5)), "Guide > Setup".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_grep_document_escapes() {
+        let source = "A \\*needle\\*\n\nfoo&nbsp;bar\n";
+
+        // the escape is skipped when locating the match in the event
+        let matches = grep(r"\*needle", source, false, false);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].position, Some((1, 4)));
+        assert_eq!(matches[0].line, "A \\*needle\\*");
+
+        // the text is not in the source and falls back to the start of the
+        // event
+        let matches = grep(r"\xA0", source, false, false);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].position, Some((3, 4)));
+        assert_eq!(matches[0].line, "foo&nbsp;bar");
+    }
+
+    #[test]
+    fn test_merge_front_matter_processors() {
+        let source = "---\nprocessors:\n  - processor: table_of_contents\n---\n# Hello";
+        let mut config = PipelineConfig::default();
+        merge_front_matter_processors(&mut config, source, false).unwrap();
+        assert_eq!(
+            stage_name(&config.processors[0]).as_deref(),
+            Some("table_of_contents")
+        );
+
+        let source = "---\nprocessors:\n  - processor: code_import\n---\n";
+        let mut config = PipelineConfig::default();
+        let err = merge_front_matter_processors(&mut config, source, false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "processor 'code_import' in front matter requires --trust-front-matter"
+        );
+        assert!(config.processors.is_empty());
+        merge_front_matter_processors(&mut config, source, true).unwrap();
+        assert_eq!(config.processors.len(), 1);
+    }
 }
@@ -0,0 +1,199 @@
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Error};
+use struckdown::html::to_html;
+use struckdown::pipeline::{Pipeline, PipelineConfig};
+use struckdown::value::Value;
+
+use crate::{load_config, merge_front_matter_processors, report_errors};
+
+/// The renderers the preprocessor can produce content for.
+///
+/// Chapters are replaced by HTML which only the HTML renderer passes
+/// through.
+const SUPPORTED_RENDERERS: &[&str] = &["html"];
+
+/// Checks if a renderer is supported.
+pub fn supports(renderer: &str) -> bool {
+    SUPPORTED_RENDERERS.contains(&renderer)
+}
+
+/// Returns the pipeline config for a book.
+///
+/// An explicitly given config takes precedence over the `config` key in
+/// the `[preprocessor.struck]` section of `book.toml` which is relative to
+/// the root of the book.
+fn book_config(context: &Value, config: Option<&Path>) -> Result<PipelineConfig, Error> {
+    let path = match config {
+        Some(config) => Some(config.to_path_buf()),
+        None => context
+            .pointer("/config/preprocessor/struck/config")
+            .and_then(|x| x.as_str())
+            .map(|path| {
+                let root = context.get("root").and_then(|x| x.as_str()).unwrap_or(".");
+                Path::new(root).join(path)
+            }),
+    };
+    match path {
+        Some(path) => Ok(load_config(&path)?.pipeline),
+        None => Ok(PipelineConfig::default()),
+    }
+}
+
+/// Runs a chapter through the pipeline and replaces its content with HTML.
+fn process_chapter(
+    chapter: &mut Value,
+    config: &PipelineConfig,
+    trust_front_matter: bool,
+) -> Result<(), Error> {
+    let path = chapter
+        .get("source_path")
+        .or_else(|| chapter.get("path"))
+        .and_then(|x| x.as_str())
+        .map(PathBuf::from);
+    let source = match chapter.get("content").and_then(|x| x.as_str()) {
+        Some(content) => content.to_string(),
+        None => return Ok(()),
+    };
+    let name = path.as_ref().map_or_else(
+        || {
+            chapter
+                .get("name")
+                .and_then(|x| x.as_str())
+                .unwrap_or("<chapter>")
+                .to_string()
+        },
+        |x| x.display().to_string(),
+    );
+
+    let mut config = config.clone();
+    merge_front_matter_processors(&mut config, &source, trust_front_matter)
+        .with_context(|| format!("cannot process {}", name))?;
+    let pipeline = Pipeline::try_from_config(&config)?;
+    let events: Vec<_> = match path {
+        Some(ref path) => pipeline.process_with_path(&source, path).collect(),
+        None => pipeline.process(&source).collect(),
+    };
+    report_errors(&name, Some(&source), &events);
+    chapter["content"] = Value::String(to_html(events.into_iter(), pipeline.renderer_options()));
+    Ok(())
+}
+
+/// Processes all chapters of a list of book items recursively.
+fn process_items(
+    items: &mut Value,
+    config: &PipelineConfig,
+    trust_front_matter: bool,
+) -> Result<(), Error> {
+    for item in items.as_array_mut().into_iter().flatten() {
+        if let Some(chapter) = item.get_mut("Chapter") {
+            process_chapter(chapter, config, trust_front_matter)?;
+            if let Some(sub_items) = chapter.get_mut("sub_items") {
+                process_items(sub_items, config, trust_front_matter)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs the preprocessor on the `[context, book]` input from mdBook.
+///
+/// Only the content of the chapters is changed, everything else is passed
+/// back to mdBook unchanged.
+pub fn preprocess(config: Option<&Path>, trust_front_matter: bool) -> Result<(), Error> {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+    let book = preprocess_book(&input, config, trust_front_matter)?;
+    serde_json::to_writer(io::stdout(), &book)?;
+    Ok(())
+}
+
+/// Processes the `[context, book]` input from mdBook and returns the book.
+fn preprocess_book(
+    input: &str,
+    config: Option<&Path>,
+    trust_front_matter: bool,
+) -> Result<Value, Error> {
+    let (context, mut book): (Value, Value) =
+        serde_json::from_str(input).context("invalid input from mdBook")?;
+
+    let renderer = context.get("renderer").and_then(|x| x.as_str());
+    if let Some(renderer) = renderer.filter(|x| !supports(x)) {
+        return Err(anyhow!("renderer '{}' is not supported", renderer));
+    }
+
+    let config = book_config(&context, config)?;
+    // older versions of mdBook call the chapters `sections`
+    for key in &["sections", "items"] {
+        if let Some(items) = book.get_mut(key) {
+            process_items(items, &config, trust_front_matter)?;
+        }
+    }
+    Ok(book)
+}
+
+#[cfg(test)]
+mod tests {
+    use struckdown::value::value;
+
+    use super::*;
+
+    fn chapter(name: &str, content: &str, sub_items: Value) -> Value {
+        value!({
+            "Chapter": {
+                "name": name,
+                "content": content,
+                "number": [1],
+                "sub_items": sub_items,
+                "path": format!("{}.md", name),
+                "source_path": format!("{}.md", name),
+                "parent_names": [],
+            }
+        })
+    }
+
+    fn input(renderer: &str, book: Value) -> String {
+        let context = value!({
+            "root": "/book",
+            "config": {"book": {"title": "Test"}},
+            "renderer": renderer,
+            "mdbook_version": "0.4.21",
+        });
+        serde_json::to_string(&value!([context, book])).unwrap()
+    }
+
+    #[test]
+    fn test_preprocess_book() {
+        let book = value!({
+            "sections": [
+                chapter("intro", "# Hello *World*", value!([
+                    chapter("nested", "Nested", value!([])),
+                ])),
+                "Separator",
+                {"PartTitle": "Reference"},
+            ],
+            "__non_exhaustive": null,
+        });
+        let output = preprocess_book(&input("html", book.clone()), None, false).unwrap();
+
+        let intro = &output["sections"][0]["Chapter"];
+        assert_eq!(intro["content"], "<h1>Hello <em>World</em></h1>\n");
+        let nested = &intro["sub_items"][0]["Chapter"];
+        assert_eq!(nested["content"], "<p>Nested</p>\n");
+
+        // everything but the contents is passed through
+        let mut expected = book;
+        expected["sections"][0]["Chapter"]["content"] = intro["content"].clone();
+        expected["sections"][0]["Chapter"]["sub_items"][0]["Chapter"]["content"] =
+            nested["content"].clone();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_preprocess_book_unsupported_renderer() {
+        let book = value!({"sections": []});
+        let err = preprocess_book(&input("pdf", book), None, false).unwrap_err();
+        assert_eq!(err.to_string(), "renderer 'pdf' is not supported");
+    }
+}
@@ -0,0 +1,387 @@
+use std::str::FromStr;
+
+use struckdown::value::Value;
+
+/// A simple selector matching a node of an event tree.
+///
+/// Nodes are matched by their type (the tag of start tags, the event type
+/// otherwise), `#id`, `.class`, `@directive` and `:contains(text)`.
+#[derive(Debug, Default, Clone)]
+struct Compound {
+    /// The node has to be a child of the node matched by the previous
+    /// compound instead of any descendant.
+    child: bool,
+    kind: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+    directive: Option<String>,
+    contains: Vec<String>,
+}
+
+/// A selector for nodes in the tree of an event stream.
+///
+/// Selectors are written like CSS selectors.  Compound selectors separated
+/// by whitespace match descendants, separated by `>` they match children.
+/// Multiple selectors are separated by commas:
+///
+/// ```text
+/// heading2#install, block_quote paragraph:contains("note"), list > list_item, @admonition
+/// ```
+#[derive(Debug, Clone)]
+pub struct Selector {
+    alternatives: Vec<Vec<Compound>>,
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+/// Reads a name from the start of a string.
+fn read_name<'a>(s: &'a str, what: &str) -> Result<(&'a str, &'a str), String> {
+    let end = s.find(|c| !is_name_char(c)).unwrap_or(s.len());
+    if end == 0 {
+        return Err(format!("expected {} in selector", what));
+    }
+    Ok((&s[..end], &s[end..]))
+}
+
+/// Reads the argument of `:contains(...)` which can be quoted.
+fn read_argument(s: &str) -> Result<(String, &str), String> {
+    let s = s.trim_start();
+    let (arg, rest) = match s.chars().next() {
+        Some(quote @ '"') | Some(quote @ '\'') => {
+            let end = s[1..]
+                .find(quote)
+                .ok_or_else(|| "unterminated string in selector".to_string())?;
+            (s[1..end + 1].to_string(), s[end + 2..].trim_start())
+        }
+        _ => {
+            let end = s.find(')').unwrap_or(s.len());
+            (s[..end].trim_end().to_string(), &s[end..])
+        }
+    };
+    match rest.strip_prefix(')') {
+        Some(rest) => Ok((arg, rest)),
+        None => Err("expected ')' in selector".into()),
+    }
+}
+
+fn parse_compound(mut s: &str) -> Result<(Compound, &str), String> {
+    let mut rv = Compound::default();
+    if let Some(rest) = s.strip_prefix('*') {
+        s = rest;
+    } else if s.starts_with(is_name_char) {
+        let (name, rest) = read_name(s, "a type")?;
+        rv.kind = Some(name.to_string());
+        s = rest;
+    }
+    loop {
+        let mut chars = s.chars();
+        match chars.next() {
+            Some('#') => {
+                let (name, rest) = read_name(chars.as_str(), "an id")?;
+                rv.id = Some(name.to_string());
+                s = rest;
+            }
+            Some('.') => {
+                let (name, rest) = read_name(chars.as_str(), "a class")?;
+                rv.classes.push(name.to_string());
+                s = rest;
+            }
+            Some('@') => {
+                let (name, rest) = read_name(chars.as_str(), "a directive name")?;
+                rv.directive = Some(name.to_string());
+                s = rest;
+            }
+            Some(':') => {
+                let rest = chars
+                    .as_str()
+                    .strip_prefix("contains(")
+                    .ok_or_else(|| "unknown pseudo class in selector".to_string())?;
+                let (text, rest) = read_argument(rest)?;
+                rv.contains.push(text);
+                s = rest;
+            }
+            _ => return Ok((rv, s)),
+        }
+    }
+}
+
+impl FromStr for Selector {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Selector, String> {
+        let mut alternatives = vec![Vec::new()];
+        let mut child = false;
+        let mut s = value.trim_start();
+        while !s.is_empty() {
+            if let Some(rest) = s.strip_prefix(',') {
+                if alternatives.last().unwrap().is_empty() || child {
+                    return Err("empty selector".into());
+                }
+                alternatives.push(Vec::new());
+                s = rest.trim_start();
+                continue;
+            }
+            if let Some(rest) = s.strip_prefix('>') {
+                if alternatives.last().unwrap().is_empty() || child {
+                    return Err("unexpected '>' in selector".into());
+                }
+                child = true;
+                s = rest.trim_start();
+                continue;
+            }
+            let (mut compound, rest) = parse_compound(s)?;
+            if rest.len() == s.len() {
+                return Err(format!(
+                    "unexpected '{}' in selector",
+                    s.chars().next().unwrap()
+                ));
+            }
+            compound.child = child;
+            child = false;
+            alternatives.last_mut().unwrap().push(compound);
+            s = rest.trim_start();
+        }
+        if alternatives.last().unwrap().is_empty() || child {
+            return Err("empty selector".into());
+        }
+        Ok(Selector { alternatives })
+    }
+}
+
+/// Returns the text of a node and its children.
+fn node_text(node: &Value, out: &mut String) {
+    for key in &["text", "code"] {
+        if let Some(text) = node.get(key).and_then(|x| x.as_str()) {
+            out.push_str(text);
+        }
+    }
+    if let Some(children) = node.get("children").and_then(|x| x.as_array()) {
+        for child in children {
+            node_text(child, out);
+        }
+    }
+}
+
+fn str_field<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value.get(key).and_then(|x| x.as_str())
+}
+
+impl Compound {
+    fn matches(&self, node: &Value) -> bool {
+        if let Some(ref kind) = self.kind {
+            let node_kind = str_field(node, "tag").or_else(|| str_field(node, "type"));
+            if node_kind != Some(kind.as_str()) {
+                return false;
+            }
+        }
+        let attrs = node.get("attrs").unwrap_or(&Value::Null);
+        if let Some(ref id) = self.id {
+            if str_field(attrs, "id") != Some(id.as_str()) {
+                return false;
+            }
+        }
+        if !self.classes.is_empty() {
+            let classes: Vec<_> = str_field(attrs, "class")
+                .unwrap_or("")
+                .split_whitespace()
+                .collect();
+            if !self.classes.iter().all(|x| classes.contains(&x.as_str())) {
+                return false;
+            }
+        }
+        if let Some(ref directive) = self.directive {
+            if str_field(node, "type") != Some("directive")
+                || str_field(node, "name") != Some(directive.as_str())
+            {
+                return false;
+            }
+        }
+        if !self.contains.is_empty() {
+            let mut text = String::new();
+            node_text(node, &mut text);
+            if !self.contains.iter().all(|x| text.contains(x.as_str())) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Matches compounds against the ancestors of a node (outermost first).
+///
+/// If `child` is set the last compound has to match the parent of the node.
+fn matches_ancestors(compounds: &[Compound], ancestors: &[&Value], child: bool) -> bool {
+    let (last, rest) = match compounds.split_last() {
+        Some(x) => x,
+        None => return true,
+    };
+    let start = if child {
+        ancestors.len().saturating_sub(1)
+    } else {
+        0
+    };
+    // try the nearest ancestors first
+    (start..ancestors.len()).rev().any(|idx| {
+        last.matches(ancestors[idx]) && matches_ancestors(rest, &ancestors[..idx], last.child)
+    })
+}
+
+impl Selector {
+    /// Checks if a node with the given ancestors (outermost first) matches.
+    fn matches(&self, node: &Value, ancestors: &[&Value]) -> bool {
+        self.alternatives.iter().any(|compounds| {
+            let (last, rest) = compounds.split_last().unwrap();
+            last.matches(node) && matches_ancestors(rest, ancestors, last.child)
+        })
+    }
+
+    fn collect<'a>(
+        &self,
+        nodes: &'a [Value],
+        ancestors: &mut Vec<&'a Value>,
+        out: &mut Vec<&'a Value>,
+    ) {
+        for node in nodes {
+            if self.matches(node, ancestors) {
+                out.push(node);
+            }
+            if let Some(children) = node.get("children").and_then(|x| x.as_array()) {
+                ancestors.push(node);
+                self.collect(children, ancestors, out);
+                ancestors.pop();
+            }
+        }
+    }
+
+    /// Returns all nodes of a tree matching the selector in document order.
+    ///
+    /// The tree is the one produced by the `json-tree` renderer.  Nested
+    /// matches are returned as well as the nodes containing them.
+    pub fn query<'a>(&self, tree: &'a [Value]) -> Vec<&'a Value> {
+        let mut rv = Vec::new();
+        self.collect(tree, &mut Vec::new(), &mut rv);
+        rv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use struckdown::pipeline::Pipeline;
+    use struckdown::value::value;
+
+    use super::*;
+
+    fn tree(source: &str) -> Vec<Value> {
+        match crate::to_json_tree(Pipeline::new().process(source)).unwrap() {
+            Value::Array(nodes) => nodes,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the text of the matching nodes.
+    fn query(selector: &str, tree: &[Value]) -> Vec<String> {
+        let selector: Selector = selector.parse().unwrap();
+        selector
+            .query(tree)
+            .into_iter()
+            .map(|node| {
+                let mut text = String::new();
+                node_text(node, &mut text);
+                text
+            })
+            .collect()
+    }
+
+    const DOCUMENT: &str = "\
+# Install {#install}
+
+> Quoted *note*
+>
+> - nested *item*
+
+- top *item*
+
+```{note} Title
+Body
+```
+";
+
+    #[test]
+    fn test_parse() {
+        let selector: Selector = "heading2#install.a.b, list > item:contains(\"x y\")"
+            .parse()
+            .unwrap();
+        assert_eq!(selector.alternatives.len(), 2);
+        let compound = &selector.alternatives[0][0];
+        assert_eq!(compound.kind.as_deref(), Some("heading2"));
+        assert_eq!(compound.id.as_deref(), Some("install"));
+        assert_eq!(compound.classes, vec!["a", "b"]);
+        let compounds = &selector.alternatives[1];
+        assert_eq!(compounds.len(), 2);
+        assert!(!compounds[0].child);
+        assert!(compounds[1].child);
+        assert_eq!(compounds[1].contains, vec!["x y"]);
+
+        let selector: Selector = "* @note :contains( plain )".parse().unwrap();
+        let compounds = &selector.alternatives[0];
+        assert_eq!(compounds[0].kind, None);
+        assert_eq!(compounds[1].directive.as_deref(), Some("note"));
+        assert_eq!(compounds[2].contains, vec!["plain"]);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let err = |selector: &str| selector.parse::<Selector>().unwrap_err();
+        assert_eq!(err(""), "empty selector");
+        assert_eq!(err("paragraph,"), "empty selector");
+        assert_eq!(err(", paragraph"), "empty selector");
+        assert_eq!(err("list >"), "empty selector");
+        assert_eq!(err("> item"), "unexpected '>' in selector");
+        assert_eq!(err("list > > item"), "unexpected '>' in selector");
+        assert_eq!(err("#"), "expected an id in selector");
+        assert_eq!(err("paragraph."), "expected a class in selector");
+        assert_eq!(err(":hover"), "unknown pseudo class in selector");
+        assert_eq!(err(":contains(\"x)"), "unterminated string in selector");
+        assert_eq!(err(":contains(x"), "expected ')' in selector");
+        assert_eq!(err("paragraph ["), "unexpected '[' in selector");
+    }
+
+    #[test]
+    fn test_query() {
+        let tree = tree(DOCUMENT);
+        assert_eq!(query("heading1", &tree), vec!["Install"]);
+        assert_eq!(query("#install", &tree), vec!["Install"]);
+        assert_eq!(query("heading1#other", &tree), Vec::<String>::new());
+        assert_eq!(query("emphasis", &tree), vec!["note", "item", "item"]);
+        assert_eq!(query("block_quote emphasis", &tree), vec!["note", "item"]);
+        assert_eq!(
+            query("block_quote > paragraph > emphasis", &tree),
+            vec!["note"]
+        );
+        assert_eq!(query("block_quote > emphasis", &tree), Vec::<String>::new());
+        assert_eq!(
+            query("list_item:contains(item)", &tree),
+            vec!["nested item", "top item"]
+        );
+        assert_eq!(query("@note", &tree), vec![""]);
+        assert_eq!(
+            query("heading1, block_quote paragraph:contains(Quoted)", &tree),
+            vec!["Install", "Quoted note"]
+        );
+    }
+
+    #[test]
+    fn test_query_classes() {
+        let tree = vec![value!({
+            "type": "start_tag",
+            "tag": "div",
+            "attrs": {"class": "note warning"},
+            "children": [{"type": "text", "text": "Careful"}],
+        })];
+        assert_eq!(query(".note", &tree), vec!["Careful"]);
+        assert_eq!(query("div.warning.note", &tree), vec!["Careful"]);
+        assert_eq!(query(".note.tip", &tree), Vec::<String>::new());
+    }
+}
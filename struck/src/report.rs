@@ -0,0 +1,205 @@
+use std::env;
+use std::fmt;
+use std::io::{self, IsTerminal};
+
+use anyhow::Error;
+use struckdown::event::{Location, Severity};
+
+/// An error pointing at a location in a file.
+#[derive(Debug)]
+pub struct SourceError {
+    pub path: String,
+    pub source: String,
+    pub location: Location,
+    pub message: String,
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.path,
+            self.location.line,
+            self.location.column + 1,
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for SourceError {}
+
+/// Indicates that documents failed and were already reported.
+#[derive(Debug)]
+pub struct DocumentsFailed(pub usize);
+
+impl fmt::Display for DocumentsFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} documents failed", self.0)
+    }
+}
+
+impl std::error::Error for DocumentsFailed {}
+
+/// Indicates that the compared documents differ.
+///
+/// The differences were already printed.
+#[derive(Debug)]
+pub struct DocumentsDiffer(pub usize);
+
+impl fmt::Display for DocumentsDiffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "documents differ in {} places", self.0)
+    }
+}
+
+impl std::error::Error for DocumentsDiffer {}
+
+/// Returns the location of a 1-based line and column in a source.
+pub fn location_at(source: &str, line: usize, column: usize) -> Location {
+    let offset = source
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(|x| x.len())
+        .sum::<usize>();
+    Location {
+        offset: offset + column.saturating_sub(1),
+        len: 1,
+        line,
+        column: column.saturating_sub(1),
+    }
+}
+
+fn use_color() -> bool {
+    env::var_os("NO_COLOR").is_none() && io::stderr().is_terminal()
+}
+
+fn paint(color: &str, text: &str) -> String {
+    if use_color() {
+        format!("\x1b[{}m{}\x1b[0m", color, text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Prints a diagnostic with a snippet of the source it points to.
+pub fn print_diagnostic(
+    severity: Severity,
+    title: &str,
+    description: Option<&str>,
+    path: &str,
+    source: Option<&str>,
+    location: Option<Location>,
+) {
+    let (label, color) = match severity {
+        Severity::Error => ("error", "1;31"),
+        Severity::Warning => ("warning", "1;33"),
+    };
+    eprintln!(
+        "{}{} {}",
+        paint(color, label),
+        paint("1", ":"),
+        paint("1", title)
+    );
+
+    let location = match location {
+        Some(location) => location,
+        None => {
+            eprintln!("  {} {}", paint("1;34", "-->"), path);
+            if let Some(description) = description {
+                eprintln!("  {} {}", paint("1;34", "="), description);
+            }
+            eprintln!();
+            return;
+        }
+    };
+    eprintln!(
+        "  {} {}:{}:{}",
+        paint("1;34", "-->"),
+        path,
+        location.line,
+        location.column + 1
+    );
+
+    let line = source.and_then(|x| x.lines().nth(location.line.saturating_sub(1)));
+    if let Some(line) = line {
+        let number = location.line.to_string();
+        let gutter = " ".repeat(number.len());
+        // locations are in bytes, the pointer is placed by characters
+        let start = line
+            .get(..location.column.min(line.len()))
+            .map_or(0, |x| x.chars().count());
+        let width = line
+            .get(location.column.min(line.len())..)
+            .map_or(0, |rest| {
+                let mut end = location.len.min(rest.len());
+                while !rest.is_char_boundary(end) {
+                    end -= 1;
+                }
+                rest[..end].chars().count()
+            })
+            .max(1);
+        eprintln!(" {} {}", gutter, paint("1;34", "|"));
+        eprintln!(
+            " {} {} {}",
+            paint("1;34", &number),
+            paint("1;34", "|"),
+            line
+        );
+        eprintln!(
+            " {} {} {}{}",
+            gutter,
+            paint("1;34", "|"),
+            " ".repeat(start),
+            paint(color, &"^".repeat(width))
+        );
+        if let Some(description) = description {
+            eprintln!(" {} {} {}", gutter, paint("1;34", "="), description);
+        }
+    } else if let Some(description) = description {
+        eprintln!("  {} {}", paint("1;34", "="), description);
+    }
+    eprintln!();
+}
+
+/// Prints an error and returns the exit code for it.
+///
+/// Documents that failed exit with `2`, all other errors with `1`.  Differing
+/// documents exit with `1` without printing anything as the differences were
+/// already printed.
+pub fn report_error(err: &Error) -> i32 {
+    if let Some(failed) = err.downcast_ref::<DocumentsFailed>() {
+        eprintln!("{}{} {}", paint("1;31", "error"), paint("1", ":"), failed);
+        return 2;
+    }
+    if err.downcast_ref::<DocumentsDiffer>().is_some() {
+        return 1;
+    }
+    if let Some(err) = err.downcast_ref::<io::Error>() {
+        // the reader went away (eg: piped into head)
+        if err.kind() == io::ErrorKind::BrokenPipe {
+            return 1;
+        }
+    }
+
+    match err.downcast_ref::<SourceError>() {
+        Some(source_err) => print_diagnostic(
+            Severity::Error,
+            &source_err.message,
+            None,
+            &source_err.path,
+            Some(&source_err.source),
+            Some(source_err.location),
+        ),
+        None => eprintln!(
+            "{}{} {}",
+            paint("1;31", "error"),
+            paint("1", ":"),
+            paint("1", &err.to_string())
+        ),
+    }
+    for cause in err.chain().skip(1) {
+        eprintln!("  {} {}", paint("1;34", "caused by:"), cause);
+    }
+    1
+}
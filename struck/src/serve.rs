@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Error};
+use tiny_http::{Header, Request, Response, Server};
+
+use crate::html_path;
+use crate::watch::{Change, Watcher};
+
+const RELOAD_PATH: &str = "/__livereload";
+
+/// Appended to every page to reload it when the sources change.
+const RELOAD_SCRIPT: &str = r#"<script>
+(function() {
+  var proto = location.protocol === "https:" ? "wss://" : "ws://";
+  var ws = new WebSocket(proto + location.host + "/__livereload");
+  ws.onmessage = function() { location.reload(); };
+})();
+</script>"#;
+
+/// The rendered pages of the project.
+#[derive(Default)]
+struct Site {
+    pages: HashMap<String, String>,
+    generation: u64,
+}
+
+type SharedSite = Arc<(Mutex<Site>, Condvar)>;
+
+fn layout(title: &str, body: &str) -> String {
+    format!(
+        "<!doctype html>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>body {{ max-width: 50em; margin: 2em auto; padding: 0 1em; \
+         font-family: sans-serif; line-height: 1.5; }} pre {{ overflow: auto; }}</style>\n\
+         <body>\n{}\n{}\n</body>\n",
+        v_htmlescape::escape(title),
+        body,
+        RELOAD_SCRIPT
+    )
+}
+
+fn index_page(site: &Site) -> String {
+    let mut pages = site.pages.keys().collect::<Vec<_>>();
+    pages.sort();
+    let mut body = String::from("<h1>Documents</h1>\n<ul>\n");
+    for page in pages {
+        body.push_str(&format!(
+            "<li><a href=\"/{0}\">{0}</a></li>\n",
+            v_htmlescape::escape(page)
+        ));
+    }
+    body.push_str("</ul>");
+    layout("Documents", &body)
+}
+
+fn html_response(status: u16, html: String) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(html)
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", "text/html; charset=utf-8").unwrap())
+}
+
+/// Accepts a websocket connection and sends a message once the site changes.
+fn handle_reload(site: SharedSite, request: Request) {
+    let key = match request
+        .headers()
+        .iter()
+        .find(|x| x.field.equiv("Sec-WebSocket-Key"))
+    {
+        Some(header) => header.value.as_str().to_string(),
+        None => {
+            request.respond(Response::empty(400)).ok();
+            return;
+        }
+    };
+    let mut hash = sha1_smol::Sha1::new();
+    hash.update(key.as_bytes());
+    hash.update(b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11");
+    let accept = base64::encode(hash.digest().bytes());
+    let response = Response::empty(101)
+        .with_header(Header::from_bytes("Sec-WebSocket-Accept", accept).unwrap());
+    let mut stream = request.upgrade("websocket", response);
+
+    let (ref lock, ref cvar) = *site;
+    let mut site = lock.lock().unwrap();
+    let generation = site.generation;
+    while site.generation == generation {
+        site = cvar.wait(site).unwrap();
+    }
+    drop(site);
+
+    // a single unmasked text frame
+    let message = b"reload";
+    let mut frame = vec![0x81, message.len() as u8];
+    frame.extend_from_slice(message);
+    stream.write_all(&frame).ok();
+    stream.flush().ok();
+}
+
+fn handle_request(site: SharedSite, request: Request) {
+    let url = request.url().split('?').next().unwrap_or("").to_string();
+    if url == RELOAD_PATH {
+        return handle_reload(site, request);
+    }
+    let mut path = url.trim_start_matches('/').to_string();
+    if path.is_empty() || path.ends_with('/') {
+        path.push_str("index.html");
+    }
+
+    let response = {
+        let site = site.0.lock().unwrap();
+        match site.pages.get(&path) {
+            Some(html) => html_response(200, layout(&path, html)),
+            None if path == "index.html" => html_response(200, index_page(&site)),
+            None => html_response(404, index_page(&site)),
+        }
+    };
+    request.respond(response).ok();
+}
+
+/// Builds the project to memory and serves it until interrupted.
+pub fn serve(mut watcher: Watcher, addr: &str, interval: Duration) -> Result<(), Error> {
+    let site = SharedSite::default();
+    // build once before serving so that the first request sees all pages
+    apply_changes(&site, watcher.poll()?);
+
+    let server = Server::http(addr).map_err(|err| anyhow!("cannot listen on {}: {}", addr, err))?;
+    eprintln!("serving on http://{}/", server.server_addr());
+
+    // the pipeline is not thread safe so the watcher stays on this thread
+    let server_site = site.clone();
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let site = server_site.clone();
+            thread::spawn(move || handle_request(site, request));
+        }
+    });
+
+    loop {
+        thread::sleep(interval);
+        match watcher.poll() {
+            Ok(changes) => apply_changes(&site, changes),
+            Err(err) => eprintln!("error: {}", err),
+        }
+    }
+}
+
+fn apply_changes(site: &SharedSite, changes: Vec<Change>) {
+    if changes.is_empty() {
+        return;
+    }
+    let (ref lock, ref cvar) = **site;
+    let mut site = lock.lock().unwrap();
+    for change in changes {
+        match change {
+            Change::Updated { path, html } => {
+                site.pages.insert(html_path(&path), html);
+            }
+            Change::Removed { path } => {
+                site.pages.remove(&html_path(&path));
+            }
+        }
+    }
+    site.generation += 1;
+    cvar.notify_all();
+}
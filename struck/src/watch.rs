@@ -0,0 +1,141 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::Error;
+use struckdown::html::to_html;
+use struckdown::pipeline::Pipeline;
+use struckdown::project::{BuildCache, Project};
+
+use crate::{find_documents, html_project, load_pipeline, report_errors};
+
+/// A change to the output of a watched project.
+pub enum Change {
+    /// The HTML of a document changed.
+    Updated { path: String, html: String },
+    /// A document was removed.
+    Removed { path: String },
+}
+
+/// Keeps a project in sync with the documents matching a pattern.
+///
+/// Only documents that changed since the last poll are processed again
+/// (see [`Project::build_incremental`]) and only documents with a changed
+/// output are reported.
+pub struct Watcher {
+    pattern: String,
+    config: Option<PathBuf>,
+    config_modified: Option<Option<SystemTime>>,
+    project: Option<Project>,
+    cache: BuildCache,
+    sources: BTreeMap<String, (Option<SystemTime>, String)>,
+    outputs: HashMap<String, String>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|x| x.modified()).ok()
+}
+
+impl Watcher {
+    /// Creates a watcher for a glob pattern and an optional config file.
+    pub fn new(pattern: &str, config: Option<&Path>) -> Watcher {
+        Watcher {
+            pattern: pattern.to_string(),
+            config: config.map(|x| x.to_path_buf()),
+            config_modified: None,
+            project: None,
+            cache: BuildCache::new(),
+            sources: BTreeMap::new(),
+            outputs: HashMap::new(),
+        }
+    }
+
+    /// Reloads the pipeline if the config changed.
+    ///
+    /// A broken config is reported and the previous pipeline is kept.
+    fn reload_pipeline(&mut self) -> bool {
+        let pipeline = match self.config {
+            Some(ref config) if self.config_modified != Some(modified(config)) => {
+                self.config_modified = Some(modified(config));
+                match load_pipeline(config) {
+                    Ok(pipeline) => pipeline,
+                    Err(err) => {
+                        eprintln!("{}: error: {}", config.display(), err);
+                        return false;
+                    }
+                }
+            }
+            None if self.project.is_none() => Pipeline::new(),
+            _ => return false,
+        };
+        let mut project = html_project(pipeline);
+        for (path, (_, source)) in &self.sources {
+            project.add_document(path, source);
+        }
+        self.project = Some(project);
+        true
+    }
+
+    /// Checks for changes and rebuilds the project if necessary.
+    pub fn poll(&mut self) -> Result<Vec<Change>, Error> {
+        let mut changed = self.reload_pipeline();
+        let mut changes = Vec::new();
+
+        let (base, documents) = find_documents(&self.pattern)?;
+        let removed = self
+            .sources
+            .keys()
+            .filter(|x| !documents.contains(x))
+            .cloned()
+            .collect::<Vec<_>>();
+        for path in removed {
+            self.sources.remove(&path);
+            self.outputs.remove(&path);
+            if let Some(ref mut project) = self.project {
+                project.remove_document(&path);
+            }
+            eprintln!("{}: removed", path);
+            changes.push(Change::Removed { path });
+            changed = true;
+        }
+        for path in documents {
+            let mtime = modified(&base.join(&path));
+            if self.sources.get(&path).map(|x| x.0) == Some(mtime) {
+                continue;
+            }
+            let source = match fs::read_to_string(base.join(&path)) {
+                Ok(source) => source,
+                Err(err) => {
+                    eprintln!("{}: error: {}", path, err);
+                    continue;
+                }
+            };
+            if let Some(ref mut project) = self.project {
+                project.add_document(&path, &source);
+            }
+            self.sources.insert(path, (mtime, source));
+            changed = true;
+        }
+
+        let project = match (changed, &self.project) {
+            (true, Some(project)) => project,
+            _ => return Ok(changes),
+        };
+        let renderer_options = project.pipeline().renderer_options();
+        for document in project.build_incremental(&mut self.cache).documents {
+            let html = to_html(document.events.iter().cloned(), renderer_options);
+            if self.outputs.get(&document.path) == Some(&html) {
+                continue;
+            }
+            let source = self.sources.get(&document.path).map(|x| x.1.as_str());
+            report_errors(&document.path, source, &document.events);
+            self.outputs.insert(document.path.clone(), html.clone());
+            changes.push(Change::Updated {
+                path: document.path,
+                html,
+            });
+        }
+        Ok(changes)
+    }
+}
@@ -0,0 +1,41 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("struck-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir_all(path.join("docs/guide")).unwrap();
+    path
+}
+
+#[test]
+fn test_build_links_to_html() {
+    let root = temp_dir("build");
+    fs::write(
+        root.join("docs/index.md"),
+        "See {ref}`setup` or [the guide](guide/setup.md)\n",
+    )
+    .unwrap();
+    fs::write(
+        root.join("docs/guide/setup.md"),
+        "# Setup {#setup}\n\nBack to [index](../index.md)\n",
+    )
+    .unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_struck"))
+        .arg("build")
+        .arg(root.join("docs/**/*.md"))
+        .arg(root.join("out"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let index = fs::read_to_string(root.join("out/index.html")).unwrap();
+    assert!(index.contains("<a href=\"guide&#x2f;setup.html#setup\">Setup</a>"));
+    assert!(index.contains("<a href=\"guide&#x2f;setup.html\">the guide</a>"));
+    let setup = fs::read_to_string(root.join("out/guide/setup.html")).unwrap();
+    assert!(setup.contains("<a href=\"..&#x2f;index.html\">index</a>"));
+
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,39 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+fn temp_file(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("struck-diff-{}-{}", std::process::id(), name));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn test_diff_exit_code() {
+    let old = temp_file("old.md", "Hello\nworld\n");
+    let reflowed = temp_file("reflowed.md", "Hello world\n");
+    let changed = temp_file("changed.md", "Hello *world*\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_struck"))
+        .arg("diff")
+        .arg(&old)
+        .arg(&reflowed)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_struck"))
+        .arg("diff")
+        .arg(&old)
+        .arg(&changed)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("--- "));
+    assert!(output.stderr.is_empty());
+
+    for path in [old, reflowed, changed] {
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,222 @@
+//! Python bindings for struckdown.
+//!
+//! The bindings are only compiled with the `python` feature and built into
+//! a Python extension module named `struckdown` with maturin:
+//!
+//! ```python
+//! import struckdown
+//!
+//! pipeline = struckdown.Pipeline({"processors": [{"processor": "auto_anchors"}]})
+//! html = pipeline.process_to_html("# Hello")
+//! events = struckdown.parse("Hello *World*", {"enable_roles": False})
+//! html = struckdown.to_html(events)
+//! ```
+//!
+//! Events, parser options, pipeline configs and renderer options are passed
+//! as dictionaries and lists in the same format as their JSON
+//! serialization.
+#![cfg(feature = "python")]
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyTypeError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Number, Value};
+
+use struckdown::event::AnnotatedEvent;
+use struckdown::html::HtmlRendererOptions;
+use struckdown::parser::ParserOptions;
+use struckdown::pipeline::PipelineConfig;
+
+create_exception!(struckdown, StruckdownError, PyException);
+
+fn to_py_err<E: std::fmt::Display>(err: E) -> PyErr {
+    StruckdownError::new_err(err.to_string())
+}
+
+/// Converts a Python object into a JSON value.
+fn to_value(obj: &Bound<'_, PyAny>) -> PyResult<Value> {
+    if obj.is_none() {
+        Ok(Value::Null)
+    } else if let Ok(value) = obj.downcast::<PyBool>() {
+        Ok(Value::Bool(value.is_true()))
+    } else if obj.is_instance_of::<PyInt>() {
+        match obj.extract::<i64>() {
+            Ok(value) => Ok(value.into()),
+            Err(_) => Ok(obj.extract::<u64>()?.into()),
+        }
+    } else if obj.is_instance_of::<PyFloat>() {
+        Number::from_f64(obj.extract()?)
+            .map(Value::Number)
+            .ok_or_else(|| PyTypeError::new_err("cannot convert nan or infinity"))
+    } else if let Ok(value) = obj.downcast::<PyString>() {
+        Ok(Value::String(value.to_str()?.to_string()))
+    } else if let Ok(dict) = obj.downcast::<PyDict>() {
+        let mut map = Map::new();
+        for (key, value) in dict.iter() {
+            let key = key
+                .downcast::<PyString>()
+                .map_err(|_| PyTypeError::new_err("dictionary keys must be strings"))?;
+            map.insert(key.to_str()?.to_string(), to_value(&value)?);
+        }
+        Ok(Value::Object(map))
+    } else if let Ok(list) = obj.downcast::<PyList>() {
+        list.iter().map(|x| to_value(&x)).collect()
+    } else if let Ok(tuple) = obj.downcast::<PyTuple>() {
+        tuple.iter().map(|x| to_value(&x)).collect()
+    } else {
+        Err(PyTypeError::new_err(format!(
+            "cannot convert {} to struckdown",
+            obj.get_type().name()?
+        )))
+    }
+}
+
+/// Converts a JSON value into a Python object.
+fn from_value<'py>(py: Python<'py>, value: &Value) -> PyResult<Bound<'py, PyAny>> {
+    Ok(match value {
+        Value::Null => py.None().into_bound(py),
+        Value::Bool(value) => PyBool::new(py, *value).to_owned().into_any(),
+        Value::Number(value) => match (value.as_i64(), value.as_u64()) {
+            (Some(value), _) => value.into_pyobject(py)?.into_any(),
+            (None, Some(value)) => value.into_pyobject(py)?.into_any(),
+            _ => value
+                .as_f64()
+                .unwrap_or_default()
+                .into_pyobject(py)?
+                .into_any(),
+        },
+        Value::String(value) => PyString::new(py, value).into_any(),
+        Value::Array(values) => {
+            let list = PyList::empty(py);
+            for value in values {
+                list.append(from_value(py, value)?)?;
+            }
+            list.into_any()
+        }
+        Value::Object(map) => {
+            let dict = PyDict::new(py);
+            for (key, value) in map {
+                dict.set_item(key, from_value(py, value)?)?;
+            }
+            dict.into_any()
+        }
+    })
+}
+
+/// Deserializes an optional Python object falling back to the default.
+fn extract<T: DeserializeOwned + Default>(obj: Option<&Bound<'_, PyAny>>) -> PyResult<T> {
+    match obj {
+        Some(obj) if !obj.is_none() => serde_json::from_value(to_value(obj)?).map_err(to_py_err),
+        _ => Ok(T::default()),
+    }
+}
+
+/// Deserializes a list of events.
+fn extract_events(obj: &Bound<'_, PyAny>) -> PyResult<Vec<AnnotatedEvent<'static>>> {
+    serde_json::from_value(to_value(obj)?).map_err(to_py_err)
+}
+
+/// Serializes a value into Python objects.
+fn to_python<'py, T: Serialize>(py: Python<'py>, value: &T) -> PyResult<Bound<'py, PyAny>> {
+    from_value(py, &serde_json::to_value(value).map_err(to_py_err)?)
+}
+
+/// A pipeline of processors created from a config.
+#[pyclass(unsendable, module = "struckdown")]
+struct Pipeline {
+    pipeline: struckdown::pipeline::Pipeline,
+}
+
+#[pymethods]
+impl Pipeline {
+    #[new]
+    #[pyo3(signature = (config=None))]
+    fn new(config: Option<&Bound<'_, PyAny>>) -> PyResult<Pipeline> {
+        let config: PipelineConfig = extract(config)?;
+        Ok(Pipeline {
+            pipeline: struckdown::pipeline::Pipeline::try_from_config(&config)
+                .map_err(to_py_err)?,
+        })
+    }
+
+    /// Parses and processes a document into a list of events.
+    fn process<'py>(&self, py: Python<'py>, source: &str) -> PyResult<Bound<'py, PyAny>> {
+        let events: Vec<_> = self
+            .pipeline
+            .try_process(source)
+            .map_err(to_py_err)?
+            .collect();
+        to_python(py, &events)
+    }
+
+    /// Processes a list of events.
+    fn apply<'py>(
+        &self,
+        py: Python<'py>,
+        events: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let events: Vec<_> = self
+            .pipeline
+            .apply_ref(extract_events(events)?.into_iter())
+            .collect();
+        to_python(py, &events)
+    }
+
+    /// Parses, processes and renders a document to HTML.
+    fn process_to_html(&self, source: &str) -> PyResult<String> {
+        let html = self
+            .pipeline
+            .process_to_writer(source, Vec::new(), self.pipeline.renderer_options())
+            .map_err(to_py_err)?;
+        Ok(String::from_utf8_lossy(&html).into_owned())
+    }
+
+    /// Returns the config of the pipeline.
+    fn config<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        to_python(py, &self.pipeline.to_config().map_err(to_py_err)?)
+    }
+}
+
+/// Parses a document into a list of events.
+#[pyfunction]
+#[pyo3(signature = (source, options=None))]
+fn parse<'py>(
+    py: Python<'py>,
+    source: &str,
+    options: Option<&Bound<'py, PyAny>>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let options: ParserOptions = extract(options)?;
+    let events: Vec<_> = struckdown::parser::parse(source, &options).collect();
+    to_python(py, &events)
+}
+
+/// Returns the default parser options.
+#[pyfunction]
+fn default_parser_options(py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+    to_python(py, &ParserOptions::default())
+}
+
+/// Renders a list of events to HTML.
+#[pyfunction]
+#[pyo3(signature = (events, options=None))]
+fn to_html(events: &Bound<'_, PyAny>, options: Option<&Bound<'_, PyAny>>) -> PyResult<String> {
+    let options: HtmlRendererOptions = extract(options)?;
+    Ok(struckdown::html::to_html(
+        extract_events(events)?.into_iter(),
+        &options,
+    ))
+}
+
+#[pymodule]
+#[pyo3(name = "struckdown")]
+fn init(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("StruckdownError", m.py().get_type::<StruckdownError>())?;
+    m.add_class::<Pipeline>()?;
+    m.add_function(wrap_pyfunction!(parse, m)?)?;
+    m.add_function(wrap_pyfunction!(default_parser_options, m)?)?;
+    m.add_function(wrap_pyfunction!(to_html, m)?)?;
+    Ok(())
+}
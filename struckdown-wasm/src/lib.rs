@@ -0,0 +1,79 @@
+//! JavaScript bindings for struckdown.
+//!
+//! This crate is compiled to WebAssembly with `wasm-pack` and exposes the
+//! parser, the pipeline and the HTML renderer to JavaScript for previews
+//! in the browser:
+//!
+//! ```js
+//! import { parse, process, toHtml } from "struckdown-wasm";
+//!
+//! const events = process("# Hello", { processors: [{ processor: "auto_anchors" }] });
+//! document.body.innerHTML = toHtml(events);
+//! ```
+//!
+//! Events, parser options, pipeline configs and renderer options are passed
+//! as plain JavaScript values in the same format as their JSON
+//! serialization.  Only processors that do not need a file system, threads
+//! or subprocesses are available.
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_wasm_bindgen::Serializer;
+use wasm_bindgen::prelude::*;
+
+use struckdown::event::AnnotatedEvent;
+use struckdown::html::HtmlRendererOptions;
+use struckdown::parser::ParserOptions;
+use struckdown::pipeline::{Pipeline, PipelineConfig};
+
+/// Converts an optional JavaScript value falling back to the default.
+fn from_js<T: DeserializeOwned + Default>(value: JsValue) -> Result<T, JsError> {
+    if value.is_undefined() || value.is_null() {
+        Ok(T::default())
+    } else {
+        serde_wasm_bindgen::from_value(value).map_err(|err| JsError::new(&err.to_string()))
+    }
+}
+
+/// Converts a value to plain JavaScript objects (not `Map`s).
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsError> {
+    value
+        .serialize(&Serializer::json_compatible())
+        .map_err(|err| JsError::new(&err.to_string()))
+}
+
+/// Parses a document into an array of events.
+#[wasm_bindgen]
+pub fn parse(source: &str, options: JsValue) -> Result<JsValue, JsError> {
+    let options: ParserOptions = from_js(options)?;
+    let events: Vec<_> = struckdown::parser::parse(source, &options).collect();
+    to_js(&events)
+}
+
+/// Parses and processes a document with a pipeline config.
+///
+/// Returns the processed events.  Use [`render`] to render the document
+/// with the renderer options of the config.
+#[wasm_bindgen]
+pub fn process(source: &str, config: JsValue) -> Result<JsValue, JsError> {
+    let config: PipelineConfig = from_js(config)?;
+    let pipeline = Pipeline::try_from_config(&config)?;
+    let events: Vec<_> = pipeline.try_process(source)?.collect();
+    to_js(&events)
+}
+
+/// Parses, processes and renders a document to HTML.
+#[wasm_bindgen]
+pub fn render(source: &str, config: JsValue) -> Result<String, JsError> {
+    let config: PipelineConfig = from_js(config)?;
+    let pipeline = Pipeline::try_from_config(&config)?;
+    let html = pipeline.process_to_writer(source, Vec::new(), &config.renderer)?;
+    Ok(String::from_utf8_lossy(&html).into_owned())
+}
+
+/// Renders an array of events to HTML.
+#[wasm_bindgen(js_name = toHtml)]
+pub fn to_html(events: JsValue, options: JsValue) -> Result<String, JsError> {
+    let events: Vec<AnnotatedEvent<'static>> = from_js(events)?;
+    let options: HtmlRendererOptions = from_js(options)?;
+    Ok(struckdown::html::to_html(events.into_iter(), &options))
+}
@@ -0,0 +1,401 @@
+//! Support for property based testing with [`quickcheck`].
+//!
+//! This module implements [`Arbitrary`] for events and their parts so that
+//! processors can be tested against random input.  Single events are
+//! generated without any regard for structure which is rarely what a
+//! processor expects.  [`BalancedStream`] instead generates whole documents
+//! where every start tag is matched by an end tag:
+//!
+//! ```
+//! use quickcheck::quickcheck;
+//! use struckdown::arbitrary::{is_balanced, BalancedStream};
+//! use struckdown::processors::{Footnotes, Processor};
+//!
+//! fn keeps_balance(stream: BalancedStream) -> bool {
+//!     let processor = Box::new(Footnotes::default());
+//!     is_balanced(processor.apply(Box::new(stream.0.into_iter())))
+//! }
+//!
+//! quickcheck(keeps_balance as fn(BalancedStream) -> bool);
+//! ```
+//!
+//! This module requires the `arbitrary` feature.
+use quickcheck::{empty_shrinker, Arbitrary, Gen};
+
+use crate::event::{
+    Alignment, AnnotatedEvent, Attrs, CheckboxEvent, CodeBlockEvent, CustomAttrs, DirectiveEvent,
+    DocumentStartEvent, EndTagEvent, ErrorEvent, Event, FootnoteReferenceEvent, ImageEvent,
+    InlineCodeEvent, InterpretedTextEvent, Location, MetaDataEvent, RawHtmlEvent, Severity,
+    StartTagEvent, Str, Tag, TextEvent,
+};
+use crate::value::Value;
+
+const TAGS: &[Tag] = &[
+    Tag::Paragraph,
+    Tag::Heading1,
+    Tag::Heading2,
+    Tag::Heading3,
+    Tag::Heading4,
+    Tag::Heading5,
+    Tag::Heading6,
+    Tag::BlockQuote,
+    Tag::OrderedList,
+    Tag::UnorderedList,
+    Tag::ListItem,
+    Tag::FootnoteDefinition,
+    Tag::Table,
+    Tag::TableHeader,
+    Tag::TableBody,
+    Tag::TableRow,
+    Tag::TableHead,
+    Tag::TableCell,
+    Tag::Emphasis,
+    Tag::EmphasisAlt,
+    Tag::Strong,
+    Tag::Strikethrough,
+    Tag::Link,
+    Tag::Container,
+    Tag::Span,
+    Tag::Abbr,
+    Tag::Nav,
+    Tag::Figure,
+    Tag::FigureCaption,
+    Tag::DefinitionList,
+    Tag::DefinitionTerm,
+    Tag::DefinitionDetails,
+];
+
+fn arbitrary_option<T: Arbitrary>(g: &mut Gen) -> Option<T> {
+    if bool::arbitrary(g) {
+        Some(T::arbitrary(g))
+    } else {
+        None
+    }
+}
+
+fn arbitrary_value(g: &mut Gen) -> Value {
+    match usize::arbitrary(g) % 4 {
+        0 => Value::Null,
+        1 => Value::Bool(bool::arbitrary(g)),
+        2 => Value::from(i64::arbitrary(g)),
+        _ => Value::String(String::arbitrary(g)),
+    }
+}
+
+impl Arbitrary for Str<'static> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        String::arbitrary(g).into()
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        Box::new(self.as_str().to_string().shrink().map(Str::from))
+    }
+}
+
+impl Arbitrary for Tag {
+    fn arbitrary(g: &mut Gen) -> Self {
+        *g.choose(TAGS).unwrap()
+    }
+}
+
+impl Arbitrary for Alignment {
+    fn arbitrary(g: &mut Gen) -> Self {
+        *g.choose(&[
+            Alignment::None,
+            Alignment::Left,
+            Alignment::Center,
+            Alignment::Right,
+        ])
+        .unwrap()
+    }
+}
+
+impl Arbitrary for Location {
+    fn arbitrary(g: &mut Gen) -> Self {
+        let size = g.size().max(1);
+        Location {
+            offset: usize::arbitrary(g) % size,
+            len: usize::arbitrary(g) % size,
+            line: usize::arbitrary(g) % size + 1,
+            column: usize::arbitrary(g) % size,
+        }
+    }
+}
+
+impl Arbitrary for Attrs<'static> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Attrs {
+            start: arbitrary_option(g),
+            alignment: Alignment::arbitrary(g),
+            id: arbitrary_option(g),
+            class: arbitrary_option(g),
+            title: arbitrary_option(g),
+            target: arbitrary_option(g),
+            custom: if bool::arbitrary(g) {
+                Some(
+                    Vec::<(String, Str<'static>)>::arbitrary(g)
+                        .into_iter()
+                        .collect::<CustomAttrs>(),
+                )
+            } else {
+                None
+            },
+        }
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        if self.is_empty() {
+            empty_shrinker()
+        } else {
+            Box::new(std::iter::once(Attrs::default()))
+        }
+    }
+}
+
+impl Arbitrary for Event<'static> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        match usize::arbitrary(g) % 3 {
+            0 => StartTagEvent {
+                tag: Tag::arbitrary(g),
+                attrs: Attrs::arbitrary(g),
+            }
+            .into(),
+            1 => EndTagEvent {
+                tag: Tag::arbitrary(g),
+            }
+            .into(),
+            _ => arbitrary_leaf(g),
+        }
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        match *self {
+            Event::Text(ref text) => {
+                Box::new(text.text.shrink().map(|text| TextEvent { text }.into()))
+            }
+            Event::StartTag(ref start) => {
+                let tag = start.tag;
+                Box::new(
+                    start
+                        .attrs
+                        .shrink()
+                        .map(move |attrs| StartTagEvent { tag, attrs }.into()),
+                )
+            }
+            _ => empty_shrinker(),
+        }
+    }
+}
+
+impl Arbitrary for AnnotatedEvent<'static> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        AnnotatedEvent::new(Event::arbitrary(g), arbitrary_option(g))
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let location = self.location;
+        Box::new(
+            self.event
+                .shrink()
+                .map(move |event| AnnotatedEvent::new(event, location)),
+        )
+    }
+}
+
+/// Generates an event that is neither a start or end tag nor a document start.
+fn arbitrary_leaf(g: &mut Gen) -> Event<'static> {
+    match usize::arbitrary(g) % 14 {
+        0..=2 => TextEvent {
+            text: Str::arbitrary(g),
+        }
+        .into(),
+        3 => InterpretedTextEvent {
+            role: Str::arbitrary(g),
+            text: Str::arbitrary(g),
+        }
+        .into(),
+        4 => CodeBlockEvent {
+            language: arbitrary_option(g),
+            args: if bool::arbitrary(g) {
+                Some(Arbitrary::arbitrary(g))
+            } else {
+                None
+            },
+            code: Str::arbitrary(g),
+        }
+        .into(),
+        5 => DirectiveEvent {
+            name: Str::arbitrary(g),
+            argument: arbitrary_option(g),
+            front_matter: None,
+            body: Str::arbitrary(g),
+        }
+        .into(),
+        6 => InlineCodeEvent {
+            code: Str::arbitrary(g),
+        }
+        .into(),
+        7 => ImageEvent {
+            target: Str::arbitrary(g),
+            alt: arbitrary_option(g),
+            title: arbitrary_option(g),
+            attrs: Attrs::arbitrary(g),
+        }
+        .into(),
+        8 => RawHtmlEvent {
+            html: Str::arbitrary(g),
+        }
+        .into(),
+        9 => g
+            .choose(&[Event::SoftBreak, Event::HardBreak, Event::Rule])
+            .unwrap()
+            .clone(),
+        10 => CheckboxEvent {
+            checked: bool::arbitrary(g),
+        }
+        .into(),
+        11 => FootnoteReferenceEvent {
+            target: Str::arbitrary(g),
+        }
+        .into(),
+        12 => MetaDataEvent {
+            key: Str::arbitrary(g),
+            value: arbitrary_value(g),
+        }
+        .into(),
+        _ => ErrorEvent {
+            title: Str::arbitrary(g),
+            description: arbitrary_option(g),
+            severity: *g.choose(&[Severity::Warning, Severity::Error]).unwrap(),
+        }
+        .into(),
+    }
+}
+
+/// A randomly generated, well formed event stream.
+///
+/// The stream starts with a document start event and every start tag is
+/// closed by a matching end tag.  The size of the [`Gen`] limits the number
+/// of nodes in the stream.  Shrinking removes whole subtrees so shrunk
+/// streams stay balanced.
+#[derive(Debug, Clone)]
+pub struct BalancedStream(pub Vec<AnnotatedEvent<'static>>);
+
+impl Arbitrary for BalancedStream {
+    fn arbitrary(g: &mut Gen) -> Self {
+        BalancedStream(arbitrary_stream(g))
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let subtrees = top_level_subtrees(&self.0);
+        let events = self.0.clone();
+        Box::new(subtrees.into_iter().rev().map(move |(start, end)| {
+            let mut events = events.clone();
+            events.drain(start..end);
+            BalancedStream(events)
+        }))
+    }
+}
+
+/// Returns the ranges of all top level subtrees after the document start.
+fn top_level_subtrees(events: &[AnnotatedEvent<'_>]) -> Vec<(usize, usize)> {
+    let mut rv = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (idx, event) in events.iter().enumerate() {
+        match event.event {
+            Event::DocumentStart(..) => continue,
+            Event::StartTag(..) => {
+                if depth == 0 {
+                    start = idx;
+                }
+                depth += 1;
+                continue;
+            }
+            Event::EndTag(..) => depth -= 1,
+            _ => {
+                if depth == 0 {
+                    start = idx;
+                }
+            }
+        }
+        if depth == 0 {
+            rv.push((start, idx + 1));
+        }
+    }
+    rv
+}
+
+fn push_nodes(
+    g: &mut Gen,
+    depth: usize,
+    budget: &mut usize,
+    events: &mut Vec<AnnotatedEvent<'static>>,
+) {
+    let count = usize::arbitrary(g) % (*budget / (depth + 1) + 1);
+    for _ in 0..count {
+        if *budget == 0 {
+            break;
+        }
+        *budget -= 1;
+        if depth < 5 && bool::arbitrary(g) {
+            let tag = Tag::arbitrary(g);
+            events.push(AnnotatedEvent::new(
+                StartTagEvent {
+                    tag,
+                    attrs: Attrs::arbitrary(g),
+                },
+                arbitrary_option(g),
+            ));
+            push_nodes(g, depth + 1, budget, events);
+            events.push(EndTagEvent { tag }.into());
+        } else {
+            events.push(AnnotatedEvent::new(arbitrary_leaf(g), arbitrary_option(g)));
+        }
+    }
+}
+
+/// Generates a random, well formed event stream.
+///
+/// This is what [`BalancedStream`] uses to generate its events.
+pub fn arbitrary_stream(g: &mut Gen) -> Vec<AnnotatedEvent<'static>> {
+    let mut events = vec![AnnotatedEvent::new(
+        DocumentStartEvent {
+            front_matter: if bool::arbitrary(g) {
+                Some(arbitrary_value(g))
+            } else {
+                None
+            },
+            front_matter_format: None,
+        },
+        None,
+    )];
+    let mut budget = g.size();
+    push_nodes(g, 0, &mut budget, &mut events);
+    events
+}
+
+/// Checks if every start tag in a stream is closed by a matching end tag.
+pub fn is_balanced<'data, I: IntoIterator<Item = AnnotatedEvent<'data>>>(iter: I) -> bool {
+    let mut stack = Vec::new();
+    for event in iter {
+        match event.event {
+            Event::StartTag(StartTagEvent { tag, .. }) => stack.push(tag),
+            Event::EndTag(EndTagEvent { tag }) if stack.pop() != Some(tag) => return false,
+            _ => {}
+        }
+    }
+    stack.is_empty()
+}
+
+#[test]
+fn test_balanced_stream() {
+    fn check(stream: BalancedStream) -> bool {
+        crate::html::to_html(stream.0.clone().into_iter(), &Default::default());
+        stream.shrink().take(5).all(|shrunk| is_balanced(shrunk.0)) && is_balanced(stream.0)
+    }
+    quickcheck::quickcheck(check as fn(BalancedStream) -> bool);
+    assert!(!is_balanced(vec![AnnotatedEvent::from(EndTagEvent {
+        tag: Tag::Paragraph
+    })]));
+}
@@ -0,0 +1,439 @@
+//! Adapters between struckdown and pulldown-cmark event streams.
+//!
+//! [`from_cmark`] turns the events of a [`pulldown_cmark`] parser into a
+//! struckdown event stream and [`to_cmark`] converts a struckdown event
+//! stream back.  This allows struckdown processors to be used in toolchains
+//! built around pulldown-cmark:
+//!
+//! ```
+//! use pulldown_cmark::{html, Parser};
+//! use struckdown::cmark::{from_cmark, to_cmark};
+//! use struckdown::pipeline::Pipeline;
+//! use struckdown::processors::Redaction;
+//!
+//! let mut pipeline = Pipeline::new();
+//! pipeline.add_processor(Redaction::default());
+//!
+//! let events = pipeline.apply(from_cmark(Parser::new("# Hello *World*")));
+//! let mut out = String::new();
+//! html::push_html(&mut out, to_cmark(events));
+//! assert_eq!(out, "<h1>Hello <em>World</em></h1>\n");
+//! ```
+//!
+//! Both conversions are lossy.  pulldown-cmark events carry no locations and
+//! the struckdown extensions (roles, directives, explicit anchors and front
+//! matter) are not recognized in them.  Events without an equivalent in
+//! pulldown-cmark (such as directives, containers or errors) are converted
+//! into raw HTML as rendered by the [`HtmlRenderer`](crate::html::HtmlRenderer),
+//! attributes of other tags are dropped.
+use std::collections::VecDeque;
+use std::iter::{self, once};
+
+use pulldown_cmark as cm;
+
+use crate::event::{
+    Alignment, AnnotatedEvent, Attrs, CheckboxEvent, CodeBlockEvent, DocumentStartEvent,
+    EndTagEvent, Event, FootnoteReferenceEvent, ImageEvent, InlineCodeEvent, RawHtmlEvent,
+    StartTagEvent, Str, Tag, TextEvent,
+};
+use crate::html::to_html;
+use crate::parser::{read_raw, split_code_block_args};
+
+fn heading_tag(level: u32) -> Tag {
+    match level {
+        1 => Tag::Heading1,
+        2 => Tag::Heading2,
+        3 => Tag::Heading3,
+        4 => Tag::Heading4,
+        5 => Tag::Heading5,
+        _ => Tag::Heading6,
+    }
+}
+
+/// Converts pulldown-cmark events into a struckdown event stream.
+///
+/// The stream starts with a [`DocumentStartEvent`] like the one produced by
+/// the struckdown parser.  Code blocks and images are collapsed into single
+/// events and tables get the implied table body.
+pub fn from_cmark<'data, I>(iter: I) -> impl Iterator<Item = AnnotatedEvent<'data>>
+where
+    I: IntoIterator<Item = cm::Event<'data>>,
+{
+    // the parser helpers operate on offset iterators
+    let mut iter = iter.into_iter().map(|event| (event, 0..0));
+    let mut tag_stack = Vec::new();
+    let mut alignments = Vec::new();
+    let mut cell_is_head = false;
+    let mut cell_index = 0;
+    let mut buffer = VecDeque::new();
+    buffer.push_back(Event::from(DocumentStartEvent::new(None)));
+
+    iter::from_fn(move || loop {
+        if let Some(event) = buffer.pop_front() {
+            return Some(AnnotatedEvent::new(event, None));
+        }
+        let event: Event = match iter.next()?.0 {
+            cm::Event::Start(cm::Tag::CodeBlock(kind)) => {
+                let info = match kind {
+                    cm::CodeBlockKind::Fenced(info) => Str::from_cm_str(info),
+                    cm::CodeBlockKind::Indented => "".into(),
+                };
+                let code = read_raw(&mut iter);
+                let (language, args) = split_code_block_args(info);
+                CodeBlockEvent {
+                    language,
+                    args,
+                    code,
+                }
+                .into()
+            }
+            cm::Event::Start(cm::Tag::Image(_, target, title)) => {
+                let alt = read_raw(&mut iter);
+                ImageEvent {
+                    target: Str::from_cm_str(target),
+                    alt: if alt.as_str().is_empty() {
+                        None
+                    } else {
+                        Some(alt)
+                    },
+                    title: if title.is_empty() {
+                        None
+                    } else {
+                        Some(Str::from_cm_str(title))
+                    },
+                    attrs: Default::default(),
+                }
+                .into()
+            }
+            cm::Event::Start(cm_tag) => {
+                let mut event = StartTagEvent {
+                    tag: Tag::Paragraph,
+                    attrs: Default::default(),
+                };
+                let attrs = &mut event.attrs;
+                event.tag = match cm_tag {
+                    cm::Tag::Paragraph => Tag::Paragraph,
+                    cm::Tag::Heading(level) => heading_tag(level),
+                    cm::Tag::BlockQuote => Tag::BlockQuote,
+                    cm::Tag::List(None) => Tag::UnorderedList,
+                    cm::Tag::List(Some(start)) => {
+                        attrs.start = Some(start as u32);
+                        Tag::OrderedList
+                    }
+                    cm::Tag::Item => Tag::ListItem,
+                    cm::Tag::FootnoteDefinition(id) => {
+                        attrs.id = Some(Str::from_cm_str(id));
+                        Tag::FootnoteDefinition
+                    }
+                    cm::Tag::Table(cm_alignments) => {
+                        alignments = cm_alignments
+                            .into_iter()
+                            .map(|cm_align| match cm_align {
+                                cm::Alignment::None => Alignment::None,
+                                cm::Alignment::Left => Alignment::Left,
+                                cm::Alignment::Center => Alignment::Center,
+                                cm::Alignment::Right => Alignment::Right,
+                            })
+                            .collect();
+                        Tag::Table
+                    }
+                    cm::Tag::TableHead => {
+                        cell_index = 0;
+                        cell_is_head = true;
+                        Tag::TableHeader
+                    }
+                    cm::Tag::TableRow => {
+                        cell_index = 0;
+                        cell_is_head = false;
+                        Tag::TableRow
+                    }
+                    cm::Tag::TableCell => {
+                        attrs.alignment = alignments
+                            .get(cell_index)
+                            .copied()
+                            .unwrap_or(Alignment::None);
+                        cell_index += 1;
+                        if cell_is_head {
+                            Tag::TableHead
+                        } else {
+                            Tag::TableCell
+                        }
+                    }
+                    cm::Tag::Emphasis => Tag::Emphasis,
+                    cm::Tag::Strong => Tag::Strong,
+                    cm::Tag::Strikethrough => Tag::Strikethrough,
+                    cm::Tag::Link(_, target, title) => {
+                        attrs.target = Some(Str::from_cm_str(target));
+                        if !title.is_empty() {
+                            attrs.title = Some(Str::from_cm_str(title));
+                        }
+                        Tag::Link
+                    }
+                    // handled above
+                    cm::Tag::CodeBlock(_) | cm::Tag::Image(..) => unreachable!(),
+                };
+                tag_stack.push(event.tag);
+                event.into()
+            }
+            cm::Event::End(_) => {
+                let tag = match tag_stack.pop() {
+                    Some(tag) => tag,
+                    None => continue,
+                };
+                match tag {
+                    // the implied table body follows the header and ends
+                    // with the table
+                    Tag::TableHeader => {
+                        buffer.push_back(EndTagEvent { tag }.into());
+                        buffer.push_back(
+                            StartTagEvent {
+                                tag: Tag::TableBody,
+                                attrs: Default::default(),
+                            }
+                            .into(),
+                        );
+                        continue;
+                    }
+                    Tag::Table => {
+                        buffer.push_back(
+                            EndTagEvent {
+                                tag: Tag::TableBody,
+                            }
+                            .into(),
+                        );
+                        buffer.push_back(EndTagEvent { tag }.into());
+                        continue;
+                    }
+                    _ => EndTagEvent { tag }.into(),
+                }
+            }
+            cm::Event::Text(text) => TextEvent {
+                text: Str::from_cm_str(text),
+            }
+            .into(),
+            cm::Event::Code(code) => InlineCodeEvent {
+                code: Str::from_cm_str(code),
+            }
+            .into(),
+            cm::Event::Html(html) => RawHtmlEvent {
+                html: Str::from_cm_str(html),
+            }
+            .into(),
+            cm::Event::FootnoteReference(target) => FootnoteReferenceEvent {
+                target: Str::from_cm_str(target),
+            }
+            .into(),
+            cm::Event::SoftBreak => Event::SoftBreak,
+            cm::Event::HardBreak => Event::HardBreak,
+            cm::Event::Rule => Event::Rule,
+            cm::Event::TaskListMarker(checked) => CheckboxEvent { checked }.into(),
+        };
+        return Some(AnnotatedEvent::new(event, None));
+    })
+}
+
+/// Returns the pulldown-cmark tag for a struckdown tag if there is one.
+fn cmark_tag<'data>(tag: Tag, attrs: &Attrs<'data>) -> Option<cm::Tag<'data>> {
+    let str_or_empty =
+        |value: &Option<Str<'data>>| value.clone().map_or("".into(), Str::into_cm_str);
+    Some(match tag {
+        Tag::Paragraph => cm::Tag::Paragraph,
+        Tag::Heading1
+        | Tag::Heading2
+        | Tag::Heading3
+        | Tag::Heading4
+        | Tag::Heading5
+        | Tag::Heading6 => cm::Tag::Heading(tag.header_level().unwrap() as u32),
+        Tag::BlockQuote => cm::Tag::BlockQuote,
+        Tag::OrderedList => cm::Tag::List(Some(attrs.start.unwrap_or(1).into())),
+        Tag::UnorderedList => cm::Tag::List(None),
+        Tag::ListItem => cm::Tag::Item,
+        Tag::FootnoteDefinition => cm::Tag::FootnoteDefinition(str_or_empty(&attrs.id)),
+        Tag::TableHeader => cm::Tag::TableHead,
+        Tag::TableRow => cm::Tag::TableRow,
+        Tag::TableHead | Tag::TableCell => cm::Tag::TableCell,
+        Tag::Emphasis | Tag::EmphasisAlt => cm::Tag::Emphasis,
+        Tag::Strong => cm::Tag::Strong,
+        Tag::Strikethrough => cm::Tag::Strikethrough,
+        Tag::Link => cm::Tag::Link(
+            cm::LinkType::Inline,
+            str_or_empty(&attrs.target),
+            str_or_empty(&attrs.title),
+        ),
+        _ => return None,
+    })
+}
+
+/// Renders an event without a pulldown-cmark equivalent to raw HTML.
+fn raw_html<'data>(event: Event<'data>) -> cm::Event<'data> {
+    cm::Event::Html(to_html(once(AnnotatedEvent::new(event, None)), &Default::default()).into())
+}
+
+/// Converts a single struckdown event into pulldown-cmark events.
+///
+/// `tags` holds the pulldown-cmark tags of the open struckdown tags so that
+/// end tags can be converted.  Tables are handled by the caller.
+fn convert<'data>(
+    event: Event<'data>,
+    tags: &mut Vec<Option<cm::Tag<'data>>>,
+    out: &mut VecDeque<cm::Event<'data>>,
+) {
+    match event {
+        Event::DocumentStart(_) | Event::MetaData(_) => {}
+        Event::StartTag(StartTagEvent {
+            tag: Tag::TableBody,
+            ..
+        }) => tags.push(None),
+        Event::EndTag(EndTagEvent {
+            tag: Tag::TableBody,
+        }) => {
+            tags.pop();
+        }
+        Event::StartTag(StartTagEvent { tag, attrs }) => match cmark_tag(tag, &attrs) {
+            Some(cm_tag) => {
+                tags.push(Some(cm_tag.clone()));
+                out.push_back(cm::Event::Start(cm_tag));
+            }
+            None => {
+                tags.push(None);
+                out.push_back(raw_html(StartTagEvent { tag, attrs }.into()));
+            }
+        },
+        Event::EndTag(end) => match tags.pop().flatten() {
+            Some(cm_tag) => out.push_back(cm::Event::End(cm_tag)),
+            None => out.push_back(raw_html(end.into())),
+        },
+        Event::Text(TextEvent { text }) => out.push_back(cm::Event::Text(text.into_cm_str())),
+        Event::CodeBlock(CodeBlockEvent {
+            language,
+            args,
+            code,
+        }) => {
+            let mut info = language.map_or_else(String::new, |x| x.as_str().to_string());
+            for (key, value) in args.into_iter().flatten() {
+                info.push_str(&format!(" {}=\"{}\"", key, value));
+            }
+            let tag = cm::Tag::CodeBlock(cm::CodeBlockKind::Fenced(info.into()));
+            out.push_back(cm::Event::Start(tag.clone()));
+            out.push_back(cm::Event::Text(code.into_cm_str()));
+            out.push_back(cm::Event::End(tag));
+        }
+        Event::InlineCode(InlineCodeEvent { code }) => {
+            out.push_back(cm::Event::Code(code.into_cm_str()))
+        }
+        Event::Image(ImageEvent {
+            target, alt, title, ..
+        }) => {
+            let tag = cm::Tag::Image(
+                cm::LinkType::Inline,
+                target.into_cm_str(),
+                title.map_or("".into(), Str::into_cm_str),
+            );
+            out.push_back(cm::Event::Start(tag.clone()));
+            if let Some(alt) = alt {
+                out.push_back(cm::Event::Text(alt.into_cm_str()));
+            }
+            out.push_back(cm::Event::End(tag));
+        }
+        Event::RawHtml(RawHtmlEvent { html }) => out.push_back(cm::Event::Html(html.into_cm_str())),
+        Event::SoftBreak => out.push_back(cm::Event::SoftBreak),
+        Event::HardBreak => out.push_back(cm::Event::HardBreak),
+        Event::Rule => out.push_back(cm::Event::Rule),
+        Event::Checkbox(CheckboxEvent { checked }) => {
+            out.push_back(cm::Event::TaskListMarker(checked))
+        }
+        Event::FootnoteReference(FootnoteReferenceEvent { target }) => {
+            out.push_back(cm::Event::FootnoteReference(target.into_cm_str()))
+        }
+        event @ Event::Directive(_)
+        | event @ Event::InterpretedText(_)
+        | event @ Event::Error(_) => out.push_back(raw_html(event)),
+    }
+}
+
+/// Converts a struckdown event stream into pulldown-cmark events.
+///
+/// pulldown-cmark needs the column alignments at the start of a table, so
+/// tables are buffered until they end.
+pub fn to_cmark<'data, I>(iter: I) -> impl Iterator<Item = cm::Event<'data>>
+where
+    I: IntoIterator<Item = AnnotatedEvent<'data>>,
+{
+    let mut iter = iter.into_iter();
+    let mut tags = Vec::new();
+    let mut buffer = VecDeque::new();
+
+    iter::from_fn(move || loop {
+        if let Some(event) = buffer.pop_front() {
+            return Some(event);
+        }
+        match iter.next()?.event {
+            Event::StartTag(StartTagEvent {
+                tag: Tag::Table, ..
+            }) => {
+                let mut alignments = Vec::new();
+                let mut in_header = false;
+                let mut table = VecDeque::new();
+                for annotated_event in iter.by_ref() {
+                    match annotated_event.event {
+                        Event::EndTag(EndTagEvent { tag: Tag::Table }) => break,
+                        Event::StartTag(StartTagEvent {
+                            tag: Tag::TableHeader,
+                            ..
+                        }) => in_header = true,
+                        Event::EndTag(EndTagEvent {
+                            tag: Tag::TableHeader,
+                        }) => in_header = false,
+                        Event::StartTag(StartTagEvent {
+                            tag: Tag::TableHead,
+                            ref attrs,
+                        }) if in_header => alignments.push(match attrs.alignment {
+                            Alignment::None => cm::Alignment::None,
+                            Alignment::Left => cm::Alignment::Left,
+                            Alignment::Center => cm::Alignment::Center,
+                            Alignment::Right => cm::Alignment::Right,
+                        }),
+                        _ => {}
+                    }
+                    convert(annotated_event.event, &mut tags, &mut table);
+                }
+                let tag = cm::Tag::Table(alignments);
+                buffer.push_back(cm::Event::Start(tag.clone()));
+                buffer.extend(table);
+                buffer.push_back(cm::Event::End(tag));
+            }
+            event => convert(event, &mut tags, &mut buffer),
+        }
+    })
+}
+
+#[test]
+fn test_cmark_roundtrip() {
+    use crate::parser::parse;
+
+    let source = "# Hello\n\n| a | b |\n|:--|--:|\n| 1 | 2 |\n\n```rust\nfn main() {}\n```\n\n![alt](x.png) [link](y \"title\")\n\n1. one\n2. two";
+    let mut options = cm::Options::empty();
+    options.insert(cm::Options::ENABLE_TABLES);
+
+    let mut expected = String::new();
+    cm::html::push_html(&mut expected, cm::Parser::new_ext(source, options));
+    let mut roundtrip = String::new();
+    cm::html::push_html(
+        &mut roundtrip,
+        to_cmark(from_cmark(cm::Parser::new_ext(source, options))),
+    );
+    assert_eq!(roundtrip, expected);
+
+    let converted: Vec<_> = from_cmark(cm::Parser::new_ext(source, options)).collect();
+    assert_eq!(
+        to_html(converted.into_iter(), &Default::default()),
+        to_html(parse(source, &Default::default()), &Default::default())
+    );
+
+    let mut out = String::new();
+    cm::html::push_html(
+        &mut out,
+        to_cmark(parse("```{note}\nHi\n```", &Default::default())),
+    );
+    assert_eq!(out, "<div class=\"directive-note\"><pre>Hi\n</pre></div>");
+}
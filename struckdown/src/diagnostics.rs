@@ -0,0 +1,182 @@
+//! Collects the problems reported during pipeline runs.
+//!
+//! Problems with a document are reported as [`ErrorEvent`]s within the event
+//! stream.  Build tools usually want to know about them without having to
+//! look through the rendered output.  [`Pipeline::process_with_diagnostics`](crate::pipeline::Pipeline::process_with_diagnostics)
+//! returns the events together with a [`Diagnostics`] report that lists all
+//! errors and the processors that emitted them.
+//!
+//! ```
+//! use struckdown::pipeline::Pipeline;
+//!
+//! let pipeline = Pipeline::default();
+//! let (events, diagnostics) = pipeline.process_with_diagnostics("Hello World!");
+//! assert!(!diagnostics.has_errors());
+//! ```
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use serde::Serialize;
+
+use crate::event::{AnnotatedEvent, ErrorEvent, Event, Location, Severity};
+
+/// A single problem reported during a pipeline run.
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    /// The severity of the problem.
+    pub severity: Severity,
+    /// The title of the error event.
+    pub title: String,
+    /// The description of the error event.
+    pub description: Option<String>,
+    /// The location of the error event if known.
+    pub location: Option<Location>,
+    /// The name of the pipeline stage that emitted the error.  Errors that
+    /// were already contained in the input stream are attributed to `input`.
+    pub processor: String,
+}
+
+/// The problems reported during a pipeline run.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(transparent)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    /// Creates an empty report.
+    pub fn new() -> Diagnostics {
+        Diagnostics::default()
+    }
+
+    /// Adds a diagnostic to the report.
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Iterates over all diagnostics in the order they were emitted.
+    pub fn iter(&self) -> std::slice::Iter<'_, Diagnostic> {
+        self.diagnostics.iter()
+    }
+
+    /// Returns the number of diagnostics.
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    /// Returns `true` if no problems were reported.
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    /// Returns `true` if any diagnostic is an error.
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    /// Iterates over all errors.
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.iter().filter(|x| x.severity == Severity::Error)
+    }
+
+    /// Iterates over all warnings.
+    pub fn warnings(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.iter().filter(|x| x.severity == Severity::Warning)
+    }
+}
+
+impl IntoIterator for Diagnostics {
+    type Item = Diagnostic;
+    type IntoIter = std::vec::IntoIter<Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.diagnostics.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Diagnostic;
+    type IntoIter = std::slice::Iter<'a, Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Identifies an error event across pipeline stages.
+type ErrorKey = (String, Option<String>, Option<(usize, usize)>);
+
+fn error_key(error: &ErrorEvent<'_>, location: Option<Location>) -> ErrorKey {
+    (
+        error.title.as_str().to_string(),
+        error.description.as_ref().map(|x| x.as_str().to_string()),
+        location.map(|x| (x.offset, x.len)),
+    )
+}
+
+/// Attributes error events to the pipeline stage that emitted them.
+///
+/// Every stage counts the errors it passes on.  An error is new if a stage
+/// passes on more copies of it than were seen by any stage before.
+#[derive(Default)]
+pub(crate) struct DiagnosticsCollector {
+    known: HashMap<ErrorKey, usize>,
+    seen: Vec<HashMap<ErrorKey, usize>>,
+    diagnostics: Diagnostics,
+}
+
+impl DiagnosticsCollector {
+    /// Registers a stage returning its index.
+    pub(crate) fn add_stage(&mut self) -> usize {
+        self.seen.push(HashMap::new());
+        self.seen.len() - 1
+    }
+
+    fn record(&mut self, stage: usize, name: &str, annotated_event: &AnnotatedEvent<'_>) {
+        let error = match annotated_event.event {
+            Event::Error(ref error) => error,
+            _ => return,
+        };
+        let key = error_key(error, annotated_event.location);
+        let seen = self.seen[stage].entry(key.clone()).or_insert(0);
+        *seen += 1;
+        let known = self.known.entry(key).or_insert(0);
+        if *seen > *known {
+            *known += 1;
+            self.diagnostics.push(Diagnostic {
+                severity: error.severity,
+                title: error.title.as_str().to_string(),
+                description: error.description.as_ref().map(|x| x.as_str().to_string()),
+                location: annotated_event.location,
+                processor: name.to_string(),
+            });
+        }
+    }
+
+    /// Takes the collected report.
+    pub(crate) fn take_diagnostics(&mut self) -> Diagnostics {
+        std::mem::take(&mut self.diagnostics)
+    }
+}
+
+/// Records the errors passing through a pipeline stage.
+pub(crate) struct DiagnosticsIter<'data> {
+    pub(crate) iter: Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data>,
+    pub(crate) stage: usize,
+    pub(crate) name: Cow<'data, str>,
+    pub(crate) collector: Rc<RefCell<DiagnosticsCollector>>,
+}
+
+impl<'data> Iterator for DiagnosticsIter<'data> {
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let annotated_event = self.iter.next()?;
+        self.collector
+            .borrow_mut()
+            .record(self.stage, &self.name, &annotated_event);
+        Some(annotated_event)
+    }
+}
@@ -0,0 +1,127 @@
+//! Provides the error type of struckdown.
+//!
+//! Most of the API is infallible: problems with the processed document are
+//! reported as [`ErrorEvent`](crate::event::ErrorEvent)s in the stream.  The
+//! [`Error`] type is used for internal failures such as a theme that cannot
+//! be loaded which the convenience APIs turn into panics.  The fallible
+//! variants (such as [`Pipeline::try_process`](crate::pipeline::Pipeline::try_process))
+//! return them instead.
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+
+/// Describes the kind of an [`Error`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// A processor could not be set up (eg: a resource failed to load).
+    ProcessorFailed,
+    /// A value could not be serialized or deserialized.
+    Serialization,
+    /// An I/O operation failed.
+    Io,
+}
+
+impl ErrorKind {
+    fn description(self) -> &'static str {
+        match self {
+            ErrorKind::ProcessorFailed => "processor failed",
+            ErrorKind::Serialization => "serialization failed",
+            ErrorKind::Io => "i/o error",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+/// Represents internal failures of struckdown.
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    detail: Option<String>,
+    source: Option<Box<dyn StdError + Send + Sync + 'static>>,
+}
+
+impl Error {
+    /// Creates a new error.
+    pub fn new<D: Into<String>>(kind: ErrorKind, detail: D) -> Error {
+        Error {
+            kind,
+            detail: Some(detail.into()),
+            source: None,
+        }
+    }
+
+    /// Attaches the underlying cause to the error.
+    pub fn with_source<E: StdError + Send + Sync + 'static>(mut self, source: E) -> Error {
+        self.source = Some(Box::new(source));
+        self
+    }
+
+    /// Returns the kind of the error.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Returns the detail message of the error.
+    pub fn detail(&self) -> Option<&str> {
+        self.detail.as_deref()
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        Error {
+            kind,
+            detail: None,
+            source: None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::from(ErrorKind::Io).with_source(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Error {
+        Error::from(ErrorKind::Serialization).with_source(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(ref detail) = self.detail {
+            write!(f, ": {}", detail)?;
+        }
+        if let Some(ref source) = self.source {
+            write!(f, " ({})", source)?;
+        }
+        Ok(())
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.source.as_ref().map(|err| err.as_ref() as _)
+    }
+}
+
+#[test]
+fn test_error_display() {
+    let err = Error::new(ErrorKind::ProcessorFailed, "failed to load theme")
+        .with_source(io::Error::new(io::ErrorKind::NotFound, "missing.tmTheme"));
+    assert_eq!(err.kind(), ErrorKind::ProcessorFailed);
+    assert_eq!(
+        err.to_string(),
+        "processor failed: failed to load theme (missing.tmTheme)"
+    );
+    assert!(err.source().is_some());
+}
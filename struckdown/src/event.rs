@@ -2,13 +2,19 @@
 use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::collections::BTreeMap;
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 use std::fmt::{self, Debug, Display};
+use std::iter::FromIterator;
+use std::marker::PhantomData;
 
 use pulldown_cmark as cm;
+use serde::de::{self, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use crate::value::Value;
+use crate::error::{Error, ErrorKind};
+use crate::value::{from_toml_value, to_toml_value, Value};
+
+mod borrowed;
 
 /// An internal string type.
 ///
@@ -33,26 +39,46 @@ impl<'data> Serialize for Str<'data> {
     }
 }
 
-impl<'data, 'de> Deserialize<'de> for Str<'data> {
+struct StrVisitor<'data>(PhantomData<Str<'data>>);
+
+impl<'de, 'data> Visitor<'de> for StrVisitor<'data> {
+    type Value = Str<'data>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a string")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Str<'data>, E> {
+        Ok(Str::copied(value))
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<Str<'data>, E> {
+        Ok(value.into())
+    }
+}
+
+/// Strings are always copied from the input so that owned events can be
+/// deserialized from any deserializer.  Use
+/// [`AnnotatedEvent::deserialize_borrowed`] to borrow them instead.
+impl<'de, 'data> Deserialize<'de> for Str<'data> {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        let cow = Cow::<'data, str>::deserialize(deserializer)?;
-        Ok(cow.into())
+        deserializer.deserialize_str(StrVisitor(PhantomData))
     }
 }
 
-impl From<String> for Str<'static> {
-    fn from(value: String) -> Str<'static> {
+impl<'data> From<String> for Str<'data> {
+    fn from(value: String) -> Str<'data> {
         Str {
             inner: value.into(),
         }
     }
 }
 
-impl From<Box<str>> for Str<'static> {
-    fn from(value: Box<str>) -> Str<'static> {
+impl<'data> From<Box<str>> for Str<'data> {
+    fn from(value: Box<str>) -> Str<'data> {
         Str {
             inner: cm::CowStr::Boxed(value),
         }
@@ -96,6 +122,11 @@ impl<'data> Str<'data> {
         Str { inner: value }
     }
 
+    /// Converts the string into a cmark string.
+    pub(crate) fn into_cm_str(self) -> cm::CowStr<'data> {
+        self.inner
+    }
+
     /// Slices the string down.
     pub(crate) fn slice(&self, start: usize, end: usize) -> Str<'data> {
         Str {
@@ -105,19 +136,34 @@ impl<'data> Str<'data> {
                     cm::CowStr::Boxed(val[start..end].to_string().into_boxed_str())
                 }
                 cm::CowStr::Inlined(ref val) => {
-                    cm::CowStr::Inlined((&val)[start..end].try_into().unwrap())
+                    cm::CowStr::Inlined(val[start..end].try_into().unwrap())
                 }
             },
         }
     }
 
+    /// Copies a string slice into an owned string.
+    ///
+    /// Short strings (such as most class names, roles and languages) are
+    /// stored inline and do not allocate.
+    fn copied(value: &str) -> Str<'static> {
+        Str {
+            inner: match cm::InlineStr::try_from(value) {
+                Ok(val) => cm::CowStr::Inlined(val),
+                Err(_) => cm::CowStr::Boxed(value.into()),
+            },
+        }
+    }
+
     /// Converts the string into a static version.
     fn into_static(self) -> Str<'static> {
-        Str {
-            inner: match self.inner {
-                cm::CowStr::Borrowed(val) => cm::CowStr::Boxed(val.to_string().into_boxed_str()),
-                cm::CowStr::Boxed(ref val) => cm::CowStr::Boxed(val.to_string().into_boxed_str()),
-                cm::CowStr::Inlined(val) => cm::CowStr::Inlined(val),
+        match self.inner {
+            cm::CowStr::Borrowed(val) => Str::copied(val),
+            cm::CowStr::Boxed(val) => Str {
+                inner: cm::CowStr::Boxed(val),
+            },
+            cm::CowStr::Inlined(val) => Str {
+                inner: cm::CowStr::Inlined(val),
             },
         }
     }
@@ -137,7 +183,7 @@ impl<'data> Debug for Str<'data> {
 
 impl<'data> PartialOrd for Str<'data> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.as_str().partial_cmp(other.as_str())
+        Some(self.cmp(other))
     }
 }
 
@@ -148,6 +194,10 @@ impl<'data> Ord for Str<'data> {
 }
 
 /// Location information for an annotated event.
+///
+/// Offsets and lines refer to the full source given to the parser,
+/// including the front matter, so they can be resolved with a
+/// [`SourceMap`](crate::source_map::SourceMap) of that source.
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 pub struct Location {
     /// Byte offset within the source document.
@@ -157,9 +207,40 @@ pub struct Location {
     /// Line in the source document (1 indexed).
     pub line: usize,
     /// Column in the source document (0 indexed).
+    ///
+    /// The column is in bytes, use [`char_column`](Self::char_column) or
+    /// [`utf16_column`](Self::utf16_column) to convert it.
     pub column: usize,
 }
 
+impl Location {
+    /// Returns the part of the line in `source` before the location.
+    ///
+    /// Returns `None` if the location does not belong to the source.
+    fn line_prefix<'a>(&self, source: &'a str) -> Option<&'a str> {
+        source.get(self.offset.checked_sub(self.column)?..self.offset)
+    }
+
+    /// Returns the column in characters (0 indexed).
+    ///
+    /// `source` must be the document the location refers to.  If the
+    /// location does not match the source the byte column is returned.
+    pub fn char_column(&self, source: &str) -> usize {
+        self.line_prefix(source)
+            .map_or(self.column, |x| x.chars().count())
+    }
+
+    /// Returns the column in UTF-16 code units (0 indexed).
+    ///
+    /// This is the column editors and the language server protocol use.
+    /// `source` must be the document the location refers to.  If the
+    /// location does not match the source the byte column is returned.
+    pub fn utf16_column(&self, source: &str) -> usize {
+        self.line_prefix(source)
+            .map_or(self.column, |x| x.chars().map(char::len_utf16).sum())
+    }
+}
+
 /// Event with annotations.
 ///
 /// An annotated event is generally the same as an [`Event`] but it contains
@@ -196,6 +277,21 @@ impl<'data> AnnotatedEvent<'data> {
     }
 }
 
+impl<'data> AnnotatedEvent<'data> {
+    /// Deserializes an event borrowing strings from the input.
+    ///
+    /// The [`Deserialize`] implementation always copies strings so that
+    /// `AnnotatedEvent<'static>` can be deserialized from any deserializer.
+    /// This instead borrows strings if the deserializer supports it (eg:
+    /// `serde_json::from_str` for strings without escapes) and can be used
+    /// with `#[serde(borrow, deserialize_with = "...")]`.
+    pub fn deserialize_borrowed<'de: 'data, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<AnnotatedEvent<'data>, D::Error> {
+        borrowed::AnnotatedEventDef::deserialize(deserializer)
+    }
+}
+
 impl<'data, T: Into<Event<'data>>> From<T> for AnnotatedEvent<'data> {
     fn from(value: T) -> AnnotatedEvent<'data> {
         AnnotatedEvent::new(value, None)
@@ -203,10 +299,11 @@ impl<'data, T: Into<Event<'data>>> From<T> for AnnotatedEvent<'data> {
 }
 
 /// Alignment information.
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum Alignment {
     /// Undefined alignment
+    #[default]
     None,
     /// Left aligned
     Left,
@@ -216,12 +313,6 @@ pub enum Alignment {
     Right,
 }
 
-impl Default for Alignment {
-    fn default() -> Alignment {
-        Alignment::None
-    }
-}
-
 /// Tag type
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
@@ -276,6 +367,20 @@ pub enum Tag {
     Container,
     /// `<span>` equivalent. Not used in syntax.
     Span,
+    /// `<abbr>` equivalent. Not used in syntax.
+    Abbr,
+    /// `<nav>` equivalent. Not used in syntax.
+    Nav,
+    /// `<figure>` equivalent. Not used in syntax.
+    Figure,
+    /// `<figcaption>` equivalent. Not used in syntax.
+    FigureCaption,
+    /// `<dl>` equivalent. Not used in syntax.
+    DefinitionList,
+    /// `<dt>` equivalent. Not used in syntax.
+    DefinitionTerm,
+    /// `<dd>` equivalent. Not used in syntax.
+    DefinitionDetails,
 }
 
 impl Tag {
@@ -296,6 +401,37 @@ impl Tag {
         }
     }
 
+    /// Returns the heading tag for a header level.
+    ///
+    /// Levels outside of the valid range are clamped to `1..=6`.
+    pub fn heading(level: usize) -> Tag {
+        match level {
+            0 | 1 => Tag::Heading1,
+            2 => Tag::Heading2,
+            3 => Tag::Heading3,
+            4 => Tag::Heading4,
+            5 => Tag::Heading5,
+            _ => Tag::Heading6,
+        }
+    }
+
+    /// Returns `true` if the tag is an inline (phrasing) tag.
+    ///
+    /// Inline tags are tags that appear within the text of a block such as
+    /// emphasis or links.
+    pub fn is_inline(self) -> bool {
+        matches!(
+            self,
+            Tag::Emphasis
+                | Tag::EmphasisAlt
+                | Tag::Strong
+                | Tag::Strikethrough
+                | Tag::Link
+                | Tag::Span
+                | Tag::Abbr
+        )
+    }
+
     /// Creates a start tag event.
     pub fn start_tag(self, attrs: Attrs<'_>) -> StartTagEvent<'_> {
         StartTagEvent { tag: self, attrs }
@@ -334,7 +470,7 @@ pub struct Attrs<'data> {
     pub target: Option<Str<'data>>,
     /// Custom attributes.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub custom: Option<BTreeMap<Cow<'static, str>, Str<'data>>>,
+    pub custom: Option<CustomAttrs<'data>>,
 }
 
 impl<'data> Attrs<'data> {
@@ -358,21 +494,251 @@ impl<'data> Attrs<'data> {
             class: self.class.map(|x| x.into_static()),
             title: self.title.map(|x| x.into_static()),
             target: self.target.map(|x| x.into_static()),
-            custom: self.custom.map(|custom| {
-                custom
-                    .into_iter()
-                    .map(|(k, v)| (Cow::Owned(k.into_owned()), v.into_static()))
-                    .collect()
-            }),
+            custom: self.custom.map(CustomAttrs::into_static),
         }
     }
 }
 
+/// Custom attributes of a tag.
+///
+/// This is a small map sorted by key.  Tags rarely carry more than a few
+/// custom attributes so they are kept in a single vector rather than a
+/// tree.
+#[derive(Default, Clone)]
+pub struct CustomAttrs<'data> {
+    entries: Vec<(Cow<'static, str>, Str<'data>)>,
+}
+
+impl<'data> CustomAttrs<'data> {
+    /// Creates an empty map of custom attributes.
+    pub fn new() -> CustomAttrs<'data> {
+        CustomAttrs::default()
+    }
+
+    /// Returns the number of attributes.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if there are no attributes.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn find(&self, key: &str) -> Result<usize, usize> {
+        self.entries.binary_search_by(|(k, _)| k.as_ref().cmp(key))
+    }
+
+    /// Returns the value of an attribute.
+    pub fn get(&self, key: &str) -> Option<&Str<'data>> {
+        self.find(key).ok().map(|idx| &self.entries[idx].1)
+    }
+
+    /// Checks if an attribute is set.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.find(key).is_ok()
+    }
+
+    /// Sets an attribute returning the old value.
+    pub fn insert<K, V>(&mut self, key: K, value: V) -> Option<Str<'data>>
+    where
+        K: Into<Cow<'static, str>>,
+        V: Into<Str<'data>>,
+    {
+        let key = key.into();
+        let value = value.into();
+        match self.find(&key) {
+            Ok(idx) => Some(std::mem::replace(&mut self.entries[idx].1, value)),
+            Err(idx) => {
+                self.entries.insert(idx, (key, value));
+                None
+            }
+        }
+    }
+
+    /// Removes an attribute returning its value.
+    pub fn remove(&mut self, key: &str) -> Option<Str<'data>> {
+        self.find(key).ok().map(|idx| self.entries.remove(idx).1)
+    }
+
+    /// Iterates over the attributes in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Str<'data>)> {
+        self.entries.iter().map(|(k, v)| (k.as_ref(), v))
+    }
+
+    /// Converts the attributes into static ones.
+    fn into_static(self) -> CustomAttrs<'static> {
+        CustomAttrs {
+            entries: self
+                .entries
+                .into_iter()
+                .map(|(k, v)| (k, v.into_static()))
+                .collect(),
+        }
+    }
+}
+
+impl<'data> Debug for CustomAttrs<'data> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'data, K, V> FromIterator<(K, V)> for CustomAttrs<'data>
+where
+    K: Into<Cow<'static, str>>,
+    V: Into<Str<'data>>,
+{
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> CustomAttrs<'data> {
+        let mut rv = CustomAttrs::new();
+        for (key, value) in iter {
+            rv.insert(key, value);
+        }
+        rv
+    }
+}
+
+impl<'data> IntoIterator for CustomAttrs<'data> {
+    type Item = (Cow<'static, str>, Str<'data>);
+    type IntoIter = std::vec::IntoIter<(Cow<'static, str>, Str<'data>)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<'data> Serialize for CustomAttrs<'data> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(self.iter())
+    }
+}
+
+struct CustomAttrsVisitor<'data>(PhantomData<CustomAttrs<'data>>);
+
+impl<'de, 'data> Visitor<'de> for CustomAttrsVisitor<'data> {
+    type Value = CustomAttrs<'data>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a map of attributes")
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<CustomAttrs<'data>, A::Error> {
+        let mut rv = CustomAttrs {
+            entries: Vec::with_capacity(map.size_hint().unwrap_or(0)),
+        };
+        while let Some((key, value)) = map.next_entry::<String, Str<'data>>()? {
+            rv.insert(key, value);
+        }
+        Ok(rv)
+    }
+}
+
+impl<'de, 'data> Deserialize<'de> for CustomAttrs<'data> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(CustomAttrsVisitor(PhantomData))
+    }
+}
+
+/// The syntax front matter was written in.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FrontMatterFormat {
+    /// YAML front matter delimited by `---` lines.
+    Yaml,
+    /// TOML front matter delimited by `+++` lines.
+    Toml,
+    /// A JSON object with the braces on their own lines.
+    Json,
+}
+
+impl FrontMatterFormat {
+    /// Parses the contents of a front matter block.
+    ///
+    /// The source must not include the `---` or `+++` delimiters.  For JSON
+    /// the braces are part of the source.
+    pub fn parse(self, source: &str) -> Result<Value, Error> {
+        match self {
+            FrontMatterFormat::Yaml => serde_yaml::from_str(source)
+                .map_err(|err| Error::from(ErrorKind::Serialization).with_source(err)),
+            FrontMatterFormat::Toml => from_toml_value(
+                toml::from_str(source)
+                    .map_err(|err| Error::from(ErrorKind::Serialization).with_source(err))?,
+            ),
+            FrontMatterFormat::Json => Ok(serde_json::from_str(source)?),
+        }
+    }
+
+    /// Serializes a value into a front matter block including delimiters.
+    ///
+    /// The output can be prepended to a document body to recreate the
+    /// document with front matter in this format.
+    pub fn render(self, value: &Value) -> Result<String, Error> {
+        let mut rv = match self {
+            FrontMatterFormat::Yaml => {
+                let yaml = serde_yaml::to_string(value)
+                    .map_err(|err| Error::from(ErrorKind::Serialization).with_source(err))?;
+                // serde_yaml already emits the opening delimiter
+                let mut rv = String::from("---\n");
+                rv.push_str(yaml.strip_prefix("---\n").unwrap_or(&yaml));
+                if !rv.ends_with('\n') {
+                    rv.push('\n');
+                }
+                rv.push_str("---");
+                rv
+            }
+            FrontMatterFormat::Toml => {
+                let toml = toml::to_string(&to_toml_value(value)?)
+                    .map_err(|err| Error::from(ErrorKind::Serialization).with_source(err))?;
+                format!("+++\n{}+++", toml)
+            }
+            FrontMatterFormat::Json => serde_json::to_string_pretty(value)?,
+        };
+        rv.push('\n');
+        Ok(rv)
+    }
+}
+
 /// Emitted at the start of a document.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+///
+/// More fields might be added in the future, use [`DocumentStartEvent::new`]
+/// to create this event.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[non_exhaustive]
 pub struct DocumentStartEvent {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub front_matter: Option<Value>,
+    /// The format the front matter was parsed from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub front_matter_format: Option<FrontMatterFormat>,
+}
+
+impl DocumentStartEvent {
+    /// Creates a document start event with optional front matter.
+    ///
+    /// The front matter format is not recorded and can be set afterwards.
+    pub fn new(front_matter: Option<Value>) -> DocumentStartEvent {
+        DocumentStartEvent {
+            front_matter,
+            front_matter_format: None,
+        }
+    }
+
+    /// Serializes the front matter back into its original format.
+    ///
+    /// Front matter without a recorded format is written as YAML.  Returns
+    /// `None` if the document has no front matter.
+    pub fn render_front_matter(&self) -> Option<Result<String, Error>> {
+        let format = self.front_matter_format.unwrap_or(FrontMatterFormat::Yaml);
+        self.front_matter
+            .as_ref()
+            .map(|front_matter| format.render(front_matter))
+    }
 }
 
 /// Emitted when a tag starts.
@@ -446,6 +812,9 @@ pub struct ImageEvent<'data> {
     pub alt: Option<Str<'data>>,
     /// The optional title of the image
     pub title: Option<Str<'data>>,
+    /// Additional attributes such as dimensions.
+    #[serde(default, skip_serializing_if = "Attrs::is_empty")]
+    pub attrs: Attrs<'data>,
 }
 
 /// Embedded raw HTML
@@ -478,11 +847,32 @@ pub struct MetaDataEvent<'data> {
     pub value: Value,
 }
 
+/// The severity of an [`ErrorEvent`].
+#[derive(
+    Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Indicates a likely problem that does not prevent rendering.
+    Warning,
+    /// Indicates a failure.
+    #[default]
+    Error,
+}
+
+impl Severity {
+    fn is_error(&self) -> bool {
+        *self == Severity::Error
+    }
+}
+
 /// An event representing an error during processing
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ErrorEvent<'data> {
     pub title: Str<'data>,
     pub description: Option<Str<'data>>,
+    #[serde(default, skip_serializing_if = "Severity::is_error")]
+    pub severity: Severity,
 }
 
 /// A event in a struckdown stream.
@@ -604,6 +994,7 @@ impl<'data> Event<'data> {
                 target: value.target.into_static(),
                 alt: value.alt.map(|x| x.into_static()),
                 title: value.title.map(|x| x.into_static()),
+                attrs: value.attrs.into_static(),
             }),
             Event::RawHtml(value) => Event::RawHtml(RawHtmlEvent {
                 html: value.html.into_static(),
@@ -622,7 +1013,130 @@ impl<'data> Event<'data> {
             Event::Error(value) => Event::Error(ErrorEvent {
                 title: value.title.into_static(),
                 description: value.description.map(|x| x.into_static()),
+                severity: value.severity,
             }),
         }
     }
 }
+
+#[test]
+fn test_borrowed_deserialization() {
+    let input = r#"{"type":"text","text":"Hello World","location":{"offset":0,"len":11,"line":1,"column":0}}"#;
+    let mut deserializer = serde_json::Deserializer::from_str(input);
+    let event = AnnotatedEvent::deserialize_borrowed(&mut deserializer).unwrap();
+    let text = match event.event {
+        Event::Text(TextEvent { ref text }) => text.as_str(),
+        _ => panic!("expected text"),
+    };
+    assert_eq!(text, "Hello World");
+    assert!(input.as_bytes().as_ptr_range().contains(&text.as_ptr()));
+    assert_eq!(event.location.unwrap().len, 11);
+
+    // escaped strings cannot be borrowed
+    let input = r#"{"type":"start_tag","tag":"link","attrs":{"target":"a\nb","custom":{"x":"y"}}}"#;
+    let mut deserializer = serde_json::Deserializer::from_str(input);
+    let event = AnnotatedEvent::deserialize_borrowed(&mut deserializer).unwrap();
+    match event.event {
+        Event::StartTag(StartTagEvent { ref attrs, .. }) => {
+            assert_eq!(attrs.target.as_ref().unwrap().as_str(), "a\nb");
+            assert_eq!(
+                attrs.custom.as_ref().unwrap().get("x").unwrap().as_str(),
+                "y"
+            );
+        }
+        _ => panic!("expected start tag"),
+    }
+
+    // the regular implementation works with owned deserializers
+    fn assert_owned<T: serde::de::DeserializeOwned>() {}
+    assert_owned::<AnnotatedEvent<'static>>();
+    let event: AnnotatedEvent<'static> = serde_json::from_reader(input.as_bytes()).unwrap();
+    assert!(matches!(event.event, Event::StartTag(_)));
+}
+
+#[test]
+fn test_location_columns() {
+    let source = "# Hällo\nä 🎉 *World*";
+    let offset = source.find('*').unwrap();
+    let location = Location {
+        offset,
+        len: 7,
+        line: 2,
+        column: offset - source.find('\n').unwrap() - 1,
+    };
+    assert_eq!(location.column, 8);
+    assert_eq!(location.char_column(source), 4);
+    assert_eq!(location.utf16_column(source), 5);
+
+    // locations from other sources fall back to bytes
+    assert_eq!(location.char_column("x"), 8);
+}
+
+#[test]
+fn test_custom_attrs() {
+    let mut custom: CustomAttrs = vec![("width", "10"), ("alt", "x")].into_iter().collect();
+    assert_eq!(custom.insert("width", "20").unwrap().as_str(), "10");
+    custom.insert("height", String::from("30"));
+    assert_eq!(
+        serde_json::to_string(&custom).unwrap(),
+        r#"{"alt":"x","height":"30","width":"20"}"#
+    );
+    assert_eq!(custom.remove("alt").unwrap().as_str(), "x");
+    assert!(!custom.contains_key("alt"));
+
+    let input = r#"{"data-role":"note"}"#;
+    let custom: CustomAttrs = serde_json::from_str(input).unwrap();
+    assert_eq!(custom.get("data-role").unwrap().as_str(), "note");
+    assert_eq!(
+        format!("{:?}", custom.into_static()),
+        r#"{"data-role": "note"}"#
+    );
+
+    // short strings do not allocate when made static
+    let text = Str::from("note").into_static();
+    assert!(matches!(text.inner, cm::CowStr::Inlined(_)));
+}
+
+#[test]
+fn test_front_matter_formats() {
+    let sources = [
+        (
+            "---\ntitle: Hello\ntags: [a, b]\n---\nBody",
+            FrontMatterFormat::Yaml,
+        ),
+        (
+            "+++\ntitle = \"Hello\"\ntags = [\"a\", \"b\"]\n+++\nBody",
+            FrontMatterFormat::Toml,
+        ),
+        (
+            "{\n  \"title\": \"Hello\",\n  \"tags\": [\"a\", \"b\"]\n}\nBody",
+            FrontMatterFormat::Json,
+        ),
+    ];
+    for &(source, format) in &sources {
+        let start = match crate::parser::parse(source, &Default::default()).next() {
+            Some(AnnotatedEvent {
+                event: Event::DocumentStart(start),
+                ..
+            }) => start,
+            _ => panic!("expected document start"),
+        };
+        assert_eq!(start.front_matter_format, Some(format));
+        assert_eq!(
+            start.front_matter,
+            Some(crate::value::value!({"title": "Hello", "tags": ["a", "b"]}))
+        );
+
+        let rendered = start.render_front_matter().unwrap().unwrap();
+        let reparsed = crate::parser::parse(&rendered, &Default::default())
+            .next()
+            .unwrap();
+        match reparsed.event {
+            Event::DocumentStart(reparsed) => {
+                assert_eq!(reparsed.front_matter_format, Some(format));
+                assert_eq!(reparsed.front_matter, start.front_matter);
+            }
+            _ => panic!("expected document start"),
+        }
+    }
+}
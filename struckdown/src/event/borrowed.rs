@@ -0,0 +1,235 @@
+//! Borrowing deserialization of events.
+//!
+//! The event types derive [`Deserialize`] with owned strings.  The types in
+//! here mirror them via `#[serde(remote)]` but borrow strings from the input
+//! where possible.  They back [`AnnotatedEvent::deserialize_borrowed`].
+use std::collections::BTreeMap;
+
+use serde::de::{Deserializer, Error, Visitor};
+use serde::Deserialize;
+
+use crate::event::{
+    Alignment, AnnotatedEvent, Attrs, CheckboxEvent, CodeBlockEvent, CustomAttrs, DirectiveEvent,
+    DocumentStartEvent, EndTagEvent, ErrorEvent, Event, FootnoteReferenceEvent, ImageEvent,
+    InlineCodeEvent, InterpretedTextEvent, Location, MetaDataEvent, RawHtmlEvent, Severity,
+    StartTagEvent, Str, Tag, TextEvent,
+};
+use crate::value::Value;
+
+/// A string that borrows from the input if possible.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct BorrowedStr<'data>(Str<'data>);
+
+struct BorrowedStrVisitor;
+
+impl<'de> Visitor<'de> for BorrowedStrVisitor {
+    type Value = BorrowedStr<'de>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("a string")
+    }
+
+    fn visit_borrowed_str<E: Error>(self, value: &'de str) -> Result<BorrowedStr<'de>, E> {
+        Ok(BorrowedStr(value.into()))
+    }
+
+    fn visit_str<E: Error>(self, value: &str) -> Result<BorrowedStr<'de>, E> {
+        Ok(BorrowedStr(Str::copied(value)))
+    }
+
+    fn visit_string<E: Error>(self, value: String) -> Result<BorrowedStr<'de>, E> {
+        Ok(BorrowedStr(value.into()))
+    }
+}
+
+impl<'de: 'data, 'data> Deserialize<'de> for BorrowedStr<'data> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(BorrowedStrVisitor)
+    }
+}
+
+fn str<'de: 'data, 'data, D: Deserializer<'de>>(deserializer: D) -> Result<Str<'data>, D::Error> {
+    BorrowedStr::deserialize(deserializer).map(|x| x.0)
+}
+
+fn opt_str<'de: 'data, 'data, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Str<'data>>, D::Error> {
+    Option::<BorrowedStr>::deserialize(deserializer).map(|x| x.map(|x| x.0))
+}
+
+fn opt_str_map<'de: 'data, 'data, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<BTreeMap<Str<'data>, Str<'data>>>, D::Error> {
+    let map = Option::<BTreeMap<BorrowedStr, BorrowedStr>>::deserialize(deserializer)?;
+    Ok(map.map(|map| map.into_iter().map(|(k, v)| (k.0, v.0)).collect()))
+}
+
+fn opt_custom_attrs<'de: 'data, 'data, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<CustomAttrs<'data>>, D::Error> {
+    let map = Option::<BTreeMap<String, BorrowedStr>>::deserialize(deserializer)?;
+    Ok(map.map(|map| map.into_iter().map(|(k, v)| (k, v.0)).collect()))
+}
+
+#[derive(Deserialize)]
+#[serde(remote = "AnnotatedEvent")]
+pub(super) struct AnnotatedEventDef<'data> {
+    #[serde(borrow, flatten, with = "EventDef")]
+    event: Event<'data>,
+    location: Option<Location>,
+}
+
+#[derive(Deserialize)]
+#[serde(remote = "Attrs")]
+struct AttrsDef<'data> {
+    start: Option<u32>,
+    #[serde(default)]
+    alignment: Alignment,
+    #[serde(borrow, default, deserialize_with = "opt_str")]
+    id: Option<Str<'data>>,
+    #[serde(borrow, default, deserialize_with = "opt_str")]
+    class: Option<Str<'data>>,
+    #[serde(borrow, default, deserialize_with = "opt_str")]
+    title: Option<Str<'data>>,
+    #[serde(borrow, default, deserialize_with = "opt_str")]
+    target: Option<Str<'data>>,
+    #[serde(borrow, default, deserialize_with = "opt_custom_attrs")]
+    custom: Option<CustomAttrs<'data>>,
+}
+
+#[derive(Deserialize)]
+#[serde(remote = "StartTagEvent")]
+struct StartTagEventDef<'data> {
+    tag: Tag,
+    #[serde(borrow, default, with = "AttrsDef")]
+    attrs: Attrs<'data>,
+}
+
+#[derive(Deserialize)]
+#[serde(remote = "TextEvent")]
+struct TextEventDef<'data> {
+    #[serde(borrow, deserialize_with = "str")]
+    text: Str<'data>,
+}
+
+#[derive(Deserialize)]
+#[serde(remote = "InterpretedTextEvent")]
+struct InterpretedTextEventDef<'data> {
+    #[serde(borrow, deserialize_with = "str")]
+    role: Str<'data>,
+    #[serde(borrow, deserialize_with = "str")]
+    text: Str<'data>,
+}
+
+#[derive(Deserialize)]
+#[serde(remote = "CodeBlockEvent")]
+struct CodeBlockEventDef<'data> {
+    #[serde(borrow, default, deserialize_with = "opt_str")]
+    language: Option<Str<'data>>,
+    #[serde(borrow, default, deserialize_with = "opt_str_map")]
+    args: Option<BTreeMap<Str<'data>, Str<'data>>>,
+    #[serde(borrow, deserialize_with = "str")]
+    code: Str<'data>,
+}
+
+#[derive(Deserialize)]
+#[serde(remote = "DirectiveEvent")]
+struct DirectiveEventDef<'data> {
+    #[serde(borrow, deserialize_with = "str")]
+    name: Str<'data>,
+    #[serde(borrow, default, deserialize_with = "opt_str")]
+    argument: Option<Str<'data>>,
+    front_matter: Option<Value>,
+    #[serde(borrow, deserialize_with = "str")]
+    body: Str<'data>,
+}
+
+#[derive(Deserialize)]
+#[serde(remote = "InlineCodeEvent")]
+struct InlineCodeEventDef<'data> {
+    #[serde(borrow, deserialize_with = "str")]
+    code: Str<'data>,
+}
+
+#[derive(Deserialize)]
+#[serde(remote = "ImageEvent")]
+struct ImageEventDef<'data> {
+    #[serde(borrow, deserialize_with = "str")]
+    target: Str<'data>,
+    #[serde(borrow, default, deserialize_with = "opt_str")]
+    alt: Option<Str<'data>>,
+    #[serde(borrow, default, deserialize_with = "opt_str")]
+    title: Option<Str<'data>>,
+    #[serde(borrow, default, with = "AttrsDef")]
+    attrs: Attrs<'data>,
+}
+
+#[derive(Deserialize)]
+#[serde(remote = "RawHtmlEvent")]
+struct RawHtmlEventDef<'data> {
+    #[serde(borrow, deserialize_with = "str")]
+    html: Str<'data>,
+}
+
+#[derive(Deserialize)]
+#[serde(remote = "FootnoteReferenceEvent")]
+struct FootnoteReferenceEventDef<'data> {
+    #[serde(borrow, deserialize_with = "str")]
+    target: Str<'data>,
+}
+
+#[derive(Deserialize)]
+#[serde(remote = "MetaDataEvent")]
+struct MetaDataEventDef<'data> {
+    #[serde(borrow, deserialize_with = "str")]
+    key: Str<'data>,
+    value: Value,
+}
+
+#[derive(Deserialize)]
+#[serde(remote = "ErrorEvent")]
+struct ErrorEventDef<'data> {
+    #[serde(borrow, deserialize_with = "str")]
+    title: Str<'data>,
+    #[serde(borrow, default, deserialize_with = "opt_str")]
+    description: Option<Str<'data>>,
+    #[serde(default)]
+    severity: Severity,
+}
+
+#[derive(Deserialize)]
+#[serde(remote = "Event", rename_all = "snake_case", tag = "type")]
+enum EventDef<'data> {
+    DocumentStart(DocumentStartEvent),
+    #[serde(borrow, with = "StartTagEventDef")]
+    StartTag(StartTagEvent<'data>),
+    EndTag(EndTagEvent),
+    #[serde(borrow, with = "TextEventDef")]
+    Text(TextEvent<'data>),
+    #[serde(borrow, with = "InterpretedTextEventDef")]
+    InterpretedText(InterpretedTextEvent<'data>),
+    #[serde(borrow, with = "CodeBlockEventDef")]
+    CodeBlock(CodeBlockEvent<'data>),
+    #[serde(borrow, with = "DirectiveEventDef")]
+    Directive(DirectiveEvent<'data>),
+    #[serde(borrow, with = "InlineCodeEventDef")]
+    InlineCode(InlineCodeEvent<'data>),
+    #[serde(borrow, with = "ImageEventDef")]
+    Image(ImageEvent<'data>),
+    #[serde(borrow, with = "RawHtmlEventDef")]
+    RawHtml(RawHtmlEvent<'data>),
+    SoftBreak,
+    HardBreak,
+    Rule,
+    Checkbox(CheckboxEvent),
+    #[serde(borrow, with = "FootnoteReferenceEventDef")]
+    FootnoteReference(FootnoteReferenceEvent<'data>),
+    #[serde(borrow, with = "MetaDataEventDef")]
+    MetaData(MetaDataEvent<'data>),
+    #[serde(borrow, with = "ErrorEventDef")]
+    Error(ErrorEvent<'data>),
+}
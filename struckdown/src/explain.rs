@@ -0,0 +1,260 @@
+//! Traces how the stages of a pipeline change an event stream.
+//!
+//! When the output of a pipeline looks wrong it's often not obvious which
+//! processor is responsible.  [`Pipeline::explain`](crate::pipeline::Pipeline::explain)
+//! runs the pipeline stage by stage and records for every stage which events
+//! it added, removed or modified.
+//!
+//! ```
+//! use struckdown::pipeline::Pipeline;
+//! use struckdown::processors::Redaction;
+//!
+//! let mut pipeline = Pipeline::new();
+//! pipeline.add_processor(Redaction::default());
+//! let trace = pipeline.explain("Hello World!");
+//! assert!(trace.stages[0].is_unchanged());
+//! ```
+use serde::Serialize;
+
+use crate::event::AnnotatedEvent;
+use crate::value::{to_value, Value};
+
+/// Middle sections larger than this (in compared pairs) are not diffed
+/// event by event but reported as replaced entirely.
+const MAX_DIFF_CELLS: usize = 1 << 22;
+
+/// A change a stage made to the event stream.
+///
+/// Indexes refer to the input and output streams of the stage.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum EventChange {
+    /// An event was added to the stream.
+    Added {
+        /// The index of the event in the output.
+        index: usize,
+        /// The added event.
+        event: AnnotatedEvent<'static>,
+    },
+    /// An event was removed from the stream.
+    Removed {
+        /// The index of the event in the input.
+        index: usize,
+        /// The removed event.
+        event: AnnotatedEvent<'static>,
+    },
+    /// An event was replaced by an event of the same type.
+    Modified {
+        /// The index of the original event in the input.
+        old_index: usize,
+        /// The index of the new event in the output.
+        new_index: usize,
+        /// The original event.
+        before: Box<AnnotatedEvent<'static>>,
+        /// The new event.
+        after: Box<AnnotatedEvent<'static>>,
+    },
+}
+
+/// The changes made by a single pipeline stage.
+#[derive(Debug, Clone, Serialize)]
+pub struct StageTrace {
+    /// The name of the stage.
+    pub name: String,
+    /// The number of events the stage received.
+    pub input_events: usize,
+    /// The number of events the stage produced.
+    pub output_events: usize,
+    /// The changes in the order of the stream.
+    pub changes: Vec<EventChange>,
+}
+
+impl StageTrace {
+    /// Returns `true` if the stage passed the stream on unchanged.
+    pub fn is_unchanged(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// The trace of a pipeline run.
+#[derive(Debug, Clone, Serialize)]
+pub struct Trace {
+    /// The number of events produced by the parser or passed as input.
+    pub input_events: usize,
+    /// The stages in the order they are applied.
+    pub stages: Vec<StageTrace>,
+}
+
+enum Op {
+    Equal,
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Calculates the edit script between two sequences.
+///
+/// The common prefix and suffix are skipped, the remainder is compared with
+/// a longest common subsequence table.
+fn diff_ops(old: &[Value], new: &[Value]) -> Vec<Op> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    let mut ops = Vec::new();
+    ops.extend((0..prefix).map(|_| Op::Equal));
+    if a.len().saturating_mul(b.len()) > MAX_DIFF_CELLS {
+        ops.extend((0..a.len()).map(|idx| Op::Delete(prefix + idx)));
+        ops.extend((0..b.len()).map(|idx| Op::Insert(prefix + idx)));
+    } else {
+        // lcs[i][j] is the length of the common subsequence of a[i..], b[j..]
+        let width = b.len() + 1;
+        let mut lcs = vec![0u32; (a.len() + 1) * width];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i * width + j] = if a[i] == b[j] {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                ops.push(Op::Equal);
+                i += 1;
+                j += 1;
+            } else if i < a.len()
+                && (j == b.len() || lcs[(i + 1) * width + j] >= lcs[i * width + j + 1])
+            {
+                ops.push(Op::Delete(prefix + i));
+                i += 1;
+            } else {
+                ops.push(Op::Insert(prefix + j));
+                j += 1;
+            }
+        }
+    }
+    ops.extend((0..suffix).map(|_| Op::Equal));
+    ops
+}
+
+fn event_type(value: &Value) -> Option<&Value> {
+    value.get("type")
+}
+
+/// Compares two event streams.
+///
+/// Returns the changes that turn `input` into `output` in the order of the
+/// stream.  Events are compared including their locations.
+///
+/// ```
+/// # use struckdown::explain::diff_events;
+/// # use struckdown::parser::parse;
+/// let old = parse("Hello *World*", &Default::default()).collect::<Vec<_>>();
+/// let new = parse("Hello *World*!", &Default::default()).collect::<Vec<_>>();
+/// assert!(!diff_events(&old, &new).is_empty());
+/// ```
+pub fn diff_events(
+    input: &[AnnotatedEvent<'_>],
+    output: &[AnnotatedEvent<'_>],
+) -> Vec<EventChange> {
+    let serialize = |events: &[AnnotatedEvent<'_>]| {
+        events
+            .iter()
+            .map(|x| to_value(x).unwrap_or(Value::Null))
+            .collect::<Vec<_>>()
+    };
+    let old = serialize(input);
+    let new = serialize(output);
+
+    let mut changes = Vec::new();
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    let mut flush = |removed: &mut Vec<usize>, added: &mut Vec<usize>| {
+        // removals directly followed by additions of the same event type
+        // are reported as modifications
+        let mut added_iter = added.drain(..).peekable();
+        for old_index in removed.drain(..) {
+            match added_iter.peek() {
+                Some(&new_index) if event_type(&old[old_index]) == event_type(&new[new_index]) => {
+                    added_iter.next();
+                    changes.push(EventChange::Modified {
+                        old_index,
+                        new_index,
+                        before: Box::new(input[old_index].clone().into_static()),
+                        after: Box::new(output[new_index].clone().into_static()),
+                    });
+                }
+                _ => changes.push(EventChange::Removed {
+                    index: old_index,
+                    event: input[old_index].clone().into_static(),
+                }),
+            }
+        }
+        changes.extend(added_iter.map(|index| EventChange::Added {
+            index,
+            event: output[index].clone().into_static(),
+        }));
+    };
+    for op in diff_ops(&old, &new) {
+        match op {
+            Op::Equal => flush(&mut removed, &mut added),
+            Op::Delete(idx) => {
+                if !added.is_empty() {
+                    flush(&mut removed, &mut added);
+                }
+                removed.push(idx);
+            }
+            Op::Insert(idx) => added.push(idx),
+        }
+    }
+    flush(&mut removed, &mut added);
+    changes
+}
+
+/// Records the changes between the input and output of a stage.
+pub(crate) fn trace_stage(
+    name: String,
+    input: &[AnnotatedEvent<'_>],
+    output: &[AnnotatedEvent<'_>],
+) -> StageTrace {
+    StageTrace {
+        name,
+        input_events: input.len(),
+        output_events: output.len(),
+        changes: diff_events(input, output),
+    }
+}
+
+#[test]
+fn test_trace_stage() {
+    use crate::event::{Event, TextEvent};
+
+    let text = |s: &'static str| AnnotatedEvent::new(TextEvent { text: s.into() }, None);
+    let input = vec![text("a"), text("b"), Event::Rule.into(), text("d")];
+    let output = vec![text("a"), text("B"), text("d"), Event::HardBreak.into()];
+    let trace = trace_stage("test".into(), &input, &output);
+    assert_eq!(trace.input_events, 4);
+    assert_eq!(trace.output_events, 4);
+    assert!(matches!(
+        trace.changes[..],
+        [
+            EventChange::Modified {
+                old_index: 1,
+                new_index: 1,
+                ..
+            },
+            EventChange::Removed { index: 2, .. },
+            EventChange::Added { index: 3, .. },
+        ]
+    ));
+
+    assert!(trace_stage("test".into(), &input, &input).is_unchanged());
+}
@@ -0,0 +1,35 @@
+//! Stable hashing of serializable values.
+use serde::Serialize;
+
+/// Incrementally calculates a 64bit FNV-1a hash.
+///
+/// This is used instead of the standard library's hasher as the latter is
+/// not guaranteed to be stable across releases.
+pub(crate) struct Fnv(pub u64);
+
+impl Default for Fnv {
+    fn default() -> Fnv {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl std::io::Write for Fnv {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for byte in buf {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x100_0000_01b3);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hashes the JSON serialization of a value.
+pub(crate) fn hash_json<T: Serialize + ?Sized>(value: &T) -> Option<u64> {
+    let mut hasher = Fnv::default();
+    serde_json::to_writer(&mut hasher, value).ok()?;
+    Some(hasher.0)
+}
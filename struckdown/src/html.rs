@@ -2,6 +2,7 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::io::{self, Write};
+use std::mem;
 
 use serde::{Deserialize, Serialize};
 use v_htmlescape::escape;
@@ -44,11 +45,35 @@ impl HtmlRendererOptions {
     }
 }
 
+/// Tells the renderer what to do with an event passed to a hook.
+///
+/// See [`HtmlRenderer::on_event`].
+#[derive(Debug, Clone)]
+pub enum RenderAction<'data> {
+    /// Renders the event as usual.
+    Render,
+    /// Renders another event in place of the event.
+    Replace(Event<'data>),
+    /// Writes raw HTML in place of the event.
+    Html(String),
+    /// Renders nothing for the event.
+    Skip,
+}
+
+type EventHook<'data, 'options> =
+    Box<dyn FnMut(&AnnotatedEvent<'data>) -> RenderAction<'data> + 'options>;
+type StartTagHook<'data, 'options> =
+    Box<dyn FnMut(Tag, &Attrs<'data>) -> RenderAction<'data> + 'options>;
+type EndTagHook<'data, 'options> = Box<dyn FnMut(Tag) -> RenderAction<'data> + 'options>;
+
 /// Object capable of rendering events to HTML.
 pub struct HtmlRenderer<'data, 'options, F> {
     out: F,
     footnotes: HashMap<Str<'data>, usize>,
     options: &'options HtmlRendererOptions,
+    event_hook: Option<EventHook<'data, 'options>>,
+    start_tag_hook: Option<StartTagHook<'data, 'options>>,
+    end_tag_hook: Option<EndTagHook<'data, 'options>>,
 }
 
 impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
@@ -58,9 +83,57 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
             out,
             footnotes: HashMap::new(),
             options,
+            event_hook: None,
+            start_tag_hook: None,
+            end_tag_hook: None,
         }
     }
 
+    /// Sets a hook that is invoked for every event before it's rendered.
+    ///
+    /// The returned [`RenderAction`] decides if the event is rendered,
+    /// replaced or skipped.  Events rendered by this hook are also passed
+    /// to the tag hooks, replacements are not.
+    ///
+    /// ```
+    /// # use struckdown::event::Event;
+    /// # use struckdown::html::{HtmlRenderer, RenderAction};
+    /// # use struckdown::parser::parse;
+    /// let options = Default::default();
+    /// let mut renderer = HtmlRenderer::new_buffered(&options);
+    /// renderer.on_event(|annotated_event| match annotated_event.event {
+    ///     Event::Rule => RenderAction::Html("<hr class=\"fancy\">".into()),
+    ///     _ => RenderAction::Render,
+    /// });
+    /// renderer.feed_stream(parse("---", &Default::default())).unwrap();
+    /// assert_eq!(renderer.into_string(), "<hr class=\"fancy\">");
+    /// ```
+    pub fn on_event<H>(&mut self, hook: H)
+    where
+        H: FnMut(&AnnotatedEvent<'data>) -> RenderAction<'data> + 'options,
+    {
+        self.event_hook = Some(Box::new(hook));
+    }
+
+    /// Sets a hook that is invoked for start tags.
+    ///
+    /// Tag hooks are independent of each other: when a start tag is skipped
+    /// or replaced the hook for end tags usually needs to do the same.
+    pub fn on_start_tag<H>(&mut self, hook: H)
+    where
+        H: FnMut(Tag, &Attrs<'data>) -> RenderAction<'data> + 'options,
+    {
+        self.start_tag_hook = Some(Box::new(hook));
+    }
+
+    /// Sets a hook that is invoked for end tags.
+    pub fn on_end_tag<H>(&mut self, hook: H)
+    where
+        H: FnMut(Tag) -> RenderAction<'data> + 'options,
+    {
+        self.end_tag_hook = Some(Box::new(hook));
+    }
+
     /// Consumes the writer and returns the inner file.
     pub fn into_writer(self) -> F {
         self.out
@@ -93,6 +166,13 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
             Tag::TableBody => true,
             Tag::Container => true,
             Tag::Span => false,
+            Tag::Abbr => false,
+            Tag::Nav => true,
+            Tag::Figure => true,
+            Tag::FigureCaption => false,
+            Tag::DefinitionList => true,
+            Tag::DefinitionTerm => false,
+            Tag::DefinitionDetails => false,
         }
     }
 
@@ -123,6 +203,13 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
             Tag::TableBody => true,
             Tag::Container => true,
             Tag::Span => false,
+            Tag::Abbr => false,
+            Tag::Nav => true,
+            Tag::Figure => true,
+            Tag::FigureCaption => true,
+            Tag::DefinitionList => true,
+            Tag::DefinitionTerm => true,
+            Tag::DefinitionDetails => true,
         }
     }
 
@@ -170,6 +257,13 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
             Tag::Link => "a",
             Tag::Container => "div",
             Tag::Span => "span",
+            Tag::Abbr => "abbr",
+            Tag::Nav => "nav",
+            Tag::Figure => "figure",
+            Tag::FigureCaption => "figcaption",
+            Tag::DefinitionList => "dl",
+            Tag::DefinitionTerm => "dt",
+            Tag::DefinitionDetails => "dd",
         }
     }
 
@@ -270,7 +364,31 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
 
     /// Feeds a single event into the renderer.
     pub fn feed_event(&mut self, event: &AnnotatedEvent<'data>) -> Result<(), io::Error> {
-        match event.event {
+        let mut action = match self.event_hook {
+            Some(ref mut hook) => hook(event),
+            None => RenderAction::Render,
+        };
+        if let RenderAction::Render = action {
+            action = match (
+                &event.event,
+                &mut self.start_tag_hook,
+                &mut self.end_tag_hook,
+            ) {
+                (Event::StartTag(StartTagEvent { tag, attrs }), Some(hook), _) => hook(*tag, attrs),
+                (Event::EndTag(EndTagEvent { tag }), _, Some(hook)) => hook(*tag),
+                _ => RenderAction::Render,
+            };
+        }
+        match action {
+            RenderAction::Render => self.render_event(&event.event),
+            RenderAction::Replace(replacement) => self.render_event(&replacement),
+            RenderAction::Html(html) => self.out.write_all(html.as_bytes()),
+            RenderAction::Skip => Ok(()),
+        }
+    }
+
+    fn render_event(&mut self, event: &Event<'data>) -> Result<(), io::Error> {
+        match *event {
             Event::DocumentStart(_) | Event::MetaData(_) => {}
             Event::StartTag(StartTagEvent { tag, ref attrs }) => {
                 self.start_tag(tag, attrs)?;
@@ -317,14 +435,27 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
                 ref target,
                 ref alt,
                 ref title,
+                ref attrs,
             }) => {
                 write!(
                     self.out,
-                    "<img src=\"{}\" alt=\"{}\" title=\"{}\">",
+                    "<img src=\"{}\" alt=\"{}\" title=\"{}\"",
                     target,
                     alt.as_ref().map_or("", |x| x.as_str()),
                     title.as_ref().map_or("", |x| x.as_str()),
                 )?;
+                if let Some(ref id) = attrs.id {
+                    write!(self.out, " id=\"{}\"", escape(id.as_str()))?;
+                }
+                if let Some(ref class) = attrs.class {
+                    write!(self.out, " class=\"{}\"", escape(class.as_str()))?;
+                }
+                if let Some(ref custom) = attrs.custom {
+                    for (key, value) in custom.iter() {
+                        write!(self.out, " {}=\"{}\"", key, escape(value.as_str()))?;
+                    }
+                }
+                write!(self.out, ">")?;
             }
             Event::RawHtml(RawHtmlEvent { ref html }) => {
                 write!(self.out, "{}", html)?;
@@ -361,6 +492,7 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
             Event::Error(ErrorEvent {
                 ref title,
                 ref description,
+                ..
             }) => {
                 write!(
                     self.out,
@@ -396,17 +528,136 @@ impl<'data, 'options> HtmlRenderer<'data, 'options, Vec<u8>> {
     }
 
     /// Converts the renderer into a string.
+    ///
+    /// The renderer only writes valid UTF-8.  Invalid bytes in a buffer
+    /// given to [`HtmlRenderer::new`] are replaced.
     pub fn into_string(self) -> String {
-        unsafe { String::from_utf8_unchecked(self.into_writer()) }
+        match String::from_utf8(self.into_writer()) {
+            Ok(rv) => rv,
+            Err(err) => String::from_utf8_lossy(err.as_bytes()).into_owned(),
+        }
     }
 }
 
 /// Convenience shortcut that renders an event stream into HTML.
+///
+/// Rendering into memory cannot fail, see [`try_to_html`] and
+/// [`to_html_writer`] for fallible variants.
 pub fn to_html<'a, I: Iterator<Item = AnnotatedEvent<'a>>>(
     iter: I,
     options: &HtmlRendererOptions,
 ) -> String {
+    let mut rv = String::new();
+    to_html_into(iter, options, &mut rv);
+    rv
+}
+
+/// Renders an event stream into HTML reporting errors instead of panicking.
+pub fn try_to_html<'a, I: Iterator<Item = AnnotatedEvent<'a>>>(
+    iter: I,
+    options: &HtmlRendererOptions,
+) -> Result<String, io::Error> {
     let mut renderer = HtmlRenderer::new_buffered(options);
-    renderer.feed_stream(iter).unwrap();
-    renderer.into_string()
+    renderer.feed_stream(iter)?;
+    Ok(renderer.into_string())
+}
+
+/// Renders an event stream into HTML into a writer.
+///
+/// Write errors are reported and the writer is flushed at the end.  Wrap
+/// unbuffered writers such as files or sockets in a [`io::BufWriter`].
+pub fn to_html_writer<'a, I, W>(
+    iter: I,
+    options: &HtmlRendererOptions,
+    writer: W,
+) -> Result<(), io::Error>
+where
+    I: Iterator<Item = AnnotatedEvent<'a>>,
+    W: Write,
+{
+    let mut renderer = HtmlRenderer::new(writer, options);
+    renderer.feed_stream(iter)?;
+    renderer.into_writer().flush()
+}
+
+/// Renders an event stream into HTML replacing the contents of a string.
+///
+/// Unlike [`to_html`] this reuses the allocation of the string.
+pub fn to_html_into<'a, I: Iterator<Item = AnnotatedEvent<'a>>>(
+    iter: I,
+    options: &HtmlRendererOptions,
+    out: &mut String,
+) {
+    let mut buf = mem::take(out).into_bytes();
+    buf.clear();
+    let mut renderer = HtmlRenderer::new(buf, options);
+    renderer
+        .feed_stream(iter)
+        .expect("writing to a buffer cannot fail");
+    *out = renderer.into_string();
+}
+
+#[test]
+fn test_to_html_writer() {
+    use crate::parser::parse;
+
+    struct Closed;
+
+    impl Write for Closed {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let options = HtmlRendererOptions::default();
+    let err = to_html_writer(parse("# Hello", &Default::default()), &options, Closed).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+
+    let mut out = Vec::new();
+    to_html_writer(parse("# Hello", &Default::default()), &options, &mut out).unwrap();
+    assert_eq!(
+        try_to_html(parse("# Hello", &Default::default()), &options).unwrap(),
+        String::from_utf8(out).unwrap()
+    );
+}
+
+#[test]
+fn test_render_hooks() {
+    use crate::parser::parse;
+
+    let options = HtmlRendererOptions::default();
+    let mut renderer = HtmlRenderer::new_buffered(&options);
+    renderer.on_event(|annotated_event| match annotated_event.event {
+        Event::Image(_) => RenderAction::Skip,
+        _ => RenderAction::Render,
+    });
+    renderer.on_start_tag(|tag, attrs| match tag {
+        Tag::Emphasis => RenderAction::Replace(
+            StartTagEvent {
+                tag,
+                attrs: Attrs {
+                    class: Some("em".into()),
+                    ..attrs.clone()
+                },
+            }
+            .into(),
+        ),
+        Tag::Strong => RenderAction::Html("<b>".into()),
+        _ => RenderAction::Render,
+    });
+    renderer.on_end_tag(|tag| match tag {
+        Tag::Strong => RenderAction::Html("</b>".into()),
+        _ => RenderAction::Render,
+    });
+    renderer
+        .feed_stream(parse("*a* **b** ![x](y.png)", &Default::default()))
+        .unwrap();
+    assert_eq!(
+        renderer.into_string(),
+        "<p><em class=\"em\">a</em> <b>b</b> </p>\n"
+    );
 }
@@ -28,25 +28,27 @@
 //! // render to html
 //! let html = to_html(stream, &Default::default());
 //! ~~~
+#[cfg(feature = "arbitrary")]
+pub mod arbitrary;
+pub mod cmark;
+pub mod diagnostics;
+pub mod error;
 pub mod event;
+pub mod explain;
 pub mod html;
+pub mod link_graph;
+pub mod merge;
+pub mod ndjson;
 pub mod parser;
 pub mod pipeline;
+pub mod plain;
 pub mod processors;
+pub mod project;
+pub mod source_map;
+pub mod split;
+pub mod value;
 
 // internal only for now
-mod plain;
+mod hash;
 
-/// Gives access to [`serde_json`] value functionality.
-///
-/// The [`Value`](crate::value::Value) type is used to represent arbitrary data in a few instances.
-/// A notable example is emitted [`front_matter`](crate::event::DocumentStartEvent::front_matter)
-/// or other [`MetaData`](crate::event::MetaDataEvent).
-pub mod value {
-    pub use serde_json::value::*;
-
-    /// A re-export of the [`serde_json::json`] macro.
-    pub use serde_json::json as value;
-
-    pub use serde_json::{from_value, to_value};
-}
+pub use self::error::{Error, ErrorKind};
@@ -0,0 +1,182 @@
+//! Collects the links between multiple documents.
+//!
+//! A [`LinkGraph`] is built by adding the event streams of all documents of
+//! a project under their path.  Afterwards it can be queried for the links
+//! of a document or the documents linking to it.  The
+//! [`Backlinks`](crate::processors::Backlinks) processor can emit the
+//! backlinks of a document as meta data.
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Event, StartTagEvent, Tag};
+
+/// A link to a document or an anchor within it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LinkTarget {
+    /// The path of the document.
+    pub document: String,
+    /// The anchor within the document.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub anchor: Option<String>,
+}
+
+/// A link from another document.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Backlink {
+    /// The path of the linking document.
+    pub document: String,
+    /// The anchor in the current document the link points to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub anchor: Option<String>,
+}
+
+/// Resolves a relative path against the path of a document.
+fn resolve_path(document: &str, path: &str) -> String {
+    let mut segments: Vec<&str> = if path.starts_with('/') {
+        Vec::new()
+    } else {
+        let mut segments: Vec<&str> = document.split('/').collect();
+        segments.pop();
+        segments
+    };
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+/// Resolves a link target relative to a document.
+///
+/// Returns `None` for external links.
+pub fn resolve_link(document: &str, target: &str) -> Option<LinkTarget> {
+    if target.contains("://") || target.starts_with("mailto:") || target.starts_with("//") {
+        return None;
+    }
+    let (path, anchor) = match target.find('#') {
+        Some(idx) => (&target[..idx], Some(&target[idx + 1..])),
+        None => (target, None),
+    };
+    let path = path.split('?').next().unwrap_or("");
+    Some(LinkTarget {
+        document: if path.is_empty() {
+            document.to_string()
+        } else {
+            resolve_path(document, path)
+        },
+        anchor: anchor.filter(|x| !x.is_empty()).map(|x| x.to_string()),
+    })
+}
+
+/// The links between documents.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct LinkGraph {
+    links: BTreeMap<String, BTreeSet<LinkTarget>>,
+}
+
+impl LinkGraph {
+    /// Creates an empty link graph.
+    pub fn new() -> LinkGraph {
+        LinkGraph::default()
+    }
+
+    /// Records the links of a document.
+    ///
+    /// Links are resolved relative to the `path` of the document.  Adding a
+    /// document again replaces its links.
+    pub fn add_document<'data: 'event, 'event, I>(&mut self, path: &str, iter: I)
+    where
+        I: Iterator<Item = &'event AnnotatedEvent<'data>>,
+    {
+        let links = self.links.entry(path.to_string()).or_default();
+        links.clear();
+        for annotated_event in iter {
+            if let Event::StartTag(StartTagEvent {
+                tag: Tag::Link,
+                ref attrs,
+            }) = annotated_event.event
+            {
+                if let Some(link) = attrs
+                    .target
+                    .as_ref()
+                    .and_then(|target| resolve_link(path, target.as_str()))
+                {
+                    links.insert(link);
+                }
+            }
+        }
+    }
+
+    /// Returns the paths of all documents.
+    pub fn documents(&self) -> impl Iterator<Item = &str> {
+        self.links.keys().map(|x| x.as_str())
+    }
+
+    /// Returns the links of a document.
+    pub fn links(&self, path: &str) -> impl Iterator<Item = &LinkTarget> {
+        self.links.get(path).into_iter().flatten()
+    }
+
+    /// Returns the links from other documents to a document.
+    pub fn backlinks(&self, path: &str) -> Vec<Backlink> {
+        let mut rv = Vec::new();
+        for (document, links) in &self.links {
+            if document == path {
+                continue;
+            }
+            for link in links {
+                if link.document == path {
+                    rv.push(Backlink {
+                        document: document.clone(),
+                        anchor: link.anchor.clone(),
+                    });
+                }
+            }
+        }
+        rv
+    }
+}
+
+#[test]
+fn test_link_graph() {
+    use crate::pipeline::Pipeline;
+
+    let pipeline = Pipeline::default();
+    let mut graph = LinkGraph::new();
+    let index = pipeline
+        .process("[A](docs/a.md#top) [B](docs/b.md) [X](https://example.com/)")
+        .collect::<Vec<_>>();
+    graph.add_document("index.md", index.iter());
+    let a = pipeline
+        .process("[B](b.md?x=1) [Index](../index.md) [Self](#here)")
+        .collect::<Vec<_>>();
+    graph.add_document("docs/a.md", a.iter());
+
+    assert_eq!(graph.links("index.md").count(), 2);
+    assert_eq!(
+        graph.backlinks("docs/b.md"),
+        vec![
+            Backlink {
+                document: "docs/a.md".into(),
+                anchor: None,
+            },
+            Backlink {
+                document: "index.md".into(),
+                anchor: None,
+            },
+        ]
+    );
+    assert_eq!(
+        graph.backlinks("docs/a.md"),
+        vec![Backlink {
+            document: "index.md".into(),
+            anchor: Some("top".into()),
+        }]
+    );
+}
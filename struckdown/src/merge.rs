@@ -0,0 +1,152 @@
+//! Concatenates multiple event streams into one document.
+//!
+//! This is the inverse of [`split`](crate::split) and useful to build single
+//! page output (such as a printable manual) from multiple documents.
+//!
+//! ```
+//! use struckdown::merge::{merge, MergeOptions};
+//! use struckdown::pipeline::Pipeline;
+//!
+//! let pipeline = Pipeline::default();
+//! let events = merge(
+//!     vec![pipeline.process("# Manual"), pipeline.process("# Chapter")],
+//!     &MergeOptions::default(),
+//! );
+//! ```
+use serde::{Deserialize, Serialize};
+
+use crate::event::{
+    AnnotatedEvent, EndTagEvent, Event, FootnoteReferenceEvent, StartTagEvent, Str, Tag,
+};
+
+/// Configures how [`merge`] concatenates documents.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct MergeOptions {
+    /// The number of levels headings of subsequent documents are demoted by.
+    pub demote_headings: usize,
+    /// Prefixes the anchors of subsequent documents.
+    pub prefix_anchors: bool,
+    /// The prefix for anchors.  It's followed by the index of the document
+    /// and a dash (`doc1-` for the second document).
+    pub anchor_prefix: String,
+}
+
+impl Default for MergeOptions {
+    fn default() -> MergeOptions {
+        MergeOptions {
+            demote_headings: 1,
+            prefix_anchors: true,
+            anchor_prefix: "doc".into(),
+        }
+    }
+}
+
+fn prefixed<'data>(prefix: &str, value: &Str<'data>) -> Str<'data> {
+    format!("{}{}", prefix, value.as_str()).into()
+}
+
+/// Concatenates event streams into a single document.
+///
+/// The front matter of the first document is retained, the document start
+/// events of all other documents are dropped.  Headings of all subsequent
+/// documents are demoted by [`MergeOptions::demote_headings`] levels (down to
+/// level 6) so they nest below the first document's title.  To avoid
+/// collisions the ids of subsequent documents are prefixed and fragment
+/// links (`#anchor`) and footnote references within these documents are
+/// rewritten accordingly.
+pub fn merge<'data, D, I>(documents: D, options: &MergeOptions) -> Vec<AnnotatedEvent<'data>>
+where
+    D: IntoIterator<Item = I>,
+    I: Iterator<Item = AnnotatedEvent<'data>>,
+{
+    let mut rv = Vec::new();
+
+    for (idx, document) in documents.into_iter().enumerate() {
+        if idx == 0 {
+            rv.extend(document);
+            continue;
+        }
+
+        let prefix = if options.prefix_anchors {
+            Some(format!("{}{}-", options.anchor_prefix, idx))
+        } else {
+            None
+        };
+
+        for mut annotated_event in document {
+            match annotated_event.event {
+                Event::DocumentStart(..) => continue,
+                Event::StartTag(StartTagEvent {
+                    ref mut tag,
+                    ref mut attrs,
+                }) => {
+                    if let Some(level) = tag.header_level() {
+                        *tag = Tag::heading(level + options.demote_headings);
+                    }
+                    if let Some(ref prefix) = prefix {
+                        if let Some(ref mut id) = attrs.id {
+                            *id = prefixed(prefix, id);
+                        }
+                        if *tag == Tag::Link {
+                            if let Some(ref mut target) = attrs.target {
+                                if let Some(fragment) = target.as_str().strip_prefix('#') {
+                                    *target = format!("#{}{}", prefix, fragment).into();
+                                }
+                            }
+                        }
+                    }
+                }
+                Event::EndTag(EndTagEvent { ref mut tag }) => {
+                    if let Some(level) = tag.header_level() {
+                        *tag = Tag::heading(level + options.demote_headings);
+                    }
+                }
+                Event::FootnoteReference(FootnoteReferenceEvent { ref mut target }) => {
+                    if let Some(ref prefix) = prefix {
+                        *target = prefixed(prefix, target);
+                    }
+                }
+                _ => {}
+            }
+            rv.push(annotated_event);
+        }
+    }
+
+    rv
+}
+
+#[test]
+fn test_merge() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let pipeline = Pipeline::default();
+    let events = merge(
+        vec![
+            pipeline.process("---\ntitle: Manual\n---\n# Manual {#intro}\nSee [intro](#intro)"),
+            pipeline.process("---\ntitle: Ignored\n---\n# Chapter {#intro}\nSee [intro](#intro)"),
+        ],
+        &MergeOptions::default(),
+    );
+    let front_matter = match events[0].event {
+        Event::DocumentStart(ref start) => start.front_matter.clone(),
+        _ => None,
+    };
+    assert_eq!(
+        front_matter,
+        Some(crate::value::value!({"title": "Manual"}))
+    );
+    assert_eq!(
+        events
+            .iter()
+            .filter(|x| matches!(x.event, Event::DocumentStart(..)))
+            .count(),
+        1
+    );
+    assert_eq!(
+        to_html(events.into_iter(), &Default::default()),
+        "<h1 id=\"intro\">Manual</h1>\n<p>See <a href=\"#intro\">intro</a></p>\n\
+         <h2 id=\"doc1-intro\">Chapter</h2>\n<p>See <a href=\"#doc1-intro\">intro</a></p>\n"
+    );
+}
@@ -0,0 +1,115 @@
+//! Reads and writes event streams as newline delimited JSON.
+//!
+//! This is the format used by the `struck` command line tool and the
+//! [`External`](crate::processors::External) processor protocol: one
+//! [`AnnotatedEvent`] per line.  Events are read and written one at a time
+//! so documents never have to be held in memory as a whole.
+//!
+//! ```
+//! use struckdown::ndjson::{read_ndjson, write_ndjson};
+//! use struckdown::parser::parse;
+//!
+//! let mut buf = Vec::new();
+//! write_ndjson(&mut buf, parse("Hello *World*!", &Default::default())).unwrap();
+//! let events = read_ndjson(&buf[..]).collect::<Result<Vec<_>, _>>().unwrap();
+//! assert_eq!(events.len(), 8);
+//! ```
+use std::io::{BufRead, Write};
+
+use crate::error::{Error, ErrorKind};
+use crate::event::AnnotatedEvent;
+
+/// Iterator over the events of an NDJSON stream.
+///
+/// Returned by [`read_ndjson`].
+pub struct NdJsonReader<R> {
+    reader: R,
+    line: String,
+    lineno: usize,
+    done: bool,
+}
+
+impl<R: BufRead> Iterator for NdJsonReader<R> {
+    type Item = Result<AnnotatedEvent<'static>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            self.line.clear();
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => self.done = true,
+                Ok(_) => {
+                    self.lineno += 1;
+                    if self.line.trim().is_empty() {
+                        continue;
+                    }
+                    return Some(serde_json::from_str(&self.line).map_err(|err| {
+                        Error::new(
+                            ErrorKind::Serialization,
+                            format!("invalid event on line {}", self.lineno),
+                        )
+                        .with_source(err)
+                    }));
+                }
+                Err(err) => {
+                    // the reader cannot recover from I/O errors
+                    self.done = true;
+                    return Some(Err(err.into()));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Reads events from an NDJSON stream.
+///
+/// Events are read line by line as the iterator is advanced.  Blank lines
+/// are skipped, lines that are not valid events produce an error but do
+/// not end the stream.
+pub fn read_ndjson<R: BufRead>(reader: R) -> NdJsonReader<R> {
+    NdJsonReader {
+        reader,
+        line: String::new(),
+        lineno: 0,
+        done: false,
+    }
+}
+
+/// Writes events as NDJSON.
+///
+/// Events are written as they are produced by the iterator.  The writer is
+/// flushed at the end of the stream.
+pub fn write_ndjson<'data, W, I>(mut writer: W, iter: I) -> Result<(), Error>
+where
+    W: Write,
+    I: IntoIterator<Item = AnnotatedEvent<'data>>,
+{
+    for event in iter {
+        serde_json::to_writer(&mut writer, &event)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[test]
+fn test_read_ndjson() {
+    let input = "{\"type\":\"text\",\"text\":\"a\"}\n\nnope\n{\"type\":\"rule\"}\n";
+    let events: Vec<_> = read_ndjson(input.as_bytes()).collect();
+    assert_eq!(events.len(), 3);
+    assert_eq!(
+        events[0]
+            .as_ref()
+            .unwrap()
+            .event
+            .raw_text()
+            .unwrap()
+            .as_str(),
+        "a"
+    );
+    assert_eq!(
+        events[1].as_ref().unwrap_err().detail(),
+        Some("invalid event on line 3")
+    );
+    assert!(events[2].is_ok());
+}
@@ -7,30 +7,55 @@ use either::Either;
 use lazy_static::lazy_static;
 use pulldown_cmark as cm;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 use crate::event::{
     Alignment, AnnotatedEvent, Attrs, CheckboxEvent, CodeBlockEvent, DirectiveEvent,
-    DocumentStartEvent, EndTagEvent, Event, FootnoteReferenceEvent, ImageEvent, InlineCodeEvent,
-    InterpretedTextEvent, Location, RawHtmlEvent, StartTagEvent, Str, Tag, TextEvent,
+    DocumentStartEvent, EndTagEvent, Event, FootnoteReferenceEvent, FrontMatterFormat, ImageEvent,
+    InlineCodeEvent, InterpretedTextEvent, Location, RawHtmlEvent, StartTagEvent, Str, Tag,
+    TextEvent,
 };
 use crate::value::Value;
 
+#[cfg(feature = "comrak-parser")]
+mod comrak_backend;
+
 lazy_static! {
     static ref TEXT_ROLE_RE: Regex = Regex::new(r"\{([^\r\n\}]+)\}$").unwrap();
     static ref DIRECTIVE_RE: Regex = Regex::new(r"^\{([^\r\n\}]+)\}(?:\s+(.*?))?$").unwrap();
     static ref HEADING_ID_RE: Regex = Regex::new(r"\s+\{#([^\r\n\}]+)\}\s*$").unwrap();
     static ref FRONTMATTER_RE: Regex = Regex::new(r"(?sm)\A---\s*$(.*?)^---\s*$\r?\n?").unwrap();
+    static ref TOML_FRONTMATTER_RE: Regex =
+        Regex::new(r"(?sm)\A\+\+\+\s*$(.*?)^\+\+\+\s*$\r?\n?").unwrap();
+    static ref JSON_FRONTMATTER_RE: Regex = Regex::new(r"(?sm)\A(\{\s*$.*?^\})\s*$\r?\n?").unwrap();
     static ref FRONTMATTER_FULL_RE: Regex = Regex::new(r"(?sm)\A---\s*$(.*)").unwrap();
     static ref CODE_LANG_RE: Regex = Regex::new(r#"(\S+)\s+"#).unwrap();
-    static ref CODE_ARG_RE: Regex = Regex::new(r#"([^=\s]+)(?:="([^"]*)"|\S+)?"#).unwrap();
+    static ref CODE_ARG_RE: Regex = Regex::new(r#"([^=\s]+)(?:="([^"]*)"|=(\S+))?"#).unwrap();
+}
+
+/// Selects the markdown parser implementation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParserBackend {
+    /// Parses with `pulldown-cmark`.  This is the default.
+    #[default]
+    Pulldown,
+    /// Parses with `comrak`.
+    ///
+    /// In addition to the regular syntax this backend understands
+    /// description lists which are emitted as [`Tag::DefinitionList`].
+    /// Requires the `comrak-parser` feature.
+    #[cfg(feature = "comrak-parser")]
+    Comrak,
 }
 
 /// Configures the parser.
 ///
 /// By default all features are enabled.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ParserOptions {
-    /// Enables or disables front matter.
+    /// Enables or disables YAML, TOML and JSON front matter.
     pub enable_frontmatter: bool,
     /// Enables or disables directives.
     pub enable_directives: bool,
@@ -46,6 +71,8 @@ pub struct ParserOptions {
     pub enable_footnotes: bool,
     /// Enables or disables explicit anchors.
     pub enable_anchors: bool,
+    /// The parser implementation to use.
+    pub backend: ParserBackend,
 }
 
 impl Default for ParserOptions {
@@ -59,6 +86,7 @@ impl Default for ParserOptions {
             enable_tasklists: true,
             enable_footnotes: true,
             enable_anchors: true,
+            backend: ParserBackend::default(),
         }
     }
 }
@@ -83,6 +111,11 @@ impl Parser {
         }
     }
 
+    /// Returns the options of the parser.
+    pub fn options(&self) -> &ParserOptions {
+        &self.options
+    }
+
     /// Parses structured cmark into an event stream.
     ///
     /// The given structured cmark input will be parsed into a well formed event
@@ -95,7 +128,38 @@ impl Parser {
     /// does not exist as such in the source.  Both of those will not have a location
     /// attached.
     pub fn parse<'data>(&self, s: &'data str) -> impl Iterator<Item = AnnotatedEvent<'data>> {
-        parse_internal(s, self.options.clone())
+        let iter: Either<_, std::vec::IntoIter<AnnotatedEvent<'data>>> = match self.options.backend
+        {
+            ParserBackend::Pulldown => Either::Left(parse_internal(s, self.options.clone())),
+            #[cfg(feature = "comrak-parser")]
+            ParserBackend::Comrak => {
+                Either::Right(comrak_backend::parse(s, &self.options).into_iter())
+            }
+        };
+        iter
+    }
+
+    /// Parses structured cmark into a vector of events.
+    ///
+    /// The vector is cleared first.  This is useful to reuse the allocation
+    /// of the vector when many documents are parsed:
+    ///
+    /// ```
+    /// # use struckdown::parser::Parser;
+    /// # use struckdown::html::to_html_into;
+    /// let parser = Parser::default();
+    /// let sources = ["# One", "# Two"];
+    /// let mut events = Vec::new();
+    /// let mut html = String::new();
+    /// for source in &sources {
+    ///     parser.parse_into(source, &mut events);
+    ///     to_html_into(events.drain(..), &Default::default(), &mut html);
+    ///     println!("{}", html);
+    /// }
+    /// ```
+    pub fn parse_into<'data>(&self, s: &'data str, events: &mut Vec<AnnotatedEvent<'data>>) {
+        events.clear();
+        events.extend(self.parse(s));
     }
 }
 
@@ -106,7 +170,7 @@ impl Parser {
 /// an alt attribute.  Because of that we normalize this into text during parsing
 /// already so that stream processors don't need to deal with this oddity.  Same
 /// applies to reading code blocks.
-fn read_raw<'a, 'data, I: Iterator<Item = (cm::Event<'data>, Range<usize>)>>(
+pub(crate) fn read_raw<'a, 'data, I: Iterator<Item = (cm::Event<'data>, Range<usize>)>>(
     iter: &'a mut I,
 ) -> Str<'data> {
     let mut depth = 1;
@@ -193,18 +257,18 @@ enum Trailer<'data> {
 ///
 /// Currently all headlines are the only tags supporting trailers.
 fn tag_supports_trailers(tag: Tag) -> bool {
-    match tag {
-        Tag::Heading1 => true,
-        Tag::Heading2 => true,
-        Tag::Heading3 => true,
-        Tag::Heading4 => true,
-        Tag::Heading5 => true,
-        Tag::Heading6 => true,
-        _ => false,
-    }
+    matches!(
+        tag,
+        Tag::Heading1
+            | Tag::Heading2
+            | Tag::Heading3
+            | Tag::Heading4
+            | Tag::Heading5
+            | Tag::Heading6
+    )
 }
 
-fn split_code_block_args<'data>(
+pub(crate) fn split_code_block_args<'data>(
     info: Str<'data>,
 ) -> (Option<Str<'data>>, Option<BTreeMap<Str<'data>, Str<'data>>>) {
     if info.as_str().trim().is_empty() {
@@ -257,8 +321,9 @@ struct TableState {
 /// table bodies which are not there in regular cmark.
 fn preliminary_parse_with_trailers<'data>(
     s: &'data str,
+    body_offset: usize,
     options: ParserOptions,
-) -> impl Iterator<Item = (AnnotatedEvent, Option<Trailer<'data>>)> {
+) -> impl Iterator<Item = (AnnotatedEvent<'data>, Option<Trailer<'data>>)> {
     let mut opts = cm::Options::empty();
     if options.enable_tables {
         opts.insert(cm::Options::ENABLE_TABLES);
@@ -273,7 +338,7 @@ fn preliminary_parse_with_trailers<'data>(
         opts.insert(cm::Options::ENABLE_FOOTNOTES);
     }
 
-    let parser = cm::Parser::new_with_broken_link_callback(s, opts, None);
+    let parser = cm::Parser::new_with_broken_link_callback(&s[body_offset..], opts, None);
     let mut iter = parser.into_offset_iter().peekable();
     let mut tag_stack = vec![];
     let mut pending_role = None;
@@ -284,6 +349,8 @@ fn preliminary_parse_with_trailers<'data>(
         let mut trailer = None;
 
         if let Some((event, range)) = iter.next() {
+            // locations are relative to the full source including front matter
+            let range = range.start + body_offset..range.end + body_offset;
             // inefficient way to find the location
             let mut location = Some(Location {
                 offset: range.start,
@@ -461,6 +528,7 @@ fn preliminary_parse_with_trailers<'data>(
                                         } else {
                                             Some(Str::from_cm_str(title))
                                         },
+                                        attrs: Attrs::default(),
                                     },
                                     location,
                                 ),
@@ -604,28 +672,61 @@ where
     buffer
 }
 
-fn parse_internal(s: &str, options: ParserOptions) -> impl Iterator<Item = AnnotatedEvent> {
-    let mut front_matter = None;
-    let mut s = s;
-    let mut front_matter_location = None;
-
+/// Splits off the front matter of a document if enabled.
+///
+/// Returns the document start event and the remaining source.
+fn split_document_start<'data>(
+    s: &'data str,
+    options: &ParserOptions,
+) -> (AnnotatedEvent<'data>, &'data str) {
     if options.enable_frontmatter {
-        if let Some(m) = FRONTMATTER_RE.captures(s) {
-            if let Ok(parsed_front_matter) = serde_yaml::from_str(&m[1]) {
+        let formats: [(&Regex, FrontMatterFormat); 3] = [
+            (&FRONTMATTER_RE, FrontMatterFormat::Yaml),
+            (&TOML_FRONTMATTER_RE, FrontMatterFormat::Toml),
+            (&JSON_FRONTMATTER_RE, FrontMatterFormat::Json),
+        ];
+        for (regex, format) in formats.iter().copied() {
+            let m = match regex.captures(s) {
+                Some(m) => m,
+                None => continue,
+            };
+            if let Ok(front_matter) = format.parse(&m[1]) {
                 let g0 = m.get(0).unwrap();
-                front_matter = Some(parsed_front_matter);
-                front_matter_location = Some(Location {
-                    offset: 0,
-                    len: g0.end(),
-                    line: 1,
-                    column: 0,
-                });
-                s = &s[g0.end()..];
+                return (
+                    AnnotatedEvent::new(
+                        DocumentStartEvent {
+                            front_matter: Some(front_matter),
+                            front_matter_format: Some(format),
+                        },
+                        Some(Location {
+                            offset: 0,
+                            len: g0.end(),
+                            line: 1,
+                            column: 0,
+                        }),
+                    ),
+                    &s[g0.end()..],
+                );
             }
         }
     }
+    (
+        AnnotatedEvent::new(
+            DocumentStartEvent {
+                front_matter: None,
+                front_matter_format: None,
+            },
+            None,
+        ),
+        s,
+    )
+}
+
+fn parse_internal(s: &str, options: ParserOptions) -> impl Iterator<Item = AnnotatedEvent<'_>> {
+    let (document_start, body) = split_document_start(s, &options);
+    let body_offset = s.len() - body.len();
 
-    let mut iter = preliminary_parse_with_trailers(s, options).filter(|x| {
+    let mut iter = preliminary_parse_with_trailers(s, body_offset, options).filter(|x| {
         // skip empty text events.  These can happen because we're slicing around.
         // Ignore them for a cleaner stream.
         if let Event::Text(ref text_event) = x.0.event {
@@ -636,11 +737,7 @@ fn parse_internal(s: &str, options: ParserOptions) -> impl Iterator<Item = Annot
         true
     });
 
-    once(AnnotatedEvent::new(
-        DocumentStartEvent { front_matter },
-        front_matter_location,
-    ))
-    .chain(
+    once(document_start).chain(
         iter::from_fn(move || {
             iter.next().map(|(annotated_event, _)| {
                 if let Event::StartTag(StartTagEvent { tag, .. }) = annotated_event.event {
@@ -683,9 +780,9 @@ fn parse_internal(s: &str, options: ParserOptions) -> impl Iterator<Item = Annot
 }
 
 /// Parses structured cmark into an event stream.
-pub fn parse<'data, 'options>(
+pub fn parse<'data>(
     s: &'data str,
-    options: &'options ParserOptions,
+    options: &ParserOptions,
 ) -> impl Iterator<Item = AnnotatedEvent<'data>> {
     Parser::new(options).parse(s)
 }
@@ -0,0 +1,462 @@
+//! Parses documents with comrak.
+//!
+//! comrak produces a syntax tree rather than an event stream.  The tree is
+//! walked once and converted into the same events the pulldown-cmark based
+//! parser produces so that processors and renderers can stay unaware of the
+//! backend in use.
+use comrak::nodes::{AstNode, ListType, NodeValue, Sourcepos, TableAlignment};
+use comrak::{parse_document, Arena, Options};
+
+use super::{
+    split_and_parse_front_matter, split_code_block_args, split_document_start, ParserOptions,
+    DIRECTIVE_RE, HEADING_ID_RE, TEXT_ROLE_RE,
+};
+use crate::event::{
+    Alignment, AnnotatedEvent, Attrs, CheckboxEvent, CodeBlockEvent, DirectiveEvent, EndTagEvent,
+    Event, FootnoteReferenceEvent, ImageEvent, InlineCodeEvent, InterpretedTextEvent, Location,
+    RawHtmlEvent, StartTagEvent, Str, Tag, TextEvent,
+};
+
+struct Converter<'data, 'options> {
+    source: &'data str,
+    line_offsets: Vec<usize>,
+    first_line: usize,
+    options: &'options ParserOptions,
+    events: Vec<AnnotatedEvent<'data>>,
+    heading: Option<usize>,
+    pending_role: Option<(Str<'data>, usize)>,
+}
+
+impl<'data, 'options> Converter<'data, 'options> {
+    fn location(&self, pos: Sourcepos) -> Option<Location> {
+        // comrak only sees the body, lines are shifted past the front matter
+        let line = self.first_line + pos.start.line.checked_sub(1)?;
+        let line_start = *self.line_offsets.get(line)?;
+        let offset = (line_start + pos.start.column.checked_sub(1)?).min(self.source.len());
+        let end = match pos
+            .end
+            .line
+            .checked_sub(1)
+            .and_then(|end_line| self.line_offsets.get(self.first_line + end_line))
+        {
+            Some(&end_line_start) => (end_line_start + pos.end.column).min(self.source.len()),
+            None => offset,
+        };
+        Some(Location {
+            offset,
+            len: end.saturating_sub(offset),
+            line: line + 1,
+            column: pos.start.column - 1,
+        })
+    }
+
+    fn push<E: Into<Event<'data>>>(&mut self, event: E, location: Option<Location>) {
+        self.events.push(AnnotatedEvent::new(event, location));
+    }
+
+    fn walk_tag<'a>(
+        &mut self,
+        node: &'a AstNode<'a>,
+        tag: Tag,
+        attrs: Attrs<'data>,
+        location: Option<Location>,
+    ) {
+        self.push(StartTagEvent { tag, attrs }, location);
+        self.walk_children(node);
+        self.push(Event::EndTag(EndTagEvent { tag }), None);
+    }
+
+    fn walk_children<'a>(&mut self, node: &'a AstNode<'a>) {
+        for child in node.children() {
+            self.walk(child);
+        }
+    }
+
+    fn walk<'a>(&mut self, node: &'a AstNode<'a>) {
+        let ast = node.data.borrow();
+        let mut location = self.location(ast.sourcepos);
+        let mut attrs = Attrs::default();
+
+        match ast.value {
+            NodeValue::Paragraph => {
+                // like pulldown-cmark we do not emit paragraphs in tight lists
+                let in_tight_list =
+                    node.parent()
+                        .and_then(|item| item.parent())
+                        .is_some_and(|list| match list.data.borrow().value {
+                            NodeValue::List(ref list) => list.tight,
+                            _ => false,
+                        });
+                if in_tight_list {
+                    self.walk_children(node);
+                } else {
+                    self.walk_tag(node, Tag::Paragraph, attrs, location);
+                }
+            }
+            NodeValue::Heading(ref heading) => {
+                let tag = match heading.level {
+                    1 => Tag::Heading1,
+                    2 => Tag::Heading2,
+                    3 => Tag::Heading3,
+                    4 => Tag::Heading4,
+                    5 => Tag::Heading5,
+                    _ => Tag::Heading6,
+                };
+                self.heading = Some(self.events.len());
+                self.walk_tag(node, tag, attrs, location);
+                self.heading = None;
+            }
+            NodeValue::BlockQuote | NodeValue::MultilineBlockQuote(..) | NodeValue::Alert(..) => {
+                self.walk_tag(node, Tag::BlockQuote, attrs, location);
+            }
+            NodeValue::List(ref list) => {
+                let tag = match list.list_type {
+                    ListType::Bullet => Tag::UnorderedList,
+                    ListType::Ordered => {
+                        attrs.start = Some(list.start as u32);
+                        Tag::OrderedList
+                    }
+                };
+                self.walk_tag(node, tag, attrs, location);
+            }
+            NodeValue::Item(..) => self.walk_tag(node, Tag::ListItem, attrs, location),
+            NodeValue::TaskItem(checked) => {
+                self.push(
+                    StartTagEvent {
+                        tag: Tag::ListItem,
+                        attrs,
+                    },
+                    location,
+                );
+                self.push(
+                    Event::Checkbox(CheckboxEvent {
+                        checked: checked.is_some(),
+                    }),
+                    None,
+                );
+                self.walk_children(node);
+                self.push(Event::EndTag(EndTagEvent { tag: Tag::ListItem }), None);
+            }
+            NodeValue::DescriptionList => self.walk_tag(node, Tag::DefinitionList, attrs, location),
+            NodeValue::DescriptionTerm => self.walk_tag(node, Tag::DefinitionTerm, attrs, location),
+            NodeValue::DescriptionDetails => {
+                self.walk_tag(node, Tag::DefinitionDetails, attrs, location)
+            }
+            NodeValue::CodeBlock(ref block) => {
+                let info = Str::from(block.info.clone());
+                if block.fenced && self.options.enable_directives {
+                    if let Some(m) = DIRECTIVE_RE.captures(info.as_str()) {
+                        let g1 = m.get(1).unwrap();
+                        let argument = m.get(2).map(|g2| info.slice(g2.start(), g2.end()));
+                        let (front_matter, body) =
+                            split_and_parse_front_matter(Str::from(block.literal.clone()));
+                        self.push(
+                            DirectiveEvent {
+                                name: info.slice(g1.start(), g1.end()),
+                                argument: argument.filter(|arg| !arg.as_str().is_empty()),
+                                front_matter,
+                                body,
+                            },
+                            location,
+                        );
+                        return;
+                    }
+                }
+                let (language, args) = if block.fenced {
+                    split_code_block_args(info)
+                } else {
+                    (None, None)
+                };
+                self.push(
+                    CodeBlockEvent {
+                        language,
+                        args,
+                        code: block.literal.clone().into(),
+                    },
+                    location,
+                );
+            }
+            NodeValue::HtmlBlock(ref block) => self.push(
+                RawHtmlEvent {
+                    html: block.literal.clone().into(),
+                },
+                location,
+            ),
+            NodeValue::HtmlInline(ref html) | NodeValue::Raw(ref html) => self.push(
+                RawHtmlEvent {
+                    html: html.clone().into(),
+                },
+                location,
+            ),
+            NodeValue::ThematicBreak => self.push(Event::Rule, location),
+            NodeValue::FootnoteDefinition(ref definition) => {
+                attrs.id = Some(definition.name.clone().into());
+                self.walk_tag(node, Tag::FootnoteDefinition, attrs, location);
+            }
+            NodeValue::FootnoteReference(ref reference) => self.push(
+                FootnoteReferenceEvent {
+                    target: reference.name.clone().into(),
+                },
+                location,
+            ),
+            NodeValue::Table(ref table) => {
+                let alignments: Vec<_> = table
+                    .alignments
+                    .iter()
+                    .map(|align| match align {
+                        TableAlignment::None => Alignment::None,
+                        TableAlignment::Left => Alignment::Left,
+                        TableAlignment::Center => Alignment::Center,
+                        TableAlignment::Right => Alignment::Right,
+                    })
+                    .collect();
+                self.push(
+                    StartTagEvent {
+                        tag: Tag::Table,
+                        attrs,
+                    },
+                    location,
+                );
+                let mut in_body = false;
+                for row in node.children() {
+                    let is_header = matches!(row.data.borrow().value, NodeValue::TableRow(true));
+                    // header rows are not wrapped in a table row, same as the
+                    // table header and body tags they do not carry a location.
+                    let (row_tag, cell_tag, row_location) = if is_header {
+                        (Tag::TableHeader, Tag::TableHead, None)
+                    } else {
+                        if !in_body {
+                            self.push(
+                                StartTagEvent {
+                                    tag: Tag::TableBody,
+                                    attrs: Attrs::default(),
+                                },
+                                None,
+                            );
+                            in_body = true;
+                        }
+                        (
+                            Tag::TableRow,
+                            Tag::TableCell,
+                            self.location(row.data.borrow().sourcepos),
+                        )
+                    };
+                    self.push(
+                        StartTagEvent {
+                            tag: row_tag,
+                            attrs: Attrs::default(),
+                        },
+                        row_location,
+                    );
+                    for (idx, cell) in row.children().enumerate() {
+                        let cell_location = self.location(cell.data.borrow().sourcepos);
+                        let cell_attrs = Attrs {
+                            alignment: alignments.get(idx).copied().unwrap_or(Alignment::None),
+                            ..Attrs::default()
+                        };
+                        self.walk_tag(cell, cell_tag, cell_attrs, cell_location);
+                    }
+                    self.push(Event::EndTag(EndTagEvent { tag: row_tag }), None);
+                }
+                // the pulldown parser always opens a body after the header
+                if !in_body {
+                    self.push(
+                        StartTagEvent {
+                            tag: Tag::TableBody,
+                            attrs: Attrs::default(),
+                        },
+                        None,
+                    );
+                }
+                self.push(
+                    Event::EndTag(EndTagEvent {
+                        tag: Tag::TableBody,
+                    }),
+                    None,
+                );
+                self.push(Event::EndTag(EndTagEvent { tag: Tag::Table }), None);
+            }
+            NodeValue::Text(ref text) => {
+                let mut text = Str::from(text.clone());
+
+                // handle roles
+                if self.options.enable_roles {
+                    let before_code = node.next_sibling().is_some_and(|next| {
+                        matches!(next.data.borrow().value, NodeValue::Code(..))
+                    });
+                    if before_code {
+                        if let Some(m) = TEXT_ROLE_RE.captures(text.as_str()) {
+                            let g0 = m.get(0).unwrap();
+                            let g1 = m.get(1).unwrap();
+                            let column_adjustment = g0.end() - g0.start();
+                            if let Some(ref mut location) = location {
+                                location.len -= column_adjustment.min(location.len);
+                            }
+                            self.pending_role =
+                                Some((text.slice(g1.start(), g1.end()), column_adjustment));
+                            text = text.slice(0, g0.start());
+                        }
+                    }
+                }
+
+                // handle explicitly defined IDs for headlines
+                if self.options.enable_anchors && node.next_sibling().is_none() {
+                    if let Some(heading) = self.heading {
+                        if let Some(m) = HEADING_ID_RE.captures(text.as_str()) {
+                            let g0 = m.get(0).unwrap();
+                            let g1 = m.get(1).unwrap();
+                            if let Some(ref mut location) = location {
+                                location.len -= (g0.end() - g0.start()).min(location.len);
+                            }
+                            if let Event::StartTag(StartTagEvent { ref mut attrs, .. }) =
+                                self.events[heading].event
+                            {
+                                attrs.id = Some(text.slice(g1.start(), g1.end()));
+                            }
+                            text = text.slice(0, g0.start());
+                        }
+                    }
+                }
+
+                if !text.as_str().is_empty() {
+                    self.push(TextEvent { text }, location);
+                }
+            }
+            NodeValue::Code(ref code) => {
+                let code = Str::from(code.literal.clone());
+                if let Some((role, column_adjustment)) = self.pending_role.take() {
+                    if let Some(ref mut location) = location {
+                        location.column -= column_adjustment.min(location.column);
+                        location.offset -= column_adjustment.min(location.offset);
+                        location.len += column_adjustment;
+                    }
+                    self.push(InterpretedTextEvent { text: code, role }, location);
+                } else {
+                    self.push(InlineCodeEvent { code }, location);
+                }
+            }
+            NodeValue::SoftBreak => self.push(Event::SoftBreak, location),
+            NodeValue::LineBreak => self.push(Event::HardBreak, location),
+            NodeValue::Emph => {
+                let tag = match location {
+                    Some(location) if self.source[location.offset..].starts_with('_') => {
+                        Tag::EmphasisAlt
+                    }
+                    _ => Tag::Emphasis,
+                };
+                self.walk_tag(node, tag, attrs, location);
+            }
+            NodeValue::Strong => self.walk_tag(node, Tag::Strong, attrs, location),
+            NodeValue::Strikethrough => self.walk_tag(node, Tag::Strikethrough, attrs, location),
+            NodeValue::Link(ref link) => {
+                attrs.target = Some(link.url.clone().into());
+                if !link.title.is_empty() {
+                    attrs.title = Some(link.title.clone().into());
+                }
+                self.walk_tag(node, Tag::Link, attrs, location);
+            }
+            NodeValue::Image(ref link) => {
+                // same as with the pulldown parser the alt text is flattened
+                let mut alt = String::new();
+                read_raw(node, &mut alt);
+                self.push(
+                    ImageEvent {
+                        target: link.url.clone().into(),
+                        alt: if alt.is_empty() {
+                            None
+                        } else {
+                            Some(alt.into())
+                        },
+                        title: if link.title.is_empty() {
+                            None
+                        } else {
+                            Some(link.title.clone().into())
+                        },
+                        attrs: Attrs::default(),
+                    },
+                    location,
+                );
+            }
+            // everything else has no equivalent and is reduced to its contents
+            _ => self.walk_children(node),
+        }
+    }
+}
+
+/// Collects the text content of a node.
+fn read_raw<'a>(node: &'a AstNode<'a>, buf: &mut String) {
+    for child in node.children() {
+        match child.data.borrow().value {
+            NodeValue::Text(ref text) => buf.push_str(text),
+            NodeValue::Code(ref code) => buf.push_str(&code.literal),
+            NodeValue::SoftBreak | NodeValue::LineBreak => buf.push('\n'),
+            _ => read_raw(child, buf),
+        }
+    }
+}
+
+/// Parses a document with comrak into events.
+pub fn parse<'data>(s: &'data str, options: &ParserOptions) -> Vec<AnnotatedEvent<'data>> {
+    let (document_start, body) = split_document_start(s, options);
+    let body_offset = s.len() - body.len();
+
+    let mut comrak_options = Options::default();
+    comrak_options.extension.table = options.enable_tables;
+    comrak_options.extension.strikethrough = options.enable_strikethrough;
+    comrak_options.extension.tasklist = options.enable_tasklists;
+    comrak_options.extension.footnotes = options.enable_footnotes;
+    comrak_options.extension.description_lists = true;
+
+    let arena = Arena::new();
+    let root = parse_document(&arena, body, &comrak_options);
+
+    let mut converter = Converter {
+        source: s,
+        line_offsets: std::iter::once(0)
+            .chain(s.match_indices('\n').map(|(idx, _)| idx + 1))
+            .collect(),
+        first_line: s[..body_offset].matches('\n').count(),
+        options,
+        events: vec![document_start],
+        heading: None,
+        pending_role: None,
+    };
+    converter.walk_children(root);
+    converter.events
+}
+
+#[test]
+fn test_matches_pulldown() {
+    let source = "# Hello {#hi}\n\nThis is *some* _text_ with {role}`code`.\n\n\
+                  - [x] a\n- b\n\n| a | b |\n|:--|--:|\n| 1 | 2 |\n\n\
+                  ```rust lines=1\nfn main() {}\n```\n\n![alt *text*](x.png)\n";
+    let strip = |events: Vec<AnnotatedEvent<'_>>| {
+        let events: Vec<_> = events.into_iter().map(|e| e.event).collect();
+        crate::value::to_value(events).unwrap()
+    };
+    let pulldown = strip(super::parse(source, &Default::default()).collect());
+    let comrak = strip(parse(source, &Default::default()));
+    assert_eq!(comrak, pulldown);
+}
+
+#[test]
+fn test_description_lists() {
+    let events = parse("Term\n\n: Details\n", &Default::default());
+    let tags: Vec<_> = events
+        .iter()
+        .filter_map(|e| match e.event {
+            Event::StartTag(StartTagEvent { tag, .. }) => Some(tag),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        tags,
+        vec![
+            Tag::DefinitionList,
+            Tag::DefinitionTerm,
+            Tag::Paragraph,
+            Tag::DefinitionDetails,
+            Tag::Paragraph
+        ]
+    );
+}
@@ -1,15 +1,501 @@
 //! Abstracts event stream modifications.
+use std::borrow::Cow;
+use std::cell::RefCell;
 use std::fmt;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::event::{AnnotatedEvent, Event, Str};
+use serde::{Deserialize, Serialize};
+
+use crate::diagnostics::{Diagnostics, DiagnosticsCollector, DiagnosticsIter};
+use crate::error::{Error, ErrorKind};
+use crate::event::{
+    AnnotatedEvent, EndTagEvent, ErrorEvent, Event, MetaDataEvent, Severity, StartTagEvent, Str,
+    Tag,
+};
+use crate::explain::{trace_stage, Trace};
+use crate::hash::hash_json;
+use crate::html::{to_html, HtmlRenderer, HtmlRendererOptions};
 use crate::parser::{Parser, ParserOptions};
-use crate::processors::Processor;
-use crate::value::Value;
+use crate::processors::{Chain, ConfiguredProcessor, Processor, StaticProcessor};
+use crate::value::{to_value, Value};
+
+type EventIter<'data> = Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data>;
+
+/// A named pipeline stage.
+type Stage<'data> = (
+    Cow<'data, str>,
+    Box<dyn FnOnce(EventIter<'data>) -> EventIter<'data> + 'data>,
+);
+
+/// Timing information of a single pipeline stage.
+#[derive(Debug, Clone, Serialize)]
+pub struct StageTiming {
+    /// The name of the stage (`input` or the stage name of the processor).
+    pub name: String,
+    /// The number of events the stage produced.
+    pub events: usize,
+    /// The wall time spent in the stage itself.
+    pub duration: Duration,
+}
+
+/// Timing information of all stages of a pipeline run.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimingReport {
+    /// The stages in the order they are applied.
+    pub stages: Vec<StageTiming>,
+}
+
+/// A callback invoked with the timing report after a pipeline run.
+#[derive(Clone)]
+pub struct TimingFn(Arc<dyn Fn(&TimingReport) + Send + Sync>);
+
+impl TimingFn {
+    /// Wraps a function for use as timing callback.
+    pub fn new<F: Fn(&TimingReport) + Send + Sync + 'static>(f: F) -> TimingFn {
+        TimingFn(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for TimingFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TimingFn").finish()
+    }
+}
+
+/// Configures how timings are reported.
+#[derive(Debug, Clone, Default)]
+struct TimingOptions {
+    callback: Option<TimingFn>,
+    metadata_key: Option<String>,
+}
+
+impl TimingOptions {
+    fn is_enabled(&self) -> bool {
+        self.callback.is_some() || self.metadata_key.is_some()
+    }
+}
+
+#[derive(Default)]
+struct StageStats {
+    events: usize,
+    // includes the time spent in all previous stages
+    total: Duration,
+}
+
+/// Measures the time spent in the wrapped stage.
+struct TimedIter<'data> {
+    iter: Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data>,
+    stage: usize,
+    stats: Rc<RefCell<Vec<StageStats>>>,
+}
+
+impl<'data> Iterator for TimedIter<'data> {
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = Instant::now();
+        let rv = self.iter.next();
+        let elapsed = start.elapsed();
+        let mut stats = self.stats.borrow_mut();
+        stats[self.stage].total += elapsed;
+        if rv.is_some() {
+            stats[self.stage].events += 1;
+        }
+        rv
+    }
+}
+
+/// Reports the timings once the stream is exhausted.
+struct ReportIter<'data> {
+    iter: TimedIter<'data>,
+    names: Vec<Cow<'data, str>>,
+    options: TimingOptions,
+    done: bool,
+}
+
+impl<'data> ReportIter<'data> {
+    fn report(&self) -> TimingReport {
+        let stats = self.iter.stats.borrow();
+        let mut previous = Duration::default();
+        let mut stages = Vec::with_capacity(stats.len());
+        for (name, stage) in self.names.iter().zip(stats.iter()) {
+            stages.push(StageTiming {
+                name: name.to_string(),
+                events: stage.events,
+                duration: stage.total.checked_sub(previous).unwrap_or_default(),
+            });
+            previous = stage.total;
+        }
+        TimingReport { stages }
+    }
+}
+
+impl<'data> Iterator for ReportIter<'data> {
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if let Some(annotated_event) = self.iter.next() {
+            return Some(annotated_event);
+        }
+        self.done = true;
+        let report = self.report();
+        if let Some(ref callback) = self.options.callback {
+            (callback.0)(&report);
+        }
+        let key = self.options.metadata_key.clone()?;
+        Some(
+            MetaDataEvent {
+                key: key.into(),
+                value: to_value(&report).unwrap(),
+            }
+            .into(),
+        )
+    }
+}
+
+/// Limits the resources a pipeline run may consume.
+///
+/// When a limit is exceeded the stream is truncated: an error event is
+/// emitted, all open tags are closed and the stream ends.  All limits are
+/// disabled by default.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResourceLimits {
+    /// The maximum number of events any stage may produce.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_events: Option<usize>,
+    /// The maximum size of the final stream in bytes as serialized to JSON.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_bytes: Option<usize>,
+    /// The maximum time in milliseconds a single stage may spend.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_stage_time_ms: Option<u64>,
+}
+
+impl ResourceLimits {
+    fn is_enabled(&self) -> bool {
+        *self != ResourceLimits::default()
+    }
+}
+
+/// Counts the bytes written to it.
+struct ByteCounter(usize);
+
+impl io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Enforces the resource limits on the output of a stage.
+struct LimitIter<'data> {
+    iter: EventIter<'data>,
+    name: Cow<'data, str>,
+    stage: usize,
+    limits: ResourceLimits,
+    // the total time spent in every stage including previous stages
+    totals: Rc<RefCell<Vec<Duration>>>,
+    count_bytes: bool,
+    events: usize,
+    bytes: usize,
+    open_tags: Vec<Tag>,
+    trailer: Option<std::vec::IntoIter<AnnotatedEvent<'data>>>,
+}
+
+impl<'data> LimitIter<'data> {
+    fn new(
+        iter: EventIter<'data>,
+        name: Cow<'data, str>,
+        stage: usize,
+        limits: &ResourceLimits,
+        totals: &Rc<RefCell<Vec<Duration>>>,
+        count_bytes: bool,
+    ) -> LimitIter<'data> {
+        LimitIter {
+            iter,
+            name,
+            stage,
+            limits: limits.clone(),
+            totals: totals.clone(),
+            count_bytes,
+            events: 0,
+            bytes: 0,
+            open_tags: Vec::new(),
+            trailer: None,
+        }
+    }
+
+    fn check(&mut self, annotated_event: &AnnotatedEvent<'data>) -> Option<String> {
+        self.events += 1;
+        if let Some(max) = self.limits.max_events {
+            if self.events > max {
+                return Some(format!(
+                    "stage '{}' produced more than {} events",
+                    self.name, max
+                ));
+            }
+        }
+        if let (true, Some(max)) = (self.count_bytes, self.limits.max_output_bytes) {
+            let mut counter = ByteCounter(0);
+            serde_json::to_writer(&mut counter, annotated_event).ok();
+            self.bytes += counter.0;
+            if self.bytes > max {
+                return Some(format!("output exceeded {} bytes", max));
+            }
+        }
+        if let Some(max) = self.limits.max_stage_time_ms {
+            let totals = self.totals.borrow();
+            let spent = totals[self.stage]
+                .checked_sub(
+                    self.stage
+                        .checked_sub(1)
+                        .map_or(Duration::default(), |x| totals[x]),
+                )
+                .unwrap_or_default();
+            if spent > Duration::from_millis(max) {
+                return Some(format!("stage '{}' took longer than {}ms", self.name, max));
+            }
+        }
+        None
+    }
+
+    /// Ends the stream with an error event and closes all open tags.
+    fn truncate(&mut self, reason: String, location: Option<crate::event::Location>) {
+        let mut trailer = vec![AnnotatedEvent::new(
+            ErrorEvent {
+                title: "Resource limit exceeded".into(),
+                description: Some(reason.into()),
+                severity: Severity::Error,
+            },
+            location,
+        )];
+        trailer.extend(
+            self.open_tags
+                .drain(..)
+                .rev()
+                .map(|tag| AnnotatedEvent::new(tag.end_tag(), None)),
+        );
+        self.trailer = Some(trailer.into_iter());
+    }
+}
+
+impl<'data> Iterator for LimitIter<'data> {
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(ref mut trailer) = self.trailer {
+            return trailer.next();
+        }
+        let start = Instant::now();
+        let rv = self.iter.next();
+        self.totals.borrow_mut()[self.stage] += start.elapsed();
+        let annotated_event = rv?;
+        if let Some(reason) = self.check(&annotated_event) {
+            self.truncate(reason, annotated_event.location);
+            return self.next();
+        }
+        match annotated_event.event {
+            Event::StartTag(StartTagEvent { tag, .. }) => self.open_tags.push(tag),
+            Event::EndTag(EndTagEvent { tag }) => {
+                if let Some(idx) = self.open_tags.iter().rposition(|x| *x == tag) {
+                    self.open_tags.truncate(idx);
+                }
+            }
+            _ => {}
+        }
+        Some(annotated_event)
+    }
+}
+
+/// Information about a document used to decide if a processor is applied.
+///
+/// See [`Pipeline::add_processor_if`].
+pub struct DocumentInfo<'a, 'data> {
+    /// The path of the document if known.
+    pub path: Option<&'a Path>,
+    /// The front matter of the document.
+    pub front_matter: Option<&'a Value>,
+    /// The events of the document as they are passed to the processor.
+    pub events: &'a [AnnotatedEvent<'data>],
+}
+
+impl<'a, 'data> DocumentInfo<'a, 'data> {
+    /// Checks if a front matter value is set and not `false`, `null`, `0`
+    /// or empty.
+    pub fn flag(&self, key: &str) -> bool {
+        match self.front_matter.and_then(|x| x.get(key)) {
+            None | Some(Value::Null) | Some(Value::Bool(false)) => false,
+            Some(Value::Number(num)) => num.as_f64() != Some(0.0),
+            Some(Value::String(s)) => !s.is_empty(),
+            Some(Value::Array(arr)) => !arr.is_empty(),
+            Some(Value::Object(obj)) => !obj.is_empty(),
+            Some(Value::Bool(true)) => true,
+        }
+    }
+
+    /// Checks if any event of the document matches a predicate.
+    pub fn has_event<F: FnMut(&Event<'data>) -> bool>(&self, mut f: F) -> bool {
+        self.events.iter().any(|x| f(&x.event))
+    }
+
+    /// Checks if the document contains a directive with the given name.
+    pub fn has_directive(&self, name: &str) -> bool {
+        self.has_event(|event| matches!(event, Event::Directive(d) if d.name.as_str() == name))
+    }
+
+    /// Checks if the document contains interpreted text with the given role.
+    pub fn has_role(&self, role: &str) -> bool {
+        self.has_event(
+            |event| matches!(event, Event::InterpretedText(t) if t.role.as_str() == role),
+        )
+    }
+}
+
+/// A predicate deciding if a processor is applied to a document.
+#[derive(Clone)]
+pub struct ActivationFn(Arc<dyn Fn(&DocumentInfo<'_, '_>) -> bool + Send + Sync>);
+
+impl ActivationFn {
+    /// Wraps a function for use as activation predicate.
+    pub fn new<F: Fn(&DocumentInfo<'_, '_>) -> bool + Send + Sync + 'static>(f: F) -> ActivationFn {
+        ActivationFn(Arc::new(f))
+    }
+
+    /// Buffers the stream and applies a processor if the predicate matches.
+    fn apply<'data, F>(&self, path: Option<&Path>, iter: EventIter<'data>, f: F) -> EventIter<'data>
+    where
+        F: FnOnce(EventIter<'data>) -> EventIter<'data>,
+    {
+        let events = iter.collect::<Vec<_>>();
+        let active = (self.0)(&DocumentInfo {
+            path,
+            front_matter: match events.first().map(|x| &x.event) {
+                Some(Event::DocumentStart(start)) => start.front_matter.as_ref(),
+                _ => None,
+            },
+            events: &events,
+        });
+        let iter = Box::new(events.into_iter());
+        if active {
+            f(iter)
+        } else {
+            iter
+        }
+    }
+}
+
+impl fmt::Debug for ActivationFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ActivationFn").finish()
+    }
+}
+
+/// Converts a type name such as `HtmlMinify` into `html_minify`.
+pub(crate) fn snake_case(name: &str) -> String {
+    let mut rv = String::new();
+    for c in name.chars() {
+        if c.is_uppercase() {
+            if !rv.is_empty() {
+                rv.push('_');
+            }
+            rv.extend(c.to_lowercase());
+        } else {
+            rv.push(c);
+        }
+    }
+    rv
+}
+
+/// A processor added to a pipeline.
+struct PipelineEntry {
+    name: Option<String>,
+    processor: Box<dyn Processor>,
+    condition: Option<ActivationFn>,
+    // the configuration of registered processors created from a config
+    config: Option<ConfiguredProcessor>,
+}
+
+impl PipelineEntry {
+    fn new(processor: Box<dyn Processor>) -> PipelineEntry {
+        PipelineEntry {
+            name: None,
+            processor,
+            condition: None,
+            config: None,
+        }
+    }
+
+    /// Returns the name of the stage.
+    fn name(&self) -> Cow<'_, str> {
+        match self.name {
+            Some(ref name) => Cow::Borrowed(name),
+            None => Cow::Owned(snake_case(self.processor.name())),
+        }
+    }
+}
+
+/// A processor in a [`PipelineConfig`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessorConfig {
+    /// The name of the stage.  Defaults to the name of the processor.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stage: Option<String>,
+    /// The processor and its options.
+    ///
+    /// Besides the builtin processors this can refer to processors
+    /// registered with [`register_processor`](crate::processors::register_processor).
+    #[serde(flatten)]
+    pub processor: ConfiguredProcessor,
+}
+
+/// The complete configuration of a pipeline.
+///
+/// This can be stored in any format supported by serde.  Processors are
+/// given with their name in the `processor` key and their options:
+///
+/// ```yaml
+/// parser:
+///   enable_roles: false
+/// processors:
+///   - processor: auto_anchors
+///   - processor: syntect
+///     stage: highlight
+///     theme: InspiredGitHub
+/// renderer:
+///   initial_headline_level: 2
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PipelineConfig {
+    /// The options of the parser.
+    pub parser: ParserOptions,
+    /// The processors in order.
+    pub processors: Vec<ProcessorConfig>,
+    /// The options of the HTML renderer.
+    pub renderer: HtmlRendererOptions,
+    /// The resource limits of pipeline runs.
+    pub limits: ResourceLimits,
+}
 
 /// Helper for applying preconfigured processors to an event stream.
 pub struct Pipeline {
     parser: Parser,
-    processors: Vec<Box<dyn Processor>>,
+    processors: Vec<PipelineEntry>,
+    timing: TimingOptions,
+    limits: ResourceLimits,
+    renderer_options: HtmlRendererOptions,
 }
 
 impl Default for Pipeline {
@@ -22,17 +508,186 @@ impl fmt::Debug for Pipeline {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Pipeline")
             .field("parser", &self.parser)
+            .field("renderer_options", &self.renderer_options)
             .finish()
     }
 }
 
+/// Builds a [`Pipeline`] validating its processors.
+///
+/// Unlike adding processors to a pipeline directly, problems with the
+/// configuration of processors (such as a missing theme or command) are
+/// reported by [`build`](Self::build) instead of surfacing when the pipeline
+/// is applied.
+///
+/// ```
+/// # use struckdown::pipeline::Pipeline;
+/// # use struckdown::processors::{AutoAnchors, HtmlMinify};
+/// let pipeline = Pipeline::builder()
+///     .processor(AutoAnchors::default())
+///     .named_processor("minify", HtmlMinify::default())
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Default)]
+pub struct PipelineBuilder {
+    pipeline: Pipeline,
+}
+
+impl PipelineBuilder {
+    /// Sets the parser options.
+    pub fn parser_options(mut self, parser_options: &ParserOptions) -> PipelineBuilder {
+        self.pipeline.set_parser_options(parser_options);
+        self
+    }
+
+    /// Sets the options for the HTML renderer.
+    pub fn renderer_options(mut self, renderer_options: &HtmlRendererOptions) -> PipelineBuilder {
+        self.pipeline.set_renderer_options(renderer_options);
+        self
+    }
+
+    /// Sets the resource limits.
+    pub fn limits(mut self, limits: &ResourceLimits) -> PipelineBuilder {
+        self.pipeline.set_limits(limits);
+        self
+    }
+
+    /// Adds a processor.
+    pub fn processor<P: Processor + 'static>(mut self, processor: P) -> PipelineBuilder {
+        self.pipeline.add_processor(processor);
+        self
+    }
+
+    /// Adds a processor under the given stage name.
+    pub fn named_processor<P: Processor + 'static>(
+        mut self,
+        name: &str,
+        processor: P,
+    ) -> PipelineBuilder {
+        self.pipeline.add_named_processor(name, processor);
+        self
+    }
+
+    /// Adds a processor that is only applied to some documents.
+    ///
+    /// See [`Pipeline::add_processor_if`].
+    pub fn processor_if<P, F>(mut self, processor: P, condition: F) -> PipelineBuilder
+    where
+        P: Processor + 'static,
+        F: Fn(&DocumentInfo<'_, '_>) -> bool + Send + Sync + 'static,
+    {
+        self.pipeline.add_processor_if(processor, condition);
+        self
+    }
+
+    /// Validates the processors and returns the pipeline.
+    pub fn build(self) -> Result<Pipeline, Error> {
+        self.pipeline.check()?;
+        Ok(self.pipeline)
+    }
+}
+
 impl Pipeline {
+    /// Returns a builder for a validated pipeline.
+    pub fn builder() -> PipelineBuilder {
+        PipelineBuilder::default()
+    }
+
     /// Creates a new pipeline.
     pub fn new() -> Pipeline {
         Pipeline {
             parser: Parser::default(),
             processors: Vec::new(),
+            timing: TimingOptions::default(),
+            limits: ResourceLimits::default(),
+            renderer_options: HtmlRendererOptions::default(),
+        }
+    }
+
+    /// Creates a pipeline from a configuration.
+    ///
+    /// This panics if a registered processor cannot be created, see
+    /// [`try_from_config`](Self::try_from_config).
+    pub fn from_config(config: &PipelineConfig) -> Pipeline {
+        Pipeline::try_from_config(config).unwrap()
+    }
+
+    /// Like [`from_config`](Self::from_config) but reports failures to create
+    /// registered processors.
+    pub fn try_from_config(config: &PipelineConfig) -> Result<Pipeline, Error> {
+        let mut rv = Pipeline::new();
+        rv.set_parser_options(&config.parser);
+        rv.set_renderer_options(&config.renderer);
+        rv.set_limits(&config.limits);
+        for processor in &config.processors {
+            rv.processors.push(PipelineEntry {
+                name: processor.stage.clone(),
+                config: match processor.processor {
+                    ConfiguredProcessor::Builtin(_) => None,
+                    ConfiguredProcessor::Custom(_) => Some(processor.processor.clone()),
+                },
+                ..PipelineEntry::new(processor.processor.create()?)
+            });
+        }
+        Ok(rv)
+    }
+
+    /// Returns the configuration of the pipeline.
+    ///
+    /// This fails if the pipeline contains custom processors that were not
+    /// created from a config, processors with activation conditions or
+    /// processors with options that can only be set from code (such as a
+    /// custom slugify function) as these cannot be represented.
+    pub fn to_config(&self) -> Result<PipelineConfig, Error> {
+        let mut processors = Vec::new();
+        for entry in &self.processors {
+            let processor = entry
+                .config
+                .clone()
+                .or_else(|| entry.processor.to_builtin().map(Into::into));
+            let processor = match (&entry.condition, processor) {
+                (None, Some(processor)) => processor,
+                _ => {
+                    return Err(Error::new(
+                        ErrorKind::Serialization,
+                        format!("stage '{}' cannot be represented as config", entry.name()),
+                    ))
+                }
+            };
+            processors.push(ProcessorConfig {
+                stage: entry.name.clone(),
+                processor,
+            });
         }
+        Ok(PipelineConfig {
+            parser: self.parser.options().clone(),
+            processors,
+            renderer: self.renderer_options.clone(),
+            limits: self.limits.clone(),
+        })
+    }
+
+    /// Returns a hash of the configuration of the pipeline.
+    ///
+    /// Pipelines with the same fingerprint produce the same output for the
+    /// same input as long as the files read by processors do not change.
+    /// Returns `None` if the pipeline cannot be represented as config (see
+    /// [`to_config`](Self::to_config)) as changes to such pipelines cannot
+    /// be detected.  This includes processors with options that can only be
+    /// set from code.
+    pub fn fingerprint(&self) -> Option<u64> {
+        hash_json(&(env!("CARGO_PKG_VERSION"), self.to_config().ok()?))
+    }
+
+    /// Returns the options for the HTML renderer.
+    pub fn renderer_options(&self) -> &HtmlRendererOptions {
+        &self.renderer_options
+    }
+
+    /// Changes the options for the HTML renderer.
+    pub fn set_renderer_options(&mut self, renderer_options: &HtmlRendererOptions) {
+        self.renderer_options = renderer_options.clone();
     }
 
     /// Changes the parsing options.
@@ -42,7 +697,233 @@ impl Pipeline {
 
     /// Adds a processor to the pipeline
     pub fn add_processor<P: Processor + 'static>(&mut self, processor: P) {
-        self.processors.push(Box::new(processor));
+        self.processors
+            .push(PipelineEntry::new(Box::new(processor)));
+    }
+
+    /// Adds a processor to the pipeline under the given stage name.
+    ///
+    /// Stages without an explicit name are named after the processor in
+    /// snake case (`syntect`, `table_of_contents`, …).
+    pub fn add_named_processor<P: Processor + 'static>(&mut self, name: &str, processor: P) {
+        self.processors.push(PipelineEntry {
+            name: Some(name.to_string()),
+            ..PipelineEntry::new(Box::new(processor))
+        });
+    }
+
+    /// Returns the names of all stages in order.
+    pub fn stage_names(&self) -> impl Iterator<Item = Cow<'_, str>> {
+        self.processors.iter().map(|entry| entry.name())
+    }
+
+    fn stage_position(&self, name: &str) -> Option<usize> {
+        self.processors
+            .iter()
+            .position(|entry| entry.name() == name)
+    }
+
+    /// Inserts a processor before the first stage with the given name.
+    ///
+    /// Returns `false` if no such stage exists.
+    pub fn insert_before<P: Processor + 'static>(&mut self, name: &str, processor: P) -> bool {
+        match self.stage_position(name) {
+            Some(idx) => {
+                self.processors
+                    .insert(idx, PipelineEntry::new(Box::new(processor)));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Inserts a processor after the first stage with the given name.
+    ///
+    /// Returns `false` if no such stage exists.
+    pub fn insert_after<P: Processor + 'static>(&mut self, name: &str, processor: P) -> bool {
+        match self.stage_position(name) {
+            Some(idx) => {
+                self.processors
+                    .insert(idx + 1, PipelineEntry::new(Box::new(processor)));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replaces the processor of the first stage with the given name.
+    ///
+    /// The stage retains its name and activation condition.  Returns `false`
+    /// if no such stage exists.
+    pub fn replace<P: Processor + 'static>(&mut self, name: &str, processor: P) -> bool {
+        match self.stage_position(name) {
+            Some(idx) => {
+                let entry = &mut self.processors[idx];
+                if entry.name.is_none() {
+                    entry.name = Some(name.to_string());
+                }
+                entry.processor = Box::new(processor);
+                entry.config = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the first stage with the given name.
+    ///
+    /// Returns `false` if no such stage exists.
+    pub fn remove(&mut self, name: &str) -> bool {
+        match self.stage_position(name) {
+            Some(idx) => {
+                self.processors.remove(idx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Adds a processor that is only applied to some documents.
+    ///
+    /// The predicate is invoked for every document with the document's
+    /// path, front matter and events.  As the predicate needs to see the
+    /// entire document the stream is buffered before the processor.
+    ///
+    /// ```
+    /// # use struckdown::pipeline::Pipeline;
+    /// # use struckdown::processors::HtmlMinify;
+    /// let mut pipeline = Pipeline::new();
+    /// pipeline.add_processor_if(HtmlMinify::default(), |doc| doc.flag("minify"));
+    /// ```
+    pub fn add_processor_if<P, F>(&mut self, processor: P, condition: F)
+    where
+        P: Processor + 'static,
+        F: Fn(&DocumentInfo<'_, '_>) -> bool + Send + Sync + 'static,
+    {
+        self.processors.push(PipelineEntry {
+            condition: Some(ActivationFn::new(condition)),
+            ..PipelineEntry::new(Box::new(processor))
+        });
+    }
+
+    /// Invokes a callback with the timings of every pipeline run.
+    ///
+    /// The callback is invoked once the stream is exhausted with the number
+    /// of events and the wall time spent in every processor.  Measuring
+    /// adds a small overhead to every event.
+    pub fn set_timing_callback<F: Fn(&TimingReport) + Send + Sync + 'static>(&mut self, f: F) {
+        self.timing.callback = Some(TimingFn::new(f));
+    }
+
+    /// Emits the timings of every pipeline run as meta data.
+    ///
+    /// If set the [`TimingReport`] is emitted under the given key at the end
+    /// of the stream.
+    pub fn set_timing_metadata_key(&mut self, key: Option<&str>) {
+        self.timing.metadata_key = key.map(|x| x.to_string());
+    }
+
+    /// Returns the resource limits.
+    pub fn limits(&self) -> &ResourceLimits {
+        &self.limits
+    }
+
+    /// Changes the resource limits of pipeline runs.
+    ///
+    /// ```
+    /// # use struckdown::pipeline::{Pipeline, ResourceLimits};
+    /// let mut pipeline = Pipeline::new();
+    /// pipeline.set_limits(&ResourceLimits {
+    ///     max_events: Some(100_000),
+    ///     max_stage_time_ms: Some(5000),
+    ///     ..Default::default()
+    /// });
+    /// ```
+    pub fn set_limits(&mut self, limits: &ResourceLimits) {
+        self.limits = limits.clone();
+    }
+
+    /// Wraps the stages of a pipeline to enforce the resource limits.
+    fn limit<'data>(
+        limits: &ResourceLimits,
+        iter: EventIter<'data>,
+        stages: Vec<Stage<'data>>,
+    ) -> (EventIter<'data>, Vec<Stage<'data>>) {
+        if !limits.is_enabled() {
+            return (iter, stages);
+        }
+        let totals = Rc::new(RefCell::new(vec![Duration::default(); stages.len() + 1]));
+        let last = stages.len();
+        let iter: EventIter<'data> = Box::new(LimitIter::new(
+            iter,
+            Cow::Borrowed("input"),
+            0,
+            limits,
+            &totals,
+            last == 0,
+        ));
+        let stages = stages
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (name, apply))| {
+                let limits = limits.clone();
+                let totals = totals.clone();
+                let stage_name = name.clone();
+                let apply: Box<dyn FnOnce(EventIter<'data>) -> EventIter<'data> + 'data> =
+                    Box::new(move |iter| {
+                        let stage = idx + 1;
+                        Box::new(LimitIter::new(
+                            apply(iter),
+                            stage_name,
+                            stage,
+                            &limits,
+                            &totals,
+                            stage == last,
+                        ))
+                    });
+                (name, apply)
+            })
+            .collect();
+        (iter, stages)
+    }
+
+    /// Wraps the stages of a pipeline for limits and timing if enabled.
+    fn instrument<'data>(
+        timing: &TimingOptions,
+        limits: &ResourceLimits,
+        iter: EventIter<'data>,
+        stages: Vec<Stage<'data>>,
+    ) -> EventIter<'data> {
+        let (iter, stages) = Pipeline::limit(limits, iter, stages);
+        if !timing.is_enabled() {
+            return stages
+                .into_iter()
+                .fold(iter, |iter, (_, apply)| apply(iter));
+        }
+
+        let stats = Rc::new(RefCell::new(Vec::new()));
+        let mut names = vec![Cow::Borrowed("input")];
+        stats.borrow_mut().push(StageStats::default());
+        let mut iter = TimedIter {
+            iter,
+            stage: 0,
+            stats: stats.clone(),
+        };
+        for (name, apply) in stages {
+            names.push(name);
+            stats.borrow_mut().push(StageStats::default());
+            iter = TimedIter {
+                iter: apply(Box::new(iter)),
+                stage: names.len() - 1,
+                stats: stats.clone(),
+            };
+        }
+        Box::new(ReportIter {
+            iter,
+            names,
+            options: timing.clone(),
+            done: false,
+        })
     }
 
     /// Applies the pipeline to a stream consuming the processor.
@@ -50,11 +931,49 @@ impl Pipeline {
         self,
         iter: I,
     ) -> Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data> {
-        let mut iter = Box::new(iter) as Box<dyn Iterator<Item = AnnotatedEvent<'data>>>;
-        for processor in self.processors {
-            iter = processor.apply(iter);
-        }
-        iter
+        let stages = self
+            .processors
+            .into_iter()
+            .map(|entry| {
+                let name = Cow::Owned(entry.name().into_owned());
+                let PipelineEntry {
+                    processor,
+                    condition,
+                    ..
+                } = entry;
+                let apply: Box<dyn FnOnce(EventIter<'data>) -> EventIter<'data> + 'data> =
+                    match condition {
+                        Some(condition) => Box::new(move |iter| {
+                            condition.apply(None, iter, |iter| processor.apply(iter))
+                        }),
+                        None => Box::new(move |iter| processor.apply(iter)),
+                    };
+                (name, apply)
+            })
+            .collect();
+        Pipeline::instrument(&self.timing, &self.limits, Box::new(iter), stages)
+    }
+
+    /// Returns the stages of the pipeline attached by reference.
+    fn stages_ref<'data, 'options: 'data>(
+        &'options self,
+        path: Option<&'data Path>,
+    ) -> Vec<Stage<'data>> {
+        self.processors
+            .iter()
+            .map(|entry| {
+                let processor = &entry.processor;
+                let apply: Box<dyn FnOnce(EventIter<'data>) -> EventIter<'data> + 'data> =
+                    match entry.condition {
+                        Some(ref condition) => Box::new(move |iter| {
+                            condition
+                                .apply(path, iter, |iter| processor.apply_ref_with_path(iter, path))
+                        }),
+                        None => Box::new(move |iter| processor.apply_ref_with_path(iter, path)),
+                    };
+                (entry.name(), apply)
+            })
+            .collect()
     }
 
     /// Applies the pipeline to a stream.
@@ -62,11 +981,116 @@ impl Pipeline {
         &'options self,
         iter: I,
     ) -> Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data> {
-        let mut iter = Box::new(iter) as Box<dyn Iterator<Item = AnnotatedEvent<'data>>>;
-        for processor in &self.processors {
-            iter = processor.apply_ref(iter);
+        Pipeline::instrument(
+            &self.timing,
+            &self.limits,
+            Box::new(iter),
+            self.stages_ref(None),
+        )
+    }
+
+    /// Applies the pipeline to the stream of the document at `path`.
+    ///
+    /// The path is not read but made available to the predicates of
+    /// processors added with [`add_processor_if`](Self::add_processor_if)
+    /// and to the processors (see [`Processor::apply_ref_with_path`]).
+    pub fn apply_ref_with_path<
+        'data,
+        'options: 'data,
+        I: Iterator<Item = AnnotatedEvent<'data>> + 'data,
+    >(
+        &'options self,
+        iter: I,
+        path: &'data Path,
+    ) -> Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data> {
+        Pipeline::instrument(
+            &self.timing,
+            &self.limits,
+            Box::new(iter),
+            self.stages_ref(Some(path)),
+        )
+    }
+
+    /// Applies the pipeline to a stream collecting all reported problems.
+    ///
+    /// The stream is consumed entirely and returned together with a
+    /// [`Diagnostics`] report of all error events and the processors that
+    /// emitted them.  The error events are retained in the stream.
+    pub fn apply_ref_with_diagnostics<
+        'data,
+        'options: 'data,
+        I: Iterator<Item = AnnotatedEvent<'data>> + 'data,
+    >(
+        &'options self,
+        iter: I,
+    ) -> (Vec<AnnotatedEvent<'data>>, Diagnostics) {
+        let collector = Rc::new(RefCell::new(DiagnosticsCollector::default()));
+        let iter = Box::new(DiagnosticsIter {
+            iter: Box::new(iter),
+            stage: collector.borrow_mut().add_stage(),
+            name: Cow::Borrowed("input"),
+            collector: collector.clone(),
+        });
+        let stages = self
+            .stages_ref(None)
+            .into_iter()
+            .map(|(name, apply)| {
+                let stage = collector.borrow_mut().add_stage();
+                let collector = collector.clone();
+                let stage_name = name.clone();
+                let apply: Box<dyn FnOnce(EventIter<'data>) -> EventIter<'data> + 'data> =
+                    Box::new(move |iter| {
+                        Box::new(DiagnosticsIter {
+                            iter: apply(iter),
+                            stage,
+                            name: stage_name,
+                            collector,
+                        })
+                    });
+                (name, apply)
+            })
+            .collect();
+        let events = Pipeline::instrument(&self.timing, &self.limits, iter, stages).collect();
+        let diagnostics = collector.borrow_mut().take_diagnostics();
+        (events, diagnostics)
+    }
+
+    /// Applies the pipeline stage by stage recording the changes of every
+    /// stage.
+    ///
+    /// Unlike the other APIs this buffers the stream after every stage.  It
+    /// is intended for debugging; see [`explain`](crate::explain) for details.
+    pub fn explain_events<
+        'data,
+        'options: 'data,
+        I: Iterator<Item = AnnotatedEvent<'data>> + 'data,
+    >(
+        &'options self,
+        iter: I,
+    ) -> Trace {
+        let mut events = iter.collect::<Vec<_>>();
+        let input_events = events.len();
+        let stages = self
+            .stage_names()
+            .zip(self.stages_ref(None))
+            .map(|(name, (_, apply))| {
+                let output = apply(Box::new(events.clone().into_iter())).collect::<Vec<_>>();
+                let trace = trace_stage(name.into_owned(), &events, &output);
+                events = output;
+                trace
+            })
+            .collect();
+        Trace {
+            input_events,
+            stages,
         }
-        iter
+    }
+
+    /// Parses a document and explains how the stages change it.
+    ///
+    /// See [`explain_events`](Self::explain_events).
+    pub fn explain(&self, source: &str) -> Trace {
+        self.explain_events(self.parser.parse(source))
     }
 
     /// Parses and processes a document returning an event stream.
@@ -77,6 +1101,98 @@ impl Pipeline {
         self.apply_ref(Box::new(self.parser.parse(source)))
     }
 
+    /// Parses, processes and renders a document to HTML.
+    ///
+    /// This is a shortcut for [`process`](Self::process) followed by
+    /// [`to_html`].
+    pub fn process_to_html(&self, source: &str, options: &HtmlRendererOptions) -> String {
+        to_html(self.process(source), options)
+    }
+
+    /// Parses, processes and renders a document to HTML into a writer.
+    ///
+    /// Unlike [`process_to_html`](Self::process_to_html) failures to set up
+    /// processors and to write are reported.
+    pub fn process_to_writer<W: io::Write>(
+        &self,
+        source: &str,
+        writer: W,
+        options: &HtmlRendererOptions,
+    ) -> Result<W, Error> {
+        let mut renderer = HtmlRenderer::new(writer, options);
+        renderer.feed_stream(self.try_process(source)?)?;
+        Ok(renderer.into_writer())
+    }
+
+    /// Parses and processes the document at `path`.
+    ///
+    /// See [`apply_ref_with_path`](Self::apply_ref_with_path).
+    pub fn process_with_path<'data, 'options: 'data>(
+        &'options self,
+        source: &'data str,
+        path: &'data Path,
+    ) -> Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data> {
+        self.apply_ref_with_path(self.parser.parse(source), path)
+    }
+
+    /// Parses and processes a document collecting all reported problems.
+    ///
+    /// See [`apply_ref_with_diagnostics`](Self::apply_ref_with_diagnostics).
+    pub fn process_with_diagnostics<'data, 'options: 'data>(
+        &'options self,
+        source: &'data str,
+    ) -> (Vec<AnnotatedEvent<'data>>, Diagnostics) {
+        self.apply_ref_with_diagnostics(self.parser.parse(source))
+    }
+
+    /// Verifies that all processors of the pipeline can be applied.
+    ///
+    /// See [`Processor::check`] for details.
+    pub fn check(&self) -> Result<(), Error> {
+        self.processors.iter().try_for_each(|entry| {
+            entry.processor.check().map_err(|err| {
+                Error::new(
+                    ErrorKind::ProcessorFailed,
+                    format!("invalid stage '{}'", entry.name()),
+                )
+                .with_source(err)
+            })
+        })
+    }
+
+    /// Like [`apply`](Self::apply) but reports failures to set up a processor.
+    pub fn try_apply<'data, I: Iterator<Item = AnnotatedEvent<'data>> + 'data>(
+        self,
+        iter: I,
+    ) -> Result<Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data>, Error> {
+        self.check()?;
+        Ok(self.apply(iter))
+    }
+
+    /// Like [`apply_ref`](Self::apply_ref) but reports failures to set up a processor.
+    pub fn try_apply_ref<
+        'data,
+        'options: 'data,
+        I: Iterator<Item = AnnotatedEvent<'data>> + 'data,
+    >(
+        &'options self,
+        iter: I,
+    ) -> Result<Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data>, Error> {
+        self.check()?;
+        Ok(self.apply_ref(iter))
+    }
+
+    /// Like [`process`](Self::process) but reports failures to set up a processor.
+    ///
+    /// Problems with the document itself are still reported as error events
+    /// in the stream.
+    pub fn try_process<'data, 'options: 'data>(
+        &'options self,
+        source: &'data str,
+    ) -> Result<Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data>, Error> {
+        self.try_apply_ref(Box::new(self.parser.parse(source)))
+    }
+
     /// Extracts a meta data tree from an event stream.
     pub fn extract_metadata<
         'data: 'event,
@@ -97,6 +1213,92 @@ impl Pipeline {
     }
 }
 
+/// A pipeline with processors fixed at compile time.
+///
+/// The processors of a [`Pipeline`] are applied through boxed iterators
+/// which prevents the compiler from inlining them.  A static pipeline
+/// instead composes the iterators of its processors into a single typed
+/// iterator (see [`StaticProcessor`]).  In exchange it cannot be created
+/// from a config and does not support conditional stages, timings or
+/// resource limits.
+///
+/// ```
+/// # use struckdown::pipeline::StaticPipeline;
+/// # use struckdown::processors::{Footnotes, TableOfContents};
+/// let pipeline = StaticPipeline::new()
+///     .processor(Footnotes::default())
+///     .processor(TableOfContents::default());
+/// let html = pipeline.process_to_html("# Hello", &Default::default());
+/// ```
+#[derive(Debug)]
+pub struct StaticPipeline<P = ()> {
+    parser: Parser,
+    processors: P,
+}
+
+impl Default for StaticPipeline {
+    fn default() -> StaticPipeline {
+        StaticPipeline::new()
+    }
+}
+
+impl StaticPipeline {
+    /// Creates a new pipeline without processors.
+    pub fn new() -> StaticPipeline {
+        StaticPipeline {
+            parser: Parser::default(),
+            processors: (),
+        }
+    }
+}
+
+impl<P> StaticPipeline<P> {
+    /// Sets the parser options.
+    pub fn parser_options(mut self, parser_options: &ParserOptions) -> StaticPipeline<P> {
+        self.parser = Parser::new(parser_options);
+        self
+    }
+
+    /// Adds a processor after the existing ones.
+    pub fn processor<Q>(self, processor: Q) -> StaticPipeline<Chain<P, Q>> {
+        StaticPipeline {
+            parser: self.parser,
+            processors: Chain(self.processors, processor),
+        }
+    }
+
+    /// Applies the processors to an event stream.
+    pub fn apply<'data, 'options, I>(&'options self, iter: I) -> P::Iter
+    where
+        I: Iterator<Item = AnnotatedEvent<'data>>,
+        P: StaticProcessor<'data, 'options, I>,
+    {
+        self.processors.apply_static(iter)
+    }
+
+    /// Parses and processes a document returning an event stream.
+    ///
+    /// The events of the parser are boxed, the processors are not.
+    pub fn process<'data, 'options>(&'options self, source: &'data str) -> P::Iter
+    where
+        P: StaticProcessor<'data, 'options, EventIter<'data>>,
+    {
+        self.apply(Box::new(self.parser.parse(source)) as EventIter<'data>)
+    }
+
+    /// Parses, processes and renders a document to HTML.
+    pub fn process_to_html<'data, 'options>(
+        &'options self,
+        source: &'data str,
+        options: &HtmlRendererOptions,
+    ) -> String
+    where
+        P: StaticProcessor<'data, 'options, EventIter<'data>>,
+    {
+        to_html(self.process(source), options)
+    }
+}
+
 #[test]
 fn test_basic_pipeline() {
     use crate::html::to_html;
@@ -109,3 +1311,333 @@ fn test_basic_pipeline() {
         &Default::default()
     ));
 }
+
+#[test]
+fn test_pipeline_timings() {
+    use crate::processors::{HtmlMinify, Redaction};
+    use std::sync::Mutex;
+
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(Redaction::default());
+    pipeline.add_named_processor("minify", HtmlMinify::default());
+    pipeline.set_timing_metadata_key(Some("timings"));
+    let reports_ref = reports.clone();
+    pipeline.set_timing_callback(move |report| {
+        reports_ref.lock().unwrap().push(report.clone());
+    });
+
+    let source = "Hello\n\n```{speaker-notes}\nx\n```";
+    let events = pipeline.process(source).collect::<Vec<_>>();
+    assert!(matches!(
+        events.last().map(|x| &x.event),
+        Some(Event::MetaData(MetaDataEvent { key, .. })) if key.as_str() == "timings"
+    ));
+
+    let reports = reports.lock().unwrap();
+    assert_eq!(reports.len(), 1);
+    let stages = &reports[0].stages;
+    assert_eq!(
+        stages.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(),
+        vec!["input", "redaction", "minify"]
+    );
+    assert_eq!(stages[0].events, Parser::default().parse(source).count());
+    assert_eq!(stages[2].events, events.len() - 1);
+}
+
+#[cfg(feature = "syntect-processor")]
+#[test]
+fn test_try_process() {
+    use crate::error::ErrorKind;
+    use crate::processors::Syntect;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(Syntect::default());
+    assert!(pipeline.try_process("Hello").is_ok());
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(Syntect {
+        theme_path: Some("does/not/exist.tmTheme".into()),
+        ..Default::default()
+    });
+    let err = pipeline.try_process("Hello").err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::ProcessorFailed);
+}
+
+#[test]
+fn test_process_with_diagnostics() {
+    use crate::event::Severity;
+    use crate::processors::{HeadingPolicy, RoleDispatch};
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(RoleDispatch {
+        fallback: crate::processors::FallbackPolicy::Error,
+        ..Default::default()
+    });
+    pipeline.add_named_processor("headings", HeadingPolicy::default());
+    let (events, diagnostics) = pipeline.process_with_diagnostics("# A\n\n### B\n\n{kbd}`x`");
+    assert_eq!(
+        events
+            .iter()
+            .filter(|x| matches!(x.event, Event::Error(..)))
+            .count(),
+        2
+    );
+    let found = diagnostics
+        .iter()
+        .map(|x| (x.processor.as_str(), x.title.as_str(), x.severity))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        vec![
+            ("headings", "Skipped heading level", Severity::Warning),
+            ("role_dispatch", "Unknown role", Severity::Error),
+        ]
+    );
+    assert!(diagnostics.iter().all(|x| x.location.is_some()));
+    assert!(diagnostics.has_errors());
+}
+
+#[test]
+fn test_conditional_processors() {
+    use crate::html::to_html;
+    use crate::processors::{HtmlMinify, Redaction};
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor_if(Redaction::default(), |doc| doc.flag("redact"));
+    pipeline.add_processor_if(
+        HtmlMinify::default(),
+        |doc| matches!(doc.path.and_then(|x| x.extension()), Some(ext) if ext == "md"),
+    );
+
+    let source = "```{speaker-notes}\nSecret\n```";
+    let html = to_html(pipeline.process(source), &Default::default());
+    assert!(html.contains("Secret"));
+    let source = "---\nredact: true\n---\n```{speaker-notes}\nSecret\n```";
+    let html = to_html(pipeline.process(source), &Default::default());
+    assert!(!html.contains("Secret"));
+
+    let source = "<p>\n  Hello\n</p>";
+    assert_ne!(
+        to_html(pipeline.process(source), &Default::default()),
+        to_html(
+            pipeline.process_with_path(source, Path::new("index.md")),
+            &Default::default()
+        )
+    );
+}
+
+#[test]
+fn test_named_stages() {
+    use crate::processors::{Footnotes, HtmlMinify, Redaction, TableOfContents};
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(Redaction::default());
+    pipeline.add_named_processor("toc", TableOfContents::default());
+    assert!(pipeline.insert_before("toc", HtmlMinify::default()));
+    assert!(pipeline.insert_after("redaction", Footnotes::default()));
+    assert!(pipeline.replace("html_minify", HtmlMinify::default()));
+    assert!(!pipeline.insert_before("missing", HtmlMinify::default()));
+    assert_eq!(
+        pipeline.stage_names().collect::<Vec<_>>(),
+        vec!["redaction", "footnotes", "html_minify", "toc"]
+    );
+    assert!(pipeline.remove("footnotes"));
+    assert_eq!(
+        pipeline.stage_names().collect::<Vec<_>>(),
+        vec!["redaction", "html_minify", "toc"]
+    );
+}
+
+#[test]
+fn test_pipeline_config() {
+    use crate::processors::HtmlMinify;
+
+    let config: PipelineConfig = serde_yaml::from_str(
+        "parser:\n  enable_roles: false\nprocessors:\n  - processor: redaction\n    \
+         stage: hide\n  - processor: html_minify\n    remove_comments: false\n\
+         renderer:\n  initial_headline_level: 2\n",
+    )
+    .unwrap();
+    let pipeline = Pipeline::from_config(&config);
+    assert_eq!(
+        pipeline.stage_names().collect::<Vec<_>>(),
+        vec!["hide", "html_minify"]
+    );
+    assert_eq!(pipeline.renderer_options().initial_headline_level, 2);
+
+    let config = pipeline.to_config().unwrap();
+    assert!(!config.parser.enable_roles);
+    assert_eq!(config.processors[0].stage.as_deref(), Some("hide"));
+    let value = crate::value::to_value(&config).unwrap();
+    assert_eq!(value["processors"][1]["processor"], "html_minify");
+    assert_eq!(value["processors"][1]["remove_comments"], false);
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor_if(HtmlMinify::default(), |_| true);
+    assert!(pipeline.to_config().is_err());
+}
+
+#[cfg(feature = "autoanchors-processor")]
+#[test]
+fn test_pipeline_config_code_only_options() {
+    use crate::processors::{AutoAnchors, BuiltinProcessor, SlugifyFn};
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(AutoAnchors::default());
+    assert!(pipeline.to_config().is_ok());
+
+    // a custom slugify function would be lost in the config
+    let auto_anchors = AutoAnchors {
+        slugify: Some(SlugifyFn::new(|text| text.to_uppercase())),
+        ..AutoAnchors::default()
+    };
+    assert!(BuiltinProcessor::from(auto_anchors.clone())
+        .to_builtin()
+        .is_none());
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(auto_anchors);
+    assert!(pipeline.to_config().is_err());
+}
+
+#[cfg(feature = "syntect-processor")]
+#[test]
+fn test_pipeline_config_code_only_syntect_options() {
+    use std::sync::Arc;
+
+    use crate::processors::{DiffHighlight, Syntect};
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(DiffHighlight::default());
+    pipeline.add_processor(Syntect::default());
+    assert!(pipeline.to_config().is_ok());
+
+    // a syntax set given from code would be lost in the config
+    let syntax_set = Arc::new(syntect::parsing::SyntaxSet::load_defaults_nonewlines());
+    let mut diff = DiffHighlight::default();
+    diff.syntect.syntax_set = Some(syntax_set.clone());
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(diff);
+    assert!(pipeline.to_config().is_err());
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(Syntect {
+        syntax_set: Some(syntax_set),
+        ..Syntect::default()
+    });
+    assert!(pipeline.to_config().is_err());
+}
+
+#[test]
+fn test_process_to_html() {
+    let pipeline = Pipeline::default();
+    let options = HtmlRendererOptions {
+        initial_headline_level: 2,
+        ..Default::default()
+    };
+    let html = pipeline.process_to_html("# Hello", &options);
+    assert_eq!(html, "<h2>Hello</h2>\n");
+    let out = pipeline
+        .process_to_writer("# Hello", Vec::new(), &options)
+        .unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), html);
+}
+
+#[cfg(feature = "external-processor")]
+#[test]
+fn test_pipeline_builder() {
+    use crate::processors::{External, HtmlMinify};
+
+    let pipeline = Pipeline::builder()
+        .named_processor("minify", HtmlMinify::default())
+        .build()
+        .unwrap();
+    assert_eq!(pipeline.stage_names().collect::<Vec<_>>(), vec!["minify"]);
+
+    let external: External =
+        serde_json::from_str(r#"{"cmd": "definitely-not-a-struckdown-command"}"#).unwrap();
+    let err = Pipeline::builder().processor(external).build().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ProcessorFailed);
+    assert!(err.to_string().contains("invalid stage 'external'"));
+}
+
+#[test]
+fn test_explain() {
+    use crate::explain::EventChange;
+    use crate::processors::{HtmlMinify, Redaction};
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(HtmlMinify::default());
+    pipeline.add_named_processor("hide", Redaction::default());
+    let trace = pipeline.explain("Hello\n\n```{speaker-notes}\nx\n```");
+    assert_eq!(trace.stages.len(), 2);
+    assert_eq!(trace.stages[0].name, "html_minify");
+    assert!(trace.stages[0].is_unchanged());
+    assert_eq!(trace.stages[1].name, "hide");
+    assert_eq!(trace.stages[1].input_events, trace.input_events);
+    assert!(trace.stages[1]
+        .changes
+        .iter()
+        .all(|x| matches!(x, EventChange::Removed { .. })));
+    assert!(!trace.stages[1].is_unchanged());
+}
+
+#[test]
+fn test_resource_limits() {
+    use crate::event::Event;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.set_limits(&ResourceLimits {
+        max_events: Some(4),
+        ..Default::default()
+    });
+    let events = pipeline.process("- a\n- b\n- c\n").collect::<Vec<_>>();
+    let error = events
+        .iter()
+        .position(|x| matches!(x.event, Event::Error(_)))
+        .unwrap();
+    assert_eq!(error, 4);
+    // all tags opened before the limit was hit are closed again
+    let depth = events.iter().fold(0i32, |depth, x| match x.event {
+        Event::StartTag(_) => depth + 1,
+        Event::EndTag(_) => depth - 1,
+        _ => depth,
+    });
+    assert_eq!(depth, 0);
+
+    pipeline.set_limits(&ResourceLimits {
+        max_output_bytes: Some(1 << 20),
+        ..Default::default()
+    });
+    assert!(!pipeline
+        .process("- a\n- b\n- c\n")
+        .any(|x| matches!(x.event, Event::Error(_))));
+}
+
+#[test]
+fn test_static_pipeline() {
+    use crate::processors::{Footnotes, TableOfContents};
+
+    let source = "# Hello\n\nSee[^1].\n\n## World\n\n[^1]: A note";
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(Footnotes::default());
+    pipeline.add_processor(TableOfContents::default());
+    let static_pipeline = StaticPipeline::new()
+        .processor(Footnotes::default())
+        .processor(TableOfContents::default());
+
+    assert_eq!(
+        static_pipeline.process_to_html(source, &Default::default()),
+        pipeline.process_to_html(source, &Default::default())
+    );
+    let events: Vec<_> = pipeline.process(source).collect();
+    assert_eq!(
+        to_value(
+            static_pipeline
+                .apply(events.clone().into_iter())
+                .collect::<Vec<_>>()
+        )
+        .ok(),
+        to_value(pipeline.apply_ref(events.into_iter()).collect::<Vec<_>>()).ok()
+    );
+}
@@ -1,4 +1,24 @@
-use crate::event::{AnnotatedEvent, Str};
+//! Implements a plain text renderer.
+//!
+//! [`to_plain_text`] extracts the raw text of a stream (eg: for the title of
+//! a heading), [`to_text`] renders an entire document to readable plain
+//! text with paragraphs, lists and optional line wrapping.
+//!
+//! ```
+//! use struckdown::parser::parse;
+//! use struckdown::plain::{to_text, PlainTextOptions};
+//!
+//! let text = to_text(parse("# Hello\n\n* a\n* b", &Default::default()), &PlainTextOptions::default());
+//! assert_eq!(text, "Hello\n=====\n\n- a\n- b\n");
+//! ```
+use std::mem;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{
+    AnnotatedEvent, CheckboxEvent, CodeBlockEvent, EndTagEvent, Event, FootnoteReferenceEvent,
+    ImageEvent, StartTagEvent, Str, Tag,
+};
 
 /// Renders an event stream to plain text.
 pub fn to_plain_text<'data: 'event, 'event, I>(iter: I) -> Str<'data>
@@ -32,3 +52,304 @@ where
         _ => "".into(),
     }
 }
+
+/// Customizes the plain text rendering.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct PlainTextOptions {
+    /// Wraps paragraphs at this width.  If not set paragraphs are emitted on
+    /// a single line.
+    pub width: Option<usize>,
+    /// Emits the targets of links in angle brackets after the link text.
+    pub show_link_targets: bool,
+}
+
+/// Wraps text at a width prefixing the first line and indenting the others.
+fn wrap_into(out: &mut String, text: &str, first: &str, indent: &str, width: Option<usize>) {
+    for (idx, segment) in text.split('\n').enumerate() {
+        let mut line = String::from(if idx == 0 { first } else { indent });
+        let mut line_empty = true;
+        for word in segment.split_whitespace() {
+            if let Some(width) = width {
+                if !line_empty && line.chars().count() + 1 + word.chars().count() > width {
+                    out.push_str(&line);
+                    out.push('\n');
+                    line = indent.to_string();
+                    line_empty = true;
+                }
+            }
+            if !line_empty {
+                line.push(' ');
+            }
+            line.push_str(word);
+            line_empty = false;
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+}
+
+struct TextRenderer<'options> {
+    options: &'options PlainTextOptions,
+    out: String,
+    inline: String,
+    // the prefix of the next line and the indentation of all other lines
+    marker: Option<String>,
+    indent: String,
+    // the lengths of `indent` before nested blocks were entered
+    indents: Vec<usize>,
+    lists: Vec<Option<u32>>,
+    links: Vec<Option<String>>,
+    need_blank: bool,
+    force_blank: bool,
+}
+
+impl<'options> TextRenderer<'options> {
+    fn flush(&mut self, tight: bool) {
+        let text = std::mem::take(&mut self.inline);
+        let text = text.trim();
+        if text.is_empty() {
+            return;
+        }
+        self.start_block(tight);
+        let first = match self.marker.take() {
+            Some(marker) => format!(
+                "{}{}",
+                &self.indent[..self.indent.len() - marker.len()],
+                marker
+            ),
+            None => self.indent.clone(),
+        };
+        let indent = self.indent.clone();
+        wrap_into(&mut self.out, text, &first, &indent, self.options.width);
+    }
+
+    fn start_block(&mut self, tight: bool) {
+        if (self.force_blank || (self.need_blank && !tight)) && !self.out.is_empty() {
+            self.out.push('\n');
+        }
+        self.need_blank = true;
+        self.force_blank = false;
+    }
+
+    fn start_tag(&mut self, tag: Tag, target: Option<String>, start: Option<u32>) {
+        match tag {
+            Tag::Paragraph | Tag::Table | Tag::DefinitionList => self.flush(false),
+            tag if tag.header_level().is_some() => self.flush(false),
+            Tag::BlockQuote => {
+                self.flush(false);
+                self.indents.push(self.indent.len());
+                self.indent.push_str("> ");
+            }
+            Tag::OrderedList | Tag::UnorderedList => {
+                self.flush(true);
+                // top level lists are separated from the previous block
+                self.force_blank = self.lists.is_empty();
+                self.lists.push(match tag {
+                    Tag::OrderedList => Some(start.unwrap_or(1)),
+                    _ => None,
+                });
+            }
+            Tag::ListItem => {
+                self.flush(true);
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "- ".to_string(),
+                };
+                self.indents.push(self.indent.len());
+                self.indent.push_str(&" ".repeat(marker.len()));
+                self.marker = Some(marker);
+                // the first block of an item goes on the line of its marker
+                self.need_blank = false;
+            }
+            Tag::TableHead | Tag::TableCell if !self.inline.trim().is_empty() => {
+                self.inline.push_str(" | ");
+            }
+            Tag::Link => self.links.push(target),
+            _ => {}
+        }
+    }
+
+    fn end_tag(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph | Tag::DefinitionList => self.flush(false),
+            Tag::Table => {
+                self.flush(true);
+                self.need_blank = true;
+            }
+            Tag::BlockQuote => {
+                self.flush(false);
+                self.pop_indent();
+            }
+            tag if tag.header_level().is_some() => {
+                let text = self.inline.trim().to_string();
+                self.flush(false);
+                match tag {
+                    Tag::Heading1 => self.underline('=', &text),
+                    Tag::Heading2 => self.underline('-', &text),
+                    _ => {}
+                }
+            }
+            Tag::OrderedList | Tag::UnorderedList => {
+                self.flush(true);
+                self.lists.pop();
+                self.need_blank = self.lists.is_empty();
+            }
+            Tag::ListItem => {
+                self.flush(true);
+                if let Some(marker) = self.marker.take() {
+                    // empty items
+                    self.start_block(true);
+                    self.out
+                        .push_str(&self.indent[..self.indent.len() - marker.len()]);
+                    self.out.push_str(marker.trim_end());
+                    self.out.push('\n');
+                }
+                self.pop_indent();
+                // tight items are not separated by blank lines
+                self.need_blank = false;
+            }
+            Tag::TableHeader | Tag::TableRow => {
+                self.flush(true);
+                self.need_blank = false;
+            }
+            Tag::Link => {
+                if let (true, Some(Some(target))) =
+                    (self.options.show_link_targets, self.links.pop())
+                {
+                    self.inline.push_str(&format!(" <{}>", target));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn pop_indent(&mut self) {
+        let len = self.indents.pop().unwrap_or(0);
+        self.indent.truncate(len);
+    }
+
+    fn underline(&mut self, c: char, text: &str) {
+        let len = text.lines().map(|x| x.chars().count()).max().unwrap_or(0);
+        self.out.push_str(&self.indent);
+        self.out.extend(std::iter::repeat_n(c, len));
+        self.out.push('\n');
+    }
+
+    fn event(&mut self, event: Event<'_>) {
+        match event {
+            Event::StartTag(StartTagEvent { tag, attrs }) => self.start_tag(
+                tag,
+                attrs.target.as_ref().map(|x| x.as_str().to_string()),
+                attrs.start,
+            ),
+            Event::EndTag(EndTagEvent { tag }) => self.end_tag(tag),
+            Event::Text(_) | Event::InterpretedText(_) | Event::InlineCode(_) => {
+                if let Some(text) = event.raw_text() {
+                    self.inline.push_str(text.as_str());
+                }
+            }
+            Event::Image(ImageEvent { alt: Some(alt), .. }) => self.inline.push_str(alt.as_str()),
+            Event::SoftBreak => self.inline.push(' '),
+            Event::HardBreak => self.inline.push('\n'),
+            Event::Checkbox(CheckboxEvent { checked }) => {
+                self.inline.push_str(if checked { "[x] " } else { "[ ] " })
+            }
+            Event::FootnoteReference(FootnoteReferenceEvent { target }) => {
+                self.inline.push_str(&format!("[{}]", target.as_str()))
+            }
+            Event::CodeBlock(CodeBlockEvent { code, .. }) => {
+                self.flush(false);
+                self.start_block(false);
+                let indent = format!("{}    ", self.indent);
+                for line in code.as_str().trim_end_matches('\n').lines() {
+                    let line = format!("{}{}", indent, line);
+                    self.out.push_str(line.trim_end());
+                    self.out.push('\n');
+                }
+            }
+            Event::Rule => {
+                self.flush(false);
+                self.start_block(false);
+                self.out.push_str(&self.indent);
+                self.out.push_str("----\n");
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Renders an event stream to plain text.
+///
+/// Paragraphs are separated by blank lines, lists are rendered with `-` or
+/// numbers and code blocks are indented.  Formatting, raw HTML, directives
+/// and meta data are dropped.
+pub fn to_text<'data, I: Iterator<Item = AnnotatedEvent<'data>>>(
+    iter: I,
+    options: &PlainTextOptions,
+) -> String {
+    let mut rv = String::new();
+    to_text_into(iter, options, &mut rv);
+    rv
+}
+
+/// Renders an event stream to plain text replacing the contents of a string.
+///
+/// Unlike [`to_text`] this reuses the allocation of the string.
+pub fn to_text_into<'data, I: Iterator<Item = AnnotatedEvent<'data>>>(
+    iter: I,
+    options: &PlainTextOptions,
+    out: &mut String,
+) {
+    out.clear();
+    let mut renderer = TextRenderer {
+        options,
+        out: mem::take(out),
+        inline: String::new(),
+        marker: None,
+        indent: String::new(),
+        indents: Vec::new(),
+        lists: Vec::new(),
+        links: Vec::new(),
+        need_blank: false,
+        force_blank: false,
+    };
+    for annotated_event in iter {
+        renderer.event(annotated_event.event);
+    }
+    renderer.flush(false);
+    *out = renderer.out;
+}
+
+#[test]
+fn test_to_text() {
+    use crate::parser::parse;
+
+    let source = "Some *long* paragraph [here](http://x).\n\n1. one\n2. two\n\n> quoted";
+    let options = PlainTextOptions {
+        width: Some(16),
+        show_link_targets: true,
+    };
+    assert_eq!(
+        to_text(parse(source, &Default::default()), &options),
+        "Some long\nparagraph here\n<http://x>.\n\n1. one\n2. two\n\n> quoted\n"
+    );
+}
+
+#[test]
+fn test_to_text_into() {
+    use crate::parser::Parser;
+
+    let parser = Parser::default();
+    let mut events = Vec::new();
+    let mut out = String::with_capacity(64);
+    for (source, expected) in &[("# Hello", "Hello\n=====\n"), ("World", "World\n")] {
+        parser.parse_into(source, &mut events);
+        to_text_into(events.drain(..), &Default::default(), &mut out);
+        assert_eq!(out, *expected);
+        assert!(out.capacity() >= 64);
+    }
+}
@@ -0,0 +1,255 @@
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, VecDeque};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::event::{
+    AnnotatedEvent, Attrs, DocumentStartEvent, EndTagEvent, Event, StartTagEvent, Tag, TextEvent,
+};
+
+/// Wraps known abbreviations in text with an [`Tag::Abbr`] span.
+///
+/// Abbreviations are taken from the configuration and additionally from
+/// the document's front matter (under the `front_matter_key` key) which
+/// take precedence.  Code and link texts are never modified.
+///
+/// When applied this wraps the stream in a [`AbbreviationsIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Abbreviations {
+    /// Abbreviations mapped to their expanded title.
+    pub abbreviations: BTreeMap<String, String>,
+    /// The front matter key to read additional abbreviations from.
+    pub front_matter_key: Option<String>,
+}
+
+impl Default for Abbreviations {
+    fn default() -> Abbreviations {
+        Abbreviations {
+            abbreviations: BTreeMap::new(),
+            front_matter_key: Some("abbreviations".into()),
+        }
+    }
+}
+
+implement_processor!(
+    Abbreviations,
+    AbbreviationsIter,
+    "Wraps known abbreviations in abbreviation spans",
+);
+
+/// The iterator implementing [`Abbreviations`].
+pub struct AbbreviationsIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    abbreviations: BTreeMap<String, String>,
+    regex: Option<Regex>,
+    link_depth: usize,
+    options: Cow<'options, Abbreviations>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    AbbreviationsIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, Abbreviations>>>(iterator: I, options: O) -> Self {
+        let options = options.into();
+        let abbreviations = options.abbreviations.clone();
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            regex: make_regex(&abbreviations),
+            abbreviations,
+            link_depth: 0,
+            options,
+        }
+    }
+}
+
+fn make_regex(abbreviations: &BTreeMap<String, String>) -> Option<Regex> {
+    // longest abbreviations first so that they win over their prefixes.
+    let mut keys = abbreviations
+        .keys()
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>();
+    if keys.is_empty() {
+        return None;
+    }
+    keys.sort_by_key(|x| Reverse(x.len()));
+    let alternatives = keys
+        .into_iter()
+        .map(|x| regex::escape(x))
+        .collect::<Vec<_>>()
+        .join("|");
+    Some(Regex::new(&format!("(?:{})", alternatives)).unwrap())
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Finds all abbreviations in a text that are not part of a longer word.
+///
+/// `\b` cannot be used for this as abbreviations such as `C++` or `.NET`
+/// start or end with non word characters.
+fn find_abbreviations(regex: &Regex, text: &str) -> Vec<(usize, usize)> {
+    let mut rv = Vec::new();
+    let mut pos = 0;
+    while let Some(m) = regex.find_at(text, pos) {
+        let before = text[..m.start()].chars().next_back();
+        let after = text[m.end()..].chars().next();
+        if before.is_some_and(is_word_char) || after.is_some_and(is_word_char) {
+            pos = m.start() + text[m.start()..].chars().next().map_or(1, char::len_utf8);
+            continue;
+        }
+        rv.push((m.start(), m.end()));
+        pos = m.end();
+    }
+    rv
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for AbbreviationsIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.buffer.pop_front() {
+            return Some(annotated_event);
+        }
+
+        let annotated_event = self.source.next()?;
+        let text = match annotated_event.event {
+            Event::DocumentStart(DocumentStartEvent {
+                front_matter: Some(ref front_matter),
+                ..
+            }) => {
+                if let Some(defs) = self
+                    .options
+                    .front_matter_key
+                    .as_ref()
+                    .and_then(|key| front_matter.get(key))
+                    .and_then(|x| x.as_object())
+                {
+                    for (key, value) in defs {
+                        if let Some(title) = value.as_str() {
+                            self.abbreviations.insert(key.clone(), title.into());
+                        }
+                    }
+                    self.regex = make_regex(&self.abbreviations);
+                }
+                return Some(annotated_event);
+            }
+            Event::StartTag(StartTagEvent { tag: Tag::Link, .. }) => {
+                self.link_depth += 1;
+                return Some(annotated_event);
+            }
+            Event::EndTag(EndTagEvent { tag: Tag::Link }) => {
+                self.link_depth -= 1;
+                return Some(annotated_event);
+            }
+            Event::Text(TextEvent { ref text }) if self.link_depth == 0 => text,
+            _ => return Some(annotated_event),
+        };
+        let regex = match self.regex {
+            Some(ref regex) => regex,
+            None => return Some(annotated_event),
+        };
+
+        let location = annotated_event.location;
+        let mut last = 0;
+        for (start, end) in find_abbreviations(regex, text.as_str()) {
+            if start > last {
+                self.buffer.push_back(AnnotatedEvent::new(
+                    TextEvent {
+                        text: text.slice(last, start),
+                    },
+                    location,
+                ));
+            }
+            self.buffer.push_back(AnnotatedEvent::new(
+                Tag::Abbr.start_tag(Attrs {
+                    title: self
+                        .abbreviations
+                        .get(&text.as_str()[start..end])
+                        .map(|x| x.clone().into()),
+                    ..Attrs::default()
+                }),
+                location,
+            ));
+            self.buffer.push_back(AnnotatedEvent::new(
+                TextEvent {
+                    text: text.slice(start, end),
+                },
+                location,
+            ));
+            self.buffer
+                .push_back(AnnotatedEvent::new(Tag::Abbr.end_tag(), location));
+            last = end;
+        }
+
+        if last == 0 {
+            return Some(annotated_event);
+        }
+        if last < text.as_str().len() {
+            self.buffer.push_back(AnnotatedEvent::new(
+                TextEvent {
+                    text: text.slice(last, text.as_str().len()),
+                },
+                location,
+            ));
+        }
+
+        self.buffer.pop_front()
+    }
+}
+
+#[test]
+fn test_abbreviations() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(Abbreviations::default());
+    assert_eq!(
+        to_html(
+            pipeline.process(
+                "---\nabbreviations:\n  HTML: Hyper Text Markup Language\n---\n\
+                 HTML and `HTML` and [HTML](#)"
+            ),
+            &Default::default()
+        ),
+        "<p><abbr title=\"Hyper Text Markup Language\">HTML</abbr> and <code>HTML</code> \
+         and <a href=\"#\">HTML</a></p>\n"
+    );
+}
+
+#[test]
+fn test_abbreviations_with_symbols() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(Abbreviations {
+        abbreviations: vec![
+            ("C++".to_string(), "C Plus Plus".to_string()),
+            (".NET".to_string(), "Dot Net".to_string()),
+            (
+                "API".to_string(),
+                "Application Programming Interface".to_string(),
+            ),
+        ]
+        .into_iter()
+        .collect(),
+        ..Abbreviations::default()
+    });
+    assert_eq!(
+        to_html(
+            pipeline.process("C++ and .NET, not APIs or C++x."),
+            &Default::default()
+        ),
+        "<p><abbr title=\"C Plus Plus\">C++</abbr> and <abbr title=\"Dot Net\">.NET</abbr>, \
+         not APIs or C++x.</p>\n"
+    );
+}
@@ -0,0 +1,206 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+
+use regex::RegexSet;
+use serde::{Deserialize, Serialize};
+
+use crate::event::{
+    AnnotatedEvent, ErrorEvent, Event, ImageEvent, MetaDataEvent, Severity, StartTagEvent, Str, Tag,
+};
+use crate::value::to_value;
+
+/// Collects referenced assets into a manifest.
+///
+/// Every image and link target that matches one of the `patterns` (regular
+/// expressions, all targets if empty) is recorded once in the order of first
+/// appearance and emitted at the end of the stream as meta data under
+/// `metadata_key`.  Targets found in `rewrites` are replaced with the mapped
+/// value (for instance a fingerprinted path).
+///
+/// When applied this wraps the stream in a [`AssetManifestIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AssetManifest {
+    /// Regular expressions the targets have to match.
+    pub patterns: Vec<String>,
+    /// Collects image targets.
+    pub include_images: bool,
+    /// Collects link targets.
+    pub include_links: bool,
+    /// Maps targets to the targets they should be rewritten to.
+    pub rewrites: BTreeMap<String, String>,
+    /// The key of the emitted meta data.
+    pub metadata_key: String,
+}
+
+impl Default for AssetManifest {
+    fn default() -> AssetManifest {
+        AssetManifest {
+            patterns: Vec::new(),
+            include_images: true,
+            include_links: true,
+            rewrites: BTreeMap::new(),
+            metadata_key: "assets".into(),
+        }
+    }
+}
+
+implement_processor!(
+    AssetManifest,
+    AssetManifestIter,
+    "Collects referenced assets into a manifest",
+);
+
+/// An entry in the asset manifest.
+#[derive(Serialize, Debug)]
+struct Asset {
+    kind: &'static str,
+    target: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rewritten: Option<String>,
+}
+
+/// The iterator implementing [`AssetManifest`].
+pub struct AssetManifestIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    patterns: Result<Option<RegexSet>, regex::Error>,
+    reported_error: bool,
+    seen: HashSet<String>,
+    assets: Option<Vec<Asset>>,
+    options: Cow<'options, AssetManifest>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    AssetManifestIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, AssetManifest>>>(iterator: I, options: O) -> Self {
+        let options = options.into();
+        Self {
+            source: iterator,
+            patterns: if options.patterns.is_empty() {
+                Ok(None)
+            } else {
+                RegexSet::new(&options.patterns).map(Some)
+            },
+            reported_error: false,
+            seen: HashSet::new(),
+            assets: Some(Vec::new()),
+            options,
+        }
+    }
+
+    /// Records a target and returns the rewritten target if there is one.
+    fn record(&mut self, kind: &'static str, target: &str) -> Option<Str<'data>> {
+        let matches = match self.patterns {
+            Ok(Some(ref patterns)) => patterns.is_match(target),
+            _ => true,
+        };
+        if !matches {
+            return None;
+        }
+        let rewritten = self.options.rewrites.get(target).cloned();
+        if self.seen.insert(target.to_string()) {
+            if let Some(ref mut assets) = self.assets {
+                assets.push(Asset {
+                    kind,
+                    target: target.to_string(),
+                    rewritten: rewritten.clone(),
+                });
+            }
+        }
+        rewritten.map(Into::into)
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for AssetManifestIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.reported_error {
+            self.reported_error = true;
+            if let Err(ref err) = self.patterns {
+                return Some(
+                    ErrorEvent {
+                        title: "Invalid asset manifest pattern".into(),
+                        description: Some(err.to_string().into()),
+                        severity: Severity::Error,
+                    }
+                    .into(),
+                );
+            }
+        }
+
+        let mut annotated_event = match self.source.next() {
+            Some(annotated_event) => annotated_event,
+            None => {
+                let assets = self.assets.take()?;
+                return Some(
+                    MetaDataEvent {
+                        key: self.options.metadata_key.clone().into(),
+                        value: to_value(&assets).expect("bad asset manifest"),
+                    }
+                    .into(),
+                );
+            }
+        };
+
+        match annotated_event.event {
+            Event::Image(ImageEvent { ref mut target, .. }) if self.options.include_images => {
+                let new_target = self.record("image", target.as_str());
+                if let Some(new_target) = new_target {
+                    *target = new_target;
+                }
+            }
+            Event::StartTag(StartTagEvent {
+                tag: Tag::Link,
+                ref mut attrs,
+            }) if self.options.include_links => {
+                if let Some(ref mut target) = attrs.target {
+                    let new_target = self.record("link", target.as_str());
+                    if let Some(new_target) = new_target {
+                        *target = new_target;
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Some(annotated_event)
+    }
+}
+
+#[test]
+fn test_asset_manifest() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    let mut rewrites = BTreeMap::new();
+    rewrites.insert("logo.png".to_string(), "logo.1234.png".to_string());
+    pipeline.add_processor(AssetManifest {
+        patterns: vec![r"\.(png|pdf)$".into()],
+        rewrites,
+        ..Default::default()
+    });
+    let events = pipeline
+        .process("![Logo](logo.png) [Doc](doc.pdf) [Home](/) ![Logo](logo.png)")
+        .collect::<Vec<_>>();
+    let meta = match events.last().unwrap().event {
+        Event::MetaData(ref meta) => meta.value.clone(),
+        _ => panic!("expected meta data"),
+    };
+    assert_eq!(
+        meta,
+        crate::value::value!([
+            {"kind": "image", "target": "logo.png", "rewritten": "logo.1234.png"},
+            {"kind": "link", "target": "doc.pdf"},
+        ])
+    );
+    assert_eq!(
+        to_html(events.into_iter(), &Default::default()),
+        "<p><img src=\"logo.1234.png\" alt=\"Logo\" title=\"\"> <a href=\"doc.pdf\">Doc</a> \
+         <a href=\"&#x2f;\">Home</a> <img src=\"logo.1234.png\" alt=\"Logo\" title=\"\"></p>\n"
+    );
+}
@@ -1,33 +1,356 @@
 use std::borrow::Cow;
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
 
+use deunicode::deunicode_char;
 use serde::{Deserialize, Serialize};
-use slug::slugify;
 
-use crate::event::{AnnotatedEvent, Event, StartTagEvent};
+use crate::error::Error;
+use crate::event::{
+    AnnotatedEvent, DocumentStartEvent, ErrorEvent, Event, MetaDataEvent, Severity, StartTagEvent,
+};
+use crate::processors::BuiltinProcessor;
+use crate::value::to_value;
+
+/// A user supplied function to turn heading text into an anchor.
+#[derive(Clone)]
+pub struct SlugifyFn(Arc<dyn Fn(&str) -> String + Send + Sync>);
+
+impl SlugifyFn {
+    /// Wraps a function for use as slugify callback.
+    pub fn new<F: Fn(&str) -> String + Send + Sync + 'static>(f: F) -> SlugifyFn {
+        SlugifyFn(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for SlugifyFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SlugifyFn").finish()
+    }
+}
+
+/// A heading anchor recorded in an [`AnchorRegistry`].
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct PublishedAnchor {
+    /// The text of the heading.
+    pub text: String,
+    /// The revision the anchor was first seen in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_seen: Option<String>,
+}
+
+/// Remembers the heading anchors of previous builds.
+///
+/// Anchors end up in permalinks so they should not change once published.
+/// When [`AutoAnchors`] is given a registry it records every heading anchor
+/// and warns if a heading that was published before gets a different anchor
+/// or if a published anchor disappears.  The registry is meant to be loaded
+/// before and saved after a build:
+///
+/// ```
+/// # use struckdown::processors::{AnchorRegistry, AutoAnchors, SharedAnchorRegistry};
+/// # use struckdown::pipeline::Pipeline;
+/// # let path = std::env::temp_dir().join("struckdown-anchors-doc.json");
+/// let registry = SharedAnchorRegistry::new(AnchorRegistry::load(&path).unwrap(), Some("v2"));
+/// let mut pipeline = Pipeline::new();
+/// pipeline.add_processor(AutoAnchors {
+///     registry: Some(registry.clone()),
+///     ..Default::default()
+/// });
+/// pipeline.process("---\npath: index.md\n---\n# Hello").for_each(drop);
+/// registry.lock().save(&path).unwrap();
+/// # std::fs::remove_file(&path).ok();
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct AnchorRegistry {
+    documents: BTreeMap<String, BTreeMap<String, PublishedAnchor>>,
+}
+
+impl AnchorRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> AnchorRegistry {
+        AnchorRegistry::default()
+    }
+
+    /// Loads a registry from a JSON file.
+    ///
+    /// A missing file results in an empty registry.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<AnchorRegistry, Error> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(AnchorRegistry::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Saves the registry as JSON file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Returns the number of recorded anchors.
+    pub fn len(&self) -> usize {
+        self.documents.values().map(|x| x.len()).sum()
+    }
+
+    /// Returns `true` if no anchors are recorded.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Looks up a recorded anchor of a document.
+    pub fn get(&self, document: &str, anchor: &str) -> Option<&PublishedAnchor> {
+        self.documents.get(document)?.get(anchor)
+    }
+
+    /// Iterates over the recorded anchors of a document.
+    pub fn anchors<'a>(
+        &'a self,
+        document: &str,
+    ) -> impl Iterator<Item = (&'a str, &'a PublishedAnchor)> {
+        self.documents
+            .get(document)
+            .into_iter()
+            .flat_map(|anchors| anchors.iter().map(|(anchor, x)| (anchor.as_str(), x)))
+    }
+
+    /// Finds the anchor a heading with the given text was published under.
+    pub fn find_by_text(&self, document: &str, text: &str) -> Option<&str> {
+        self.anchors(document)
+            .find(|(_, published)| published.text == text)
+            .map(|(anchor, _)| anchor)
+    }
+
+    /// Records an anchor.
+    ///
+    /// If the anchor is already known its text is updated but the revision
+    /// it was first seen in is retained.  Returns `true` if the anchor is new.
+    pub fn record(
+        &mut self,
+        document: &str,
+        anchor: &str,
+        text: &str,
+        revision: Option<&str>,
+    ) -> bool {
+        let anchors = self.documents.entry(document.to_string()).or_default();
+        match anchors.get_mut(anchor) {
+            Some(published) => {
+                if published.text != text {
+                    published.text = text.to_string();
+                }
+                false
+            }
+            None => {
+                anchors.insert(
+                    anchor.to_string(),
+                    PublishedAnchor {
+                        text: text.to_string(),
+                        first_seen: revision.map(|x| x.to_string()),
+                    },
+                );
+                true
+            }
+        }
+    }
+
+    /// Removes a recorded anchor.
+    pub fn remove(&mut self, document: &str, anchor: &str) -> Option<PublishedAnchor> {
+        self.documents.get_mut(document)?.remove(anchor)
+    }
+}
+
+/// An [`AnchorRegistry`] shared between pipeline runs.
+///
+/// Cloning the handle shares the registry.
+#[derive(Debug, Clone)]
+pub struct SharedAnchorRegistry {
+    registry: Arc<Mutex<AnchorRegistry>>,
+    revision: Option<String>,
+}
+
+impl SharedAnchorRegistry {
+    /// Shares a registry recording new anchors with the given revision.
+    pub fn new<R: Into<String>>(registry: AnchorRegistry, revision: Option<R>) -> Self {
+        SharedAnchorRegistry {
+            registry: Arc::new(Mutex::new(registry)),
+            revision: revision.map(Into::into),
+        }
+    }
+
+    /// Returns the revision new anchors are recorded with.
+    pub fn revision(&self) -> Option<&str> {
+        self.revision.as_deref()
+    }
+
+    /// Locks the registry.
+    pub fn lock(&self) -> MutexGuard<'_, AnchorRegistry> {
+        self.registry.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
 
 /// Automatically add anchors to all headers when missing.
 ///
+/// By default the anchors are generated the same way as `slug::slugify`
+/// does it which can be customized with the options below.  If a
+/// custom `slugify` function is set, it's used instead of the built-in
+/// logic (the `prefix` is still applied).
+///
 /// When applied this wraps the stream in a [`AutoAnchorsIter`].
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(default)]
 pub struct AutoAnchors {
     /// The maximum level of headline that should get IDs.
     pub max_level: usize,
+    /// Transliterates non ASCII characters (eg: `ü` becomes `u`).
+    pub transliterate: bool,
+    /// Lowercases the anchors.
+    pub lowercase: bool,
+    /// Drops all non ASCII characters.  When disabled unicode letters and
+    /// digits that were not transliterated are retained.
+    pub ascii_only: bool,
+    /// The maximum length of the anchor in characters (excluding the prefix).
+    pub max_length: Option<usize>,
+    /// A prefix to add to all generated anchors.
+    pub prefix: Option<String>,
+    /// Controls if a map of all heading anchors should be emitted as meta data.
+    pub emit_metadata: bool,
+    /// The meta data key for the anchor map.
+    pub metadata_key: String,
+    /// A custom slugify function.  This can only be set from code.
+    #[serde(skip)]
+    pub slugify: Option<SlugifyFn>,
+    /// A registry of published anchors to check against and update.  This
+    /// can only be set from code.
+    #[serde(skip)]
+    pub registry: Option<SharedAnchorRegistry>,
+    /// The front matter key naming the document in the registry.  This is
+    /// only used if the document is not processed with a path (see
+    /// [`Pipeline::process_with_path`](crate::pipeline::Pipeline::process_with_path)).
+    /// Documents without a name are recorded under an empty name and are not
+    /// checked for removed anchors.
+    pub document_key: String,
 }
 
 impl Default for AutoAnchors {
     fn default() -> AutoAnchors {
-        AutoAnchors { max_level: 6 }
+        AutoAnchors {
+            max_level: 6,
+            transliterate: true,
+            lowercase: true,
+            ascii_only: true,
+            max_length: None,
+            prefix: None,
+            emit_metadata: false,
+            metadata_key: "anchors".into(),
+            slugify: None,
+            registry: None,
+            document_key: "path".into(),
+        }
+    }
+}
+
+impl AutoAnchors {
+    /// Returns the configuration unless options only settable from code are used.
+    fn builtin_config(&self) -> Option<BuiltinProcessor> {
+        if self.slugify.is_some() || self.registry.is_some() {
+            return None;
+        }
+        Some(self.clone().into())
+    }
+
+    /// Applies the processor to a document naming it by its path.
+    fn apply_with_path<'data, 'options: 'data>(
+        &'options self,
+        iter: Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data>,
+        path: Option<&'data Path>,
+    ) -> AutoAnchorsIter<'data, 'options, Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data>>
+    {
+        let mut rv = AutoAnchorsIter::new(iter, Cow::Borrowed(self));
+        rv.document = path.map(|x| x.to_string_lossy().into_owned());
+        rv
+    }
+
+    /// Generates an anchor for some heading text.
+    pub fn make_anchor(&self, text: &str) -> String {
+        let slug = match self.slugify {
+            Some(ref slugify) => (slugify.0)(text),
+            None => self.default_slugify(text),
+        };
+        match self.prefix {
+            Some(ref prefix) => format!("{}{}", prefix, slug),
+            None => slug,
+        }
+    }
+
+    fn default_slugify(&self, text: &str) -> String {
+        let mut rv = String::with_capacity(text.len());
+        let mut prev_is_dash = true;
+        {
+            let mut push_char = |c: char| {
+                if c.is_alphanumeric() && (c.is_ascii() || !self.ascii_only) {
+                    if self.lowercase {
+                        rv.extend(c.to_lowercase());
+                    } else {
+                        rv.push(c);
+                    }
+                    prev_is_dash = false;
+                } else if !prev_is_dash {
+                    rv.push('-');
+                    prev_is_dash = true;
+                }
+            };
+
+            for c in text.chars() {
+                if c.is_ascii() || !self.transliterate {
+                    push_char(c);
+                } else {
+                    for c in deunicode_char(c).unwrap_or("-").chars() {
+                        push_char(c);
+                    }
+                }
+            }
+        }
+
+        if let Some(max_length) = self.max_length {
+            if let Some((idx, _)) = rv.char_indices().nth(max_length) {
+                rv.truncate(idx);
+            }
+        }
+        while rv.ends_with('-') {
+            rv.pop();
+        }
+        rv
     }
 }
 
-implement_processor!(AutoAnchors, AutoAnchorsIter);
+implement_processor!(
+    AutoAnchors,
+    AutoAnchorsIter,
+    "Adds anchors to headers that do not have one",
+    to_builtin = AutoAnchors::builtin_config,
+    with_path = AutoAnchors::apply_with_path,
+);
+
+#[derive(Serialize, Debug)]
+struct AnchorInfo {
+    text: String,
+    anchor: String,
+    level: usize,
+}
 
 /// The iterator implementing [`AutoAnchors`].
 pub struct AutoAnchorsIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
     source: I,
     buffer: VecDeque<AnnotatedEvent<'data>>,
+    anchors: Option<Vec<AnchorInfo>>,
+    document: Option<String>,
+    seen: Option<BTreeSet<String>>,
     options: Cow<'options, AutoAnchors>,
 }
 
@@ -35,10 +358,87 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
     AutoAnchorsIter<'data, 'options, I>
 {
     pub fn new<O: Into<Cow<'options, AutoAnchors>>>(iterator: I, options: O) -> Self {
+        let options = options.into();
         Self {
             source: iterator,
             buffer: VecDeque::new(),
-            options: options.into(),
+            anchors: if options.emit_metadata {
+                Some(Vec::new())
+            } else {
+                None
+            },
+            document: None,
+            seen: if options.registry.is_some() {
+                Some(BTreeSet::new())
+            } else {
+                None
+            },
+            options,
+        }
+    }
+
+    /// Checks a heading anchor against the registry and records it.
+    fn check_registry(&mut self, text: &str, anchor: &str) -> Option<ErrorEvent<'data>> {
+        let registry = self.options.registry.as_ref()?;
+        let seen = self.seen.as_mut()?;
+        let document = self.document.as_deref().unwrap_or("");
+        let mut guard = registry.lock();
+        seen.insert(anchor.to_string());
+        let unchanged = guard
+            .get(document, anchor)
+            .is_some_and(|published| published.text == text);
+        let rv = match guard.find_by_text(document, text) {
+            Some(published) if !unchanged && !seen.contains(published) => {
+                seen.insert(published.to_string());
+                Some(ErrorEvent {
+                    title: "published anchor changed".into(),
+                    description: Some(
+                        format!(
+                            "heading '{}' was published as #{} but is now #{}",
+                            text, published, anchor
+                        )
+                        .into(),
+                    ),
+                    severity: Severity::Warning,
+                })
+            }
+            _ => None,
+        };
+        guard.record(document, anchor, text, registry.revision());
+        rv
+    }
+
+    /// Queues warnings for published anchors that were not seen.
+    ///
+    /// This is skipped for documents without a name as their anchors cannot
+    /// be told apart from the ones of other unnamed documents.
+    fn queue_missing_anchors(&mut self) {
+        let (registry, seen, document) = match (
+            self.options.registry.as_ref(),
+            self.seen.take(),
+            self.document.as_ref(),
+        ) {
+            (Some(registry), Some(seen), Some(document)) => (registry, seen, document),
+            _ => return,
+        };
+        let guard = registry.lock();
+        for (anchor, published) in guard.anchors(document) {
+            if !seen.contains(anchor) {
+                self.buffer.push_back(
+                    ErrorEvent {
+                        title: "published anchor removed".into(),
+                        description: Some(
+                            format!(
+                                "anchor #{} of heading '{}' is no longer generated",
+                                anchor, published.text
+                            )
+                            .into(),
+                        ),
+                        severity: Severity::Warning,
+                    }
+                    .into(),
+                );
+            }
         }
     }
 }
@@ -53,44 +453,238 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
             return Some(annotated_event);
         }
 
-        self.source.next().map(|mut annotated_event| {
-            let (header_level, attrs) = match annotated_event.event {
-                Event::StartTag(StartTagEvent { tag, ref mut attrs }) => {
-                    if let Some(header_level) = tag.header_level() {
-                        (header_level, attrs)
-                    } else {
-                        return annotated_event;
-                    }
+        let mut annotated_event = match self.source.next() {
+            Some(annotated_event) => annotated_event,
+            None => {
+                self.queue_missing_anchors();
+                let key = &self.options.metadata_key;
+                if let Some(anchors) = self.anchors.take() {
+                    self.buffer.push_back(
+                        MetaDataEvent {
+                            key: key.clone().into(),
+                            value: to_value(&anchors).expect("bad anchor map"),
+                        }
+                        .into(),
+                    );
                 }
-                _ => return annotated_event,
-            };
+                return self.buffer.pop_front();
+            }
+        };
 
-            if attrs.id.is_some() || header_level > self.options.max_level {
-                return annotated_event;
+        let location = annotated_event.location;
+        let (header_level, attrs) = match annotated_event.event {
+            Event::DocumentStart(DocumentStartEvent {
+                ref front_matter, ..
+            }) => {
+                if self.document.is_none() {
+                    self.document = front_matter
+                        .as_ref()
+                        .and_then(|x| x.get(&self.options.document_key))
+                        .and_then(|x| x.as_str())
+                        .map(|x| x.to_string());
+                }
+                return Some(annotated_event);
+            }
+            Event::StartTag(StartTagEvent { tag, ref mut attrs }) => {
+                if let Some(header_level) = tag.header_level() {
+                    (header_level, attrs)
+                } else {
+                    return Some(annotated_event);
+                }
             }
+            _ => return Some(annotated_event),
+        };
 
-            let mut depth = 1;
-            let mut raw_text = String::new();
+        let needs_id = attrs.id.is_none() && header_level <= self.options.max_level;
+        if !needs_id && self.anchors.is_none() && self.seen.is_none() {
+            return Some(annotated_event);
+        }
 
-            while let Some(next_annotated_event) = self.source.next() {
-                match next_annotated_event.event {
-                    Event::StartTag(..) => depth += 1,
-                    Event::EndTag(..) => depth -= 1,
-                    ref event => {
-                        if let Some(text) = event.raw_text() {
-                            raw_text.push_str(text.as_str());
-                        }
+        let mut depth = 1;
+        let mut raw_text = String::new();
+
+        for next_annotated_event in self.source.by_ref() {
+            match next_annotated_event.event {
+                Event::StartTag(..) => depth += 1,
+                Event::EndTag(..) => depth -= 1,
+                ref event => {
+                    if let Some(text) = event.raw_text() {
+                        raw_text.push_str(text.as_str());
                     }
                 }
-                self.buffer.push_back(next_annotated_event);
-                if depth == 0 {
-                    break;
-                }
             }
+            self.buffer.push_back(next_annotated_event);
+            if depth == 0 {
+                break;
+            }
+        }
+
+        if needs_id {
+            attrs.id = Some(self.options.make_anchor(&raw_text).into());
+        }
 
-            attrs.id = Some(slugify(raw_text).into());
+        let id = match attrs.id {
+            Some(ref id) => id.as_str().to_string(),
+            None => return Some(annotated_event),
+        };
 
-            annotated_event
-        })
+        let warning = self.check_registry(&raw_text, &id);
+
+        if let Some(anchors) = self.anchors.as_mut() {
+            anchors.push(AnchorInfo {
+                text: raw_text,
+                anchor: id,
+                level: header_level,
+            });
+        }
+
+        if let Some(warning) = warning {
+            self.buffer.push_front(annotated_event);
+            return Some(AnnotatedEvent::new(warning, location));
+        }
+
+        Some(annotated_event)
     }
 }
+
+#[test]
+fn test_slugify_options() {
+    let options = AutoAnchors::default();
+    assert_eq!(options.make_anchor("Æúű--cool?"), "aeuu-cool");
+    assert_eq!(options.make_anchor("You & Me"), "you-me");
+
+    let options = AutoAnchors {
+        transliterate: false,
+        ascii_only: false,
+        lowercase: false,
+        max_length: Some(8),
+        prefix: Some("h-".into()),
+        ..Default::default()
+    };
+    assert_eq!(options.make_anchor("Über alles Dinge"), "h-Über-all");
+
+    let options = AutoAnchors {
+        slugify: Some(SlugifyFn::new(|text| text.replace(' ', "_"))),
+        ..Default::default()
+    };
+    assert_eq!(options.make_anchor("Hello World"), "Hello_World");
+}
+
+#[test]
+fn test_anchor_metadata() {
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(AutoAnchors {
+        emit_metadata: true,
+        ..Default::default()
+    });
+    let events = pipeline
+        .process("# Hello World\n\n## Sub {#custom}")
+        .collect::<Vec<_>>();
+    let anchors = pipeline
+        .extract_metadata(events.iter())
+        .find(|(key, _)| key.as_str() == "anchors")
+        .map(|(_, value)| value.clone())
+        .unwrap();
+    assert_eq!(
+        anchors,
+        crate::value::value!([
+            {"text": "Hello World", "anchor": "hello-world", "level": 1},
+            {"text": "Sub", "anchor": "custom", "level": 2},
+        ])
+    );
+}
+
+#[test]
+fn test_anchor_registry() {
+    use crate::pipeline::Pipeline;
+
+    let registry = SharedAnchorRegistry::new(AnchorRegistry::new(), Some("v1"));
+    let render = |source: &str, max_length: Option<usize>| {
+        let mut pipeline = Pipeline::new();
+        pipeline.add_processor(AutoAnchors {
+            registry: Some(registry.clone()),
+            max_length,
+            ..Default::default()
+        });
+        pipeline
+            .process(source)
+            .filter_map(|x| match x.event {
+                Event::Error(err) => Some((err.title.as_str().to_string(), x.location.is_some())),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let source = "---\npath: index.md\n---\n# Hello World\n\n## Sub {#custom}";
+    assert!(render(source, None).is_empty());
+    assert_eq!(registry.lock().len(), 2);
+    assert!(render(source, None).is_empty());
+
+    assert_eq!(
+        render(source, Some(5)),
+        vec![("published anchor changed".to_string(), true)]
+    );
+    assert_eq!(
+        render("---\npath: index.md\n---\n# Hello World", None),
+        vec![
+            ("published anchor removed".to_string(), false),
+            ("published anchor removed".to_string(), false),
+        ]
+    );
+    assert!(render("# Hello World", None).is_empty());
+
+    let registry = registry.lock();
+    let published = registry.get("index.md", "hello-world").unwrap();
+    assert_eq!(published.first_seen.as_deref(), Some("v1"));
+    assert_eq!(registry.find_by_text("index.md", "Sub"), Some("custom"));
+    assert_eq!(registry.get("", "hello-world"), Some(published));
+    let roundtrip: AnchorRegistry =
+        serde_json::from_str(&serde_json::to_string(&*registry).unwrap()).unwrap();
+    assert_eq!(roundtrip, *registry);
+}
+
+#[test]
+fn test_anchor_registry_paths() {
+    use crate::pipeline::Pipeline;
+    use std::path::Path;
+
+    let registry = SharedAnchorRegistry::new(AnchorRegistry::new(), None::<String>);
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(AutoAnchors {
+        registry: Some(registry.clone()),
+        ..Default::default()
+    });
+    let warnings = |source: &str, path: Option<&str>| {
+        let events: Vec<_> = match path {
+            Some(path) => pipeline
+                .process_with_path(source, Path::new(path))
+                .collect(),
+            None => pipeline.process(source).collect(),
+        };
+        events
+            .into_iter()
+            .filter_map(|x| match x.event {
+                Event::Error(err) => Some(err.title.as_str().to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // documents are keyed by their path which wins over the front matter
+    assert!(warnings("---\npath: other.md\n---\n# Intro", Some("a.md")).is_empty());
+    assert!(warnings("# Setup", Some("b.md")).is_empty());
+    assert!(registry.lock().get("a.md", "intro").is_some());
+    assert!(registry.lock().get("other.md", "intro").is_none());
+    assert!(registry.lock().get("b.md", "setup").is_some());
+    assert_eq!(
+        warnings("# Changed", Some("b.md")),
+        vec!["published anchor removed"]
+    );
+
+    // unnamed documents are not checked for removed anchors
+    assert!(warnings("# One", None).is_empty());
+    assert!(warnings("# Two", None).is_empty());
+    assert!(registry.lock().get("", "one").is_some());
+}
@@ -0,0 +1,128 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Event, MetaDataEvent};
+use crate::link_graph::LinkGraph;
+use crate::value::to_value;
+
+/// Emits the documents linking to the current document.
+///
+/// The links are looked up in a [`LinkGraph`] that was built from all
+/// documents of the project beforehand.  The backlinks of `document` are
+/// emitted as meta data under `metadata_key` directly after the document
+/// start.
+///
+/// When applied this wraps the stream in a [`BacklinksIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Backlinks {
+    /// The path of the current document in the link graph.
+    pub document: String,
+    /// The links between all documents.
+    pub graph: LinkGraph,
+    /// The key of the emitted meta data.
+    pub metadata_key: String,
+}
+
+impl Default for Backlinks {
+    fn default() -> Backlinks {
+        Backlinks {
+            document: String::new(),
+            graph: LinkGraph::new(),
+            metadata_key: "backlinks".into(),
+        }
+    }
+}
+
+implement_processor!(
+    Backlinks,
+    BacklinksIter,
+    "Emits the documents linking to the current document",
+);
+
+/// The iterator implementing [`Backlinks`].
+pub struct BacklinksIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    pending: Option<AnnotatedEvent<'data>>,
+    emitted: bool,
+    options: Cow<'options, Backlinks>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> BacklinksIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Backlinks>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            pending: None,
+            emitted: false,
+            options: options.into(),
+        }
+    }
+
+    fn metadata(&self) -> AnnotatedEvent<'data> {
+        MetaDataEvent {
+            key: self.options.metadata_key.clone().into(),
+            value: to_value(self.options.graph.backlinks(&self.options.document)).unwrap(),
+        }
+        .into()
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for BacklinksIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.pending.take() {
+            return Some(annotated_event);
+        }
+        if self.emitted {
+            return self.source.next();
+        }
+
+        self.emitted = true;
+        let annotated_event = self.source.next();
+        match annotated_event {
+            Some(annotated_event) if matches!(annotated_event.event, Event::DocumentStart(..)) => {
+                self.pending = Some(self.metadata());
+                Some(annotated_event)
+            }
+            annotated_event => {
+                self.pending = annotated_event;
+                Some(self.metadata())
+            }
+        }
+    }
+}
+
+#[test]
+fn test_backlinks() {
+    use crate::pipeline::Pipeline;
+    use crate::value::value;
+
+    let mut graph = LinkGraph::new();
+    let pipeline = Pipeline::default();
+    let index = pipeline
+        .process("See [the guide](guide.md#setup)")
+        .collect::<Vec<_>>();
+    graph.add_document("index.md", index.iter());
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(Backlinks {
+        document: "guide.md".into(),
+        graph,
+        ..Default::default()
+    });
+    let metadata = pipeline
+        .process("# Guide")
+        .find_map(|x| match x.event {
+            Event::MetaData(MetaDataEvent { value, .. }) => Some(value),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(
+        metadata,
+        value!([{"document": "index.md", "anchor": "setup"}])
+    );
+}
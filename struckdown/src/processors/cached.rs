@@ -0,0 +1,239 @@
+use std::any::type_name;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::event::{AnnotatedEvent, Event, Severity};
+use crate::hash::hash_json;
+use crate::processors::Processor;
+
+/// Caches the output of a processor on disk.
+///
+/// The input events are hashed together with the processor's type and
+/// options.  If the cache directory contains an output for that hash it's
+/// loaded instead of running the processor, otherwise the processor runs and
+/// its output is stored.  This avoids re-running expensive processors (such
+/// as syntax highlighting or external commands) on unchanged content.
+///
+/// As the hash has to be calculated first, the input stream is buffered
+/// entirely.  Outputs containing errors are not stored so that failures
+/// (such as a timed out external command) are retried on the next run.
+/// Failures to read or write the cache are ignored and make the processor
+/// run as if it was not cached.
+///
+/// ```
+/// # use struckdown::processors::{Cached, Syntect};
+/// # use struckdown::pipeline::Pipeline;
+/// let mut pipeline = Pipeline::new();
+/// pipeline.add_processor(Cached::new(Syntect::default(), "target/struckdown-cache"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Cached<P> {
+    processor: P,
+    cache_dir: PathBuf,
+}
+
+impl<P: Processor + Serialize> Cached<P> {
+    /// Wraps a processor to cache its output in the given directory.
+    pub fn new<D: AsRef<Path>>(processor: P, cache_dir: D) -> Cached<P> {
+        Cached {
+            processor,
+            cache_dir: cache_dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Returns the wrapped processor.
+    pub fn processor(&self) -> &P {
+        &self.processor
+    }
+
+    /// Returns the cache directory.
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
+    fn cache_path(&self, events: &[AnnotatedEvent<'_>]) -> Option<PathBuf> {
+        let hash = hash_json(&(
+            env!("CARGO_PKG_VERSION"),
+            type_name::<P>(),
+            &self.processor,
+            events,
+        ))?;
+        Some(self.cache_dir.join(format!("{:016x}.json", hash)))
+    }
+}
+
+fn load_events(path: &Path) -> Option<Vec<AnnotatedEvent<'static>>> {
+    let contents = fs::read_to_string(path).ok()?;
+    serde_json::from_str::<Vec<AnnotatedEvent<'_>>>(&contents)
+        .ok()
+        .map(|events| events.into_iter().map(|x| x.into_static()).collect())
+}
+
+fn store_events(cache_dir: &Path, path: &Path, events: &[AnnotatedEvent<'_>]) {
+    let failed = events
+        .iter()
+        .any(|x| matches!(x.event, Event::Error(ref err) if err.severity == Severity::Error));
+    if failed || fs::create_dir_all(cache_dir).is_err() {
+        return;
+    }
+    if let Ok(contents) = serde_json::to_string(events) {
+        // write to a temporary file first so that concurrent readers never
+        // see partial outputs.
+        let tmp = path.with_extension("tmp");
+        if fs::write(&tmp, contents).is_ok() {
+            fs::rename(&tmp, path).ok();
+        }
+    }
+}
+
+impl<P: Processor + Serialize> Processor for Cached<P> {
+    fn apply<'data>(
+        self: Box<Self>,
+        iter: Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data>,
+    ) -> Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data> {
+        let input = iter.collect::<Vec<_>>();
+        let path = self.cache_path(&input);
+        if let Some(events) = path.as_deref().and_then(load_events) {
+            return Box::new(events.into_iter());
+        }
+        let cache_dir = self.cache_dir.clone();
+        let output = Box::new(self.processor)
+            .apply(Box::new(input.into_iter()))
+            .collect::<Vec<_>>();
+        if let Some(path) = path {
+            store_events(&cache_dir, &path, &output);
+        }
+        Box::new(output.into_iter())
+    }
+
+    fn apply_ref<'data, 'options: 'data>(
+        &'options self,
+        iter: Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data>,
+    ) -> Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data> {
+        let input = iter.collect::<Vec<_>>();
+        let path = self.cache_path(&input);
+        if let Some(events) = path.as_deref().and_then(load_events) {
+            return Box::new(events.into_iter());
+        }
+        let output = self
+            .processor
+            .apply_ref(Box::new(input.into_iter()))
+            .collect::<Vec<_>>();
+        if let Some(path) = path {
+            store_events(&self.cache_dir, &path, &output);
+        }
+        Box::new(output.into_iter())
+    }
+
+    fn name(&self) -> &'static str {
+        self.processor.name()
+    }
+
+    fn description(&self) -> &'static str {
+        self.processor.description()
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        self.processor.check()
+    }
+}
+
+#[test]
+fn test_cached() {
+    use crate::event::TextEvent;
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+    use crate::processors::HtmlMinify;
+
+    let cache_dir = std::env::temp_dir().join(format!("struckdown-cache-{}", std::process::id()));
+    fs::remove_dir_all(&cache_dir).ok();
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(Cached::new(HtmlMinify::default(), &cache_dir));
+    assert_eq!(
+        to_html(pipeline.process("Hello"), &Default::default()),
+        "<p>Hello</p>\n"
+    );
+
+    // tamper with the cached output to see that it's used
+    let entries = fs::read_dir(&cache_dir)
+        .unwrap()
+        .map(|x| x.unwrap().path())
+        .collect::<Vec<_>>();
+    assert_eq!(entries.len(), 1);
+    let cached: Vec<AnnotatedEvent> = vec![Event::from(TextEvent {
+        text: "From cache".into(),
+    })
+    .into()];
+    fs::write(&entries[0], serde_json::to_string(&cached).unwrap()).unwrap();
+    assert_eq!(
+        to_html(pipeline.process("Hello"), &Default::default()),
+        "From cache"
+    );
+    assert_eq!(
+        to_html(pipeline.process("Other"), &Default::default()),
+        "<p>Other</p>\n"
+    );
+
+    fs::remove_dir_all(&cache_dir).ok();
+}
+
+#[test]
+fn test_cached_failure() {
+    use std::cell::Cell;
+
+    use crate::event::ErrorEvent;
+    use crate::pipeline::Pipeline;
+
+    /// Appends an error and counts how often it ran.
+    #[derive(Serialize)]
+    struct Failing<'a> {
+        #[serde(skip)]
+        runs: &'a Cell<usize>,
+    }
+
+    impl<'a> Processor for Failing<'a> {
+        fn apply<'data>(
+            self: Box<Self>,
+            iter: Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data>,
+        ) -> Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data> {
+            self.runs.set(self.runs.get() + 1);
+            Box::new(
+                iter.chain(Some(
+                    ErrorEvent {
+                        title: "failed".into(),
+                        description: None,
+                        severity: Severity::Error,
+                    }
+                    .into(),
+                )),
+            )
+        }
+
+        fn apply_ref<'data, 'options: 'data>(
+            &'options self,
+            iter: Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data>,
+        ) -> Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data> {
+            Box::new(Failing { runs: self.runs }).apply(iter)
+        }
+    }
+
+    let cache_dir =
+        std::env::temp_dir().join(format!("struckdown-cache-failure-{}", std::process::id()));
+    fs::remove_dir_all(&cache_dir).ok();
+
+    let runs = Cell::new(0);
+    let cached = Cached::new(Failing { runs: &runs }, &cache_dir);
+    let pipeline = Pipeline::new();
+    for _ in 0..2 {
+        let events = cached
+            .apply_ref(Box::new(pipeline.process("Hello")))
+            .collect::<Vec<_>>();
+        assert!(matches!(events.last().unwrap().event, Event::Error(..)));
+    }
+    assert_eq!(runs.get(), 2);
+    assert!(!cache_dir.exists());
+}
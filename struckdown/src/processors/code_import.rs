@@ -0,0 +1,215 @@
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, CodeBlockEvent, ErrorEvent, Event, Severity, Str};
+
+/// Loads the contents of code blocks from files.
+///
+/// Code blocks with a `file` argument get their code replaced with the
+/// contents of that file.  The optional `lines` argument restricts the
+/// snippet to a range of lines (`10-30`, `10-` or `10`, one based and
+/// inclusive).  Both arguments are removed from the code block.
+///
+/// Files are resolved relative to `base_path` and must be located within
+/// one of the `allowed_paths`.  Files that cannot be loaded are replaced
+/// with an error event.
+///
+/// When applied this wraps the stream in a [`CodeImportIter`].
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct CodeImport {
+    /// The path files are resolved relative to.  Defaults to the current
+    /// working directory.
+    pub base_path: Option<PathBuf>,
+    /// The folders files may be imported from (relative to `base_path`).
+    /// If empty only files within `base_path` may be imported.
+    pub allowed_paths: Vec<PathBuf>,
+}
+
+/// Resolves a file relative to a base path.
+///
+/// The file must be located within one of the `allowed_paths` (relative to
+/// the base path) or within the base path if none are given.
+pub(crate) fn resolve_file(
+    base_path: Option<&Path>,
+    allowed_paths: &[PathBuf],
+    filename: &str,
+) -> io::Result<PathBuf> {
+    let base_path = base_path.unwrap_or_else(|| Path::new("."));
+    let path = base_path.join(filename).canonicalize()?;
+    let is_allowed = if allowed_paths.is_empty() {
+        path.starts_with(base_path.canonicalize()?)
+    } else {
+        allowed_paths.iter().any(|allowed| {
+            base_path
+                .join(allowed)
+                .canonicalize()
+                .map(|allowed| path.starts_with(allowed))
+                .unwrap_or(false)
+        })
+    };
+    if is_allowed {
+        Ok(path)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "file is not in an allowed path",
+        ))
+    }
+}
+
+impl CodeImport {
+    fn resolve(&self, filename: &str) -> io::Result<PathBuf> {
+        resolve_file(self.base_path.as_deref(), &self.allowed_paths, filename)
+    }
+
+    fn load(&self, filename: &str, lines: Option<&str>) -> io::Result<String> {
+        let contents = fs::read_to_string(self.resolve(filename)?)?;
+        let (start, end) = match lines {
+            Some(lines) => parse_line_range(lines)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid line range"))?,
+            None => return Ok(contents),
+        };
+        let mut rv = String::new();
+        for line in contents
+            .lines()
+            .skip(start - 1)
+            .take(end.map_or(usize::MAX, |end| end + 1 - start))
+        {
+            rv.push_str(line);
+            rv.push('\n');
+        }
+        Ok(rv)
+    }
+}
+
+/// Parses a line range such as `10-30`, `10-` or `10`.
+fn parse_line_range(spec: &str) -> Option<(usize, Option<usize>)> {
+    let mut pieces = spec.splitn(2, '-');
+    let start = pieces.next()?.trim().parse::<usize>().ok()?;
+    let end = match pieces.next().map(str::trim) {
+        Some("") => None,
+        Some(end) => Some(end.parse::<usize>().ok()?),
+        None => Some(start),
+    };
+    if start == 0 || matches!(end, Some(end) if end < start) {
+        None
+    } else {
+        Some((start, end))
+    }
+}
+
+implement_processor!(
+    CodeImport,
+    CodeImportIter,
+    "Loads the contents of code blocks from files"
+);
+
+/// The iterator implementing [`CodeImport`].
+pub struct CodeImportIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    options: Cow<'options, CodeImport>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    CodeImportIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, CodeImport>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for CodeImportIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut annotated_event = self.source.next()?;
+        let code_block = match annotated_event.event {
+            Event::CodeBlock(ref mut code_block) => code_block,
+            _ => return Some(annotated_event),
+        };
+        let CodeBlockEvent {
+            ref mut args,
+            ref mut code,
+            ..
+        } = *code_block;
+
+        let filename = match args
+            .as_mut()
+            .and_then(|args| args.remove(&Str::new("file")))
+        {
+            Some(filename) => filename,
+            None => return Some(annotated_event),
+        };
+        let lines = args
+            .as_mut()
+            .and_then(|args| args.remove(&Str::new("lines")));
+        if matches!(args, Some(ref args) if args.is_empty()) {
+            *args = None;
+        }
+
+        match self
+            .options
+            .load(filename.as_str(), lines.as_ref().map(|x| x.as_str()))
+        {
+            Ok(contents) => {
+                *code = contents.into();
+                Some(annotated_event)
+            }
+            Err(err) => Some(AnnotatedEvent::new(
+                ErrorEvent {
+                    title: format!("Failed to import code from '{}'", filename.as_str()).into(),
+                    description: Some(err.to_string().into()),
+                    severity: Severity::Error,
+                },
+                annotated_event.location,
+            )),
+        }
+    }
+}
+
+#[test]
+fn test_parse_line_range() {
+    assert_eq!(parse_line_range("10-30"), Some((10, Some(30))));
+    assert_eq!(parse_line_range("10-"), Some((10, None)));
+    assert_eq!(parse_line_range("7"), Some((7, Some(7))));
+    assert_eq!(parse_line_range("0-2"), None);
+    assert_eq!(parse_line_range("5-2"), None);
+}
+
+#[test]
+fn test_code_import() {
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(CodeImport {
+        base_path: Some(env!("CARGO_MANIFEST_DIR").into()),
+        ..Default::default()
+    });
+    let events = pipeline
+        .process(
+            "```toml file=\"Cargo.toml\" lines=\"1-2\"\n```\n\n\
+             ```text file=\"../../../etc/passwd\"\n```",
+        )
+        .collect::<Vec<_>>();
+    match events[1].event {
+        Event::CodeBlock(ref code_block) => {
+            assert_eq!(
+                code_block.code.as_str(),
+                "[package]\nname = \"struckdown\"\n"
+            );
+            assert!(code_block.args.is_none());
+        }
+        _ => panic!("expected code block"),
+    }
+    assert!(matches!(events[2].event, Event::Error(..)));
+}
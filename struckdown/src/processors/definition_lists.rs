@@ -0,0 +1,282 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Attrs, EndTagEvent, Event, StartTagEvent, Tag, TextEvent};
+
+/// Converts term and definition paragraphs into definition lists.
+///
+/// A paragraph of one or more term lines followed by lines starting with
+/// `: ` becomes an entry of a [`Tag::DefinitionList`]:
+///
+/// ```text
+/// Term
+/// : The definition
+/// ```
+///
+/// The definitions can also be in the directly following paragraph.
+/// Consecutive entries are combined into a single list.  Lines after a
+/// definition that do not start with `: ` continue that definition.
+///
+/// When applied this wraps the stream in a [`DefinitionListsIter`].
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct DefinitionLists {
+    /// The class added to the definition lists.
+    pub class_name: Option<String>,
+}
+
+implement_processor!(
+    DefinitionLists,
+    DefinitionListsIter,
+    "Converts term and definition paragraphs into definition lists",
+);
+
+type Line<'data> = Vec<AnnotatedEvent<'data>>;
+
+/// A buffered paragraph.
+struct Paragraph<'data> {
+    events: Vec<AnnotatedEvent<'data>>,
+}
+
+impl<'data> Paragraph<'data> {
+    /// Splits the contents of the paragraph at soft breaks.
+    fn lines(&self) -> Vec<Line<'data>> {
+        let inner = &self.events[1..self.events.len() - 1];
+        inner
+            .split(|x| matches!(x.event, Event::SoftBreak))
+            .map(|x| x.to_vec())
+            .collect()
+    }
+}
+
+/// A term with its definitions.
+struct Entry<'data> {
+    terms: Vec<Line<'data>>,
+    definitions: Vec<Vec<Line<'data>>>,
+}
+
+/// Returns the length of the definition marker if the line starts with one.
+fn definition_marker(line: &[AnnotatedEvent<'_>]) -> Option<usize> {
+    match line.first() {
+        Some(AnnotatedEvent {
+            event: Event::Text(TextEvent { text }),
+            ..
+        }) => {
+            let text = text.as_str();
+            let rest = text.strip_prefix(':')?;
+            let trimmed = rest.trim_start_matches([' ', '\t']);
+            if trimmed.len() == rest.len() && !rest.is_empty() {
+                None
+            } else {
+                Some(text.len() - trimmed.len())
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Removes the definition marker from a line.
+fn strip_marker(mut line: Line<'_>) -> Line<'_> {
+    if let Some(len) = definition_marker(&line) {
+        if let Event::Text(TextEvent { ref mut text }) = line[0].event {
+            if len == text.as_str().len() {
+                line.remove(0);
+            } else {
+                *text = text.slice(len, text.as_str().len());
+            }
+        }
+    }
+    line
+}
+
+/// Groups lines into definitions.
+fn collect_definitions(lines: Vec<Line<'_>>) -> Vec<Vec<Line<'_>>> {
+    let mut rv: Vec<Vec<Line<'_>>> = Vec::new();
+    for line in lines {
+        if definition_marker(&line).is_some() || rv.is_empty() {
+            rv.push(vec![strip_marker(line)]);
+        } else {
+            rv.last_mut().unwrap().push(line);
+        }
+    }
+    rv
+}
+
+/// The iterator implementing [`DefinitionLists`].
+pub struct DefinitionListsIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    lookahead: VecDeque<AnnotatedEvent<'data>>,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, DefinitionLists>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    DefinitionListsIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, DefinitionLists>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            lookahead: VecDeque::new(),
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+
+    fn pull(&mut self) -> Option<AnnotatedEvent<'data>> {
+        self.lookahead.pop_front().or_else(|| self.source.next())
+    }
+
+    fn unread(&mut self, events: Vec<AnnotatedEvent<'data>>) {
+        for annotated_event in events.into_iter().rev() {
+            self.lookahead.push_front(annotated_event);
+        }
+    }
+
+    /// Reads the next paragraph if the stream continues with one.
+    fn read_paragraph(&mut self) -> Option<Paragraph<'data>> {
+        let start = self.pull()?;
+        if !matches!(
+            start.event,
+            Event::StartTag(StartTagEvent {
+                tag: Tag::Paragraph,
+                ..
+            })
+        ) {
+            self.lookahead.push_front(start);
+            return None;
+        }
+        let mut events = vec![start];
+        while let Some(annotated_event) = self.pull() {
+            let is_end = matches!(
+                annotated_event.event,
+                Event::EndTag(EndTagEvent {
+                    tag: Tag::Paragraph
+                })
+            );
+            events.push(annotated_event);
+            if is_end {
+                return Some(Paragraph { events });
+            }
+        }
+        // unterminated paragraph, leave it alone
+        self.unread(events);
+        None
+    }
+
+    /// Reads an entry from the following paragraphs.
+    fn read_entry(&mut self) -> Option<Entry<'data>> {
+        let paragraph = self.read_paragraph()?;
+        let mut lines = paragraph.lines();
+        if let Some(idx) = lines
+            .iter()
+            .skip(1)
+            .position(|line| definition_marker(line).is_some())
+        {
+            let definitions = lines.split_off(idx + 1);
+            return Some(Entry {
+                terms: lines,
+                definitions: collect_definitions(definitions),
+            });
+        }
+
+        if definition_marker(&lines[0]).is_none() {
+            if let Some(next) = self.read_paragraph() {
+                let definitions = next.lines();
+                if definition_marker(&definitions[0]).is_some() {
+                    return Some(Entry {
+                        terms: lines,
+                        definitions: collect_definitions(definitions),
+                    });
+                }
+                self.unread(next.events);
+            }
+        }
+
+        self.unread(paragraph.events);
+        None
+    }
+
+    fn push_tag(&mut self, tag: Tag, events: Vec<AnnotatedEvent<'data>>) {
+        let location = match events.first() {
+            Some(annotated_event) => annotated_event.location,
+            None => return,
+        };
+        self.buffer.push_back(AnnotatedEvent::new(
+            tag.start_tag(Attrs::default()),
+            location,
+        ));
+        self.buffer.extend(events);
+        self.buffer
+            .push_back(AnnotatedEvent::new(tag.end_tag(), location));
+    }
+
+    fn push_entry(&mut self, entry: Entry<'data>) {
+        for term in entry.terms {
+            self.push_tag(Tag::DefinitionTerm, term);
+        }
+        for definition in entry.definitions {
+            let mut events = Vec::new();
+            for (idx, line) in definition.into_iter().enumerate() {
+                if idx > 0 {
+                    events.push(Event::SoftBreak.into());
+                }
+                events.extend(line);
+            }
+            self.push_tag(Tag::DefinitionDetails, events);
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for DefinitionListsIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.buffer.pop_front() {
+            return Some(annotated_event);
+        }
+
+        let entry = match self.read_entry() {
+            Some(entry) => entry,
+            None => return self.pull(),
+        };
+
+        self.buffer.push_back(
+            Tag::DefinitionList
+                .start_tag(Attrs {
+                    class: self.options.class_name.clone().map(Into::into),
+                    ..Attrs::default()
+                })
+                .into(),
+        );
+        self.push_entry(entry);
+        while let Some(entry) = self.read_entry() {
+            self.push_entry(entry);
+        }
+        self.buffer.push_back(Tag::DefinitionList.end_tag().into());
+        self.buffer.pop_front()
+    }
+}
+
+#[test]
+fn test_definition_lists() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(DefinitionLists::default());
+    assert_eq!(
+        to_html(
+            pipeline.process(
+                "Apple\n: A *fruit*\ncontinued\n: A company\n\nPear\n\n: Another fruit\n\n\
+                 Just text: not a list"
+            ),
+            &Default::default()
+        ),
+        "<dl>\n<dt>Apple</dt>\n<dd>A <em>fruit</em>\ncontinued</dd>\n<dd>A company</dd>\n\
+         <dt>Pear</dt>\n<dd>Another fruit</dd>\n</dl>\n<p>Just text: not a list</p>\n"
+    );
+}
@@ -0,0 +1,262 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use syntect::highlighting::Theme;
+use syntect::parsing::SyntaxSet;
+use v_htmlescape::escape;
+
+use crate::error::Error;
+use crate::event::{
+    AnnotatedEvent, CodeBlockEvent, ErrorEvent, Event, RawHtmlEvent, Severity, Str,
+};
+use crate::processors::syntect::highlight_lines;
+use crate::processors::{BuiltinProcessor, Processor, Syntect};
+
+/// Renders diffs with per line classes layered over syntax highlighting.
+///
+/// This handles `diff` code blocks as well as code blocks in any language
+/// with a `diff` argument (eg: `rust diff=true`).  For `diff` code blocks the
+/// language of the diffed code can be given with the `lang` argument,
+/// otherwise the lines are not highlighted.  Added and removed lines are
+/// wrapped in spans with `added_class` and `removed_class`, hunk headers
+/// (`@@ ... @@`) in a span with `hunk_class`.
+///
+/// All other code blocks are passed through so this should run before
+/// [`Syntect`].  If the theme or syntaxes cannot be loaded an error event is
+/// emitted and diffs are rendered without highlighting.
+///
+/// When applied this wraps the stream in a [`DiffHighlightIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct DiffHighlight {
+    /// The class for added lines.
+    pub added_class: String,
+    /// The class for removed lines.
+    pub removed_class: String,
+    /// The class for hunk headers.
+    pub hunk_class: String,
+    /// The options for highlighting the diffed code.
+    pub syntect: Syntect,
+}
+
+impl Default for DiffHighlight {
+    fn default() -> DiffHighlight {
+        DiffHighlight {
+            added_class: "diff-add".into(),
+            removed_class: "diff-del".into(),
+            hunk_class: "diff-hunk".into(),
+            syntect: Syntect::default(),
+        }
+    }
+}
+
+impl DiffHighlight {
+    /// Verifies that the theme and syntaxes can be loaded.
+    fn check(&self) -> Result<(), Error> {
+        Processor::check(&self.syntect)
+    }
+
+    /// Returns the configuration unless options only settable from code are used.
+    fn builtin_config(&self) -> Option<BuiltinProcessor> {
+        if self.syntect.syntax_set.is_some() || self.syntect.theme_set.is_some() {
+            return None;
+        }
+        Some(self.clone().into())
+    }
+}
+
+implement_processor!(
+    DiffHighlight,
+    DiffHighlightIter,
+    "Highlights diffs with per line classes",
+    check = DiffHighlight::check,
+    to_builtin = DiffHighlight::builtin_config,
+);
+
+/// Splits a diff line into the marker and the code.
+fn split_marker(line: &str) -> (&str, &str) {
+    match line.char_indices().nth(1) {
+        Some((idx, _)) => line.split_at(idx),
+        None => (line, ""),
+    }
+}
+
+/// The iterator implementing [`DiffHighlight`].
+pub struct DiffHighlightIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    highlighting: Option<(Arc<SyntaxSet>, Theme)>,
+    error: Option<Error>,
+    options: Cow<'options, DiffHighlight>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    DiffHighlightIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, DiffHighlight>>>(iterator: I, options: O) -> Self {
+        let options = options.into();
+        let (highlighting, error) = match options
+            .syntect
+            .try_load_syntax_set()
+            .and_then(|syntax_set| Ok((syntax_set, options.syntect.try_load_theme()?)))
+        {
+            Ok(highlighting) => (Some(highlighting), None),
+            Err(err) => (None, Some(err)),
+        };
+        Self {
+            source: iterator,
+            highlighting,
+            error,
+            options,
+        }
+    }
+
+    fn render(&self, language: Option<&str>, code: &str) -> String {
+        let lines = code.lines().collect::<Vec<_>>();
+        let is_hunk = |line: &str| line.starts_with("@@");
+        let highlighted = match (language, &self.highlighting) {
+            (Some(language), Some((syntax_set, theme))) => highlight_lines(
+                &self.options.syntect,
+                syntax_set,
+                theme,
+                language,
+                lines
+                    .iter()
+                    .filter(|line| !is_hunk(line))
+                    .map(|line| split_marker(line).1),
+            ),
+            _ => lines
+                .iter()
+                .filter(|line| !is_hunk(line))
+                .map(|line| escape(split_marker(line).1).to_string())
+                .collect(),
+        };
+
+        let mut highlighted = highlighted.into_iter();
+        let mut rv = String::new();
+        match language {
+            Some(language) => write!(rv, "<pre><code class=\"lang-{}\">", escape(language)),
+            None => write!(rv, "<pre><code>"),
+        }
+        .unwrap();
+        for line in lines {
+            if is_hunk(line) {
+                writeln!(
+                    rv,
+                    "<span class=\"{}\">{}</span>",
+                    escape(&self.options.hunk_class),
+                    escape(line)
+                )
+                .unwrap();
+                continue;
+            }
+            let (marker, _) = split_marker(line);
+            let class = match marker {
+                "+" => Some(&self.options.added_class),
+                "-" => Some(&self.options.removed_class),
+                _ => None,
+            };
+            if let Some(class) = class {
+                write!(rv, "<span class=\"{}\">", escape(class)).unwrap();
+            }
+            write!(rv, "{}", escape(marker)).unwrap();
+            rv.push_str(&highlighted.next().unwrap_or_default());
+            if class.is_some() {
+                rv.push_str("</span>");
+            }
+            rv.push('\n');
+        }
+        rv.push_str("</code></pre>");
+        rv
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for DiffHighlightIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(err) = self.error.take() {
+            return Some(
+                ErrorEvent {
+                    title: "Failed to load syntax highlighting for diffs".into(),
+                    description: Some(err.to_string().into()),
+                    severity: Severity::Error,
+                }
+                .into(),
+            );
+        }
+        let annotated_event = self.source.next()?;
+        if let Event::CodeBlock(CodeBlockEvent {
+            language: Some(ref language),
+            ref code,
+            ref args,
+        }) = annotated_event.event
+        {
+            let arg = |name: &'static str| {
+                args.as_ref()
+                    .and_then(|args| args.get(&Str::new(name)))
+                    .map(|x| x.as_str())
+            };
+            let language = if language.as_str() == "diff" {
+                Some(arg("lang"))
+            } else if matches!(arg("diff"), Some(value) if value != "false") {
+                Some(Some(language.as_str()))
+            } else {
+                None
+            };
+            if let Some(language) = language {
+                return Some(AnnotatedEvent::new(
+                    RawHtmlEvent {
+                        html: self.render(language, code.as_str()).into(),
+                    },
+                    annotated_event.location,
+                ));
+            }
+        }
+        Some(annotated_event)
+    }
+}
+
+#[test]
+fn test_diff_highlight() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(DiffHighlight::default());
+    assert_eq!(
+        to_html(
+            pipeline.process("```diff\n@@ -1 +1 @@\n-a < b\n+a > b\n c\n```"),
+            &Default::default()
+        ),
+        "<pre><code><span class=\"diff-hunk\">@@ -1 +1 @@</span>\n\
+         <span class=\"diff-del\">-a &lt; b</span>\n\
+         <span class=\"diff-add\">+a &gt; b</span>\n \
+         c\n</code></pre>"
+    );
+}
+
+#[test]
+fn test_diff_highlight_missing_theme() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut options = DiffHighlight::default();
+    options.syntect.theme_path = Some("/missing/theme.tmTheme".into());
+    assert!(options.check().is_err());
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(options);
+    assert!(pipeline.try_process("Hello").is_err());
+    let events = pipeline
+        .process("```rust diff=true\n+fn x() {}\n```")
+        .collect::<Vec<_>>();
+    assert!(matches!(events[0].event, Event::Error(..)));
+    assert_eq!(
+        to_html(events.into_iter().skip(1), &Default::default()),
+        "<pre><code class=\"lang-rust\"><span class=\"diff-add\">+fn x() {}</span>\n</code></pre>"
+    );
+}
@@ -0,0 +1,204 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, DirectiveEvent, ErrorEvent, Event, Severity};
+
+/// A handler for a directive.
+///
+/// It's invoked with the directive and returns the events that replace it.
+/// Events without a location are given the location of the directive.
+#[derive(Clone)]
+pub struct DirectiveHandlerFn(
+    Arc<dyn for<'data> Fn(DirectiveEvent<'data>) -> Vec<AnnotatedEvent<'data>> + Send + Sync>,
+);
+
+impl DirectiveHandlerFn {
+    /// Wraps a function for use as directive handler.
+    pub fn new<F>(f: F) -> DirectiveHandlerFn
+    where
+        F: for<'data> Fn(DirectiveEvent<'data>) -> Vec<AnnotatedEvent<'data>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        DirectiveHandlerFn(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for DirectiveHandlerFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DirectiveHandlerFn").finish()
+    }
+}
+
+/// What the dispatch processors do with events without a handler.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FallbackPolicy {
+    /// Replaces the event with an error.
+    Error,
+    /// Keeps the event as is.
+    Passthrough,
+    /// Removes the event.
+    Drop,
+}
+
+/// Dispatches directives to registered handlers.
+///
+/// Handlers are registered by directive name with [`register`](Self::register)
+/// and replace the directive with the events they return.  This allows
+/// implementing custom directives without writing a processor for each of
+/// them.  Directives without a handler are treated according to `fallback`.
+/// Handlers can only be registered from code, hence this processor is not
+/// available in pipeline configurations.
+///
+/// When applied this wraps the stream in a [`DirectiveDispatchIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct DirectiveDispatch {
+    /// What to do with directives without a handler.
+    pub fallback: FallbackPolicy,
+    /// The registered handlers by directive name.
+    #[serde(skip)]
+    pub handlers: HashMap<String, DirectiveHandlerFn>,
+}
+
+impl Default for DirectiveDispatch {
+    fn default() -> DirectiveDispatch {
+        DirectiveDispatch {
+            fallback: FallbackPolicy::Passthrough,
+            handlers: HashMap::new(),
+        }
+    }
+}
+
+impl DirectiveDispatch {
+    /// Registers a handler for a directive.
+    ///
+    /// An already registered handler for the same name is replaced.
+    pub fn register<F>(&mut self, name: &str, f: F)
+    where
+        F: for<'data> Fn(DirectiveEvent<'data>) -> Vec<AnnotatedEvent<'data>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.handlers
+            .insert(name.to_string(), DirectiveHandlerFn::new(f));
+    }
+}
+
+implement_processor!(
+    DirectiveDispatch,
+    DirectiveDispatchIter,
+    "Dispatches directives to registered handlers",
+    to_builtin = crate::processors::utils::not_builtin,
+);
+
+/// The iterator implementing [`DirectiveDispatch`].
+pub struct DirectiveDispatchIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, DirectiveDispatch>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    DirectiveDispatchIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, DirectiveDispatch>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for DirectiveDispatchIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(annotated_event) = self.buffer.pop_front() {
+                return Some(annotated_event);
+            }
+
+            let annotated_event = self.source.next()?;
+            let location = annotated_event.location;
+            let directive = match annotated_event.event {
+                Event::Directive(directive) => directive,
+                event => return Some(AnnotatedEvent { event, location }),
+            };
+
+            match self.options.handlers.get(directive.name.as_str()) {
+                Some(handler) => {
+                    for mut annotated_event in (handler.0)(directive) {
+                        if annotated_event.location.is_none() {
+                            annotated_event.location = location;
+                        }
+                        self.buffer.push_back(annotated_event);
+                    }
+                }
+                None => match self.options.fallback {
+                    FallbackPolicy::Error => {
+                        return Some(AnnotatedEvent::new(
+                            ErrorEvent {
+                                title: "Unknown directive".into(),
+                                description: Some(
+                                    format!("no handler for '{}'", directive.name.as_str()).into(),
+                                ),
+                                severity: Severity::Error,
+                            },
+                            location,
+                        ));
+                    }
+                    FallbackPolicy::Passthrough => {
+                        return Some(AnnotatedEvent::new(directive, location));
+                    }
+                    FallbackPolicy::Drop => {}
+                },
+            }
+        }
+    }
+}
+
+#[test]
+fn test_directive_dispatch() {
+    use crate::event::{Attrs, Tag, TextEvent};
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut options = DirectiveDispatch {
+        fallback: FallbackPolicy::Drop,
+        ..Default::default()
+    };
+    options.register("shout", |directive| {
+        vec![
+            Tag::Paragraph.start_tag(Attrs::default()).into(),
+            TextEvent {
+                text: directive.body.as_str().trim().to_uppercase().into(),
+            }
+            .into(),
+            Tag::Paragraph.end_tag().into(),
+        ]
+    });
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(options);
+    assert_eq!(
+        to_html(
+            pipeline.process("```{shout}\nhello\n```\n\n```{other}\nhidden\n```\n\nDone"),
+            &Default::default()
+        ),
+        "<p>HELLO</p>\n<p>Done</p>\n"
+    );
+
+    // the handlers cannot be represented in a configuration
+    assert!(pipeline.to_config().is_err());
+}
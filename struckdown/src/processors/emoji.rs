@@ -0,0 +1,208 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Attrs, Event, ImageEvent, Str, Tag, TextEvent};
+
+lazy_static! {
+    static ref SHORTCODE_RE: Regex = Regex::new(r":[a-zA-Z0-9_+-]+:").unwrap();
+}
+
+/// Replaces `:shortcode:` sequences in text with emoji.
+///
+/// By default the GitHub shortcodes are resolved to their unicode emoji.
+/// Shortcodes listed in `images` (or all shortcodes if `image_url` is set)
+/// are instead emitted as images wrapped in a span.  Only text events are
+/// considered, so code is never touched.
+///
+/// When applied this wraps the stream in a [`EmojiIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Emoji {
+    /// Additional shortcodes mapped to the text they should be replaced with.
+    pub custom: BTreeMap<String, String>,
+    /// Shortcodes mapped to image URLs.
+    pub images: BTreeMap<String, String>,
+    /// If set, all known shortcodes are emitted as images.  The `{name}`
+    /// placeholder is replaced with the shortcode.
+    pub image_url: Option<String>,
+    /// The class that should be added to the span wrapping emoji images.
+    pub class_name: Option<String>,
+}
+
+impl Default for Emoji {
+    fn default() -> Emoji {
+        Emoji {
+            custom: BTreeMap::new(),
+            images: BTreeMap::new(),
+            image_url: None,
+            class_name: Some("emoji".into()),
+        }
+    }
+}
+
+implement_processor!(Emoji, EmojiIter, "Replaces emoji shortcodes in text");
+
+/// The iterator implementing [`Emoji`].
+pub struct EmojiIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, Emoji>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> EmojiIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Emoji>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+}
+
+enum Replacement {
+    Text(String),
+    Image(String),
+}
+
+fn resolve_shortcode(options: &Emoji, name: &str) -> Option<Replacement> {
+    if let Some(url) = options.images.get(name) {
+        return Some(Replacement::Image(url.clone()));
+    }
+    let known = options.custom.contains_key(name) || emojis::get_by_shortcode(name).is_some();
+    match options.image_url {
+        Some(ref template) if known => Some(Replacement::Image(template.replace("{name}", name))),
+        _ => {
+            if let Some(text) = options.custom.get(name) {
+                Some(Replacement::Text(text.clone()))
+            } else {
+                emojis::get_by_shortcode(name).map(|x| Replacement::Text(x.as_str().into()))
+            }
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for EmojiIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.buffer.pop_front() {
+            return Some(annotated_event);
+        }
+
+        let annotated_event = self.source.next()?;
+        let text = match annotated_event.event {
+            Event::Text(TextEvent { ref text }) => text,
+            _ => return Some(annotated_event),
+        };
+
+        let location = annotated_event.location;
+        let mut last = 0;
+        let mut pos = 0;
+        while let Some(g0) = SHORTCODE_RE.find_at(text.as_str(), pos) {
+            let name = &text.as_str()[g0.start() + 1..g0.end() - 1];
+            let replacement = match resolve_shortcode(&self.options, name) {
+                Some(replacement) => replacement,
+                None => {
+                    // the closing colon might open the next shortcode
+                    pos = g0.end() - 1;
+                    continue;
+                }
+            };
+            if g0.start() > last {
+                self.buffer.push_back(AnnotatedEvent::new(
+                    TextEvent {
+                        text: text.slice(last, g0.start()),
+                    },
+                    location,
+                ));
+            }
+            match replacement {
+                Replacement::Text(emoji) => {
+                    self.buffer.push_back(AnnotatedEvent::new(
+                        TextEvent { text: emoji.into() },
+                        location,
+                    ));
+                }
+                Replacement::Image(target) => {
+                    self.buffer.push_back(AnnotatedEvent::new(
+                        Tag::Span.start_tag(Attrs {
+                            class: self.options.class_name.clone().map(Str::from),
+                            ..Attrs::default()
+                        }),
+                        location,
+                    ));
+                    self.buffer.push_back(AnnotatedEvent::new(
+                        ImageEvent {
+                            target: target.into(),
+                            alt: Some(text.slice(g0.start(), g0.end())),
+                            title: Some(text.slice(g0.start() + 1, g0.end() - 1)),
+                            attrs: Attrs::default(),
+                        },
+                        location,
+                    ));
+                    self.buffer
+                        .push_back(AnnotatedEvent::new(Tag::Span.end_tag(), location));
+                }
+            }
+            last = g0.end();
+            pos = g0.end();
+        }
+
+        if last == 0 {
+            return Some(annotated_event);
+        }
+        if last < text.as_str().len() {
+            self.buffer.push_back(AnnotatedEvent::new(
+                TextEvent {
+                    text: text.slice(last, text.as_str().len()),
+                },
+                location,
+            ));
+        }
+
+        self.buffer.pop_front()
+    }
+}
+
+#[test]
+fn test_emoji() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(Emoji {
+        images: vec![("party".to_string(), "/party.png".to_string())]
+            .into_iter()
+            .collect(),
+        ..Emoji::default()
+    });
+    assert_eq!(
+        to_html(
+            pipeline.process("Hello :wave: :party: :nope:"),
+            &Default::default()
+        ),
+        "<p>Hello \u{1f44b} <span class=\"emoji\"><img src=\"/party.png\" alt=\":party:\" title=\"party\"></span> :nope:</p>\n"
+    );
+}
+
+#[test]
+fn test_emoji_adjacent_shortcodes() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(Emoji::default());
+    assert_eq!(
+        to_html(
+            pipeline.process(":foo:wave: and :+1::tada:"),
+            &Default::default()
+        ),
+        "<p>:foo\u{1f44b} and \u{1f44d}\u{1f389}</p>\n"
+    );
+}
@@ -0,0 +1,203 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{
+    AnnotatedEvent, DirectiveEvent, Event, MetaDataEvent, RawHtmlEvent, StartTagEvent, Tag,
+};
+use crate::plain::to_plain_text;
+use crate::value::to_value;
+
+/// Extracts a summary of the document and emits it as meta data.
+///
+/// The excerpt is everything up to a top level marker (`<!--more-->` or a
+/// `{more}` directive) or, if no marker exists, the first paragraph.  The
+/// marker itself is always removed from the stream.  The emitted meta data
+/// holds both the `events` of the excerpt and its plain `text`.
+///
+/// When applied this wraps the stream in a [`ExcerptIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Excerpt {
+    /// The meta data key to emit the excerpt as.
+    pub key: String,
+    /// The name of the marker.  This is used both for the HTML comment and
+    /// the directive form.
+    pub marker: Option<String>,
+    /// If enabled the document is truncated after the excerpt.
+    pub truncate: bool,
+}
+
+impl Default for Excerpt {
+    fn default() -> Excerpt {
+        Excerpt {
+            key: "excerpt".into(),
+            marker: Some("more".into()),
+            truncate: false,
+        }
+    }
+}
+
+implement_processor!(Excerpt, ExcerptIter, "Extracts a summary of the document");
+
+/// The iterator implementing [`Excerpt`].
+pub struct ExcerptIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source_iter: Option<I>,
+    iter: std::vec::IntoIter<AnnotatedEvent<'data>>,
+    options: Cow<'options, Excerpt>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> ExcerptIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Excerpt>>>(iterator: I, options: O) -> Self {
+        Self {
+            source_iter: Some(iterator),
+            iter: Vec::new().into_iter(),
+            options: options.into(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ExcerptInfo<'data> {
+    text: String,
+    events: Vec<AnnotatedEvent<'data>>,
+}
+
+fn is_marker(event: &Event, marker: &str) -> bool {
+    match *event {
+        Event::RawHtml(RawHtmlEvent { ref html }) => {
+            let html = html.as_str().trim();
+            html.len() >= 7
+                && html.starts_with("<!--")
+                && html.ends_with("-->")
+                && html[4..html.len() - 3].trim() == marker
+        }
+        Event::Directive(DirectiveEvent { ref name, .. }) => name.as_str() == marker,
+        _ => false,
+    }
+}
+
+/// Finds the top level blocks making up the excerpt and the optional marker.
+fn find_excerpt(
+    buffer: &[AnnotatedEvent<'_>],
+    marker: Option<&str>,
+) -> (Vec<Range<usize>>, Option<usize>) {
+    let mut blocks = Vec::new();
+    let mut block_start = None;
+    let mut depth = 0;
+
+    for (idx, annotated_event) in buffer.iter().enumerate() {
+        match annotated_event.event {
+            Event::StartTag(..) => {
+                if depth == 0 {
+                    block_start = Some(idx);
+                }
+                depth += 1;
+            }
+            Event::EndTag(..) => {
+                depth -= 1;
+                if depth == 0 {
+                    blocks.push(block_start.take().unwrap()..idx + 1);
+                }
+            }
+            Event::DocumentStart(..) | Event::MetaData(..) | Event::Error(..) => {}
+            ref event if depth == 0 => {
+                if matches!(marker, Some(marker) if is_marker(event, marker)) {
+                    return (blocks, Some(idx));
+                }
+                blocks.push(idx..idx + 1);
+            }
+            _ => {}
+        }
+    }
+
+    let first_paragraph = blocks.into_iter().find(|range| {
+        matches!(
+            buffer[range.start].event,
+            Event::StartTag(StartTagEvent {
+                tag: Tag::Paragraph,
+                ..
+            })
+        )
+    });
+    (first_paragraph.into_iter().collect(), None)
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for ExcerptIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(source) = self.source_iter.take() {
+            let mut buffer = source.collect::<Vec<_>>();
+            let (blocks, marker) = find_excerpt(&buffer, self.options.marker.as_deref());
+
+            let mut info = ExcerptInfo {
+                text: String::new(),
+                events: Vec::new(),
+            };
+            for range in &blocks {
+                let text = to_plain_text(buffer[range.clone()].iter());
+                if !text.as_str().is_empty() {
+                    if !info.text.is_empty() {
+                        info.text.push_str("\n\n");
+                    }
+                    info.text.push_str(text.as_str());
+                }
+                info.events.extend(buffer[range.clone()].iter().cloned());
+            }
+
+            if let Some(cutoff) = marker.or_else(|| blocks.last().map(|range| range.end)) {
+                let rest = buffer.split_off(cutoff);
+                let keep_rest = !self.options.truncate;
+                buffer.extend(rest.into_iter().enumerate().filter_map(|(idx, event)| {
+                    if marker.is_some() && idx == 0 {
+                        None
+                    } else if keep_rest || matches!(event.event, Event::MetaData(..)) {
+                        Some(event)
+                    } else {
+                        None
+                    }
+                }));
+            }
+
+            buffer.push(
+                MetaDataEvent {
+                    key: self.options.key.clone().into(),
+                    value: to_value(&info).expect("bad excerpt"),
+                }
+                .into(),
+            );
+            self.iter = buffer.into_iter();
+        }
+
+        self.iter.next()
+    }
+}
+
+#[test]
+fn test_excerpt_marker() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(Excerpt {
+        truncate: true,
+        ..Default::default()
+    });
+    let events = pipeline
+        .process("# Title\n\nFirst\n\nSecond\n\n<!-- more -->\n\nRest")
+        .collect::<Vec<_>>();
+    let excerpt = pipeline
+        .extract_metadata(events.iter())
+        .find(|(key, _)| key.as_str() == "excerpt")
+        .map(|(_, value)| value.clone())
+        .unwrap();
+    assert_eq!(excerpt["text"], "Title\n\nFirst\n\nSecond");
+    assert_eq!(
+        to_html(events.into_iter(), &Default::default()),
+        "<h1>Title</h1>\n<p>First</p>\n<p>Second</p>\n"
+    );
+}
@@ -1,18 +1,150 @@
 use std::borrow::Cow;
 use std::collections::BTreeMap;
+use std::env;
 use std::fmt::Display;
-use std::path::PathBuf;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::time::{Duration, Instant};
 
 use serde::{Deserialize, Serialize};
-use tokio::io::BufReader;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
-use tokio::process::{ChildStdin, ChildStdout, Command};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
 use tokio::runtime::Runtime;
 
-use crate::event::{AnnotatedEvent, ErrorEvent};
+use crate::event::{AnnotatedEvent, ErrorEvent, Severity};
+use crate::value::{value, Value};
 
-/// Passes a JSON serialized stream through an external program.
+/// The framing used to exchange events with an external processor.
+///
+/// The framing is passed to the processor in the `STRUCKDOWN_FRAMING`
+/// environment variable.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Framing {
+    /// One JSON document per line.
+    #[default]
+    JsonLines,
+    /// JSON documents prefixed with their length as big endian `u32`.
+    LengthPrefixedJson,
+    /// MessagePack documents prefixed with their length as big endian `u32`.
+    MessagePack,
+}
+
+impl Framing {
+    fn name(self) -> &'static str {
+        match self {
+            Framing::JsonLines => "json_lines",
+            Framing::LengthPrefixedJson => "length_prefixed_json",
+            Framing::MessagePack => "message_pack",
+        }
+    }
+
+    fn encode(self, event: &AnnotatedEvent) -> Vec<u8> {
+        let mut rv = match self {
+            Framing::JsonLines | Framing::LengthPrefixedJson => serde_json::to_vec(event)
+                .expect("Serializing events to external processors should never fail"),
+            Framing::MessagePack => rmp_serde::to_vec_named(event)
+                .expect("Serializing events to external processors should never fail"),
+        };
+        if self == Framing::JsonLines {
+            rv.push(b'\n');
+        } else {
+            let mut framed = (rv.len() as u32).to_be_bytes().to_vec();
+            framed.append(&mut rv);
+            rv = framed;
+        }
+        rv
+    }
+
+    fn decode(self, frame: &[u8]) -> Result<AnnotatedEvent<'static>, String> {
+        match self {
+            Framing::JsonLines | Framing::LengthPrefixedJson => {
+                serde_json::from_slice(frame).map_err(|err| err.to_string())
+            }
+            Framing::MessagePack => rmp_serde::from_slice(frame).map_err(|err| err.to_string()),
+        }
+    }
+
+    /// Splits a complete frame off the front of the buffer.
+    fn split_frame(self, buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+        if self == Framing::JsonLines {
+            let idx = buf.iter().position(|&c| c == b'\n')?;
+            let mut frame = buf.drain(..=idx).collect::<Vec<_>>();
+            frame.pop();
+            Some(frame)
+        } else {
+            if buf.len() < 4 {
+                return None;
+            }
+            let len = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+            if buf.len() < 4 + len {
+                return None;
+            }
+            Some(buf.drain(..4 + len).skip(4).collect())
+        }
+    }
+
+    /// Reads the next frame.
+    ///
+    /// Partially read frames are kept in `buf` so this is safe to cancel.
+    async fn read_frame(
+        self,
+        stdout: &mut ChildStdout,
+        buf: &mut Vec<u8>,
+    ) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            if let Some(frame) = self.split_frame(buf) {
+                if self == Framing::JsonLines && frame.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                return Ok(Some(frame));
+            }
+            let mut chunk = [0; 8192];
+            let read = stdout.read(&mut chunk).await?;
+            if read == 0 {
+                // a final line is not required to be terminated
+                if self == Framing::JsonLines && !buf.iter().all(u8::is_ascii_whitespace) {
+                    return Ok(Some(mem::take(buf)));
+                }
+                return Ok(None);
+            }
+            buf.extend_from_slice(&chunk[..read]);
+        }
+    }
+
+    fn encode_batch(self, events: &[AnnotatedEvent]) -> Vec<u8> {
+        match self {
+            Framing::JsonLines | Framing::LengthPrefixedJson => serde_json::to_vec(events)
+                .expect("Serializing events to external processors should never fail"),
+            Framing::MessagePack => rmp_serde::to_vec_named(events)
+                .expect("Serializing events to external processors should never fail"),
+        }
+    }
+
+    fn decode_batch(self, data: &[u8]) -> Result<Vec<AnnotatedEvent<'static>>, String> {
+        match self {
+            Framing::JsonLines | Framing::LengthPrefixedJson => {
+                serde_json::from_slice(data).map_err(|err| err.to_string())
+            }
+            Framing::MessagePack => rmp_serde::from_slice(data).map_err(|err| err.to_string()),
+        }
+    }
+}
+
+/// Passes a serialized stream through an external program.
+///
+/// By default events are exchanged as JSON lines, alternative framings can
+/// be selected with `framing`.
+///
+/// In `batch` mode the whole stream is sent as a single array (JSON or
+/// MessagePack depending on the framing), stdin is closed and the program is
+/// expected to respond with a single array of events.  Only `deadline_ms`
+/// applies in that mode.
+///
+/// All timeouts are given in milliseconds.  When a timeout expires the
+/// external program is killed and an error event is emitted.
 ///
 /// When applied this wraps the stream in a [`ExternalIter`].
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -27,14 +159,143 @@ pub struct External {
     pub env: BTreeMap<String, String>,
     /// An optional working directory.
     pub cwd: Option<PathBuf>,
+    /// The framing to use for the exchanged events.
+    #[serde(default)]
+    pub framing: Framing,
+    /// How long to wait for the first event from the program.
+    pub startup_timeout_ms: Option<u64>,
+    /// How long to wait for further events from the program.
+    pub read_timeout_ms: Option<u64>,
+    /// How long writing a single event to the program may take.
+    pub write_timeout_ms: Option<u64>,
+    /// The maximum time the program may run overall.
+    pub deadline_ms: Option<u64>,
+    /// Exchanges the whole document at once instead of streaming events.
+    #[serde(default)]
+    pub batch: bool,
+}
+
+impl External {
+    /// Verifies that the command and working directory exist.
+    fn check(&self) -> Result<(), crate::error::Error> {
+        use crate::error::{Error, ErrorKind};
+
+        if let Some(ref cwd) = self.cwd {
+            if !cwd.is_dir() {
+                return Err(Error::new(
+                    ErrorKind::ProcessorFailed,
+                    format!("working directory {} does not exist", cwd.display()),
+                ));
+            }
+        }
+        if resolve_command(&self.cmd, self.cwd.as_deref()).is_none() {
+            return Err(Error::new(
+                ErrorKind::ProcessorFailed,
+                format!("command {} not found", self.cmd.display()),
+            ));
+        }
+        Ok(())
+    }
+
+    fn remaining_deadline(&self, started: Instant) -> Option<(Duration, &'static str)> {
+        self.deadline_ms.map(|ms| {
+            (
+                Duration::from_millis(ms).saturating_sub(started.elapsed()),
+                "the overall deadline",
+            )
+        })
+    }
+
+    /// Returns the timeout that applies next and what it's for.
+    fn next_timeout(
+        &self,
+        started: Instant,
+        writing: bool,
+        received_event: bool,
+    ) -> Option<(Duration, &'static str)> {
+        let timeout = if writing {
+            self.write_timeout_ms.map(|x| (x, "writing an event"))
+        } else if received_event {
+            self.read_timeout_ms.map(|x| (x, "reading an event"))
+        } else {
+            self.startup_timeout_ms.map(|x| (x, "waiting for startup"))
+        }
+        .map(|(ms, reason)| (Duration::from_millis(ms), reason));
+        let deadline = self.remaining_deadline(started);
+        match (timeout, deadline) {
+            (Some(a), Some(b)) => Some(if a.0 <= b.0 { a } else { b }),
+            (a, b) => a.or(b),
+        }
+    }
 }
 
-implement_processor!(External, ExternalIter);
+impl External {
+    fn options_schema() -> Value {
+        value!({
+            "type": "object",
+            "properties": {
+                "cmd": {"type": "string"},
+                "args": {"type": "array", "items": {"type": "string"}, "default": []},
+                "env": {"type": "object", "additionalProperties": {"type": "string"}, "default": {}},
+                "cwd": {"type": "string"},
+                "framing": {
+                    "enum": ["json_lines", "length_prefixed_json", "message_pack"],
+                    "default": "json_lines"
+                },
+                "startup_timeout_ms": {"type": "integer"},
+                "read_timeout_ms": {"type": "integer"},
+                "write_timeout_ms": {"type": "integer"},
+                "deadline_ms": {"type": "integer"},
+                "batch": {"type": "boolean", "default": false},
+            },
+            "required": ["cmd"],
+        })
+    }
+}
+
+implement_processor!(
+    External,
+    ExternalIter,
+    "Passes the stream through an external program",
+    check = External::check,
+    schema = External::options_schema,
+);
+
+/// Resolves a command the way it would be spawned.
+///
+/// Bare command names are looked up in `PATH`, other paths are resolved
+/// relative to the working directory.
+fn resolve_command(cmd: &Path, cwd: Option<&Path>) -> Option<PathBuf> {
+    if cmd.components().count() > 1 {
+        let path = match cwd {
+            Some(cwd) if cmd.is_relative() => cwd.join(cmd),
+            _ => cmd.to_path_buf(),
+        };
+        return Some(path).filter(|x| x.is_file());
+    }
+    let extensions: &[&str] = if cfg!(windows) {
+        &["", "exe", "bat", "cmd"]
+    } else {
+        &[""]
+    };
+    env::split_paths(&env::var_os("PATH")?).find_map(|dir| {
+        extensions.iter().find_map(|ext| {
+            let mut name = cmd.as_os_str().to_owned();
+            if !ext.is_empty() {
+                name.push(".");
+                name.push(ext);
+            }
+            Some(dir.join(name)).filter(|x| x.is_file())
+        })
+    })
+}
 
 #[derive(PartialEq)]
 enum State {
     Initial,
     Communicating,
+    Batch,
+    Draining,
     Done,
 }
 
@@ -42,9 +303,14 @@ enum State {
 pub struct ExternalIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
     source: I,
     state: State,
+    child: Option<Child>,
+    started: Option<Instant>,
+    received_event: bool,
     stdin: Option<ChildStdin>,
-    stdout: Option<BufReader<ChildStdout>>,
+    stdout: Option<ChildStdout>,
+    read_buffer: Vec<u8>,
     buffered_event: Option<Vec<u8>>,
+    batch_events: std::vec::IntoIter<AnnotatedEvent<'data>>,
     options: Cow<'options, External>,
     rt: Option<Runtime>,
 }
@@ -58,13 +324,56 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> ExternalIter<'d
         Self {
             source: iterator,
             state: State::Initial,
+            child: None,
+            started: None,
+            received_event: false,
             stdin: None,
             stdout: None,
+            read_buffer: Vec::new(),
             buffered_event: None,
+            batch_events: Vec::new().into_iter(),
             options: options.into(),
             rt: Some(rt),
         }
     }
+
+    fn run_batch(&mut self) -> Result<Vec<AnnotatedEvent<'static>>, String> {
+        let framing = self.options.framing;
+        let input = framing.encode_batch(&self.source.by_ref().collect::<Vec<_>>());
+        let mut stdin = self.stdin.take();
+        let mut stdout = self.stdout.take().unwrap();
+        let mut output = Vec::new();
+        let timeout = self.options.remaining_deadline(self.started.unwrap());
+        let rt = self.rt.as_ref().unwrap();
+
+        let result = rt.block_on(async {
+            let write_task = async {
+                if let Some(ref mut stdin) = stdin {
+                    stdin.write_all(&input).await?;
+                }
+                // close stdin so the program knows the document is complete.
+                drop(stdin.take());
+                Ok::<_, io::Error>(())
+            };
+            let read_task = stdout.read_to_end(&mut output);
+            let task = async { tokio::try_join!(write_task, read_task) };
+            match timeout {
+                Some((timeout, reason)) => tokio::time::timeout(timeout, task)
+                    .await
+                    .map_err(|_| format!("timed out {}", reason))?
+                    .map_err(|err| err.to_string()),
+                None => task.await.map_err(|err| err.to_string()),
+            }
+        });
+
+        if let Err(err) = result {
+            if let Some(mut child) = self.child.take() {
+                child.start_kill().ok();
+            }
+            return Err(err);
+        }
+        framing.decode_batch(&output)
+    }
 }
 
 fn error_event<D: Display>(err: &D, options: &External) -> ErrorEvent<'static> {
@@ -75,6 +384,7 @@ fn error_event<D: Display>(err: &D, options: &External) -> ErrorEvent<'static> {
         )
         .into(),
         description: Some(err.to_string().into()),
+        severity: Severity::Error,
     }
 }
 
@@ -87,6 +397,22 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
         loop {
             match self.state {
                 State::Done => return None,
+                State::Draining => {
+                    if let Some(rv) = self.batch_events.next() {
+                        return Some(rv);
+                    }
+                    self.state = State::Done;
+                }
+                State::Batch => match self.run_batch() {
+                    Ok(events) => {
+                        self.batch_events = events.into_iter();
+                        self.state = State::Draining;
+                    }
+                    Err(err) => {
+                        self.state = State::Done;
+                        return Some(error_event(&err, &self.options).into());
+                    }
+                },
                 State::Initial => {
                     let rt = self.rt.take().unwrap();
                     let mut error = None;
@@ -95,6 +421,8 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
                         cmd.args(&self.options.args)
                             .stdin(Stdio::piped())
                             .stdout(Stdio::piped())
+                            .kill_on_drop(true)
+                            .env("STRUCKDOWN_FRAMING", self.options.framing.name())
                             .envs(&self.options.env);
                         if let Some(ref cwd) = self.options.cwd {
                             cmd.current_dir(cwd);
@@ -102,7 +430,9 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
                         match cmd.spawn() {
                             Ok(mut process) => {
                                 self.stdin = process.stdin.take();
-                                self.stdout = process.stdout.take().map(BufReader::new);
+                                self.stdout = process.stdout.take();
+                                self.child = Some(process);
+                                self.started = Some(Instant::now());
                             }
                             Err(ref err) => {
                                 error = Some(error_event(err, &self.options));
@@ -114,7 +444,11 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
                         self.state = State::Done;
                         return Some(error.into());
                     } else {
-                        self.state = State::Communicating;
+                        self.state = if self.options.batch {
+                            State::Batch
+                        } else {
+                            State::Communicating
+                        };
                         self.rt = Some(rt);
                         continue;
                     }
@@ -122,11 +456,7 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
                 State::Communicating => {
                     if self.buffered_event.is_none() {
                         if let Some(event) = self.source.next() {
-                            let mut serialized_event = serde_json::to_vec(&event).expect(
-                                "Serializing events to external processors should never fail",
-                            );
-                            serialized_event.push(b'\n');
-                            self.buffered_event = Some(serialized_event);
+                            self.buffered_event = Some(self.options.framing.encode(&event));
                         } else {
                             // close stdin if we're done writing.
                             self.stdin.take();
@@ -134,12 +464,19 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
                     }
                     let mut stdin = self.stdin.take();
                     let mut stdout = self.stdout.take().unwrap();
+                    let mut read_buffer = mem::take(&mut self.read_buffer);
+                    let framing = self.options.framing;
                     let mut rv = None;
                     let mut done = false;
+                    let mut timed_out = None;
                     let rt = self.rt.take().unwrap();
+                    let timeout = self.options.next_timeout(
+                        self.started.unwrap(),
+                        self.buffered_event.is_some() && stdin.is_some(),
+                        self.received_event,
+                    );
 
                     rt.block_on(async {
-                        let mut line = String::new();
                         let should_write = self.buffered_event.is_some();
                         let write_task = async {
                             if let (Some(ref mut stdin), Some(ref buffered_event)) =
@@ -154,11 +491,11 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
                         let should_read = rv.is_none();
                         if should_read {
                             tokio::select! {
-                                read = stdout.read_line(&mut line) => {
+                                read = framing.read_frame(&mut stdout, &mut read_buffer) => {
                                     match read {
-                                        Ok(0) | Err(_) => done = true,
-                                        Ok(_) => {
-                                            rv = Some(match serde_json::from_str(&line) {
+                                        Ok(None) | Err(_) => done = true,
+                                        Ok(Some(frame)) => {
+                                            rv = Some(match framing.decode(&frame) {
                                                 Ok(event) => event,
                                                 Err(ref err) => {
                                                     self.state = State::Done;
@@ -175,14 +512,33 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
                                         rv = Some(error_event(&"failed to write to subprocess", &self.options).into());
                                     }
                                 }
+                                _ = tokio::time::sleep(timeout.map_or(Duration::from_secs(0), |x| x.0)),
+                                    if timeout.is_some() => {
+                                    timed_out = timeout.map(|x| x.1);
+                                }
                             }
                         }
                     });
 
                     self.stdin = stdin;
                     self.stdout = Some(stdout);
+                    self.read_buffer = read_buffer;
+
+                    if let Some(reason) = timed_out {
+                        if let Some(mut child) = self.child.take() {
+                            child.start_kill().ok();
+                        }
+                        self.stdin.take();
+                        self.stdout.take();
+                        self.state = State::Done;
+                        self.rt = Some(rt);
+                        return Some(
+                            error_event(&format!("timed out {}", reason), &self.options).into(),
+                        );
+                    }
 
                     if let Some(rv) = rv {
+                        self.received_event = true;
                         self.rt = Some(rt);
                         return Some(rv);
                     } else if done {
@@ -199,3 +555,104 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
         }
     }
 }
+
+#[test]
+fn test_framing_roundtrip() {
+    use crate::event::TextEvent;
+
+    for &framing in &[
+        Framing::JsonLines,
+        Framing::LengthPrefixedJson,
+        Framing::MessagePack,
+    ] {
+        let event: AnnotatedEvent = TextEvent {
+            text: "a\nb".into(),
+        }
+        .into();
+        let mut buf = framing.encode(&event);
+        buf.extend(framing.encode(&event));
+        for _ in 0..2 {
+            let frame = framing.split_frame(&mut buf).unwrap();
+            let decoded = framing.decode(&frame).unwrap();
+            assert_eq!(decoded.event.raw_text().unwrap().as_str(), "a\nb");
+        }
+        assert!(buf.is_empty());
+    }
+}
+
+#[test]
+fn test_next_timeout() {
+    let options = External {
+        cmd: "cat".into(),
+        args: Vec::new(),
+        env: BTreeMap::new(),
+        cwd: None,
+        framing: Framing::JsonLines,
+        startup_timeout_ms: Some(5000),
+        read_timeout_ms: None,
+        write_timeout_ms: Some(100),
+        deadline_ms: Some(1000),
+        batch: false,
+    };
+    let started = Instant::now();
+    assert_eq!(
+        options.next_timeout(started, true, false).unwrap().1,
+        "writing an event"
+    );
+    assert_eq!(
+        options.next_timeout(started, false, false).unwrap().1,
+        "the overall deadline"
+    );
+    assert_eq!(
+        options.next_timeout(started, false, true).unwrap().1,
+        "the overall deadline"
+    );
+}
+
+#[test]
+fn test_batch_mode() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(External {
+        cmd: "cat".into(),
+        args: Vec::new(),
+        env: BTreeMap::new(),
+        cwd: None,
+        framing: Framing::JsonLines,
+        startup_timeout_ms: None,
+        read_timeout_ms: None,
+        write_timeout_ms: None,
+        deadline_ms: Some(10000),
+        batch: true,
+    });
+    assert_eq!(
+        to_html(pipeline.process("Hello **World**!"), &Default::default()),
+        "<p>Hello <strong>World</strong>!</p>\n"
+    );
+}
+
+#[test]
+fn test_check_command() {
+    let external = External {
+        cmd: "definitely-not-a-struckdown-command".into(),
+        args: Vec::new(),
+        env: BTreeMap::new(),
+        cwd: None,
+        framing: Framing::default(),
+        startup_timeout_ms: None,
+        read_timeout_ms: None,
+        write_timeout_ms: None,
+        deadline_ms: None,
+        batch: false,
+    };
+    assert!(external.check().is_err());
+    if cfg!(unix) {
+        let external = External {
+            cmd: "sh".into(),
+            ..external
+        };
+        assert!(external.check().is_ok());
+    }
+}
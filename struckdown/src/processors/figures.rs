@@ -0,0 +1,311 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{
+    AnnotatedEvent, Attrs, DirectiveEvent, EndTagEvent, Event, ImageEvent, Location, StartTagEvent,
+    Tag, TextEvent,
+};
+
+/// Converts standalone images into figures.
+///
+/// A paragraph that contains nothing but an image becomes a
+/// [`Tag::Figure`] with a [`Tag::FigureCaption`].  The caption is taken from
+/// a directly following paragraph that is entirely emphasized (if
+/// `caption_from_emphasis` is enabled, the emphasis is removed) or otherwise
+/// from the image title.
+/// Images without a caption are left alone.
+///
+/// Additionally a directive (`figure` by default) creates a figure
+/// explicitly: the argument is the image target, the body the caption and the
+/// front matter can provide `alt` and `class`.
+///
+/// When applied this wraps the stream in a [`FiguresIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Figures {
+    /// The class added to the figures.
+    pub class_name: Option<String>,
+    /// Uses an emphasized paragraph following the image as caption.
+    pub caption_from_emphasis: bool,
+    /// Uses the image title as caption.
+    pub caption_from_title: bool,
+    /// The name of the directive that creates figures.
+    pub directive_name: Option<String>,
+}
+
+impl Default for Figures {
+    fn default() -> Figures {
+        Figures {
+            class_name: None,
+            caption_from_emphasis: true,
+            caption_from_title: true,
+            directive_name: Some("figure".into()),
+        }
+    }
+}
+
+implement_processor!(
+    Figures,
+    FiguresIter,
+    "Converts standalone images into figures"
+);
+
+/// The iterator implementing [`Figures`].
+pub struct FiguresIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    lookahead: VecDeque<AnnotatedEvent<'data>>,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, Figures>,
+}
+
+fn is_blank(event: &Event) -> bool {
+    match event {
+        Event::Text(TextEvent { text }) => text.as_str().trim().is_empty(),
+        Event::SoftBreak => true,
+        _ => false,
+    }
+}
+
+/// Returns the index of the image if the paragraph contains nothing else.
+fn standalone_image(paragraph: &[AnnotatedEvent<'_>]) -> Option<usize> {
+    let mut rv = None;
+    for (idx, annotated_event) in paragraph.iter().enumerate() {
+        match annotated_event.event {
+            Event::Image(..) if rv.is_none() => rv = Some(idx),
+            ref event if is_blank(event) => {}
+            _ => return None,
+        }
+    }
+    rv
+}
+
+/// Checks if the paragraph is a single emphasis.
+fn is_emphasized(paragraph: &[AnnotatedEvent<'_>]) -> bool {
+    let mut depth = 0;
+    for (idx, annotated_event) in paragraph.iter().enumerate() {
+        match annotated_event.event {
+            Event::StartTag(StartTagEvent {
+                tag: Tag::Emphasis, ..
+            }) => depth += 1,
+            Event::EndTag(EndTagEvent { tag: Tag::Emphasis }) => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            return idx > 0 && idx == paragraph.len() - 1;
+        }
+    }
+    false
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> FiguresIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Figures>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            lookahead: VecDeque::new(),
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+
+    fn pull(&mut self) -> Option<AnnotatedEvent<'data>> {
+        self.lookahead.pop_front().or_else(|| self.source.next())
+    }
+
+    /// Reads the contents of a paragraph after its start tag.
+    fn read_paragraph(&mut self) -> (Vec<AnnotatedEvent<'data>>, Option<AnnotatedEvent<'data>>) {
+        let mut rv = Vec::new();
+        while let Some(annotated_event) = self.pull() {
+            if let Event::EndTag(EndTagEvent {
+                tag: Tag::Paragraph,
+            }) = annotated_event.event
+            {
+                return (rv, Some(annotated_event));
+            }
+            rv.push(annotated_event);
+        }
+        (rv, None)
+    }
+
+    /// Reads the next paragraph if it is fully emphasized.
+    fn read_emphasized_paragraph(&mut self) -> Option<Vec<AnnotatedEvent<'data>>> {
+        let start = self.pull()?;
+        if !matches!(
+            start.event,
+            Event::StartTag(StartTagEvent {
+                tag: Tag::Paragraph,
+                ..
+            })
+        ) {
+            self.lookahead.push_front(start);
+            return None;
+        }
+        let (mut contents, end) = self.read_paragraph();
+        if end.is_some() && is_emphasized(&contents) {
+            contents.pop();
+            contents.remove(0);
+            return Some(contents);
+        }
+        self.lookahead.extend(end);
+        for annotated_event in contents.into_iter().rev() {
+            self.lookahead.push_front(annotated_event);
+        }
+        self.lookahead.push_front(start);
+        None
+    }
+
+    fn push_figure(
+        &mut self,
+        image: AnnotatedEvent<'data>,
+        caption: Vec<AnnotatedEvent<'data>>,
+        class: Option<String>,
+        location: Option<Location>,
+    ) {
+        let class = class.or_else(|| self.options.class_name.clone());
+        self.buffer.push_back(AnnotatedEvent::new(
+            Tag::Figure.start_tag(Attrs {
+                class: class.map(Into::into),
+                ..Attrs::default()
+            }),
+            location,
+        ));
+        self.buffer.push_back(image);
+        self.buffer.push_back(AnnotatedEvent::new(
+            Tag::FigureCaption.start_tag(Attrs::default()),
+            location,
+        ));
+        self.buffer.extend(caption);
+        self.buffer
+            .push_back(AnnotatedEvent::new(Tag::FigureCaption.end_tag(), location));
+        self.buffer
+            .push_back(AnnotatedEvent::new(Tag::Figure.end_tag(), location));
+    }
+
+    fn handle_paragraph(&mut self, start: AnnotatedEvent<'data>) {
+        let (mut contents, end) = self.read_paragraph();
+        let image_idx = match (standalone_image(&contents), &end) {
+            (Some(idx), Some(_)) => idx,
+            _ => {
+                self.buffer.push_back(start);
+                self.buffer.extend(contents);
+                self.buffer.extend(end);
+                return;
+            }
+        };
+
+        let mut caption = if self.options.caption_from_emphasis {
+            self.read_emphasized_paragraph()
+        } else {
+            None
+        };
+        if caption.is_none() && self.options.caption_from_title {
+            if let Event::Image(ImageEvent {
+                title: Some(ref title),
+                ..
+            }) = contents[image_idx].event
+            {
+                caption = Some(vec![AnnotatedEvent::new(
+                    TextEvent {
+                        text: title.clone(),
+                    },
+                    contents[image_idx].location,
+                )]);
+            }
+        }
+
+        match caption {
+            Some(caption) => {
+                let image = contents.swap_remove(image_idx);
+                self.push_figure(image, caption, None, start.location);
+            }
+            None => {
+                self.buffer.push_back(start);
+                self.buffer.extend(contents);
+                self.buffer.extend(end);
+            }
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for FiguresIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(annotated_event) = self.buffer.pop_front() {
+                return Some(annotated_event);
+            }
+
+            let annotated_event = self.pull()?;
+            match annotated_event.event {
+                Event::StartTag(StartTagEvent {
+                    tag: Tag::Paragraph,
+                    ..
+                }) => self.handle_paragraph(annotated_event),
+                Event::Directive(DirectiveEvent {
+                    ref name,
+                    argument: Some(ref target),
+                    ref front_matter,
+                    ref body,
+                }) if Some(name.as_str()) == self.options.directive_name.as_deref() => {
+                    let get = |key: &str| {
+                        front_matter
+                            .as_ref()
+                            .and_then(|x| x.get(key))
+                            .and_then(|x| x.as_str())
+                            .map(|x| x.to_string())
+                    };
+                    let image = AnnotatedEvent::new(
+                        ImageEvent {
+                            target: target.clone(),
+                            alt: get("alt").map(Into::into),
+                            title: None,
+                            attrs: Attrs::default(),
+                        },
+                        annotated_event.location,
+                    );
+                    let caption = body.as_str().trim();
+                    let caption = if caption.is_empty() {
+                        Vec::new()
+                    } else {
+                        vec![AnnotatedEvent::new(
+                            TextEvent {
+                                text: caption.to_string().into(),
+                            },
+                            annotated_event.location,
+                        )]
+                    };
+                    self.push_figure(image, caption, get("class"), annotated_event.location);
+                }
+                _ => return Some(annotated_event),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_figures() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(Figures::default());
+    assert_eq!(
+        to_html(
+            pipeline.process(
+                "![A](a.png \"Title\")\n\n![B](b.png)\n\n*Caption*\n\n![C](c.png)\n\n\
+                 Text ![D](d.png \"D\")"
+            ),
+            &Default::default()
+        ),
+        "<figure>\n<img src=\"a.png\" alt=\"A\" title=\"Title\">\
+         <figcaption>Title</figcaption>\n</figure>\n\
+         <figure>\n<img src=\"b.png\" alt=\"B\" title=\"\">\
+         <figcaption>Caption</figcaption>\n</figure>\n\
+         <p><img src=\"c.png\" alt=\"C\" title=\"\"></p>\n\
+         <p>Text <img src=\"d.png\" alt=\"D\" title=\"D\"></p>\n"
+    );
+}
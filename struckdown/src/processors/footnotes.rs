@@ -0,0 +1,309 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::vec;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{
+    AnnotatedEvent, Attrs, EndTagEvent, Event, FootnoteReferenceEvent, StartTagEvent, Str, Tag,
+    TextEvent,
+};
+
+/// Moves footnote definitions to the end of the document.
+///
+/// The definitions are ordered by the first reference to them (which is
+/// also the number the HTML renderer gives to references) and unreferenced
+/// definitions are appended after them.  Every reference gets an anchor
+/// (`reference_id_prefix` followed by the footnote name and a counter for
+/// repeated references) and every definition gets a number label as well as
+/// links back to its references.
+///
+/// When applied this wraps the stream in a [`FootnotesIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Footnotes {
+    /// An optional heading emitted before the footnotes.
+    pub heading: Option<String>,
+    /// The level of the heading.
+    pub heading_level: usize,
+    /// The class of the container that holds the footnotes.
+    pub container_class: Option<String>,
+    /// The class of the span holding the footnote number.
+    pub label_class: Option<String>,
+    /// The text of the backlinks.
+    pub backlink_text: String,
+    /// The class of the backlinks.
+    pub backlink_class: Option<String>,
+    /// The prefix for the anchors added to references.
+    pub reference_id_prefix: String,
+}
+
+impl Default for Footnotes {
+    fn default() -> Footnotes {
+        Footnotes {
+            heading: None,
+            heading_level: 2,
+            container_class: Some("footnotes".into()),
+            label_class: Some("footnote-label".into()),
+            backlink_text: "\u{21a9}".into(),
+            backlink_class: Some("footnote-backlink".into()),
+            reference_id_prefix: "fnref-".into(),
+        }
+    }
+}
+
+implement_processor!(
+    Footnotes,
+    FootnotesIter,
+    "Moves footnote definitions to the end of the document",
+);
+
+/// The iterator implementing [`Footnotes`].
+pub struct FootnotesIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: Option<I>,
+    iter: vec::IntoIter<AnnotatedEvent<'data>>,
+    options: Cow<'options, Footnotes>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> FootnotesIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Footnotes>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: Some(iterator),
+            iter: Vec::new().into_iter(),
+            options: options.into(),
+        }
+    }
+}
+
+fn class_attrs(class: Option<&str>) -> Attrs<'static> {
+    Attrs {
+        class: class.map(|x| x.to_string().into()),
+        ..Attrs::default()
+    }
+}
+
+impl Footnotes {
+    fn reference_id(&self, name: &str, count: usize) -> String {
+        if count == 1 {
+            format!("{}{}", self.reference_id_prefix, name)
+        } else {
+            format!("{}{}-{}", self.reference_id_prefix, name, count)
+        }
+    }
+
+    fn backlinks<'data>(&self, name: &str, references: usize) -> Vec<AnnotatedEvent<'data>> {
+        let mut rv = Vec::new();
+        for count in 1..=references {
+            rv.push(TextEvent { text: " ".into() }.into());
+            rv.push(
+                Tag::Link
+                    .start_tag(Attrs {
+                        target: Some(format!("#{}", self.reference_id(name, count)).into()),
+                        ..class_attrs(self.backlink_class.as_deref())
+                    })
+                    .into(),
+            );
+            rv.push(
+                TextEvent {
+                    text: self.backlink_text.clone().into(),
+                }
+                .into(),
+            );
+            rv.push(Tag::Link.end_tag().into());
+        }
+        rv
+    }
+
+    /// Renders a footnote definition with a label and backlinks.
+    fn render_definition<'data>(
+        &self,
+        out: &mut Vec<AnnotatedEvent<'data>>,
+        number: usize,
+        name: &str,
+        references: usize,
+        mut definition: Vec<AnnotatedEvent<'data>>,
+    ) {
+        let end = definition.pop();
+        let trailing_paragraph = match definition.last() {
+            Some(AnnotatedEvent {
+                event:
+                    Event::EndTag(EndTagEvent {
+                        tag: Tag::Paragraph,
+                    }),
+                ..
+            }) => definition.pop(),
+            _ => None,
+        };
+        let mut events = definition.into_iter();
+        out.extend(events.next());
+        out.push(
+            Tag::Span
+                .start_tag(class_attrs(self.label_class.as_deref()))
+                .into(),
+        );
+        out.push(
+            TextEvent {
+                text: number.to_string().into(),
+            }
+            .into(),
+        );
+        out.push(Tag::Span.end_tag().into());
+        out.extend(events);
+        out.extend(self.backlinks(name, references));
+        out.extend(trailing_paragraph);
+        out.extend(end);
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for FootnotesIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(source) = self.source.take() {
+            let mut events = Vec::new();
+            let mut definitions = HashMap::new();
+            let mut definition_order = Vec::new();
+            let mut reference_order = Vec::<Str<'data>>::new();
+            let mut reference_counts = HashMap::<Str<'data>, usize>::new();
+            let mut current_definition: Option<(Str<'data>, Vec<_>)> = None;
+            let mut depth = 0;
+
+            for annotated_event in source {
+                if current_definition.is_some() {
+                    match annotated_event.event {
+                        Event::StartTag(..) => depth += 1,
+                        Event::EndTag(..) => depth -= 1,
+                        _ => {}
+                    }
+                } else if let Event::StartTag(StartTagEvent {
+                    tag: Tag::FootnoteDefinition,
+                    ref attrs,
+                }) = annotated_event.event
+                {
+                    let name = attrs.id.clone().unwrap_or_else(|| Str::new(""));
+                    current_definition = Some((name, Vec::new()));
+                    depth = 1;
+                }
+
+                if let Event::FootnoteReference(FootnoteReferenceEvent { ref target }) =
+                    annotated_event.event
+                {
+                    let count = reference_counts.entry(target.clone()).or_insert(0);
+                    if *count == 0 {
+                        reference_order.push(target.clone());
+                    }
+                    *count += 1;
+                    let anchor = Tag::Span.start_tag(Attrs {
+                        id: Some(self.options.reference_id(target.as_str(), *count).into()),
+                        ..Attrs::default()
+                    });
+                    let buf = match current_definition {
+                        Some((_, ref mut buf)) => buf,
+                        None => &mut events,
+                    };
+                    buf.push(AnnotatedEvent::new(anchor, annotated_event.location));
+                    buf.push(AnnotatedEvent::new(
+                        Tag::Span.end_tag(),
+                        annotated_event.location,
+                    ));
+                }
+
+                match current_definition {
+                    Some((_, ref mut buf)) => {
+                        buf.push(annotated_event);
+                        if depth == 0 {
+                            let (name, buf) = current_definition.take().unwrap();
+                            definition_order.push(name.clone());
+                            definitions.insert(name, buf);
+                        }
+                    }
+                    None => events.push(annotated_event),
+                }
+            }
+
+            // definitions that were not closed are left where they are
+            if let Some((_, buf)) = current_definition {
+                events.extend(buf);
+            }
+
+            let ordered = reference_order
+                .into_iter()
+                .filter(|name| definitions.contains_key(name))
+                .chain(
+                    definition_order
+                        .into_iter()
+                        .filter(|name| !reference_counts.contains_key(name)),
+                )
+                .collect::<Vec<_>>();
+
+            if !ordered.is_empty() {
+                events.push(
+                    Tag::Container
+                        .start_tag(class_attrs(self.options.container_class.as_deref()))
+                        .into(),
+                );
+                if let Some(ref heading) = self.options.heading {
+                    let tag = Tag::heading(self.options.heading_level);
+                    events.push(tag.start_tag(Attrs::default()).into());
+                    events.push(
+                        TextEvent {
+                            text: heading.clone().into(),
+                        }
+                        .into(),
+                    );
+                    events.push(tag.end_tag().into());
+                }
+                for (idx, name) in ordered.into_iter().enumerate() {
+                    if let Some(definition) = definitions.remove(&name) {
+                        let references = reference_counts.get(&name).copied().unwrap_or(0);
+                        self.options.render_definition(
+                            &mut events,
+                            idx + 1,
+                            name.as_str(),
+                            references,
+                            definition,
+                        );
+                    }
+                }
+                events.push(Tag::Container.end_tag().into());
+            }
+
+            self.iter = events.into_iter();
+        }
+
+        self.iter.next()
+    }
+}
+
+#[test]
+fn test_footnotes() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(Footnotes {
+        heading: Some("Notes".into()),
+        ..Default::default()
+    });
+    let html = to_html(
+        pipeline.process("[^b]: Second\n\n[^a]: First\n\nA[^a] B[^b] A[^a]"),
+        &Default::default(),
+    );
+    assert_eq!(
+        html,
+        "<p>A<span id=\"fnref-a\"></span><sup class=\"footnote-reference\"><a href=\"#a\">1</a></sup> \
+         B<span id=\"fnref-b\"></span><sup class=\"footnote-reference\"><a href=\"#b\">2</a></sup> \
+         A<span id=\"fnref-a-2\"></span><sup class=\"footnote-reference\"><a href=\"#a\">1</a></sup></p>\n\
+         <div class=\"footnotes\">\n<h2>Notes</h2>\n\
+         <div id=\"a\" class=\"footnote-definition\">\n<span class=\"footnote-label\">1</span>\
+         <p>First \
+         <a href=\"#fnref-a\" class=\"footnote-backlink\">\u{21a9}</a> \
+         <a href=\"#fnref-a-2\" class=\"footnote-backlink\">\u{21a9}</a></p>\n</div>\n\
+         <div id=\"b\" class=\"footnote-definition\">\n<span class=\"footnote-label\">2</span>\
+         <p>Second \
+         <a href=\"#fnref-b\" class=\"footnote-backlink\">\u{21a9}</a></p>\n</div>\n\
+         </div>\n"
+    );
+}
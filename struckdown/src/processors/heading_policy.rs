@@ -0,0 +1,230 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{
+    AnnotatedEvent, EndTagEvent, ErrorEvent, Event, Location, Severity, StartTagEvent, Tag,
+};
+
+/// How [`HeadingPolicy`] deals with violations.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyMode {
+    /// Emits an error event before the offending heading.
+    Report,
+    /// Changes the level of the offending heading.
+    Fix,
+}
+
+/// Enforces rules for the heading structure of a document.
+///
+/// The rules are: only a single top-level heading (`single_h1`), no skipped
+/// levels such as a level 3 heading directly below a level 1 heading
+/// (`no_skipped_levels`) and a maximum heading level (`max_level`).
+///
+/// In `report` mode violations are reported with an error event before the
+/// heading.  In `fix` mode additional top-level headings are demoted to
+/// level 2, skipped levels are closed by promoting the heading and its
+/// subheadings and headings that are too deep are clamped to `max_level`.
+///
+/// When applied this wraps the stream in a [`HeadingPolicyIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct HeadingPolicy {
+    /// Whether violations are reported or fixed.
+    pub mode: PolicyMode,
+    /// Only allows a single level 1 heading.
+    pub single_h1: bool,
+    /// Disallows skipping heading levels.
+    pub no_skipped_levels: bool,
+    /// The maximum heading level.
+    pub max_level: Option<usize>,
+}
+
+impl Default for HeadingPolicy {
+    fn default() -> HeadingPolicy {
+        HeadingPolicy {
+            mode: PolicyMode::Report,
+            single_h1: true,
+            no_skipped_levels: true,
+            max_level: None,
+        }
+    }
+}
+
+implement_processor!(
+    HeadingPolicy,
+    HeadingPolicyIter,
+    "Enforces rules for the heading structure"
+);
+
+/// The iterator implementing [`HeadingPolicy`].
+pub struct HeadingPolicyIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    // original and fixed level of the enclosing headings
+    stack: Vec<(usize, usize)>,
+    seen_h1: bool,
+    current: Option<Tag>,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, HeadingPolicy>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    HeadingPolicyIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, HeadingPolicy>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            stack: Vec::new(),
+            seen_h1: false,
+            current: None,
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+
+    fn report(&mut self, title: &str, description: String, location: Option<Location>) {
+        self.buffer.push_back(AnnotatedEvent::new(
+            ErrorEvent {
+                title: title.to_string().into(),
+                description: Some(description.into()),
+                severity: Severity::Warning,
+            },
+            location,
+        ));
+    }
+
+    /// Checks a heading and returns the level it should have.
+    fn check_heading(&mut self, level: usize, location: Option<Location>) -> usize {
+        let fix = self.options.mode == PolicyMode::Fix;
+        let mut new_level = level;
+
+        while matches!(self.stack.last(), Some(&(orig, _)) if orig >= level) {
+            self.stack.pop();
+        }
+        if self.options.no_skipped_levels {
+            if let Some(&(orig, parent)) = self.stack.last() {
+                if level > parent + 1 {
+                    if fix {
+                        new_level = parent + 1;
+                    } else {
+                        self.report(
+                            "Skipped heading level",
+                            format!("level {} heading follows level {} heading", level, orig),
+                            location,
+                        );
+                    }
+                }
+            }
+        }
+        self.stack.push((level, new_level));
+
+        if self.options.single_h1 && new_level == 1 {
+            if !self.seen_h1 {
+                self.seen_h1 = true;
+            } else if fix {
+                new_level = 2;
+            } else {
+                self.report(
+                    "Multiple top-level headings",
+                    "a document should only have a single level 1 heading".into(),
+                    location,
+                );
+            }
+        }
+
+        if let Some(max_level) = self.options.max_level {
+            if new_level > max_level {
+                if fix {
+                    new_level = max_level.max(1);
+                } else {
+                    self.report(
+                        "Heading too deep",
+                        format!(
+                            "level {} heading exceeds the maximum level {}",
+                            new_level, max_level
+                        ),
+                        location,
+                    );
+                }
+            }
+        }
+
+        new_level
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for HeadingPolicyIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.buffer.pop_front() {
+            return Some(annotated_event);
+        }
+
+        let mut annotated_event = self.source.next()?;
+        match annotated_event.event {
+            Event::StartTag(StartTagEvent { ref mut tag, .. }) => {
+                if let Some(level) = tag.header_level() {
+                    let new_level = self.check_heading(level, annotated_event.location);
+                    *tag = Tag::heading(new_level);
+                    self.current = Some(*tag);
+                }
+            }
+            Event::EndTag(EndTagEvent { ref mut tag }) if tag.header_level().is_some() => {
+                if let Some(current) = self.current.take() {
+                    *tag = current;
+                }
+            }
+            _ => {}
+        }
+
+        // reported errors go before the heading
+        if self.buffer.is_empty() {
+            Some(annotated_event)
+        } else {
+            self.buffer.push_back(annotated_event);
+            self.buffer.pop_front()
+        }
+    }
+}
+
+#[test]
+fn test_heading_policy() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let source = "# A\n\n### B\n\n#### C\n\n### D\n\n# E";
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(HeadingPolicy::default());
+    let errors = pipeline
+        .process(source)
+        .filter_map(|x| match x.event {
+            Event::Error(ErrorEvent { title, .. }) => Some(title.as_str().to_string()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        errors,
+        vec![
+            "Skipped heading level",
+            "Skipped heading level",
+            "Multiple top-level headings"
+        ]
+    );
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(HeadingPolicy {
+        mode: PolicyMode::Fix,
+        max_level: Some(2),
+        ..Default::default()
+    });
+    assert_eq!(
+        to_html(pipeline.process(source), &Default::default()),
+        "<h1>A</h1>\n<h2>B</h2>\n<h2>C</h2>\n<h2>D</h2>\n<h2>E</h2>\n"
+    );
+}
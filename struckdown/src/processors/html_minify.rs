@@ -0,0 +1,220 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Event, RawHtmlEvent};
+
+/// Elements whose contents are never minified.
+const PRESERVED_ELEMENTS: &[&str] = &["pre", "textarea", "script", "style"];
+
+/// Minifies embedded raw HTML.
+///
+/// Runs of whitespace outside of tags are collapsed into a single space and
+/// comments are removed if `remove_comments` is enabled.  The contents of
+/// `<pre>`, `<textarea>`, `<script>` and `<style>` elements are preserved.
+/// As the parser splits HTML blocks into multiple events the state is
+/// carried over from one raw HTML event to the next.
+///
+/// When applied this wraps the stream in a [`HtmlMinifyIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct HtmlMinify {
+    /// Collapses whitespace.
+    pub collapse_whitespace: bool,
+    /// Removes HTML comments.
+    pub remove_comments: bool,
+}
+
+impl Default for HtmlMinify {
+    fn default() -> HtmlMinify {
+        HtmlMinify {
+            collapse_whitespace: true,
+            remove_comments: true,
+        }
+    }
+}
+
+implement_processor!(HtmlMinify, HtmlMinifyIter, "Minifies embedded raw HTML");
+
+/// Where the minifier currently is.
+#[derive(Debug, PartialEq)]
+enum State {
+    Text,
+    Comment,
+    Tag,
+    Preserved(&'static str),
+}
+
+/// Finds the start of a closing tag case insensitively.
+fn find_closing_tag(html: &str, name: &str) -> Option<usize> {
+    let lower = html.to_ascii_lowercase();
+    let needle = format!("</{}", name);
+    lower.find(&needle)
+}
+
+/// Returns the preserved element a start tag opens.
+fn preserved_element(tag: &str) -> Option<&'static str> {
+    let name = tag
+        .trim_start_matches('<')
+        .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .next()?;
+    PRESERVED_ELEMENTS
+        .iter()
+        .copied()
+        .find(|x| x.eq_ignore_ascii_case(name))
+}
+
+struct Minifier {
+    state: State,
+    pending_space: bool,
+}
+
+impl Minifier {
+    fn minify(&mut self, options: &HtmlMinify, html: &str) -> String {
+        let mut rv = String::with_capacity(html.len());
+        let mut rest = html;
+
+        while !rest.is_empty() {
+            match self.state {
+                State::Preserved(name) => match find_closing_tag(rest, name) {
+                    Some(idx) => {
+                        rv.push_str(&rest[..idx]);
+                        rest = &rest[idx..];
+                        self.state = State::Text;
+                    }
+                    None => {
+                        rv.push_str(rest);
+                        rest = "";
+                    }
+                },
+                State::Comment => {
+                    let (comment, end) = match rest.find("-->") {
+                        Some(idx) => (&rest[..idx + 3], true),
+                        None => (rest, false),
+                    };
+                    if !options.remove_comments {
+                        rv.push_str(comment);
+                    }
+                    rest = &rest[comment.len()..];
+                    if end {
+                        self.state = State::Text;
+                    }
+                }
+                State::Tag => {
+                    let (tag, end) = match rest.find('>') {
+                        Some(idx) => (&rest[..idx + 1], true),
+                        None => (rest, false),
+                    };
+                    rv.push_str(tag);
+                    rest = &rest[tag.len()..];
+                    if end {
+                        self.state = State::Text;
+                    }
+                }
+                State::Text => {
+                    let c = rest.chars().next().unwrap();
+                    if c.is_ascii_whitespace() && options.collapse_whitespace {
+                        self.pending_space = true;
+                        rest = &rest[1..];
+                        continue;
+                    }
+                    if self.pending_space {
+                        rv.push(' ');
+                        self.pending_space = false;
+                    }
+                    if rest.starts_with("<!--") {
+                        self.state = State::Comment;
+                        if options.remove_comments {
+                            // the whitespace before a comment is still
+                            // pending once the comment is gone.
+                            self.pending_space = rv.ends_with(' ');
+                            if self.pending_space {
+                                rv.pop();
+                            }
+                        }
+                    } else if c == '<' {
+                        let tag_end = rest.find('>').map_or(rest.len(), |idx| idx + 1);
+                        let tag = &rest[..tag_end];
+                        rv.push_str(tag);
+                        rest = &rest[tag_end..];
+                        if !tag.ends_with('>') {
+                            self.state = State::Tag;
+                        } else if let Some(name) = preserved_element(tag) {
+                            self.state = State::Preserved(name);
+                        }
+                    } else {
+                        rv.push(c);
+                        rest = &rest[c.len_utf8()..];
+                    }
+                }
+            }
+        }
+
+        rv
+    }
+}
+
+/// The iterator implementing [`HtmlMinify`].
+pub struct HtmlMinifyIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    minifier: Minifier,
+    options: Cow<'options, HtmlMinify>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    HtmlMinifyIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, HtmlMinify>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            minifier: Minifier {
+                state: State::Text,
+                pending_space: false,
+            },
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for HtmlMinifyIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut annotated_event = self.source.next()?;
+            match annotated_event.event {
+                Event::RawHtml(RawHtmlEvent { ref mut html }) => {
+                    let minified = self.minifier.minify(&self.options, html.as_str());
+                    if minified.is_empty() {
+                        continue;
+                    }
+                    *html = minified.into();
+                }
+                // trailing whitespace of an html run is dropped
+                _ => self.minifier.pending_space = false,
+            }
+            return Some(annotated_event);
+        }
+    }
+}
+
+#[test]
+fn test_minify() {
+    let mut minifier = Minifier {
+        state: State::Text,
+        pending_space: false,
+    };
+    let options = HtmlMinify::default();
+    assert_eq!(
+        minifier.minify(&options, "<div>\n  <!-- comment -->\n  <p>a   b</p>\n"),
+        "<div> <p>a b</p>"
+    );
+    assert_eq!(minifier.minify(&options, "  <pre>\n  x\n"), " <pre>\n  x\n");
+    assert_eq!(
+        minifier.minify(&options, "</PRE>\n</div>\n"),
+        "</PRE> </div>"
+    );
+    assert_eq!(minifier.state, State::Text);
+}
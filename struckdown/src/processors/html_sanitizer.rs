@@ -1,5 +1,8 @@
 use std::borrow::Cow;
 use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
 
 use ammonia::Builder;
 use lazy_static::lazy_static;
@@ -7,13 +10,53 @@ use regex::Regex;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::event::{AnnotatedEvent, Event};
+use crate::event::{AnnotatedEvent, Event, ImageEvent, StartTagEvent, Tag};
+use crate::processors::BuiltinProcessor;
 
 lazy_static! {
     static ref MARKER_RE: Regex = Regex::new(r"\A\.\.\.([a-f0-9]{32})\.\.\.").unwrap();
 }
 
-/// Automatically add anchors to all headers when missing.
+/// Attributes that hold URLs which are passed to the URL rewriting.
+const URL_ATTRIBUTES: &[&str] = &[
+    "action",
+    "background",
+    "cite",
+    "formaction",
+    "href",
+    "poster",
+    "src",
+];
+
+/// A user supplied function to rewrite URLs.
+///
+/// It's invoked with the tag, the attribute and the URL and returns the new
+/// URL or `None` to keep the URL as is.
+#[derive(Clone)]
+#[allow(clippy::type_complexity)]
+pub struct UrlRewriteFn(Arc<dyn Fn(&str, &str, &str) -> Option<String> + Send + Sync>);
+
+impl UrlRewriteFn {
+    /// Wraps a function for use as URL rewrite callback.
+    pub fn new<F: Fn(&str, &str, &str) -> Option<String> + Send + Sync + 'static>(
+        f: F,
+    ) -> UrlRewriteFn {
+        UrlRewriteFn(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for UrlRewriteFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("UrlRewriteFn").finish()
+    }
+}
+
+/// Sanitizes raw HTML in the stream.
+///
+/// URLs can be rewritten during sanitization: `force_https` upgrades `http`
+/// URLs, `image_proxy` routes external images through a proxy and a custom
+/// `rewrite_url` function can be set from code.  With `rewrite_events` the
+/// rewriting also applies to links and images outside of raw HTML.
 ///
 /// When applied this wraps the stream in a [`HtmlSanitizerIter`].
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -29,6 +72,27 @@ pub struct HtmlSanitizer {
     pub allow_style: bool,
     /// If set to `false` then comments are removed.
     pub allow_comments: bool,
+    /// Additional tags to permit.
+    pub allowed_tags: HashSet<String>,
+    /// Tags to remove from the default set of permitted tags.
+    pub removed_tags: HashSet<String>,
+    /// Additional attributes to permit on all tags.
+    pub allowed_attributes: HashSet<String>,
+    /// Additional attributes to permit by tag.
+    pub tag_attributes: BTreeMap<String, HashSet<String>>,
+    /// Attributes to permit by tag only with the given values (eg: the
+    /// `src` of an `iframe` for known embeds).
+    pub tag_attribute_values: BTreeMap<String, BTreeMap<String, HashSet<String>>>,
+    /// Rewrites `http://` URLs to `https://`.
+    pub force_https: bool,
+    /// A URL to proxy external images through.  The `{url}` placeholder is
+    /// replaced with the percent encoded image URL.
+    pub image_proxy: Option<String>,
+    /// Controls if links and images in the event stream are rewritten too.
+    pub rewrite_events: bool,
+    /// A custom URL rewrite function.  This can only be set from code.
+    #[serde(skip)]
+    pub rewrite_url: Option<UrlRewriteFn>,
 }
 
 impl Default for HtmlSanitizer {
@@ -47,13 +111,105 @@ impl Default for HtmlSanitizer {
             allow_class: false,
             allow_style: false,
             allow_comments: true,
+            allowed_tags: HashSet::new(),
+            removed_tags: HashSet::new(),
+            allowed_attributes: HashSet::new(),
+            tag_attributes: BTreeMap::new(),
+            tag_attribute_values: BTreeMap::new(),
+            force_https: false,
+            image_proxy: None,
+            rewrite_events: false,
+            rewrite_url: None,
         }
     }
 }
 
-implement_processor!(HtmlSanitizer, HtmlSanitizerIter);
+impl HtmlSanitizer {
+    /// Returns the configuration unless options only settable from code are used.
+    fn builtin_config(&self) -> Option<BuiltinProcessor> {
+        if self.rewrite_url.is_some() {
+            return None;
+        }
+        Some(self.clone().into())
+    }
+
+    fn rewrites_urls(&self) -> bool {
+        self.force_https || self.image_proxy.is_some() || self.rewrite_url.is_some()
+    }
+
+    /// Rewrites a URL found in the given tag and attribute.
+    ///
+    /// Returns `None` if the URL is unchanged.
+    pub fn rewrite_url(&self, tag: &str, attribute: &str, url: &str) -> Option<String> {
+        let mut rv = None;
+        if self.force_https {
+            if let Some(rest) = url.strip_prefix("http://") {
+                rv = Some(format!("https://{}", rest));
+            }
+        }
+        if let Some(ref image_proxy) = self.image_proxy {
+            let current = rv.as_deref().unwrap_or(url);
+            let proxy_prefix = image_proxy.split("{url}").next().unwrap_or("");
+            let is_proxied = !proxy_prefix.is_empty() && current.starts_with(proxy_prefix);
+            if tag == "img"
+                && attribute == "src"
+                && (current.starts_with("http://") || current.starts_with("https://"))
+                && !is_proxied
+            {
+                rv = Some(image_proxy.replace("{url}", &percent_encode(current)));
+            }
+        }
+        if let Some(ref rewrite_url) = self.rewrite_url {
+            if let Some(new_url) = (rewrite_url.0)(tag, attribute, rv.as_deref().unwrap_or(url)) {
+                rv = Some(new_url);
+            }
+        }
+        rv
+    }
 
-fn make_ammonia(options: &HtmlSanitizer) -> Builder {
+    fn rewrite_event(&self, event: &mut Event) {
+        match *event {
+            Event::StartTag(StartTagEvent {
+                tag: Tag::Link,
+                ref mut attrs,
+            }) => {
+                if let Some(ref mut target) = attrs.target {
+                    if let Some(new_url) = self.rewrite_url("a", "href", target.as_str()) {
+                        *target = new_url.into();
+                    }
+                }
+            }
+            Event::Image(ImageEvent { ref mut target, .. }) => {
+                if let Some(new_url) = self.rewrite_url("img", "src", target.as_str()) {
+                    *target = new_url.into();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Percent encodes everything but unreserved characters.
+fn percent_encode(value: &str) -> String {
+    let mut rv = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            rv.push(byte as char);
+        } else {
+            rv.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    rv
+}
+
+implement_processor!(
+    HtmlSanitizer,
+    HtmlSanitizerIter,
+    "Sanitizes raw HTML",
+    to_builtin = HtmlSanitizer::builtin_config,
+);
+
+fn make_ammonia(options: &HtmlSanitizer) -> Builder<'_> {
     let mut ammonia = Builder::default();
     let mut clean_content_tags = HashSet::new();
     clean_content_tags.insert("script");
@@ -68,16 +224,153 @@ fn make_ammonia(options: &HtmlSanitizer) -> Builder {
     } else {
         clean_content_tags.insert("style");
     }
+    ammonia
+        .add_tags(&options.allowed_tags)
+        .rm_tags(&options.removed_tags)
+        .add_generic_attributes(&options.allowed_attributes);
+    for (tag, attributes) in &options.tag_attributes {
+        ammonia.add_tag_attributes(tag, attributes);
+    }
+    for (tag, attributes) in &options.tag_attribute_values {
+        for (attribute, values) in attributes {
+            ammonia.add_tag_attribute_values(tag, attribute, values);
+        }
+    }
+    // explicitly permitted tags win over tags that are removed with their
+    // contents.  ammonia refuses to have a tag in both lists.
+    clean_content_tags.retain(|tag| !options.allowed_tags.contains(*tag));
     ammonia.clean_content_tags(clean_content_tags);
+    if options.rewrites_urls() {
+        let options = options.clone();
+        ammonia.attribute_filter(move |tag, attribute, value| {
+            if URL_ATTRIBUTES.contains(&attribute) {
+                if let Some(new_url) = options.rewrite_url(tag, attribute, value) {
+                    return Some(new_url.into());
+                }
+            }
+            Some(value.into())
+        });
+    }
     ammonia.link_rel(options.link_rel.as_deref());
     ammonia.strip_comments(!options.allow_comments);
     ammonia
 }
 
+/// Elements that never contain content.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Elements that are commonly left unclosed in HTML.
+const OPTIONAL_END_ELEMENTS: &[&str] = &[
+    "dd", "dt", "li", "option", "p", "rp", "rt", "td", "th", "tr",
+];
+
+/// Elements the contents of which are not parsed as HTML.
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style"];
+
+/// Returns by how much a fragment of HTML changes the element nesting.
+///
+/// This is only a heuristic to find out where HTML blocks end.
+fn nesting_delta(html: &str) -> isize {
+    let mut delta = 0;
+    let mut rest = html;
+    while let Some(idx) = rest.find('<') {
+        rest = &rest[idx + 1..];
+        if let Some(comment) = rest.strip_prefix("!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let (closing, tag) = match rest.strip_prefix('/') {
+            Some(tag) => (true, tag),
+            None => (false, rest),
+        };
+        let name = tag
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        if name.is_empty()
+            || VOID_ELEMENTS.contains(&name.as_str())
+            || OPTIONAL_END_ELEMENTS.contains(&name.as_str())
+        {
+            continue;
+        }
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        if closing {
+            delta -= 1;
+        } else if !rest[..end].ends_with('/') {
+            delta += 1;
+            if RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+                let close = format!("</{}", name);
+                match rest.to_ascii_lowercase().find(&close) {
+                    Some(idx) => rest = &rest[idx..],
+                    None => break,
+                }
+            }
+        }
+    }
+    delta
+}
+
+/// Sanitizes all raw HTML events together.
+///
+/// The fragments are joined with markers so that HTML elements spanning
+/// multiple events are handled correctly.
+fn sanitize_events(buffer: &mut [AnnotatedEvent<'_>], options: &HtmlSanitizer) {
+    let marker = format!("...{}...", Uuid::new_v4().to_simple());
+    let mut html_buf = String::new();
+    let ammonia = make_ammonia(options);
+    let mut segments = BTreeMap::new();
+    let mut idx = 0;
+    for annotated_event in buffer.iter() {
+        if let Event::RawHtml(ref raw_html) = annotated_event.event {
+            idx += 1;
+            let id = Uuid::new_v4();
+            html_buf.push_str(&format!("...{}...", id.to_simple()));
+            html_buf.push_str(raw_html.html.as_str());
+            html_buf.push_str(&marker);
+            segments.insert(id, idx);
+        }
+    }
+    let cleaned = ammonia.clean(&html_buf).to_string();
+
+    let mut replacements = BTreeMap::new();
+    for segment in cleaned.split(&marker) {
+        if let Some(id) = MARKER_RE.captures(segment) {
+            if let Some(&idx) = segments.get(&Uuid::parse_str(&id[1]).unwrap()) {
+                replacements.insert(idx, &segment[id.get(0).unwrap().end()..]);
+            }
+        }
+    }
+
+    let mut idx = 0;
+    for annotated_event in buffer.iter_mut() {
+        if let Event::RawHtml(ref mut raw_html) = annotated_event.event {
+            idx += 1;
+            if let Some(&new) = replacements.get(&idx) {
+                raw_html.html = new.to_string().into();
+                continue;
+            }
+            raw_html.html = "".into();
+        }
+    }
+}
+
 /// The iterator implementing [`HtmlSanitizer`].
+///
+/// Events are only buffered from the start of an HTML element until it's
+/// closed again, so the memory use is proportional to the largest HTML
+/// block rather than the document.
 pub struct HtmlSanitizerIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
-    source_iter: Option<I>,
-    processed_iter: std::vec::IntoIter<AnnotatedEvent<'data>>,
+    source: I,
+    pending: Vec<AnnotatedEvent<'data>>,
+    depth: isize,
+    ready: std::vec::IntoIter<AnnotatedEvent<'data>>,
     options: Cow<'options, HtmlSanitizer>,
 }
 
@@ -86,11 +379,20 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
 {
     pub fn new<O: Into<Cow<'options, HtmlSanitizer>>>(iterator: I, options: O) -> Self {
         Self {
-            source_iter: Some(iterator),
-            processed_iter: Vec::new().into_iter(),
+            source: iterator,
+            pending: Vec::new(),
+            depth: 0,
+            ready: Vec::new().into_iter(),
             options: options.into(),
         }
     }
+
+    fn flush(&mut self) {
+        let mut pending = mem::take(&mut self.pending);
+        sanitize_events(&mut pending, &self.options);
+        self.ready = pending.into_iter();
+        self.depth = 0;
+    }
 }
 
 impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
@@ -99,48 +401,88 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
     type Item = AnnotatedEvent<'data>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(source_iter) = self.source_iter.take() {
-            let marker = format!("...{}...", Uuid::new_v4().to_simple());
-            let mut buffer = source_iter.collect::<Vec<_>>();
-            let mut html_buf = String::new();
-            let ammonia = make_ammonia(&self.options);
-            let mut segments = BTreeMap::new();
-            let mut idx = 0;
-            for annotated_event in &mut buffer {
-                if let Event::RawHtml(ref mut raw_html) = annotated_event.event {
-                    idx += 1;
-                    let id = Uuid::new_v4();
-                    html_buf.push_str(&format!("...{}...", id.to_simple()));
-                    html_buf.push_str(raw_html.html.as_str());
-                    html_buf.push_str(&marker);
-                    segments.insert(id, idx);
-                }
+        loop {
+            if let Some(annotated_event) = self.ready.next() {
+                return Some(annotated_event);
             }
-            let cleaned = ammonia.clean(&html_buf).to_string();
 
-            let mut replacements = BTreeMap::new();
-            for segment in cleaned.split(&marker) {
-                if let Some(id) = MARKER_RE.captures(segment) {
-                    if let Some(&idx) = segments.get(&Uuid::parse_str(&id[1]).unwrap()) {
-                        replacements.insert(idx, &segment[id.get(0).unwrap().end()..]);
-                    }
+            let mut annotated_event = match self.source.next() {
+                Some(annotated_event) => annotated_event,
+                None if self.pending.is_empty() => return None,
+                None => {
+                    self.flush();
+                    continue;
                 }
-            }
+            };
 
-            let mut idx = 0;
-            for annotated_event in &mut buffer {
-                if let Event::RawHtml(ref mut raw_html) = annotated_event.event {
-                    idx += 1;
-                    if let Some(&new) = replacements.get(&idx) {
-                        raw_html.html = new.to_string().into();
-                        continue;
-                    }
-                    raw_html.html = "".into();
-                }
+            if self.options.rewrite_events && self.options.rewrites_urls() {
+                self.options.rewrite_event(&mut annotated_event.event);
+            }
+            if let Event::RawHtml(ref raw_html) = annotated_event.event {
+                self.depth += nesting_delta(raw_html.html.as_str());
+            } else if self.pending.is_empty() {
+                return Some(annotated_event);
+            }
+            self.pending.push(annotated_event);
+            if self.depth <= 0 {
+                self.flush();
             }
-
-            self.processed_iter = buffer.into_iter();
         }
-        self.processed_iter.next()
     }
 }
+
+#[test]
+fn test_allow_lists() {
+    let mut options = HtmlSanitizer::default();
+    options.allowed_tags.insert("iframe".into());
+    options.removed_tags.insert("em".into());
+    options
+        .tag_attribute_values
+        .entry("iframe".into())
+        .or_default()
+        .entry("src".into())
+        .or_default()
+        .insert("https://www.youtube.com/embed/x".into());
+    let ammonia = make_ammonia(&options);
+    assert_eq!(
+        ammonia
+            .clean(
+                "<iframe src=\"https://www.youtube.com/embed/x\"></iframe>\
+                 <iframe src=\"https://evil.invalid/\"></iframe><em>x</em>"
+            )
+            .to_string(),
+        "<iframe src=\"https://www.youtube.com/embed/x\"></iframe><iframe></iframe>x"
+    );
+}
+
+#[test]
+fn test_url_rewriting() {
+    let options = HtmlSanitizer {
+        force_https: true,
+        image_proxy: Some("https://camo.example.com/?url={url}".into()),
+        rewrite_url: Some(UrlRewriteFn::new(|_, attribute, url| {
+            if attribute == "href" && url.starts_with('/') {
+                Some(format!("/docs{}", url))
+            } else {
+                None
+            }
+        })),
+        ..Default::default()
+    };
+    assert_eq!(
+        make_ammonia(&options)
+            .clean("<a href=\"/x\">x</a><img src=\"http://example.com/a.png\">")
+            .to_string(),
+        "<a href=\"/docs/x\">x</a>\
+         <img src=\"https://camo.example.com/?url=https%3A%2F%2Fexample.com%2Fa.png\">"
+    );
+}
+
+#[test]
+fn test_nesting_delta() {
+    assert_eq!(nesting_delta("<div class=\"x\">"), 1);
+    assert_eq!(nesting_delta("</div>"), -1);
+    assert_eq!(nesting_delta("<div><p>Hello<br></div>"), 0);
+    assert_eq!(nesting_delta("<!-- <div> --><img src=x />"), 0);
+    assert_eq!(nesting_delta("<script>if (a <b) {}</script>"), 0);
+}
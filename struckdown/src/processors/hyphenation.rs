@@ -0,0 +1,202 @@
+use std::borrow::Cow;
+
+use hyphenation::{Hyphenator, Language, Load, Standard};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::event::{
+    AnnotatedEvent, DocumentStartEvent, EndTagEvent, ErrorEvent, Event, Severity, StartTagEvent,
+    Tag, TextEvent,
+};
+
+lazy_static! {
+    static ref WORD_RE: Regex = Regex::new(r"\p{Alphabetic}+").unwrap();
+}
+
+/// The soft hyphen inserted at break opportunities.
+const SOFT_HYPHEN: char = '\u{ad}';
+
+/// Inserts soft hyphens into long words.
+///
+/// Break opportunities are found with the hyphenation patterns of the
+/// document language which is read from the front matter (`lang` by default)
+/// and falls back to `language`.  Languages are given as codes such as
+/// `en-us` or `de-1996`.  Link texts are never modified and code is not
+/// affected as it is not carried in text events.
+///
+/// When applied this wraps the stream in a [`HyphenationIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Hyphenation {
+    /// The default language code.
+    pub language: String,
+    /// The front matter key to read the language from.
+    pub front_matter_key: Option<String>,
+    /// Words shorter than this (in characters) are not hyphenated.
+    pub min_word_length: usize,
+}
+
+impl Default for Hyphenation {
+    fn default() -> Hyphenation {
+        Hyphenation {
+            language: "en-us".into(),
+            front_matter_key: Some("lang".into()),
+            min_word_length: 8,
+        }
+    }
+}
+
+implement_processor!(
+    Hyphenation,
+    HyphenationIter,
+    "Inserts soft hyphens into long words"
+);
+
+/// The iterator implementing [`Hyphenation`].
+pub struct HyphenationIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    dictionary: Option<Standard>,
+    loaded: bool,
+    pending: Option<AnnotatedEvent<'data>>,
+    link_depth: usize,
+    options: Cow<'options, Hyphenation>,
+}
+
+fn load_dictionary(code: &str) -> Result<Standard, String> {
+    let language = Language::try_from_code(code.to_lowercase())
+        .ok_or_else(|| format!("unknown language '{}'", code))?;
+    Standard::from_embedded(language).map_err(|err| err.to_string())
+}
+
+fn hyphenate(dictionary: &Standard, text: &str, min_word_length: usize) -> Option<String> {
+    let mut rv = String::new();
+    let mut last = 0;
+    for m in WORD_RE.find_iter(text) {
+        let word = m.as_str();
+        if word.chars().count() < min_word_length {
+            continue;
+        }
+        // patterns are lowercase so look up the lowercase word if its
+        // byte offsets match the original.
+        let lowercase = word.to_lowercase();
+        let breaks = if lowercase.len() == word.len() {
+            dictionary.hyphenate(&lowercase).breaks
+        } else {
+            dictionary.hyphenate(word).breaks
+        };
+        if breaks.is_empty() {
+            continue;
+        }
+        rv.push_str(&text[last..m.start()]);
+        let mut word_last = 0;
+        for idx in breaks {
+            rv.push_str(&word[word_last..idx]);
+            rv.push(SOFT_HYPHEN);
+            word_last = idx;
+        }
+        rv.push_str(&word[word_last..]);
+        last = m.end();
+    }
+    if last == 0 {
+        None
+    } else {
+        rv.push_str(&text[last..]);
+        Some(rv)
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    HyphenationIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, Hyphenation>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            dictionary: None,
+            loaded: false,
+            pending: None,
+            link_depth: 0,
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for HyphenationIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.pending.take() {
+            return Some(annotated_event);
+        }
+
+        let mut annotated_event = self.source.next()?;
+        match annotated_event.event {
+            Event::DocumentStart(DocumentStartEvent {
+                ref front_matter, ..
+            }) => {
+                let language = self
+                    .options
+                    .front_matter_key
+                    .as_ref()
+                    .and_then(|key| front_matter.as_ref()?.get(key)?.as_str())
+                    .unwrap_or(&self.options.language);
+                self.loaded = true;
+                match load_dictionary(language) {
+                    Ok(dictionary) => self.dictionary = Some(dictionary),
+                    Err(err) => {
+                        self.dictionary = None;
+                        self.pending = Some(AnnotatedEvent::new(
+                            ErrorEvent {
+                                title: "Failed to load hyphenation patterns".into(),
+                                description: Some(err.into()),
+                                severity: Severity::Error,
+                            },
+                            annotated_event.location,
+                        ));
+                    }
+                }
+            }
+            Event::StartTag(StartTagEvent { tag: Tag::Link, .. }) => {
+                self.link_depth += 1;
+            }
+            Event::EndTag(EndTagEvent { tag: Tag::Link }) => {
+                self.link_depth -= 1;
+            }
+            Event::Text(TextEvent { ref mut text }) if self.link_depth == 0 => {
+                if !self.loaded {
+                    self.loaded = true;
+                    self.dictionary = load_dictionary(&self.options.language).ok();
+                }
+                if let Some(ref dictionary) = self.dictionary {
+                    if let Some(hyphenated) =
+                        hyphenate(dictionary, text.as_str(), self.options.min_word_length)
+                    {
+                        *text = hyphenated.into();
+                    }
+                }
+            }
+            _ => {}
+        }
+        Some(annotated_event)
+    }
+}
+
+#[test]
+fn test_hyphenation() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(Hyphenation::default());
+    let html = to_html(
+        pipeline.process("Hyphenation in `hyphenation` and [hyphenation](#)"),
+        &Default::default(),
+    );
+    assert!(html.starts_with("<p>Hy\u{ad}"));
+    assert!(html.ends_with(
+        " in <code>hyphenation</code> and \
+         <a href=\"#\">hyphenation</a></p>\n"
+    ));
+}
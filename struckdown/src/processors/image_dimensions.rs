@@ -0,0 +1,129 @@
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, CustomAttrs, ErrorEvent, Event, ImageEvent, Severity};
+
+/// Attaches `width` and `height` attributes to local images.
+///
+/// Image targets are resolved relative to `base_path` and the dimensions
+/// are read from the file headers via [`imagesize`].  Remote images (targets
+/// with a scheme or starting with `//`) and images that already carry a
+/// `width` or `height` attribute are left alone.  If a local image cannot be
+/// read an error event is emitted after the image.
+///
+/// When applied this wraps the stream in a [`ImageDimensionsIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ImageDimensions {
+    /// The path images are resolved relative to.  Defaults to the current
+    /// working directory.
+    pub base_path: Option<PathBuf>,
+    /// Emits error events for images that cannot be read.
+    pub report_errors: bool,
+}
+
+impl Default for ImageDimensions {
+    fn default() -> ImageDimensions {
+        ImageDimensions {
+            base_path: None,
+            report_errors: true,
+        }
+    }
+}
+
+implement_processor!(
+    ImageDimensions,
+    ImageDimensionsIter,
+    "Attaches dimensions to local images"
+);
+
+fn is_remote(target: &str) -> bool {
+    target.starts_with("//")
+        || target.starts_with("data:")
+        || target
+            .find("://")
+            .map(|idx| target[..idx].chars().all(|c| c.is_ascii_alphanumeric()))
+            .unwrap_or(false)
+}
+
+impl ImageDimensions {
+    fn resolve(&self, target: &str) -> PathBuf {
+        let target = target.split(&['?', '#'][..]).next().unwrap_or("");
+        let base_path = self.base_path.as_deref().unwrap_or_else(|| Path::new("."));
+        base_path.join(target.trim_start_matches('/'))
+    }
+}
+
+/// The iterator implementing [`ImageDimensions`].
+pub struct ImageDimensionsIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    pending_error: Option<AnnotatedEvent<'data>>,
+    options: Cow<'options, ImageDimensions>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    ImageDimensionsIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, ImageDimensions>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            pending_error: None,
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for ImageDimensionsIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.pending_error.take() {
+            return Some(error);
+        }
+
+        let mut annotated_event = self.source.next()?;
+        if let Event::Image(ImageEvent {
+            ref target,
+            ref mut attrs,
+            ..
+        }) = annotated_event.event
+        {
+            let has_dimensions = matches!(attrs.custom, Some(ref custom)
+                if custom.contains_key("width") || custom.contains_key("height"));
+            if !has_dimensions && !is_remote(target.as_str()) {
+                match imagesize::size(self.options.resolve(target.as_str())) {
+                    Ok(size) => {
+                        let custom = attrs.custom.get_or_insert_with(CustomAttrs::new);
+                        custom.insert("width", size.width.to_string());
+                        custom.insert("height", size.height.to_string());
+                    }
+                    Err(err) if self.options.report_errors => {
+                        self.pending_error = Some(AnnotatedEvent::new(
+                            ErrorEvent {
+                                title: format!("Failed to read image '{}'", target.as_str()).into(),
+                                description: Some(err.to_string().into()),
+                                severity: Severity::Error,
+                            },
+                            annotated_event.location,
+                        ));
+                    }
+                    Err(_) => {}
+                }
+            }
+        }
+        Some(annotated_event)
+    }
+}
+
+#[test]
+fn test_is_remote() {
+    assert!(is_remote("https://example.com/a.png"));
+    assert!(is_remote("//example.com/a.png"));
+    assert!(is_remote("data:image/png;base64,AAAA"));
+    assert!(!is_remote("images/a.png"));
+    assert!(!is_remote("/images/a.png?v=1://"));
+}
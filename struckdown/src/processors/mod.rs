@@ -7,7 +7,28 @@
 #[macro_use]
 mod utils;
 
+mod abbreviations;
+mod asset_manifest;
+mod backlinks;
+mod cached;
+mod code_import;
+mod definition_lists;
+mod directive_dispatch;
+mod excerpt;
+mod figures;
+mod footnotes;
+mod heading_policy;
+mod html_minify;
+mod redaction;
+mod registry;
+mod responsive_images;
+mod role_dispatch;
+mod search_index;
+mod snippets;
+mod statistics;
 mod toc;
+mod translation;
+mod unique_anchors;
 
 #[cfg(feature = "autoanchors-processor")]
 mod autoanchors;
@@ -18,26 +39,119 @@ mod external;
 #[cfg(feature = "syntect-processor")]
 mod syntect;
 
+#[cfg(feature = "syntect-processor")]
+mod diff;
+
 #[cfg(feature = "html-sanitizer-processor")]
 mod html_sanitizer;
 
-use serde::Deserialize;
+#[cfg(feature = "emoji-processor")]
+mod emoji;
+
+#[cfg(feature = "tree-sitter-processor")]
+mod tree_sitter;
+
+#[cfg(feature = "wasm-processor")]
+mod wasm;
+
+#[cfg(feature = "image-dimensions-processor")]
+mod image_dimensions;
+
+#[cfg(feature = "hyphenation-processor")]
+mod hyphenation;
+
+#[cfg(feature = "normalize-processor")]
+mod normalize;
+
+#[cfg(feature = "template-processor")]
+mod template;
+
+#[cfg(feature = "native-plugin-processor")]
+mod native_plugin;
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
 
+use crate::error::Error;
 use crate::event::AnnotatedEvent;
+use crate::value::Value;
 
+pub use self::abbreviations::{Abbreviations, AbbreviationsIter};
+pub use self::asset_manifest::{AssetManifest, AssetManifestIter};
+pub use self::backlinks::{Backlinks, BacklinksIter};
+pub use self::cached::Cached;
+pub use self::code_import::{CodeImport, CodeImportIter};
+pub use self::definition_lists::{DefinitionLists, DefinitionListsIter};
+pub use self::directive_dispatch::{
+    DirectiveDispatch, DirectiveDispatchIter, DirectiveHandlerFn, FallbackPolicy,
+};
+pub use self::excerpt::{Excerpt, ExcerptIter};
+pub use self::figures::{Figures, FiguresIter};
+pub use self::footnotes::{Footnotes, FootnotesIter};
+pub use self::heading_policy::{HeadingPolicy, HeadingPolicyIter, PolicyMode};
+pub use self::html_minify::{HtmlMinify, HtmlMinifyIter};
+pub use self::redaction::{Redaction, RedactionIter};
+pub use self::registry::{
+    register_processor, registered_processor, ConfiguredProcessor, CustomProcessor,
+    ProcessorFactory,
+};
+pub use self::responsive_images::{ResponsiveImages, ResponsiveImagesIter};
+pub use self::role_dispatch::{RoleDispatch, RoleDispatchIter, RoleHandlerFn};
+pub use self::search_index::{SearchIndex, SearchIndexIter};
+pub use self::snippets::{Snippets, SnippetsIter};
+pub use self::statistics::{DocumentStatistics, DocumentStatisticsIter, Statistics};
 pub use self::toc::{TableOfContents, TableOfContentsIter};
+pub use self::translation::{
+    messages_to_pot, ApplyTranslations, ApplyTranslationsIter, ExtractTranslations,
+    ExtractTranslationsIter, Message, Translation,
+};
+pub use self::unique_anchors::{SuffixStrategy, UniqueAnchors, UniqueAnchorsIter};
 
 #[cfg(feature = "autoanchors-processor")]
-pub use self::autoanchors::{AutoAnchors, AutoAnchorsIter};
+pub use self::autoanchors::{
+    AnchorRegistry, AutoAnchors, AutoAnchorsIter, PublishedAnchor, SharedAnchorRegistry, SlugifyFn,
+};
 
 #[cfg(feature = "external-processor")]
-pub use self::external::{External, ExternalIter};
+pub use self::external::{External, ExternalIter, Framing};
 
 #[cfg(feature = "syntect-processor")]
 pub use self::syntect::{Syntect, SyntectIter};
 
+#[cfg(feature = "syntect-processor")]
+pub use self::diff::{DiffHighlight, DiffHighlightIter};
+
 #[cfg(feature = "html-sanitizer-processor")]
-pub use self::html_sanitizer::{HtmlSanitizer, HtmlSanitizerIter};
+pub use self::html_sanitizer::{HtmlSanitizer, HtmlSanitizerIter, UrlRewriteFn};
+
+#[cfg(feature = "emoji-processor")]
+pub use self::emoji::{Emoji, EmojiIter};
+
+#[cfg(feature = "tree-sitter-processor")]
+pub use self::tree_sitter::{
+    TreeSitterHighlight, TreeSitterHighlightIter, TreeSitterLanguage, HIGHLIGHT_NAMES,
+};
+
+#[cfg(feature = "wasm-processor")]
+pub use self::wasm::{WasmProcessor, WasmProcessorIter};
+
+#[cfg(feature = "image-dimensions-processor")]
+pub use self::image_dimensions::{ImageDimensions, ImageDimensionsIter};
+
+#[cfg(feature = "hyphenation-processor")]
+pub use self::hyphenation::{Hyphenation, HyphenationIter};
+
+#[cfg(feature = "normalize-processor")]
+pub use self::normalize::{Normalize, NormalizeIter};
+
+#[cfg(feature = "template-processor")]
+pub use self::template::{Template, TemplateEnvFn, TemplateIter};
+
+#[cfg(feature = "native-plugin-processor")]
+pub use self::native_plugin::{
+    NativePlugin, NativePluginIter, PluginBuffer, NATIVE_PLUGIN_ABI_VERSION,
+};
 
 /// Common trait for all stream processors.
 pub trait Processor {
@@ -56,6 +170,126 @@ pub trait Processor {
         &'options self,
         iter: Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data>,
     ) -> Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data>;
+
+    /// Applies the processor by reference to the stream of a document.
+    ///
+    /// This is used by [`Pipeline::apply_ref_with_path`] with the path of
+    /// the document if known and defaults to [`apply_ref`](Self::apply_ref).
+    /// Processors keeping state per document (such as `AutoAnchors`) use the
+    /// path to tell documents apart.
+    ///
+    /// [`Pipeline::apply_ref_with_path`]: crate::pipeline::Pipeline::apply_ref_with_path
+    fn apply_ref_with_path<'data, 'options: 'data>(
+        &'options self,
+        iter: Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data>,
+        path: Option<&'data Path>,
+    ) -> Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data> {
+        let _ = path;
+        self.apply_ref(iter)
+    }
+
+    /// Returns the name of the processor.
+    ///
+    /// This is used for diagnostics and defaults to the name of the type.
+    fn name(&self) -> &'static str {
+        let name = std::any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name)
+    }
+
+    /// Returns a short description of the processor.
+    ///
+    /// This is intended for tools such as command line help and is empty
+    /// unless provided by the processor.
+    fn description(&self) -> &'static str {
+        ""
+    }
+
+    /// Returns a JSON Schema describing the options of the processor.
+    ///
+    /// The builtin processors list all options that can be given in a
+    /// configuration together with their defaults.
+    fn options_schema(&self) -> Option<Value> {
+        None
+    }
+
+    /// Verifies that the processor can be applied.
+    ///
+    /// Processors that need to load resources (such as themes) do so here
+    /// so that failures can be reported instead of panicking when the
+    /// processor is applied.  This is invoked by the fallible pipeline APIs
+    /// such as [`Pipeline::try_process`](crate::pipeline::Pipeline::try_process).
+    fn check(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Returns the configuration of the processor.
+    ///
+    /// This is implemented by all builtin processors and used by
+    /// [`Pipeline::to_config`](crate::pipeline::Pipeline::to_config).
+    /// Custom processors cannot be represented and return `None`, as do
+    /// builtin processors with options that can only be set from code (such
+    /// as callbacks) if these are set.
+    fn to_builtin(&self) -> Option<BuiltinProcessor> {
+        None
+    }
+}
+
+/// Statically dispatched application of a processor.
+///
+/// Unlike [`Processor::apply_ref`] this does not box the event stream, the
+/// returned iterator wraps the input iterator directly so that the compiler
+/// can inline the stages into each other.  It is implemented by all builtin
+/// processors, by `()` which passes events through unchanged and by
+/// [`Chain`] which composes two processors.  Typed pipelines are normally
+/// built with [`StaticPipeline`](crate::pipeline::StaticPipeline).
+pub trait StaticProcessor<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    /// The iterator wrapping the event stream.
+    type Iter: Iterator<Item = AnnotatedEvent<'data>>;
+
+    /// Applies the processor to an event stream by reference.
+    fn apply_static(&'options self, iter: I) -> Self::Iter;
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> StaticProcessor<'data, 'options, I>
+    for ()
+{
+    type Iter = I;
+
+    fn apply_static(&'options self, iter: I) -> I {
+        iter
+    }
+}
+
+/// Two processors applied one after another.
+///
+/// The events are first passed through `A` and then through `B`.
+#[derive(Debug, Clone, Default)]
+pub struct Chain<A, B>(pub A, pub B);
+
+impl<'data, 'options, I, A, B> StaticProcessor<'data, 'options, I> for Chain<A, B>
+where
+    I: Iterator<Item = AnnotatedEvent<'data>>,
+    A: StaticProcessor<'data, 'options, I>,
+    B: StaticProcessor<'data, 'options, A::Iter>,
+{
+    type Iter = B::Iter;
+
+    fn apply_static(&'options self, iter: I) -> B::Iter {
+        self.1.apply_static(self.0.apply_static(iter))
+    }
+}
+
+/// Describes an available builtin processor.
+///
+/// See [`BuiltinProcessor::available`].
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessorInfo {
+    /// The name of the processor in configurations (eg: `html_minify`).
+    pub name: String,
+    /// A short description of the processor.
+    pub description: &'static str,
+    /// The JSON Schema of the options of the processor.
+    pub options_schema: Value,
 }
 
 macro_rules! builtin_processors {
@@ -63,7 +297,7 @@ macro_rules! builtin_processors {
         $($(#[$attr:meta])* type $name:ident;)*
     ) => {
         /// Utility struct for processor configurations.
-        #[derive(Debug, Deserialize, Clone)]
+        #[derive(Debug, Serialize, Deserialize, Clone)]
         #[serde(tag = "processor", rename_all = "snake_case")]
         pub enum BuiltinProcessor {
             $($(#[$attr])* $name(Box<$name>),)*
@@ -87,18 +321,145 @@ macro_rules! builtin_processors {
                     $($(#[$attr])* Self::$name(options) => options.apply_ref(iter),)*
                 }
             }
+
+            fn apply_ref_with_path<'data, 'options: 'data>(
+                &'options self,
+                iter: Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data>,
+                path: Option<&'data Path>,
+            ) -> Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data> {
+                match self {
+                    $($(#[$attr])* Self::$name(options) => options.apply_ref_with_path(iter, path),)*
+                }
+            }
+
+            fn name(&self) -> &'static str {
+                match self {
+                    $($(#[$attr])* Self::$name(options) => options.name(),)*
+                }
+            }
+
+            fn description(&self) -> &'static str {
+                match self {
+                    $($(#[$attr])* Self::$name(options) => options.description(),)*
+                }
+            }
+
+            fn options_schema(&self) -> Option<Value> {
+                match self {
+                    $($(#[$attr])* Self::$name(options) => options.options_schema(),)*
+                }
+            }
+
+            fn check(&self) -> Result<(), Error> {
+                match self {
+                    $($(#[$attr])* Self::$name(options) => options.check(),)*
+                }
+            }
+
+            fn to_builtin(&self) -> Option<BuiltinProcessor> {
+                match self {
+                    $($(#[$attr])* Self::$name(options) => options.to_builtin(),)*
+                }
+            }
         }
+
+        impl BuiltinProcessor {
+            /// Returns information about all available builtin processors.
+            ///
+            /// ```
+            /// # use struckdown::processors::BuiltinProcessor;
+            /// for info in BuiltinProcessor::available() {
+            ///     println!("{}: {}", info.name, info.description);
+            /// }
+            /// ```
+            pub fn available() -> Vec<ProcessorInfo> {
+                vec![
+                    $(
+                        $(#[$attr])*
+                        ProcessorInfo {
+                            name: crate::pipeline::snake_case(stringify!($name)),
+                            description: <$name as utils::BuiltinInfo>::DESCRIPTION,
+                            options_schema: <$name as utils::BuiltinInfo>::options_schema(),
+                        },
+                    )*
+                ]
+            }
+        }
+
+        $(
+            $(#[$attr])*
+            impl From<$name> for BuiltinProcessor {
+                fn from(value: $name) -> BuiltinProcessor {
+                    BuiltinProcessor::$name(Box::new(value))
+                }
+            }
+        )*
     };
 }
 
 builtin_processors! {
     type TableOfContents;
+    type Abbreviations;
+    type DocumentStatistics;
+    type Excerpt;
+    type SearchIndex;
+    type UniqueAnchors;
+    type CodeImport;
+    type Footnotes;
+    type AssetManifest;
+    type Figures;
+    type ResponsiveImages;
+    type Redaction;
+    type HtmlMinify;
+    type DefinitionLists;
+    type ExtractTranslations;
+    type ApplyTranslations;
+    type HeadingPolicy;
+    type Backlinks;
+    type Snippets;
     #[cfg(feature = "autoanchors-processor")]
     type AutoAnchors;
     #[cfg(feature = "external-processor")]
     type External;
     #[cfg(feature = "syntect-processor")]
     type Syntect;
+    #[cfg(feature = "syntect-processor")]
+    type DiffHighlight;
     #[cfg(feature = "html-sanitizer-processor")]
     type HtmlSanitizer;
+    #[cfg(feature = "emoji-processor")]
+    type Emoji;
+    #[cfg(feature = "tree-sitter-processor")]
+    type TreeSitterHighlight;
+    #[cfg(feature = "wasm-processor")]
+    type WasmProcessor;
+    #[cfg(feature = "image-dimensions-processor")]
+    type ImageDimensions;
+    #[cfg(feature = "hyphenation-processor")]
+    type Hyphenation;
+    #[cfg(feature = "normalize-processor")]
+    type Normalize;
+    #[cfg(feature = "template-processor")]
+    type Template;
+    #[cfg(feature = "native-plugin-processor")]
+    type NativePlugin;
+}
+
+#[test]
+fn test_available_processors() {
+    let available = BuiltinProcessor::available();
+    let info = available.iter().find(|x| x.name == "html_minify").unwrap();
+    assert_eq!(info.description, "Minifies embedded raw HTML");
+    assert_eq!(
+        info.options_schema["properties"]["remove_comments"]["type"],
+        "boolean"
+    );
+
+    let processor = BuiltinProcessor::from(HtmlMinify::default());
+    assert_eq!(processor.description(), info.description);
+    assert_eq!(
+        processor.options_schema().as_ref(),
+        Some(&info.options_schema)
+    );
+    assert!(available.iter().any(|x| x.name == "table_of_contents"));
 }
@@ -0,0 +1,599 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::ffi::c_void;
+use std::path::{Path, PathBuf};
+use std::{ptr, slice};
+
+use libloading::Library;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, ErrorKind};
+use crate::event::{AnnotatedEvent, ErrorEvent, Severity};
+use crate::value::{value, Value};
+
+/// The version of the native plugin ABI.
+///
+/// Plugins report the version they were built against and are rejected if
+/// it does not match.
+pub const NATIVE_PLUGIN_ABI_VERSION: u32 = 1;
+
+/// A buffer passed from a native plugin to the host.
+///
+/// The buffer is owned by the plugin and handed back to
+/// `struckdown_plugin_free_buffer` once the host is done with it.  If `error`
+/// is set the buffer holds a UTF-8 error message instead of events.
+#[repr(C)]
+#[derive(Debug)]
+pub struct PluginBuffer {
+    /// Pointer to the data.
+    pub data: *mut u8,
+    /// The length of the data in bytes.
+    pub len: usize,
+    /// The capacity of the allocation.
+    pub capacity: usize,
+    /// Indicates that the buffer holds an error message.
+    pub error: bool,
+}
+
+impl PluginBuffer {
+    /// Creates a buffer from a vector.
+    ///
+    /// This is intended to be used by plugins written in Rust.
+    pub fn from_vec(data: Vec<u8>) -> PluginBuffer {
+        let mut data = std::mem::ManuallyDrop::new(data);
+        PluginBuffer {
+            data: data.as_mut_ptr(),
+            len: data.len(),
+            capacity: data.capacity(),
+            error: false,
+        }
+    }
+
+    /// Creates a buffer holding an error message.
+    pub fn from_error(message: &str) -> PluginBuffer {
+        PluginBuffer {
+            error: true,
+            ..PluginBuffer::from_vec(message.as_bytes().to_vec())
+        }
+    }
+
+    /// Converts the buffer back into a vector.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must have been created by [`PluginBuffer::from_vec`] or
+    /// [`PluginBuffer::from_error`] in the same binary.
+    pub unsafe fn into_vec(self) -> Vec<u8> {
+        Vec::from_raw_parts(self.data, self.len, self.capacity)
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        if self.data.is_null() {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(self.data, self.len) }
+        }
+    }
+}
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type CreateFn = unsafe extern "C" fn(*const u8, usize) -> *mut c_void;
+type ProcessFn = unsafe extern "C" fn(*mut c_void, *const u8, usize) -> PluginBuffer;
+type FinishFn = unsafe extern "C" fn(*mut c_void) -> PluginBuffer;
+type FreeBufferFn = unsafe extern "C" fn(PluginBuffer);
+type DestroyFn = unsafe extern "C" fn(*mut c_void);
+
+/// Passes the stream through a compiled plugin loaded at runtime.
+///
+/// The plugin is a shared library (`.so`, `.dylib` or `.dll`) that exports
+/// the following C functions:
+///
+/// * `struckdown_plugin_abi_version() -> u32`: returns
+///   [`NATIVE_PLUGIN_ABI_VERSION`]
+/// * `struckdown_plugin_create(config: *const u8, len: usize) -> *mut c_void`:
+///   creates the plugin state from the JSON serialized `config`.  Returning
+///   null indicates failure.
+/// * `struckdown_plugin_process(state, event: *const u8, len: usize) -> PluginBuffer`:
+///   is invoked with a JSON serialized event for every event in the stream
+///   and returns a JSON array of events to emit.
+/// * `struckdown_plugin_finish(state) -> PluginBuffer` (optional): is invoked
+///   at the end of the stream and returns a JSON array of further events.
+/// * `struckdown_plugin_free_buffer(buffer: PluginBuffer)`: frees a buffer
+///   returned by the plugin.
+/// * `struckdown_plugin_destroy(state)`: frees the plugin state.
+///
+/// See [`PluginBuffer`] for the buffer layout.  If the plugin fails an error
+/// event is emitted and the stream ends.
+///
+/// Loading a plugin runs arbitrary native code, so only load trusted
+/// plugins.
+///
+/// When applied this wraps the stream in a [`NativePluginIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NativePlugin {
+    /// The path to the shared library.
+    pub path: PathBuf,
+    /// The configuration passed to the plugin.
+    #[serde(default)]
+    pub config: Value,
+}
+
+impl NativePlugin {
+    /// Verifies that the library exists, loads and exports the plugin ABI.
+    fn check(&self) -> Result<(), Error> {
+        unsafe {
+            let library = Library::new(&self.path).map_err(|err| {
+                Error::new(
+                    ErrorKind::ProcessorFailed,
+                    format!("failed to load native plugin {}", self.path.display()),
+                )
+                .with_source(err)
+            })?;
+            let api = PluginApi::resolve(&library).map_err(|err| {
+                Error::new(
+                    ErrorKind::ProcessorFailed,
+                    format!("invalid native plugin {}: {}", self.path.display(), err),
+                )
+            })?;
+            let version = (api.abi_version)();
+            if version != NATIVE_PLUGIN_ABI_VERSION {
+                return Err(Error::new(
+                    ErrorKind::ProcessorFailed,
+                    format!(
+                        "native plugin {} uses ABI version {} but version {} is required",
+                        self.path.display(),
+                        version,
+                        NATIVE_PLUGIN_ABI_VERSION
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn options_schema() -> Value {
+        value!({
+            "type": "object",
+            "properties": {
+                "path": {"type": "string"},
+                "config": {"default": null},
+            },
+            "required": ["path"],
+        })
+    }
+}
+
+implement_processor!(
+    NativePlugin,
+    NativePluginIter,
+    "Passes the stream through a native plugin",
+    check = NativePlugin::check,
+    schema = NativePlugin::options_schema,
+);
+
+/// The functions exported by a plugin.
+#[derive(Clone, Copy)]
+struct PluginApi {
+    abi_version: AbiVersionFn,
+    create: CreateFn,
+    process: ProcessFn,
+    finish: Option<FinishFn>,
+    free_buffer: FreeBufferFn,
+    destroy: DestroyFn,
+}
+
+impl PluginApi {
+    /// Looks up the exported functions in a library.
+    ///
+    /// The functions are only valid for as long as the library is loaded.
+    unsafe fn resolve(library: &Library) -> Result<PluginApi, String> {
+        Ok(PluginApi {
+            abi_version: *library
+                .get::<AbiVersionFn>(b"struckdown_plugin_abi_version\0")
+                .map_err(|err| err.to_string())?,
+            create: *library
+                .get::<CreateFn>(b"struckdown_plugin_create\0")
+                .map_err(|err| err.to_string())?,
+            process: *library
+                .get::<ProcessFn>(b"struckdown_plugin_process\0")
+                .map_err(|err| err.to_string())?,
+            finish: library
+                .get::<FinishFn>(b"struckdown_plugin_finish\0")
+                .ok()
+                .map(|x| *x),
+            free_buffer: *library
+                .get::<FreeBufferFn>(b"struckdown_plugin_free_buffer\0")
+                .map_err(|err| err.to_string())?,
+            destroy: *library
+                .get::<DestroyFn>(b"struckdown_plugin_destroy\0")
+                .map_err(|err| err.to_string())?,
+        })
+    }
+}
+
+struct Plugin {
+    state: *mut c_void,
+    api: PluginApi,
+    // must be dropped last as the functions above point into it
+    _library: Option<Library>,
+}
+
+impl Plugin {
+    fn load(path: &Path, config: &Value) -> Result<Plugin, String> {
+        unsafe {
+            let library = Library::new(path).map_err(|err| err.to_string())?;
+            let api = PluginApi::resolve(&library)?;
+            Plugin::create(api, config, Some(library))
+        }
+    }
+
+    /// Checks the ABI version and creates the plugin state.
+    ///
+    /// The functions in `api` must stay valid for as long as the plugin is
+    /// alive which is ensured by passing the library they were loaded from.
+    unsafe fn create(
+        api: PluginApi,
+        config: &Value,
+        library: Option<Library>,
+    ) -> Result<Plugin, String> {
+        let version = (api.abi_version)();
+        if version != NATIVE_PLUGIN_ABI_VERSION {
+            return Err(format!(
+                "plugin uses ABI version {} but version {} is required",
+                version, NATIVE_PLUGIN_ABI_VERSION
+            ));
+        }
+
+        let config = serde_json::to_vec(config).map_err(|err| err.to_string())?;
+        let state = (api.create)(config.as_ptr(), config.len());
+        if state.is_null() {
+            return Err("plugin failed to initialize".into());
+        }
+
+        Ok(Plugin {
+            state,
+            api,
+            _library: library,
+        })
+    }
+
+    fn read_events(
+        &mut self,
+        buffer: PluginBuffer,
+    ) -> Result<Vec<AnnotatedEvent<'static>>, String> {
+        let rv = if buffer.error {
+            Err(String::from_utf8_lossy(buffer.as_bytes()).into_owned())
+        } else {
+            serde_json::from_slice::<Vec<AnnotatedEvent<'_>>>(buffer.as_bytes())
+                .map(|events| events.into_iter().map(|x| x.into_static()).collect())
+                .map_err(|err| err.to_string())
+        };
+        unsafe { (self.api.free_buffer)(buffer) };
+        rv
+    }
+
+    fn process(&mut self, event: &AnnotatedEvent) -> Result<Vec<AnnotatedEvent<'static>>, String> {
+        let input = serde_json::to_vec(event).map_err(|err| err.to_string())?;
+        let buffer = unsafe { (self.api.process)(self.state, input.as_ptr(), input.len()) };
+        self.read_events(buffer)
+    }
+
+    fn finish(&mut self) -> Result<Vec<AnnotatedEvent<'static>>, String> {
+        match self.api.finish {
+            Some(finish) => {
+                let buffer = unsafe { finish(self.state) };
+                self.read_events(buffer)
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+impl Drop for Plugin {
+    fn drop(&mut self) {
+        unsafe { (self.api.destroy)(self.state) };
+        self.state = ptr::null_mut();
+    }
+}
+
+/// The iterator implementing [`NativePlugin`].
+pub struct NativePluginIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    plugin: Option<Plugin>,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    started: bool,
+    options: Cow<'options, NativePlugin>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    NativePluginIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, NativePlugin>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            plugin: None,
+            buffer: VecDeque::new(),
+            started: false,
+            options: options.into(),
+        }
+    }
+
+    fn fail(&mut self, err: String) -> AnnotatedEvent<'data> {
+        self.plugin = None;
+        ErrorEvent {
+            title: format!(
+                "Failed to execute native plugin '{}'",
+                self.options.path.display()
+            )
+            .into(),
+            description: Some(err.into()),
+            severity: Severity::Error,
+        }
+        .into()
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for NativePluginIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            match Plugin::load(&self.options.path, &self.options.config) {
+                Ok(plugin) => self.plugin = Some(plugin),
+                Err(err) => return Some(self.fail(err)),
+            }
+        }
+
+        loop {
+            if let Some(event) = self.buffer.pop_front() {
+                return Some(event);
+            }
+            let plugin = self.plugin.as_mut()?;
+            let result = match self.source.next() {
+                Some(event) => plugin.process(&event),
+                None => {
+                    let result = plugin.finish();
+                    self.plugin = None;
+                    result
+                }
+            };
+            match result {
+                Ok(events) => self.buffer.extend(events),
+                Err(err) => return Some(self.fail(err)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::event::{Event, TextEvent};
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    // The functions below implement the plugin ABI like a plugin built as a
+    // shared library would.  The counters track buffers and states handed
+    // to the host which have not been freed yet.
+    thread_local! {
+        static LIVE_BUFFERS: Cell<isize> = const { Cell::new(0) };
+        static LIVE_STATES: Cell<isize> = const { Cell::new(0) };
+    }
+
+    #[derive(Deserialize)]
+    struct State {
+        suffix: String,
+    }
+
+    fn track(counter: &'static std::thread::LocalKey<Cell<isize>>, delta: isize) {
+        counter.with(|x| x.set(x.get() + delta));
+    }
+
+    fn event_buffer(events: &[AnnotatedEvent<'_>]) -> PluginBuffer {
+        track(&LIVE_BUFFERS, 1);
+        PluginBuffer::from_vec(serde_json::to_vec(events).unwrap())
+    }
+
+    extern "C" fn abi_version() -> u32 {
+        NATIVE_PLUGIN_ABI_VERSION
+    }
+
+    extern "C" fn unsupported_abi_version() -> u32 {
+        NATIVE_PLUGIN_ABI_VERSION + 1
+    }
+
+    unsafe extern "C" fn create(config: *const u8, len: usize) -> *mut c_void {
+        match serde_json::from_slice::<State>(slice::from_raw_parts(config, len)) {
+            Ok(state) => {
+                track(&LIVE_STATES, 1);
+                Box::into_raw(Box::new(state)) as *mut c_void
+            }
+            Err(_) => ptr::null_mut(),
+        }
+    }
+
+    /// Appends the configured suffix to texts and fails on the text `fail`.
+    unsafe extern "C" fn process(state: *mut c_void, event: *const u8, len: usize) -> PluginBuffer {
+        let state = &*(state as *const State);
+        let mut event: AnnotatedEvent<'_> =
+            serde_json::from_slice(slice::from_raw_parts(event, len)).unwrap();
+        if let Event::Text(ref mut text) = event.event {
+            if text.text.as_str() == "fail" {
+                track(&LIVE_BUFFERS, 1);
+                return PluginBuffer::from_error("cannot process 'fail'");
+            }
+            text.text = format!("{}{}", text.text.as_str(), state.suffix).into();
+        }
+        event_buffer(&[event])
+    }
+
+    unsafe extern "C" fn finish(_state: *mut c_void) -> PluginBuffer {
+        event_buffer(&[TextEvent {
+            text: "done".into(),
+        }
+        .into()])
+    }
+
+    unsafe extern "C" fn free_buffer(buffer: PluginBuffer) {
+        track(&LIVE_BUFFERS, -1);
+        drop(buffer.into_vec());
+    }
+
+    unsafe extern "C" fn destroy(state: *mut c_void) {
+        track(&LIVE_STATES, -1);
+        drop(Box::from_raw(state as *mut State));
+    }
+
+    fn api() -> PluginApi {
+        PluginApi {
+            abi_version,
+            create,
+            process,
+            finish: Some(finish),
+            free_buffer,
+            destroy,
+        }
+    }
+
+    fn run(source: &str, plugin: Plugin) -> Vec<AnnotatedEvent<'static>> {
+        let options = NativePlugin {
+            path: "test-plugin".into(),
+            config: Value::Null,
+        };
+        let pipeline = Pipeline::new();
+        let mut iter = NativePluginIter::new(pipeline.process(source), Cow::Owned(options));
+        iter.started = true;
+        iter.plugin = Some(plugin);
+        iter.map(AnnotatedEvent::into_static).collect()
+    }
+
+    #[test]
+    fn test_process_and_finish() {
+        let plugin = unsafe { Plugin::create(api(), &value!({"suffix": "!"}), None) }.unwrap();
+        assert_eq!(LIVE_STATES.with(Cell::get), 1);
+        let events = run("Hello *World*", plugin);
+        assert_eq!(
+            to_html(events.into_iter(), &Default::default()),
+            "<p>Hello !<em>World!</em></p>\ndone"
+        );
+        assert_eq!(LIVE_BUFFERS.with(Cell::get), 0);
+        assert_eq!(LIVE_STATES.with(Cell::get), 0);
+    }
+
+    #[test]
+    fn test_plugin_error() {
+        let plugin = unsafe { Plugin::create(api(), &value!({"suffix": "!"}), None) }.unwrap();
+        let events = run("Hello\n\nfail\n\nWorld", plugin);
+        let texts: Vec<_> = events
+            .iter()
+            .filter_map(|x| match x.event {
+                Event::Text(ref text) => Some(text.text.as_str().to_string()),
+                Event::Error(ref err) => Some(format!(
+                    "{}: {}",
+                    err.title.as_str(),
+                    err.description.as_ref().map_or("", |x| x.as_str())
+                )),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                "Hello!",
+                "Failed to execute native plugin 'test-plugin': cannot process 'fail'"
+            ]
+        );
+        assert_eq!(LIVE_BUFFERS.with(Cell::get), 0);
+        assert_eq!(LIVE_STATES.with(Cell::get), 0);
+    }
+
+    #[test]
+    fn test_abi_version_mismatch() {
+        let api = PluginApi {
+            abi_version: unsupported_abi_version,
+            ..api()
+        };
+        let err = unsafe { Plugin::create(api, &value!({"suffix": "!"}), None) }
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            format!(
+                "plugin uses ABI version {} but version {} is required",
+                NATIVE_PLUGIN_ABI_VERSION + 1,
+                NATIVE_PLUGIN_ABI_VERSION
+            )
+        );
+        assert_eq!(LIVE_STATES.with(Cell::get), 0);
+    }
+
+    #[test]
+    fn test_create_failure() {
+        let err = unsafe { Plugin::create(api(), &Value::Null, None) }
+            .err()
+            .unwrap();
+        assert_eq!(err, "plugin failed to initialize");
+    }
+
+    #[test]
+    fn test_plugin_buffer() {
+        let buffer = PluginBuffer::from_vec(b"[]".to_vec());
+        assert!(!buffer.error);
+        assert_eq!(buffer.as_bytes(), b"[]");
+        assert_eq!(unsafe { buffer.into_vec() }, b"[]");
+
+        let buffer = PluginBuffer::from_error("oops");
+        assert!(buffer.error);
+        assert_eq!(unsafe { buffer.into_vec() }, b"oops");
+
+        let buffer = PluginBuffer {
+            data: ptr::null_mut(),
+            len: 0,
+            capacity: 0,
+            error: false,
+        };
+        assert_eq!(buffer.as_bytes(), b"");
+    }
+
+    #[test]
+    fn test_missing_plugin() {
+        let mut pipeline = Pipeline::new();
+        pipeline.add_processor(NativePlugin {
+            path: "does-not-exist.so".into(),
+            config: Value::Null,
+        });
+        let html = to_html(pipeline.process("Hello"), &Default::default());
+        assert!(html.contains("Failed to execute native plugin"));
+    }
+
+    #[test]
+    fn test_check() {
+        let plugin = NativePlugin {
+            path: "does-not-exist.so".into(),
+            config: Value::Null,
+        };
+        assert_eq!(
+            plugin.check().unwrap_err().kind(),
+            ErrorKind::ProcessorFailed
+        );
+
+        // not a shared library
+        let path = std::env::temp_dir().join(format!(
+            "struckdown-native-plugin-{}.so",
+            std::process::id()
+        ));
+        std::fs::write(&path, "not a plugin").unwrap();
+        let plugin = NativePlugin {
+            path: path.clone(),
+            config: Value::Null,
+        };
+        let result = plugin.check();
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,207 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+use crate::event::{AnnotatedEvent, EndTagEvent, Event, StartTagEvent, Str, Tag, TextEvent};
+
+lazy_static! {
+    static ref WHITESPACE_RE: Regex = Regex::new(r"[ \t\r\n]{2,}|[\t\r\n]").unwrap();
+}
+
+/// Normalizes text in the stream.
+///
+/// Depending on the options this merges adjacent text events, collapses runs
+/// of whitespace in text into a single space, normalizes text to unicode
+/// normalization form C and drops paragraphs without content.  Code and raw
+/// HTML are never modified.
+///
+/// When applied this wraps the stream in a [`NormalizeIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Normalize {
+    /// Merges adjacent text events.
+    pub merge_text: bool,
+    /// Collapses whitespace in text.
+    pub collapse_whitespace: bool,
+    /// Normalizes text to NFC.
+    pub unicode_nfc: bool,
+    /// Drops paragraphs that only contain whitespace.
+    pub drop_empty_paragraphs: bool,
+}
+
+impl Default for Normalize {
+    fn default() -> Normalize {
+        Normalize {
+            merge_text: true,
+            collapse_whitespace: true,
+            unicode_nfc: true,
+            drop_empty_paragraphs: true,
+        }
+    }
+}
+
+implement_processor!(Normalize, NormalizeIter, "Normalizes text");
+
+fn is_blank(event: &Event) -> bool {
+    match event {
+        Event::Text(TextEvent { text }) => text.as_str().trim().is_empty(),
+        Event::SoftBreak | Event::HardBreak => true,
+        _ => false,
+    }
+}
+
+impl Normalize {
+    fn normalize_text<'data>(&self, text: Str<'data>) -> Str<'data> {
+        let mut text = text;
+        if self.collapse_whitespace && WHITESPACE_RE.is_match(text.as_str()) {
+            text = WHITESPACE_RE
+                .replace_all(text.as_str(), " ")
+                .into_owned()
+                .into();
+        }
+        if self.unicode_nfc && !is_nfc(text.as_str()) {
+            text = text.as_str().nfc().collect::<String>().into();
+        }
+        text
+    }
+
+    /// Normalizes a run of events.
+    fn normalize_events<'data>(
+        &self,
+        events: Vec<AnnotatedEvent<'data>>,
+    ) -> Vec<AnnotatedEvent<'data>> {
+        let mut rv: Vec<AnnotatedEvent<'data>> = Vec::with_capacity(events.len());
+        for annotated_event in events {
+            if let Event::Text(TextEvent { ref text }) = annotated_event.event {
+                if self.merge_text {
+                    if let Some(AnnotatedEvent {
+                        event: Event::Text(TextEvent { text: prev }),
+                        ..
+                    }) = rv.last_mut()
+                    {
+                        *prev = format!("{}{}", prev.as_str(), text.as_str()).into();
+                        continue;
+                    }
+                }
+            }
+            rv.push(annotated_event);
+        }
+        for annotated_event in rv.iter_mut() {
+            if let Event::Text(TextEvent { ref mut text }) = annotated_event.event {
+                *text = self.normalize_text(text.clone());
+            }
+        }
+        rv
+    }
+}
+
+/// The iterator implementing [`Normalize`].
+pub struct NormalizeIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    lookahead: Option<AnnotatedEvent<'data>>,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, Normalize>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> NormalizeIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Normalize>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            lookahead: None,
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+
+    fn pull(&mut self) -> Option<AnnotatedEvent<'data>> {
+        self.lookahead.take().or_else(|| self.source.next())
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for NormalizeIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(annotated_event) = self.buffer.pop_front() {
+                return Some(annotated_event);
+            }
+
+            let annotated_event = self.pull()?;
+            match annotated_event.event {
+                Event::StartTag(StartTagEvent {
+                    tag: Tag::Paragraph,
+                    ..
+                }) if self.options.drop_empty_paragraphs => {
+                    let mut contents = Vec::new();
+                    let mut end = None;
+                    while let Some(next) = self.pull() {
+                        if let Event::EndTag(EndTagEvent {
+                            tag: Tag::Paragraph,
+                        }) = next.event
+                        {
+                            end = Some(next);
+                            break;
+                        }
+                        contents.push(next);
+                    }
+                    let contents = self.options.normalize_events(contents);
+                    if end.is_some() && contents.iter().all(|x| is_blank(&x.event)) {
+                        continue;
+                    }
+                    self.buffer.push_back(annotated_event);
+                    self.buffer.extend(contents);
+                    self.buffer.extend(end);
+                }
+                Event::Text(..) => {
+                    let mut run = vec![annotated_event];
+                    while let Some(next) = self.pull() {
+                        if let Event::Text(..) = next.event {
+                            run.push(next);
+                        } else {
+                            self.lookahead = Some(next);
+                            break;
+                        }
+                    }
+                    self.buffer.extend(self.options.normalize_events(run));
+                }
+                _ => return Some(annotated_event),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_normalize() {
+    use crate::event::Attrs;
+    use crate::html::to_html;
+
+    let events: Vec<AnnotatedEvent> = vec![
+        Tag::Paragraph.start_tag(Attrs::default()).into(),
+        TextEvent {
+            text: "Cafe\u{301}  and".into(),
+        }
+        .into(),
+        TextEvent {
+            text: "\tmore".into(),
+        }
+        .into(),
+        Tag::Paragraph.end_tag().into(),
+        Tag::Paragraph.start_tag(Attrs::default()).into(),
+        TextEvent { text: "  ".into() }.into(),
+        Tag::Paragraph.end_tag().into(),
+    ];
+    let events = NormalizeIter::new(events.into_iter(), Cow::Owned(Normalize::default()))
+        .collect::<Vec<_>>();
+    assert_eq!(events.len(), 3);
+    assert_eq!(
+        to_html(events.into_iter(), &Default::default()),
+        "<p>Caf\u{e9} and more</p>\n"
+    );
+}
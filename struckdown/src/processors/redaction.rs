@@ -0,0 +1,184 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, DirectiveEvent, DocumentStartEvent, Event, StartTagEvent};
+use crate::value::Value;
+
+/// Removes content that is marked as internal.
+///
+/// This removes tags (including their contents) with one of the configured
+/// `classes` as well as directives with one of the configured `directives`
+/// names.  If the document's front matter has a truthy value for
+/// `front_matter_flag` the entire document body is removed.  This allows the
+/// same source to produce public and internal variants by toggling the
+/// processor.
+///
+/// When applied this wraps the stream in a [`RedactionIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Redaction {
+    /// Tags with one of these classes are removed.
+    pub classes: Vec<String>,
+    /// Directives with one of these names are removed.
+    pub directives: Vec<String>,
+    /// The front matter flag that removes the entire document.
+    pub front_matter_flag: Option<String>,
+}
+
+impl Default for Redaction {
+    fn default() -> Redaction {
+        Redaction {
+            classes: vec!["internal".into()],
+            directives: vec!["speaker-notes".into()],
+            front_matter_flag: Some("internal".into()),
+        }
+    }
+}
+
+implement_processor!(
+    Redaction,
+    RedactionIter,
+    "Removes content marked as internal"
+);
+
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(value) => *value,
+        Value::Number(value) => value.as_f64() != Some(0.0),
+        Value::String(value) => !value.is_empty(),
+        Value::Array(value) => !value.is_empty(),
+        Value::Object(value) => !value.is_empty(),
+    }
+}
+
+impl Redaction {
+    fn is_redacted_class(&self, class: &str) -> bool {
+        class
+            .split_whitespace()
+            .any(|class| self.classes.iter().any(|x| x == class))
+    }
+}
+
+/// The iterator implementing [`Redaction`].
+pub struct RedactionIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    depth: usize,
+    redact_all: bool,
+    options: Cow<'options, Redaction>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> RedactionIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Redaction>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            depth: 0,
+            redact_all: false,
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for RedactionIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let annotated_event = self.source.next()?;
+
+            if self.depth > 0 {
+                match annotated_event.event {
+                    Event::StartTag(..) => self.depth += 1,
+                    Event::EndTag(..) => self.depth -= 1,
+                    _ => {}
+                }
+                continue;
+            }
+
+            match annotated_event.event {
+                Event::DocumentStart(DocumentStartEvent {
+                    ref front_matter, ..
+                }) => {
+                    self.redact_all = self
+                        .options
+                        .front_matter_flag
+                        .as_ref()
+                        .and_then(|flag| front_matter.as_ref()?.get(flag))
+                        .map(is_truthy)
+                        .unwrap_or(false);
+                }
+                _ if self.redact_all => continue,
+                Event::StartTag(StartTagEvent { ref attrs, .. }) => {
+                    if let Some(ref class) = attrs.class {
+                        if self.options.is_redacted_class(class.as_str()) {
+                            self.depth = 1;
+                            continue;
+                        }
+                    }
+                }
+                Event::Directive(DirectiveEvent { ref name, .. })
+                    if self.options.directives.iter().any(|x| x == name.as_str()) =>
+                {
+                    continue;
+                }
+                _ => {}
+            }
+
+            return Some(annotated_event);
+        }
+    }
+}
+
+#[test]
+fn test_redaction() {
+    use crate::event::{Attrs, Tag, TextEvent};
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(Redaction::default());
+    assert_eq!(
+        to_html(
+            pipeline.process("Public\n\n```{speaker-notes}\nSecret\n```\n\nAlso public"),
+            &Default::default()
+        ),
+        "<p>Public</p>\n<p>Also public</p>\n"
+    );
+    assert_eq!(
+        to_html(
+            pipeline.process("---\ninternal: true\n---\nSecret"),
+            &Default::default()
+        ),
+        ""
+    );
+
+    let events: Vec<AnnotatedEvent> = vec![
+        Tag::Container
+            .start_tag(Attrs {
+                class: Some("note internal".into()),
+                ..Attrs::default()
+            })
+            .into(),
+        Tag::Paragraph.start_tag(Attrs::default()).into(),
+        TextEvent {
+            text: "Secret".into(),
+        }
+        .into(),
+        Tag::Paragraph.end_tag().into(),
+        Tag::Container.end_tag().into(),
+        TextEvent {
+            text: "Public".into(),
+        }
+        .into(),
+    ];
+    assert_eq!(
+        to_html(
+            RedactionIter::new(events.into_iter(), Cow::Owned(Redaction::default())),
+            &Default::default()
+        ),
+        "Public"
+    );
+}
@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use lazy_static::lazy_static;
+use serde::de::Error as _;
+use serde::ser::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::Error;
+use crate::processors::{BuiltinProcessor, Processor};
+use crate::value::{from_value, to_value, Value};
+
+lazy_static! {
+    static ref REGISTRY: RwLock<HashMap<String, ProcessorFactory>> = RwLock::new(HashMap::new());
+}
+
+/// A factory creating a processor from its configured options.
+#[derive(Clone)]
+#[allow(clippy::type_complexity)]
+pub struct ProcessorFactory(Arc<dyn Fn(&Value) -> Result<Box<dyn Processor>, Error> + Send + Sync>);
+
+impl ProcessorFactory {
+    /// Wraps a function for use as processor factory.
+    pub fn new<F>(f: F) -> ProcessorFactory
+    where
+        F: Fn(&Value) -> Result<Box<dyn Processor>, Error> + Send + Sync + 'static,
+    {
+        ProcessorFactory(Arc::new(f))
+    }
+
+    /// Creates a processor from the given options.
+    pub fn create(&self, options: &Value) -> Result<Box<dyn Processor>, Error> {
+        (self.0)(options)
+    }
+}
+
+impl fmt::Debug for ProcessorFactory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ProcessorFactory").finish()
+    }
+}
+
+/// Registers a custom processor for use in configurations.
+///
+/// Processors in configurations (such as a
+/// [`PipelineConfig`](crate::pipeline::PipelineConfig)) that are not builtin
+/// are looked up by the value of their `processor` key.  The factory is
+/// invoked with the remaining keys as options.  Registering a name again
+/// replaces the previous factory.
+///
+/// ```
+/// # use struckdown::processors::{register_processor, HtmlMinify};
+/// # use struckdown::pipeline::PipelineConfig;
+/// # use struckdown::value::from_value;
+/// register_processor("my_minify", |options| {
+///     Ok(Box::new(from_value::<HtmlMinify>(options.clone())?))
+/// });
+/// let config: PipelineConfig = serde_yaml::from_str("processors:\n  - processor: my_minify\n").unwrap();
+/// ```
+pub fn register_processor<F>(name: &str, factory: F)
+where
+    F: Fn(&Value) -> Result<Box<dyn Processor>, Error> + Send + Sync + 'static,
+{
+    REGISTRY
+        .write()
+        .unwrap()
+        .insert(name.to_string(), ProcessorFactory::new(factory));
+}
+
+/// Looks up the factory of a registered processor.
+pub fn registered_processor(name: &str) -> Option<ProcessorFactory> {
+    REGISTRY.read().unwrap().get(name).cloned()
+}
+
+/// The configuration of a processor registered with [`register_processor`].
+#[derive(Debug, Clone)]
+pub struct CustomProcessor {
+    name: String,
+    options: Value,
+    factory: ProcessorFactory,
+}
+
+impl CustomProcessor {
+    /// Returns the name the processor was registered with.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the options passed to the factory.
+    pub fn options(&self) -> &Value {
+        &self.options
+    }
+}
+
+/// A processor as it appears in a configuration.
+///
+/// This is either one of the [`BuiltinProcessor`]s or a processor registered
+/// with [`register_processor`].  Builtin processors take precedence, the
+/// registry is only consulted for names that are not builtin.
+#[derive(Debug, Clone)]
+pub enum ConfiguredProcessor {
+    /// A builtin processor.
+    Builtin(BuiltinProcessor),
+    /// A registered processor.
+    Custom(CustomProcessor),
+}
+
+impl ConfiguredProcessor {
+    /// Creates the processor.
+    pub fn create(&self) -> Result<Box<dyn Processor>, Error> {
+        match self {
+            ConfiguredProcessor::Builtin(processor) => Ok(Box::new(processor.clone())),
+            ConfiguredProcessor::Custom(custom) => custom.factory.create(&custom.options),
+        }
+    }
+}
+
+impl From<BuiltinProcessor> for ConfiguredProcessor {
+    fn from(value: BuiltinProcessor) -> ConfiguredProcessor {
+        ConfiguredProcessor::Builtin(value)
+    }
+}
+
+impl Serialize for ConfiguredProcessor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ConfiguredProcessor::Builtin(processor) => processor.serialize(serializer),
+            ConfiguredProcessor::Custom(custom) => {
+                let mut value = to_value(&custom.options).map_err(S::Error::custom)?;
+                match value {
+                    Value::Object(ref mut map) => {
+                        map.insert("processor".into(), Value::String(custom.name.clone()));
+                    }
+                    Value::Null => {
+                        value = crate::value::value!({ "processor": custom.name });
+                    }
+                    _ => return Err(S::Error::custom("processor options must be a map")),
+                }
+                value.serialize(serializer)
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ConfiguredProcessor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        let err = match from_value::<BuiltinProcessor>(value.clone()) {
+            Ok(processor) => return Ok(ConfiguredProcessor::Builtin(processor)),
+            Err(err) => err,
+        };
+        let (name, factory) = match value
+            .get("processor")
+            .and_then(|x| x.as_str())
+            .and_then(|name| Some((name, registered_processor(name)?)))
+        {
+            Some(rv) => rv,
+            None => return Err(D::Error::custom(err)),
+        };
+        let mut options = value.clone();
+        if let Some(map) = options.as_object_mut() {
+            map.remove("processor");
+        }
+        // create the processor once so that invalid options are reported
+        factory.create(&options).map_err(D::Error::custom)?;
+        Ok(ConfiguredProcessor::Custom(CustomProcessor {
+            name: name.to_string(),
+            options,
+            factory,
+        }))
+    }
+}
+
+#[test]
+fn test_registry() {
+    use crate::processors::HtmlMinify;
+
+    register_processor("test_registry_minify", |options| {
+        Ok(Box::new(from_value::<HtmlMinify>(options.clone())?))
+    });
+
+    let processor: ConfiguredProcessor =
+        serde_yaml::from_str("processor: test_registry_minify\nremove_comments: false\n").unwrap();
+    match processor {
+        ConfiguredProcessor::Custom(ref custom) => {
+            assert_eq!(custom.name(), "test_registry_minify");
+            assert_eq!(custom.options()["remove_comments"], false);
+        }
+        _ => panic!("expected custom processor"),
+    }
+    assert_eq!(processor.create().unwrap().name(), "HtmlMinify");
+    let value = to_value(&processor).unwrap();
+    assert_eq!(value["processor"], "test_registry_minify");
+
+    let processor: ConfiguredProcessor = serde_yaml::from_str("processor: html_minify\n").unwrap();
+    assert!(matches!(processor, ConfiguredProcessor::Builtin(_)));
+
+    assert!(serde_yaml::from_str::<ConfiguredProcessor>("processor: missing\n").is_err());
+    assert!(serde_yaml::from_str::<ConfiguredProcessor>(
+        "processor: test_registry_minify\nremove_comments: 42\n"
+    )
+    .is_err());
+}
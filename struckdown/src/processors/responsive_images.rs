@@ -0,0 +1,123 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, CustomAttrs, Event, ImageEvent};
+
+/// Adds `srcset` and `sizes` attributes to images.
+///
+/// The `variants` map image targets to the available variants of the image
+/// by width in pixels.  Images with variants get a `srcset` attribute listing
+/// them and a `sizes` attribute if configured.  Attributes that are already
+/// set are left alone.
+///
+/// When applied this wraps the stream in a [`ResponsiveImagesIter`].
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ResponsiveImages {
+    /// Maps image targets to their variants (width to URL).
+    pub variants: BTreeMap<String, BTreeMap<u32, String>>,
+    /// The value of the `sizes` attribute.
+    pub sizes: Option<String>,
+    /// Replaces the image target with the variant of this width if available.
+    pub default_width: Option<u32>,
+}
+
+implement_processor!(
+    ResponsiveImages,
+    ResponsiveImagesIter,
+    "Adds srcset and sizes attributes to images",
+);
+
+/// Formats the variants as value for a `srcset` attribute.
+fn format_srcset(variants: &BTreeMap<u32, String>) -> String {
+    variants
+        .iter()
+        .map(|(width, url)| format!("{} {}w", url, width))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The iterator implementing [`ResponsiveImages`].
+pub struct ResponsiveImagesIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    options: Cow<'options, ResponsiveImages>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    ResponsiveImagesIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, ResponsiveImages>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for ResponsiveImagesIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut annotated_event = self.source.next()?;
+        if let Event::Image(ImageEvent {
+            ref mut target,
+            ref mut attrs,
+            ..
+        }) = annotated_event.event
+        {
+            let variants = match self.options.variants.get(target.as_str()) {
+                Some(variants) if !variants.is_empty() => variants,
+                _ => return Some(annotated_event),
+            };
+            let custom = attrs.custom.get_or_insert_with(CustomAttrs::new);
+            if !custom.contains_key("srcset") {
+                custom.insert("srcset", format_srcset(variants));
+            }
+            if let Some(ref sizes) = self.options.sizes {
+                if !custom.contains_key("sizes") {
+                    custom.insert("sizes", sizes.clone());
+                }
+            }
+            if let Some(url) = self
+                .options
+                .default_width
+                .and_then(|width| variants.get(&width))
+            {
+                *target = url.clone().into();
+            }
+        }
+        Some(annotated_event)
+    }
+}
+
+#[test]
+fn test_responsive_images() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut variants = BTreeMap::new();
+    variants.insert(480, "a-480.png".to_string());
+    variants.insert(960, "a-960.png".to_string());
+    let mut options = ResponsiveImages {
+        sizes: Some("(max-width: 600px) 480px, 960px".into()),
+        ..Default::default()
+    };
+    options.variants.insert("a.png".into(), variants);
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(options);
+    assert_eq!(
+        to_html(
+            pipeline.process("![A](a.png) ![B](b.png)"),
+            &Default::default()
+        ),
+        "<p><img src=\"a.png\" alt=\"A\" title=\"\" \
+         sizes=\"(max-width: 600px) 480px, 960px\" \
+         srcset=\"a-480.png 480w, a-960.png 960w\"> \
+         <img src=\"b.png\" alt=\"B\" title=\"\"></p>\n"
+    );
+}
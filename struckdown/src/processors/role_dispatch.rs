@@ -0,0 +1,198 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, ErrorEvent, Event, InterpretedTextEvent, Severity};
+use crate::processors::FallbackPolicy;
+
+/// A handler for a role.
+///
+/// It's invoked with the interpreted text and returns the events that
+/// replace it.  Events without a location are given the location of the
+/// interpreted text.
+#[derive(Clone)]
+pub struct RoleHandlerFn(
+    Arc<dyn for<'data> Fn(InterpretedTextEvent<'data>) -> Vec<AnnotatedEvent<'data>> + Send + Sync>,
+);
+
+impl RoleHandlerFn {
+    /// Wraps a function for use as role handler.
+    pub fn new<F>(f: F) -> RoleHandlerFn
+    where
+        F: for<'data> Fn(InterpretedTextEvent<'data>) -> Vec<AnnotatedEvent<'data>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        RoleHandlerFn(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for RoleHandlerFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RoleHandlerFn").finish()
+    }
+}
+
+/// Dispatches interpreted text to registered role handlers.
+///
+/// This is the inline counterpart to
+/// [`DirectiveDispatch`](crate::processors::DirectiveDispatch).  Handlers are
+/// registered by role name with [`register`](Self::register) and replace the
+/// interpreted text with the events they return.  Interpreted text with an
+/// unknown role is treated according to `fallback`.  Handlers can only be
+/// registered from code, hence this processor is not available in pipeline
+/// configurations.
+///
+/// When applied this wraps the stream in a [`RoleDispatchIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RoleDispatch {
+    /// What to do with interpreted text with an unknown role.
+    pub fallback: FallbackPolicy,
+    /// The registered handlers by role name.
+    #[serde(skip)]
+    pub handlers: HashMap<String, RoleHandlerFn>,
+}
+
+impl Default for RoleDispatch {
+    fn default() -> RoleDispatch {
+        RoleDispatch {
+            fallback: FallbackPolicy::Passthrough,
+            handlers: HashMap::new(),
+        }
+    }
+}
+
+impl RoleDispatch {
+    /// Registers a handler for a role.
+    ///
+    /// An already registered handler for the same name is replaced.
+    pub fn register<F>(&mut self, name: &str, f: F)
+    where
+        F: for<'data> Fn(InterpretedTextEvent<'data>) -> Vec<AnnotatedEvent<'data>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.handlers
+            .insert(name.to_string(), RoleHandlerFn::new(f));
+    }
+}
+
+implement_processor!(
+    RoleDispatch,
+    RoleDispatchIter,
+    "Dispatches interpreted text to registered role handlers",
+    to_builtin = crate::processors::utils::not_builtin,
+);
+
+/// The iterator implementing [`RoleDispatch`].
+pub struct RoleDispatchIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, RoleDispatch>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    RoleDispatchIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, RoleDispatch>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for RoleDispatchIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(annotated_event) = self.buffer.pop_front() {
+                return Some(annotated_event);
+            }
+
+            let annotated_event = self.source.next()?;
+            let location = annotated_event.location;
+            let interpreted_text = match annotated_event.event {
+                Event::InterpretedText(interpreted_text) => interpreted_text,
+                event => return Some(AnnotatedEvent { event, location }),
+            };
+
+            match self.options.handlers.get(interpreted_text.role.as_str()) {
+                Some(handler) => {
+                    for mut annotated_event in (handler.0)(interpreted_text) {
+                        if annotated_event.location.is_none() {
+                            annotated_event.location = location;
+                        }
+                        self.buffer.push_back(annotated_event);
+                    }
+                }
+                None => match self.options.fallback {
+                    FallbackPolicy::Error => {
+                        return Some(AnnotatedEvent::new(
+                            ErrorEvent {
+                                title: "Unknown role".into(),
+                                description: Some(
+                                    format!("no handler for '{}'", interpreted_text.role.as_str())
+                                        .into(),
+                                ),
+                                severity: Severity::Error,
+                            },
+                            location,
+                        ));
+                    }
+                    FallbackPolicy::Passthrough => {
+                        return Some(AnnotatedEvent::new(interpreted_text, location));
+                    }
+                    FallbackPolicy::Drop => {}
+                },
+            }
+        }
+    }
+}
+
+#[test]
+fn test_role_dispatch() {
+    use crate::event::{Attrs, Tag, TextEvent};
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut options = RoleDispatch {
+        fallback: FallbackPolicy::Error,
+        ..Default::default()
+    };
+    options.register("kbd", |interpreted_text| {
+        vec![
+            Tag::Strong.start_tag(Attrs::default()).into(),
+            TextEvent {
+                text: interpreted_text.text,
+            }
+            .into(),
+            Tag::Strong.end_tag().into(),
+        ]
+    });
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(options);
+    let events = pipeline
+        .process("Press {kbd}`Ctrl` or {nope}`x`")
+        .collect::<Vec<_>>();
+    assert!(events.iter().any(|x| matches!(
+        x.event,
+        Event::Error(ErrorEvent { ref title, .. }) if title.as_str() == "Unknown role"
+    )));
+    assert!(to_html(events.into_iter(), &Default::default())
+        .starts_with("<p>Press <strong>Ctrl</strong> or <div class=\"error\">"));
+
+    // the handlers cannot be represented in a configuration
+    assert!(pipeline.to_config().is_err());
+}
@@ -0,0 +1,200 @@
+use std::borrow::Cow;
+use std::mem;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, EndTagEvent, Event, MetaDataEvent, StartTagEvent};
+use crate::value::to_value;
+
+/// Extracts records for a search index and emits them as meta data.
+///
+/// The document is split into sections at every heading up to `max_level`.
+/// Each section becomes a record with the heading's anchor, the heading
+/// text, the titles of the enclosing sections and the section's body text.
+/// Content before the first heading is emitted as a record without title.
+/// This should run after anchors were assigned (eg: by `AutoAnchors`).
+///
+/// When applied this wraps the stream in a [`SearchIndexIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SearchIndex {
+    /// The meta data key to emit the records as.
+    pub key: String,
+    /// The maximum level of headline that starts a new record.
+    pub max_level: usize,
+    /// Controls if code (inline and blocks) is included in the body text.
+    pub include_code: bool,
+}
+
+impl Default for SearchIndex {
+    fn default() -> SearchIndex {
+        SearchIndex {
+            key: "search_index".into(),
+            max_level: 6,
+            include_code: false,
+        }
+    }
+}
+
+implement_processor!(
+    SearchIndex,
+    SearchIndexIter,
+    "Extracts records for a search index"
+);
+
+#[derive(Serialize, Debug, Default)]
+struct SearchRecord {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    anchor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    level: usize,
+    hierarchy: Vec<String>,
+    text: String,
+}
+
+/// The iterator implementing [`SearchIndex`].
+pub struct SearchIndexIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    done: bool,
+    records: Vec<SearchRecord>,
+    current: SearchRecord,
+    parents: Vec<(usize, String)>,
+    heading: Option<(usize, Option<String>, String)>,
+    depth: usize,
+    options: Cow<'options, SearchIndex>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    SearchIndexIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, SearchIndex>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            done: false,
+            records: Vec::new(),
+            current: SearchRecord::default(),
+            parents: Vec::new(),
+            heading: None,
+            depth: 0,
+            options: options.into(),
+        }
+    }
+
+    fn finish_record(&mut self) {
+        let mut record = mem::take(&mut self.current);
+        record.text = record.text.trim().to_string();
+        if record.title.is_some() || !record.text.is_empty() {
+            self.records.push(record);
+        }
+    }
+
+    fn handle_event(&mut self, event: &Event<'data>) {
+        match *event {
+            Event::StartTag(StartTagEvent { tag, ref attrs }) => {
+                if self.heading.is_some() {
+                    self.depth += 1;
+                } else if let Some(level) = tag
+                    .header_level()
+                    .filter(|&level| level <= self.options.max_level)
+                {
+                    self.heading = Some((
+                        level,
+                        attrs.id.as_ref().map(|x| x.as_str().to_string()),
+                        String::new(),
+                    ));
+                    self.depth = 1;
+                }
+            }
+            Event::EndTag(EndTagEvent { tag }) => {
+                if self.heading.is_some() {
+                    self.depth -= 1;
+                    if self.depth == 0 {
+                        let (level, anchor, title) = self.heading.take().unwrap();
+                        let title = title.trim().to_string();
+                        self.finish_record();
+                        while let Some(&(parent_level, _)) = self.parents.last() {
+                            if parent_level < level {
+                                break;
+                            }
+                            self.parents.pop();
+                        }
+                        self.current = SearchRecord {
+                            anchor,
+                            title: Some(title.clone()),
+                            level,
+                            hierarchy: self.parents.iter().map(|x| x.1.clone()).collect(),
+                            text: String::new(),
+                        };
+                        self.parents.push((level, title));
+                    }
+                } else if !tag.is_inline() && !self.current.text.ends_with(char::is_whitespace) {
+                    self.current.text.push('\n');
+                }
+            }
+            Event::CodeBlock(..) | Event::InlineCode(..) if !self.options.include_code => {}
+            Event::MetaData(..) | Event::Error(..) | Event::Directive(..) => {}
+            ref event => {
+                if let Some(text) = event.raw_text() {
+                    match self.heading {
+                        Some((_, _, ref mut title)) => title.push_str(text.as_str()),
+                        None => self.current.text.push_str(text.as_str()),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for SearchIndexIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.source.next() {
+            Some(annotated_event) => {
+                self.handle_event(&annotated_event.event);
+                Some(annotated_event)
+            }
+            None => {
+                self.done = true;
+                self.finish_record();
+                Some(
+                    MetaDataEvent {
+                        key: self.options.key.clone().into(),
+                        value: to_value(&self.records).expect("bad search index"),
+                    }
+                    .into(),
+                )
+            }
+        }
+    }
+}
+
+#[test]
+fn test_search_index() {
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(SearchIndex::default());
+    let events = pipeline
+        .process("Intro\n\n# A {#a}\n\nText *a*\n\n## B\n\nText b\n\n# C\n\nText c")
+        .collect::<Vec<_>>();
+    let records = pipeline
+        .extract_metadata(events.iter())
+        .find(|(key, _)| key.as_str() == "search_index")
+        .map(|(_, value)| value.clone())
+        .unwrap();
+    assert_eq!(records[0]["text"], "Intro");
+    assert_eq!(records[1]["anchor"], "a");
+    assert_eq!(records[1]["title"], "A");
+    assert_eq!(records[1]["text"], "Text a");
+    assert_eq!(records[2]["hierarchy"][0], "A");
+    assert_eq!(records[3]["title"], "C");
+    assert_eq!(records[3]["hierarchy"].as_array().unwrap().len(), 0);
+}
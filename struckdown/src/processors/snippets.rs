@@ -0,0 +1,261 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, DirectiveEvent, ErrorEvent, Event, Severity, StartTagEvent};
+use crate::parser::Parser;
+use crate::plain::to_plain_text;
+use crate::processors::code_import::resolve_file;
+
+/// Includes sections of other documents.
+///
+/// A `snippet` directive with an argument such as `other.md#anchor` is
+/// replaced with the referenced section of the other document.  If the
+/// anchor refers to a heading the section spans up to the next heading of
+/// the same or a higher level, for other elements the element itself is
+/// included.  Anchors match explicit ids as well as the lowercased heading
+/// text with non alphanumeric characters replaced by dashes.  Without an
+/// anchor the entire document is included.
+///
+/// Documents are looked up in `sources` first and then loaded from the file
+/// system relative to `base_path` (restricted to the `allowed_paths` the
+/// same way as for [`CodeImport`](crate::processors::CodeImport)).
+/// Snippets within included sections are not expanded.
+///
+/// When applied this wraps the stream in a [`SnippetsIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Snippets {
+    /// The name of the directive.
+    pub directive_name: String,
+    /// The path files are resolved relative to.  Defaults to the current
+    /// working directory.
+    pub base_path: Option<PathBuf>,
+    /// The folders files may be included from (relative to `base_path`).
+    /// If empty only files within `base_path` may be included.
+    pub allowed_paths: Vec<PathBuf>,
+    /// In-memory documents by path.
+    pub sources: BTreeMap<String, String>,
+    /// Includes the heading of a section.
+    pub include_heading: bool,
+}
+
+impl Default for Snippets {
+    fn default() -> Snippets {
+        Snippets {
+            directive_name: "snippet".into(),
+            base_path: None,
+            allowed_paths: Vec::new(),
+            sources: BTreeMap::new(),
+            include_heading: true,
+        }
+    }
+}
+
+implement_processor!(
+    Snippets,
+    SnippetsIter,
+    "Includes sections of other documents"
+);
+
+fn simple_slug(text: &str) -> String {
+    let mut rv = String::new();
+    for c in text.trim().chars() {
+        if c.is_alphanumeric() {
+            rv.extend(c.to_lowercase());
+        } else if !rv.ends_with('-') {
+            rv.push('-');
+        }
+    }
+    rv.trim_matches('-').to_string()
+}
+
+/// Checks if the element started at `idx` carries the anchor.
+fn matches_anchor(events: &[AnnotatedEvent<'_>], idx: usize, anchor: &str) -> bool {
+    let (tag, attrs) = match events[idx].event {
+        Event::StartTag(StartTagEvent { tag, ref attrs }) => (tag, attrs),
+        _ => return false,
+    };
+    if let Some(ref id) = attrs.id {
+        return id.as_str() == anchor;
+    }
+    if tag.header_level().is_none() {
+        return false;
+    }
+    let heading = events[idx + 1..]
+        .iter()
+        .take_while(|x| !matches!(x.event, Event::EndTag(ref end) if end.tag == tag));
+    simple_slug(to_plain_text(heading).as_str()) == anchor
+}
+
+/// Extracts the section with the given anchor.
+fn extract_section(
+    events: Vec<AnnotatedEvent<'static>>,
+    anchor: Option<&str>,
+    include_heading: bool,
+) -> Option<Vec<AnnotatedEvent<'static>>> {
+    let anchor = match anchor {
+        Some(anchor) => anchor,
+        None => {
+            return Some(
+                events
+                    .into_iter()
+                    .filter(|x| !matches!(x.event, Event::DocumentStart(..)))
+                    .collect(),
+            )
+        }
+    };
+
+    let start = (0..events.len()).find(|&idx| matches_anchor(&events, idx, anchor))?;
+    let tag = match events[start].event {
+        Event::StartTag(StartTagEvent { tag, .. }) => tag,
+        _ => unreachable!(),
+    };
+
+    let mut end = events.len();
+    let mut depth = 0;
+    let mut body_start = start;
+    for (idx, annotated_event) in events.iter().enumerate().skip(start) {
+        match (tag.header_level(), &annotated_event.event) {
+            (Some(level), Event::StartTag(StartTagEvent { tag: other, .. })) if idx > start => {
+                if matches!(other.header_level(), Some(other) if other <= level) {
+                    end = idx;
+                    break;
+                }
+            }
+            (Some(_), Event::EndTag(end_tag)) if end_tag.tag == tag && body_start == start => {
+                body_start = idx + 1;
+            }
+            (None, Event::StartTag(..)) => depth += 1,
+            (None, Event::EndTag(..)) => {
+                depth -= 1;
+                if depth == 0 {
+                    end = idx + 1;
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let start = if tag.header_level().is_some() && !include_heading {
+        body_start
+    } else {
+        start
+    };
+    Some(events.into_iter().take(end).skip(start).collect())
+}
+
+/// The iterator implementing [`Snippets`].
+pub struct SnippetsIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, Snippets>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> SnippetsIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Snippets>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+
+    fn load(&self, reference: &str) -> Result<Vec<AnnotatedEvent<'static>>, String> {
+        let (path, anchor) = match reference.find('#') {
+            Some(idx) => (&reference[..idx], Some(&reference[idx + 1..])),
+            None => (reference, None),
+        };
+        let contents = match self.options.sources.get(path) {
+            Some(contents) => Cow::Borrowed(contents.as_str()),
+            None => resolve_file(
+                self.options.base_path.as_deref(),
+                &self.options.allowed_paths,
+                path,
+            )
+            .and_then(fs::read_to_string)
+            .map(Cow::Owned)
+            .map_err(|err| err.to_string())?,
+        };
+        let events = Parser::default()
+            .parse(&contents)
+            .map(|x| x.into_static())
+            .collect();
+        extract_section(events, anchor, self.options.include_heading)
+            .ok_or_else(|| format!("anchor '{}' not found", anchor.unwrap_or_default()))
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for SnippetsIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.buffer.pop_front() {
+            return Some(annotated_event);
+        }
+
+        let annotated_event = self.source.next()?;
+        let reference = match annotated_event.event {
+            Event::Directive(DirectiveEvent {
+                ref name,
+                ref argument,
+                ..
+            }) if name.as_str() == self.options.directive_name => {
+                argument.as_ref().map(|x| x.as_str().trim()).unwrap_or("")
+            }
+            _ => return Some(annotated_event),
+        };
+
+        match self.load(reference) {
+            Ok(events) => {
+                self.buffer.extend(events);
+                self.buffer.pop_front().or_else(|| self.next())
+            }
+            Err(err) => Some(AnnotatedEvent::new(
+                ErrorEvent {
+                    title: "Failed to include snippet".into(),
+                    description: Some(format!("{}: {}", reference, err).into()),
+                    severity: Severity::Error,
+                },
+                annotated_event.location,
+            )),
+        }
+    }
+}
+
+#[test]
+fn test_snippets() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(Snippets {
+        sources: vec![(
+            "other.md".to_string(),
+            "# Other\n\n## Setup Guide\nInstall it.\n### Details\nMore.\n## Usage\nUse it."
+                .to_string(),
+        )]
+        .into_iter()
+        .collect(),
+        ..Default::default()
+    });
+    assert_eq!(
+        to_html(
+            pipeline.process("Before\n\n```{snippet} other.md#setup-guide\n```\n\nAfter"),
+            &Default::default()
+        ),
+        "<p>Before</p>\n<h2>Setup Guide</h2>\n<p>Install it.</p>\n<h3>Details</h3>\n\
+         <p>More.</p>\n<p>After</p>\n"
+    );
+    let html = to_html(
+        pipeline.process("```{snippet} other.md#missing\n```"),
+        &Default::default(),
+    );
+    assert!(html.contains("Failed to include snippet"));
+}
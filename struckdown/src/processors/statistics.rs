@@ -0,0 +1,175 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{
+    AnnotatedEvent, EndTagEvent, Event, ImageEvent, MetaDataEvent, StartTagEvent, Tag,
+};
+use crate::value::to_value;
+
+/// Collects statistics about a document and emits them as meta data.
+///
+/// The statistics are emitted as a single [`MetaDataEvent`] at the end of
+/// the stream.
+///
+/// When applied this wraps the stream in a [`DocumentStatisticsIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct DocumentStatistics {
+    /// The meta data key to emit the statistics as.
+    pub key: String,
+    /// Controls if code (inline and blocks) counts towards words and characters.
+    pub include_code: bool,
+}
+
+impl Default for DocumentStatistics {
+    fn default() -> DocumentStatistics {
+        DocumentStatistics {
+            key: "statistics".into(),
+            include_code: false,
+        }
+    }
+}
+
+implement_processor!(
+    DocumentStatistics,
+    DocumentStatisticsIter,
+    "Collects statistics about the document",
+);
+
+/// The statistics collected by [`DocumentStatistics`].
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Statistics {
+    /// The number of words.
+    pub words: usize,
+    /// The number of characters (unicode scalar values) excluding line breaks.
+    pub characters: usize,
+    /// The number of headings.
+    pub headings: usize,
+    /// The number of links.
+    pub links: usize,
+    /// The number of images.
+    pub images: usize,
+    /// The number of code blocks.
+    pub code_blocks: usize,
+}
+
+/// The iterator implementing [`DocumentStatistics`].
+pub struct DocumentStatisticsIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    statistics: Option<Statistics>,
+    in_word: bool,
+    options: Cow<'options, DocumentStatistics>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    DocumentStatisticsIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, DocumentStatistics>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            statistics: Some(Statistics::default()),
+            in_word: false,
+            options: options.into(),
+        }
+    }
+}
+
+fn count_text(stats: &mut Statistics, in_word: &mut bool, text: &str) {
+    for c in text.chars() {
+        if c.is_whitespace() {
+            *in_word = false;
+        } else if !*in_word && c.is_alphanumeric() {
+            stats.words += 1;
+            *in_word = true;
+        }
+        if c != '\n' && c != '\r' {
+            stats.characters += 1;
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for DocumentStatisticsIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let stats = self.statistics.as_mut()?;
+        let annotated_event = match self.source.next() {
+            Some(annotated_event) => annotated_event,
+            None => {
+                let stats = self.statistics.take().unwrap();
+                return Some(
+                    MetaDataEvent {
+                        key: self.options.key.clone().into(),
+                        value: to_value(&stats).expect("bad statistics"),
+                    }
+                    .into(),
+                );
+            }
+        };
+
+        let in_word = &mut self.in_word;
+        match annotated_event.event {
+            Event::StartTag(StartTagEvent { tag, .. }) => {
+                if tag.header_level().is_some() {
+                    stats.headings += 1;
+                } else if tag == Tag::Link {
+                    stats.links += 1;
+                }
+                if !tag.is_inline() {
+                    *in_word = false;
+                }
+            }
+            Event::EndTag(EndTagEvent { tag }) => {
+                if !tag.is_inline() {
+                    *in_word = false;
+                }
+            }
+            Event::Image(ImageEvent { .. }) => {
+                stats.images += 1;
+                *in_word = false;
+            }
+            Event::CodeBlock(ref code_block) => {
+                stats.code_blocks += 1;
+                *in_word = false;
+                if self.options.include_code {
+                    count_text(stats, in_word, code_block.code.as_str());
+                    *in_word = false;
+                }
+            }
+            Event::InlineCode(ref inline_code) => {
+                if self.options.include_code {
+                    count_text(stats, in_word, inline_code.code.as_str());
+                }
+            }
+            Event::Text(ref text) => count_text(stats, in_word, text.text.as_str()),
+            Event::InterpretedText(ref text) => count_text(stats, in_word, text.text.as_str()),
+            _ => *in_word = false,
+        }
+
+        Some(annotated_event)
+    }
+}
+
+#[test]
+fn test_document_statistics() {
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(DocumentStatistics::default());
+    let events = pipeline
+        .process("# Hello World\n\nA [link](/) and ![image](x.png).\n\n```\ncode\n```")
+        .collect::<Vec<_>>();
+    let stats = pipeline
+        .extract_metadata(events.iter())
+        .find(|(key, _)| key.as_str() == "statistics")
+        .map(|(_, value)| value.clone())
+        .unwrap();
+    assert_eq!(stats["words"], 5);
+    assert_eq!(stats["headings"], 1);
+    assert_eq!(stats["links"], 1);
+    assert_eq!(stats["images"], 1);
+    assert_eq!(stats["code_blocks"], 1);
+}
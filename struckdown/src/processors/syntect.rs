@@ -1,84 +1,485 @@
 use std::borrow::Cow;
-use std::marker::PhantomData;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
+use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use syntect::easy::HighlightLines;
 use syntect::highlighting::{Theme, ThemeSet};
-use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
-use syntect::parsing::SyntaxSet;
+use syntect::html::{
+    css_for_theme_with_class_style, line_tokens_to_classed_spans, styled_line_to_highlighted_html,
+    ClassStyle, IncludeBackground,
+};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxSet};
+use v_htmlescape::escape;
 
-use crate::event::{AnnotatedEvent, CodeBlockEvent, Event, RawHtmlEvent};
+use crate::error::{Error, ErrorKind};
+use crate::event::{AnnotatedEvent, CodeBlockEvent, Event, RawHtmlEvent, Str};
+use crate::processors::BuiltinProcessor;
 
 const DEFAULT_THEME: &str = "InspiredGitHub";
+const LINENO_CLASS: &str = "lineno";
+const HIGHLIGHTED_LINE_CLASS: &str = "hll";
+const CONSUMED_ARGS: &[&str] = &["linenos", "linenostart", "hl_lines"];
+
+lazy_static! {
+    static ref DEFAULT_SYNTAX_SET: Arc<SyntaxSet> = Arc::new(SyntaxSet::load_defaults_nonewlines());
+    static ref DEFAULT_THEME_SET: Arc<ThemeSet> = Arc::new(ThemeSet::load_defaults());
+    static ref SYNTAX_SET_CACHE: Mutex<HashMap<PathBuf, Arc<SyntaxSet>>> =
+        Mutex::new(HashMap::new());
+    static ref THEME_SET_CACHE: Mutex<HashMap<PathBuf, Arc<ThemeSet>>> = Mutex::new(HashMap::new());
+    static ref THEME_CACHE: Mutex<HashMap<PathBuf, Arc<Theme>>> = Mutex::new(HashMap::new());
+}
 
 /// Implements syntax highlighting via [`syntect`].
 ///
+/// The following code block arguments are understood:
+///
+/// * `linenos`: emits a gutter with line numbers (`<span class="lineno">`)
+/// * `linenostart=N`: the number of the first line (defaults to `1`)
+/// * `hl_lines="1 3-5"`: highlights the given lines (`<span class="hll">`).
+///   Line numbers are relative to the code block.
+///
+/// Syntax and theme sets are loaded once per process and shared between
+/// all instances of the processor.  Pre-built sets can also be provided
+/// via `syntax_set` and `theme_set`.
+///
 /// When applied this wraps the stream in a [`SyntectIter`].
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 #[serde(default)]
 pub struct Syntect {
     /// The name of the theme to use.  If both this and `theme_path` is not
     /// set then a default theme is loaded.  If `theme_path` is set then the
-    /// theme in the `theme_path` folder is used.
+    /// theme in the `theme_path` folder is used.  Unknown themes fall back to
+    /// the default theme but are reported by
+    /// [`Processor::check`](crate::processors::Processor::check).
     pub theme: Option<String>,
     /// When `theme` is not set, then the path to the `.tmTheme` file to load
     /// otherwise the folder to a collection of theme files.
     pub theme_path: Option<PathBuf>,
+    /// An optional element to wrap the `<pre>` in.  This is given in the form
+    /// `tag.class1.class2` (eg: `figure.highlight`).
+    pub wrapper: Option<String>,
+    /// When enabled the language is emitted as `lang-*` class on the `<code>`
+    /// element and the code block arguments as `data-*` attributes on the
+    /// `<pre>` element.
+    pub preserve_attrs: bool,
+    /// Emits CSS classes instead of inline styles.  The matching stylesheet
+    /// can be retrieved from [`Syntect::theme_css`].
+    pub use_classes: bool,
+    /// An optional folder with additional `.sublime-syntax` definitions to load.
+    pub syntax_path: Option<PathBuf>,
+    /// Maps language aliases to the languages they should be highlighted as.
+    pub aliases: BTreeMap<String, String>,
+    /// A pre-built syntax set to use instead of loading one.  When set
+    /// `syntax_path` is ignored.  This can only be set from code.
+    #[serde(skip)]
+    pub syntax_set: Option<Arc<SyntaxSet>>,
+    /// A pre-built theme set to pick `theme` from instead of loading one.
+    /// This can only be set from code.
+    #[serde(skip)]
+    pub theme_set: Option<Arc<ThemeSet>>,
+}
+
+impl Syntect {
+    /// Loads the configured theme.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the theme cannot be loaded.  Use [`Syntect::try_load_theme`]
+    /// to handle the failure.
+    pub fn load_theme(&self) -> Theme {
+        self.try_load_theme()
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Loads the configured theme reporting failures.
+    pub fn try_load_theme(&self) -> Result<Theme, Error> {
+        if let (None, None, Some(path)) = (&self.theme, &self.theme_set, &self.theme_path) {
+            let theme = try_cached(&THEME_CACHE, path, || {
+                ThemeSet::get_theme(path).map_err(|err| {
+                    Error::new(
+                        ErrorKind::ProcessorFailed,
+                        format!("failed to load theme from {}", path.display()),
+                    )
+                    .with_source(err)
+                })
+            })?;
+            return Ok(Theme::clone(&theme));
+        }
+        let theme_set = self.try_load_theme_set()?;
+        let name = self.theme.as_deref().unwrap_or(DEFAULT_THEME);
+        theme_set
+            .themes
+            .get(name)
+            .or_else(|| theme_set.themes.get(DEFAULT_THEME))
+            .cloned()
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::ProcessorFailed,
+                    format!("theme {} not found", name),
+                )
+            })
+    }
+
+    /// Loads the themes `theme` is picked from reporting failures.
+    pub fn try_load_theme_set(&self) -> Result<Arc<ThemeSet>, Error> {
+        if let Some(ref theme_set) = self.theme_set {
+            return Ok(theme_set.clone());
+        }
+        match (&self.theme, &self.theme_path) {
+            (Some(_), Some(path)) => try_cached(&THEME_SET_CACHE, path, || {
+                ThemeSet::load_from_folder(path).map_err(|err| {
+                    Error::new(
+                        ErrorKind::ProcessorFailed,
+                        format!("failed to load themes from {}", path.display()),
+                    )
+                    .with_source(err)
+                })
+            }),
+            _ => Ok(DEFAULT_THEME_SET.clone()),
+        }
+    }
+
+    /// Loads the default syntaxes and the ones from `syntax_path`.
+    ///
+    /// The result is cached so repeated calls are cheap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the syntaxes cannot be loaded.  Use
+    /// [`Syntect::try_load_syntax_set`] to handle the failure.
+    pub fn load_syntax_set(&self) -> Arc<SyntaxSet> {
+        self.try_load_syntax_set()
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Loads the syntaxes reporting failures.
+    pub fn try_load_syntax_set(&self) -> Result<Arc<SyntaxSet>, Error> {
+        if let Some(ref syntax_set) = self.syntax_set {
+            return Ok(syntax_set.clone());
+        }
+        match self.syntax_path {
+            Some(ref path) => try_cached(&SYNTAX_SET_CACHE, path, || {
+                let mut builder = SyntaxSet::load_defaults_nonewlines().into_builder();
+                builder.add_from_folder(path, false).map_err(|err| {
+                    Error::new(
+                        ErrorKind::ProcessorFailed,
+                        format!("failed to load syntaxes from {}", path.display()),
+                    )
+                    .with_source(err)
+                })?;
+                Ok(builder.build())
+            }),
+            None => Ok(DEFAULT_SYNTAX_SET.clone()),
+        }
+    }
+
+    /// Returns the stylesheet for the configured theme.
+    ///
+    /// This is intended to be used together with `use_classes`.
+    pub fn theme_css(&self) -> String {
+        css_for_theme_with_class_style(&self.load_theme(), ClassStyle::Spaced)
+    }
+
+    /// Verifies that the theme and syntaxes can be loaded.
+    fn check(&self) -> Result<(), Error> {
+        if let Some(ref name) = self.theme {
+            if !self.try_load_theme_set()?.themes.contains_key(name) {
+                return Err(Error::new(
+                    ErrorKind::ProcessorFailed,
+                    format!("theme {} not found", name),
+                ));
+            }
+        }
+        self.try_load_theme()?;
+        self.try_load_syntax_set()?;
+        Ok(())
+    }
+
+    /// Returns the configuration unless options only settable from code are used.
+    fn builtin_config(&self) -> Option<BuiltinProcessor> {
+        if self.syntax_set.is_some() || self.theme_set.is_some() {
+            return None;
+        }
+        Some(self.clone().into())
+    }
+}
+
+implement_processor!(
+    Syntect,
+    SyntectIter,
+    "Highlights code blocks with syntect",
+    check = Syntect::check,
+    to_builtin = Syntect::builtin_config,
+);
+
+/// Looks up a value in a path keyed cache or creates it.
+///
+/// Failures are not cached.
+fn try_cached<T, F: FnOnce() -> Result<T, Error>>(
+    cache: &Mutex<HashMap<PathBuf, Arc<T>>>,
+    path: &Path,
+    f: F,
+) -> Result<Arc<T>, Error> {
+    let mut cache = cache.lock().unwrap();
+    if let Some(rv) = cache.get(path) {
+        return Ok(rv.clone());
+    }
+    let rv = Arc::new(f()?);
+    cache.insert(path.to_path_buf(), rv.clone());
+    Ok(rv)
 }
 
-impl Default for Syntect {
-    fn default() -> Syntect {
-        Syntect {
-            theme: None,
-            theme_path: None,
+/// Highlights code line by line either with inline styles or classes.
+enum LineHighlighter<'a> {
+    Styled(HighlightLines<'a>),
+    Classed {
+        parse_state: ParseState,
+        scope_stack: ScopeStack,
+        open_spans: Vec<String>,
+    },
+}
+
+impl<'a> LineHighlighter<'a> {
+    fn new(
+        options: &Syntect,
+        syntax_set: &'a SyntaxSet,
+        theme: &'a Theme,
+        language: &str,
+    ) -> LineHighlighter<'a> {
+        let language = options
+            .aliases
+            .get(language)
+            .map_or(language, |x| x.as_str());
+        let syntax = syntax_set
+            .find_syntax_by_token(language)
+            .unwrap_or_else(|| syntax_set.find_syntax_plain_text());
+        if options.use_classes {
+            LineHighlighter::Classed {
+                parse_state: ParseState::new(syntax),
+                scope_stack: ScopeStack::new(),
+                open_spans: Vec::new(),
+            }
+        } else {
+            LineHighlighter::Styled(HighlightLines::new(syntax, theme))
+        }
+    }
+
+    /// Highlights a single line.
+    ///
+    /// The returned HTML is always balanced.  In class mode spans that are
+    /// still open at the end of a line are closed and reopened on the next.
+    fn highlight_line(&mut self, line: &str, syntax_set: &SyntaxSet) -> String {
+        match *self {
+            LineHighlighter::Styled(ref mut h) => {
+                let regions = h.highlight(line, syntax_set);
+                styled_line_to_highlighted_html(&regions[..], IncludeBackground::No)
+            }
+            LineHighlighter::Classed {
+                ref mut parse_state,
+                ref mut scope_stack,
+                ref mut open_spans,
+            } => {
+                let mut rv = open_spans.concat();
+                let ops = parse_state.parse_line(line, syntax_set);
+                let (html, _) =
+                    line_tokens_to_classed_spans(line, &ops[..], ClassStyle::Spaced, scope_stack);
+                track_open_spans(&html, open_spans);
+                rv.push_str(&html);
+                for _ in 0..open_spans.len() {
+                    rv.push_str("</span>");
+                }
+                rv
+            }
         }
     }
 }
 
-implement_processor!(Syntect, SyntectIter);
+/// Highlights code line by line.
+///
+/// Each returned line is balanced HTML without the trailing newline.
+pub(crate) fn highlight_lines<'l, L: IntoIterator<Item = &'l str>>(
+    options: &Syntect,
+    syntax_set: &SyntaxSet,
+    theme: &Theme,
+    language: &str,
+    lines: L,
+) -> Vec<String> {
+    let mut h = LineHighlighter::new(options, syntax_set, theme, language);
+    lines
+        .into_iter()
+        .map(|line| h.highlight_line(line, syntax_set))
+        .collect()
+}
+
+/// Updates the stack of open `<span>` tags from a chunk of generated HTML.
+fn track_open_spans(html: &str, open_spans: &mut Vec<String>) {
+    let mut rest = html;
+    while let Some(idx) = rest.find('<') {
+        rest = &rest[idx..];
+        if let Some(after) = rest.strip_prefix("</span>") {
+            open_spans.pop();
+            rest = after;
+        } else if rest.starts_with("<span") {
+            let end = match rest.find('>') {
+                Some(end) => end + 1,
+                None => break,
+            };
+            open_spans.push(rest[..end].to_string());
+            rest = &rest[end..];
+        } else {
+            rest = &rest[1..];
+        }
+    }
+}
 
 /// The iterator implementing [`Syntect`].
 pub struct SyntectIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
     source: I,
-    syntax_set: SyntaxSet,
+    syntax_set: Arc<SyntaxSet>,
     theme: Theme,
-    options: PhantomData<&'options Syntect>,
+    options: Cow<'options, Syntect>,
 }
 
 impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> SyntectIter<'data, 'options, I> {
     pub fn new<O: Into<Cow<'options, Syntect>>>(iterator: I, options: O) -> Self {
         let options = options.into();
-        let theme = match (&options.theme, &options.theme_path) {
-            (Some(theme), None) => {
-                let mut theme_set = ThemeSet::load_defaults();
-                match theme_set.themes.remove(theme) {
-                    Some(theme) => theme,
-                    None => theme_set.themes.remove(DEFAULT_THEME).unwrap(),
-                }
+        let theme = options.load_theme();
+        Self {
+            source: iterator,
+            syntax_set: options.load_syntax_set(),
+            theme,
+            options,
+        }
+    }
+}
+
+/// Splits a wrapper specification (`tag.class1.class2`) into tag and classes.
+fn parse_wrapper(spec: &str) -> (&str, String) {
+    let mut pieces = spec.split('.');
+    let tag = match pieces.next() {
+        Some("") | None => "div",
+        Some(tag) => tag,
+    };
+    (tag, pieces.collect::<Vec<_>>().join(" "))
+}
+
+/// Parses a line range specification such as `1 3-5` or `1,3-5`.
+///
+/// Ranges are clamped to `line_count` lines, lines past the end are ignored.
+fn parse_line_ranges(spec: &str, line_count: usize) -> BTreeSet<usize> {
+    let mut rv = BTreeSet::new();
+    for item in spec.split(|c: char| c == ',' || c.is_whitespace()) {
+        let mut pieces = item.splitn(2, '-');
+        let start = match pieces.next().and_then(|x| x.trim().parse::<usize>().ok()) {
+            Some(start) => start,
+            None => continue,
+        };
+        let end = match pieces.next() {
+            Some(end) => match end.trim().parse::<usize>() {
+                Ok(end) => end,
+                Err(_) => continue,
+            },
+            None => start,
+        };
+        rv.extend(start..=end.min(line_count));
+    }
+    rv
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> SyntectIter<'data, 'options, I> {
+    fn render_block(
+        &self,
+        language: &str,
+        code: &str,
+        args: Option<&BTreeMap<Str<'data>, Str<'data>>>,
+    ) -> String {
+        let mut rv = String::new();
+        let wrapper = self.options.wrapper.as_deref().map(parse_wrapper);
+        if let Some((tag, ref class)) = wrapper {
+            write!(rv, "<{}", tag).unwrap();
+            if !class.is_empty() {
+                write!(rv, " class=\"{}\"", escape(class)).unwrap();
             }
-            (Some(theme), Some(path)) => {
-                let mut theme_set =
-                    ThemeSet::load_from_folder(path).expect("failed to initialized theme folder");
-                match theme_set.themes.remove(theme) {
-                    Some(theme) => theme,
-                    None => theme_set.themes.remove(DEFAULT_THEME).unwrap(),
+            rv.push('>');
+        }
+
+        rv.push_str("<pre");
+        if self.options.preserve_attrs {
+            for (key, value) in args.into_iter().flatten() {
+                let key = key.as_str();
+                if CONSUMED_ARGS.contains(&key) {
+                    continue;
                 }
+                let key = key
+                    .chars()
+                    .filter(|&c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                    .collect::<String>();
+                if !key.is_empty() {
+                    write!(rv, " data-{}=\"{}\"", key, escape(value.as_str())).unwrap();
+                }
+            }
+        }
+        rv.push_str("><code");
+        if self.options.preserve_attrs {
+            write!(rv, " class=\"lang-{}\"", escape(language)).unwrap();
+        }
+        write!(rv, ">{}</code></pre>", self.highlight(language, code, args)).unwrap();
+
+        if let Some((tag, _)) = wrapper {
+            write!(rv, "</{}>", tag).unwrap();
+        }
+        rv
+    }
+
+    fn highlight(
+        &self,
+        language: &str,
+        code: &str,
+        args: Option<&BTreeMap<Str<'data>, Str<'data>>>,
+    ) -> String {
+        let mut h = LineHighlighter::new(&self.options, &self.syntax_set, &self.theme, language);
+        let arg = |name: &'static str| args.and_then(|args| args.get(&Str::new(name)));
+        let linenos = arg("linenos").is_some();
+        let hl_lines = arg("hl_lines")
+            .map(|x| parse_line_ranges(x.as_str(), code.lines().count()))
+            .unwrap_or_default();
+
+        if !linenos && hl_lines.is_empty() {
+            if let LineHighlighter::Styled(ref mut h) = h {
+                let regions = h.highlight(code, &self.syntax_set);
+                return styled_line_to_highlighted_html(&regions[..], IncludeBackground::No);
             }
-            (None, Some(ref path)) => {
-                ThemeSet::get_theme(path).expect("failed to load theme by path")
+        }
+
+        let start = arg("linenostart")
+            .and_then(|x| x.as_str().parse::<usize>().ok())
+            .unwrap_or(1);
+        let mut rv = String::new();
+        for (idx, line) in code.lines().enumerate() {
+            let highlighted = hl_lines.contains(&(idx + 1));
+            if highlighted {
+                write!(rv, "<span class=\"{}\">", HIGHLIGHTED_LINE_CLASS).unwrap();
             }
-            (None, None) => {
-                let mut theme_set = ThemeSet::load_defaults();
-                theme_set.themes.remove(DEFAULT_THEME).unwrap()
+            if linenos {
+                write!(
+                    rv,
+                    "<span class=\"{}\">{}</span>",
+                    LINENO_CLASS,
+                    start + idx
+                )
+                .unwrap();
             }
-        };
-        Self {
-            source: iterator,
-            syntax_set: SyntaxSet::load_defaults_nonewlines(),
-            theme,
-            options: PhantomData,
+            rv.push_str(&h.highlight_line(line, &self.syntax_set));
+            if highlighted {
+                rv.push_str("</span>");
+            }
+            rv.push('\n');
         }
+        rv
     }
 }
 
@@ -92,23 +493,14 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
         if let Event::CodeBlock(CodeBlockEvent {
             language: Some(ref language),
             ref code,
-            ..
+            ref args,
         }) = annotated_event.event
         {
-            let language = language.as_str();
-            let syntax = self
-                .syntax_set
-                .find_syntax_by_token(language)
-                .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
-            let mut h = HighlightLines::new(syntax, &self.theme);
-            let regions = h.highlight(code.as_str(), &self.syntax_set);
             return Some(AnnotatedEvent::new(
                 RawHtmlEvent {
-                    html: format!(
-                        "<pre><code>{}</code></pre>",
-                        styled_line_to_highlighted_html(&regions[..], IncludeBackground::No)
-                    )
-                    .into(),
+                    html: self
+                        .render_block(language.as_str(), code.as_str(), args.as_ref())
+                        .into(),
                 },
                 annotated_event.location,
             ));
@@ -116,3 +508,68 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
         Some(annotated_event)
     }
 }
+
+#[test]
+fn test_track_open_spans() {
+    let mut open_spans = Vec::new();
+    track_open_spans(
+        "<span class=\"source\"><span class=\"string\">x</span>",
+        &mut open_spans,
+    );
+    assert_eq!(open_spans, vec!["<span class=\"source\">".to_string()]);
+}
+
+#[test]
+fn test_parse_wrapper() {
+    assert_eq!(
+        parse_wrapper("figure.highlight"),
+        ("figure", "highlight".into())
+    );
+    assert_eq!(parse_wrapper(".a.b"), ("div", "a b".into()));
+}
+
+#[test]
+fn test_parse_line_ranges() {
+    assert_eq!(
+        parse_line_ranges("1 3-5,8", 10)
+            .into_iter()
+            .collect::<Vec<_>>(),
+        vec![1, 3, 4, 5, 8]
+    );
+    assert_eq!(
+        parse_line_ranges("2-18446744073709551615 7", 3)
+            .into_iter()
+            .collect::<Vec<_>>(),
+        vec![2, 3]
+    );
+    assert!(parse_line_ranges("x", 10).is_empty());
+}
+
+#[test]
+fn test_missing_default_theme() {
+    let syntect = Syntect {
+        theme_set: Some(Arc::new(ThemeSet::new())),
+        ..Syntect::default()
+    };
+    assert_eq!(
+        syntect.try_load_theme().unwrap_err().kind(),
+        ErrorKind::ProcessorFailed
+    );
+}
+
+#[test]
+fn test_check_unknown_theme() {
+    let syntect = Syntect {
+        theme: Some("does-not-exist".into()),
+        ..Syntect::default()
+    };
+    let err = syntect.check().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ProcessorFailed);
+    assert!(err.to_string().contains("theme does-not-exist not found"));
+
+    let syntect = Syntect {
+        theme: Some("base16-ocean.dark".into()),
+        ..Syntect::default()
+    };
+    assert!(syntect.check().is_ok());
+}
@@ -0,0 +1,248 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+
+use minijinja::Environment;
+use serde::{Deserialize, Serialize};
+
+use crate::event::{
+    AnnotatedEvent, DirectiveEvent, DocumentStartEvent, ErrorEvent, Event, Location, Severity,
+    StartTagEvent, Str, Tag, TextEvent,
+};
+use crate::processors::BuiltinProcessor;
+use crate::value::Value;
+
+/// A function that configures the template environment.
+///
+/// This can be used to register custom filters, tests and functions.
+#[derive(Clone)]
+pub struct TemplateEnvFn(Arc<dyn Fn(&mut Environment<'static>) + Send + Sync>);
+
+impl TemplateEnvFn {
+    /// Wraps a function for use as environment callback.
+    pub fn new<F: Fn(&mut Environment<'static>) + Send + Sync + 'static>(f: F) -> TemplateEnvFn {
+        TemplateEnvFn(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for TemplateEnvFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TemplateEnvFn").finish()
+    }
+}
+
+/// Interpolates template expressions with minijinja.
+///
+/// Text, link targets and directive bodies are rendered as templates with
+/// the front matter and the configured `context` as variables (the front
+/// matter takes precedence).  All of minijinja's syntax is available, but as
+/// every text is rendered on its own blocks such as `{% if %}` cannot span
+/// multiple paragraphs or formatting.  Failing templates are left as is and
+/// followed by an error event.
+///
+/// When applied this wraps the stream in a [`TemplateIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Template {
+    /// Additional variables.
+    pub context: BTreeMap<String, Value>,
+    /// Renders text.
+    pub render_text: bool,
+    /// Renders link targets.
+    pub render_links: bool,
+    /// Renders directive bodies.
+    pub render_directives: bool,
+    /// Configures the environment.  This can only be set from code.
+    #[serde(skip)]
+    pub configure: Option<TemplateEnvFn>,
+}
+
+impl Default for Template {
+    fn default() -> Template {
+        Template {
+            context: BTreeMap::new(),
+            render_text: true,
+            render_links: true,
+            render_directives: true,
+            configure: None,
+        }
+    }
+}
+
+impl Template {
+    /// Returns the configuration unless options only settable from code are used.
+    fn builtin_config(&self) -> Option<BuiltinProcessor> {
+        if self.configure.is_some() {
+            return None;
+        }
+        Some(self.clone().into())
+    }
+}
+
+implement_processor!(
+    Template,
+    TemplateIter,
+    "Interpolates template expressions with minijinja",
+    to_builtin = Template::builtin_config,
+);
+
+fn is_template(source: &str) -> bool {
+    source.contains("{{") || source.contains("{%") || source.contains("{#")
+}
+
+/// The iterator implementing [`Template`].
+pub struct TemplateIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    env: Environment<'static>,
+    context: serde_json::Map<String, Value>,
+    lookahead: Option<AnnotatedEvent<'data>>,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, Template>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> TemplateIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Template>>>(iterator: I, options: O) -> Self {
+        let options = options.into();
+        let mut env = Environment::new();
+        if let Some(ref configure) = options.configure {
+            (configure.0)(&mut env);
+        }
+        Self {
+            source: iterator,
+            env,
+            context: options
+                .context
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            lookahead: None,
+            buffer: VecDeque::new(),
+            options,
+        }
+    }
+
+    fn pull(&mut self) -> Option<AnnotatedEvent<'data>> {
+        self.lookahead.take().or_else(|| self.source.next())
+    }
+
+    /// Renders a template and queues an error on failure.
+    fn render(&mut self, source: &Str<'data>, location: Option<Location>) -> Option<Str<'data>> {
+        if !is_template(source.as_str()) {
+            return None;
+        }
+        match self.env.render_str(source.as_str(), &self.context) {
+            Ok(rendered) => Some(rendered.into()),
+            Err(err) => {
+                self.buffer.push_back(AnnotatedEvent::new(
+                    ErrorEvent {
+                        title: "Template error".into(),
+                        description: Some(err.to_string().into()),
+                        severity: Severity::Error,
+                    },
+                    location,
+                ));
+                None
+            }
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for TemplateIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.buffer.pop_front() {
+            return Some(annotated_event);
+        }
+
+        let mut annotated_event = self.pull()?;
+        let location = annotated_event.location;
+        match annotated_event.event {
+            Event::DocumentStart(DocumentStartEvent {
+                front_matter: Some(Value::Object(ref front_matter)),
+                ..
+            }) => {
+                for (key, value) in front_matter {
+                    self.context.insert(key.clone(), value.clone());
+                }
+            }
+            Event::Text(TextEvent { ref mut text }) if self.options.render_text => {
+                // the parser splits text at characters that could start
+                // inline markup so merge the run before rendering.
+                let mut merged = None::<String>;
+                while let Some(next) = self.pull() {
+                    if let Event::Text(TextEvent {
+                        text: ref next_text,
+                    }) = next.event
+                    {
+                        merged
+                            .get_or_insert_with(|| text.as_str().to_string())
+                            .push_str(next_text.as_str());
+                    } else {
+                        self.lookahead = Some(next);
+                        break;
+                    }
+                }
+                if let Some(merged) = merged {
+                    *text = merged.into();
+                }
+                if let Some(rendered) = self.render(text, location) {
+                    *text = rendered;
+                }
+            }
+            Event::StartTag(StartTagEvent {
+                tag: Tag::Link,
+                ref mut attrs,
+            }) if self.options.render_links => {
+                if let Some(ref mut target) = attrs.target {
+                    if let Some(rendered) = self.render(target, location) {
+                        *target = rendered;
+                    }
+                }
+            }
+            Event::Directive(DirectiveEvent { ref mut body, .. })
+                if self.options.render_directives =>
+            {
+                if let Some(rendered) = self.render(body, location) {
+                    *body = rendered;
+                }
+            }
+            _ => {}
+        }
+
+        // errors are queued by render and follow the event.
+        Some(annotated_event)
+    }
+}
+
+#[test]
+fn test_template() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(Template {
+        context: vec![("site".to_string(), Value::from("example.com"))]
+            .into_iter()
+            .collect(),
+        configure: Some(TemplateEnvFn::new(|env| {
+            env.add_filter("shout", |value: String| format!("{}!", value));
+        })),
+        ..Default::default()
+    });
+    assert_eq!(
+        to_html(
+            pipeline.process(
+                "---\nname: world\nbeta: false\n---\n\
+                 Hello {{ name|upper }} from [{{ site }}](https://{{site}}/)\n\n\
+                 {% if beta %}Beta{% else %}{{ \"stable\"|shout }}{% endif %}"
+            ),
+            &Default::default()
+        ),
+        "<p>Hello WORLD from <a href=\"https:&#x2f;&#x2f;example.com&#x2f;\">example.com</a></p>\n\
+         <p>stable!</p>\n"
+    );
+}
@@ -1,16 +1,21 @@
 use std::borrow::Cow;
-use std::{iter, mem};
+use std::iter;
 
 use either::Either;
 use serde::{Deserialize, Serialize};
 
 use crate::event::{
-    AnnotatedEvent, Attrs, DirectiveEvent, Event, MetaDataEvent, StartTagEvent, Str, Tag,
+    AnnotatedEvent, Attrs, DirectiveEvent, Event, MetaDataEvent, StartTagEvent, Str, Tag, TextEvent,
 };
 use crate::plain::to_plain_text;
-use crate::value::to_value;
+use crate::value::{to_value, Value};
 
-/// Automatically add anchors to all headers when missing.
+/// Generates a table of contents.
+///
+/// The TOC is inserted where the `{toc}` directive is placed.  The directive
+/// can override `min_depth`, `max_depth` and `scope_to_section` in its front
+/// matter and additionally restrict the TOC to the section with a given
+/// `anchor` or set a `title`.
 ///
 /// When applied this wraps the stream in a [`TableOfContentsIter`].
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -22,6 +27,36 @@ pub struct TableOfContents {
     pub emit_metadata: bool,
     /// The class that should be added to the TOC.
     pub class_name: Option<String>,
+    /// The minimum heading level to include.
+    pub min_depth: usize,
+    /// The maximum heading level to include.
+    pub max_depth: usize,
+    /// If enabled the TOC only covers the section containing the directive.
+    pub scope_to_section: bool,
+    /// Emits ordered lists (`<ol>`) instead of unordered ones.
+    pub ordered: bool,
+    /// Additional classes for the lists by nesting level.
+    ///
+    /// The first class goes to the outermost list, the second class to
+    /// the lists nested within it and so forth.
+    pub level_classes: Vec<String>,
+    /// Wraps the TOC in a `<nav>` container.
+    pub nav: bool,
+    /// The class of the `<nav>` container.
+    pub nav_class: Option<String>,
+    /// An optional title emitted before the list.
+    pub title: Option<String>,
+    /// The class of the title.
+    pub title_class: Option<String>,
+    /// Includes section numbers in the TOC entries and the metadata.
+    pub section_numbers: bool,
+    /// The class of the span holding a section number.
+    pub section_number_class: Option<String>,
+    /// Only emits the `toc` metadata.
+    ///
+    /// In this mode the stream is not buffered and TOC directives are
+    /// passed through unchanged.
+    pub metadata_only: bool,
 }
 
 impl Default for TableOfContents {
@@ -30,15 +65,32 @@ impl Default for TableOfContents {
             role_name: Some("toc".into()),
             emit_metadata: true,
             class_name: Some("table-of-contents".into()),
+            min_depth: 1,
+            max_depth: 6,
+            scope_to_section: false,
+            ordered: false,
+            level_classes: Vec::new(),
+            nav: false,
+            nav_class: None,
+            title: None,
+            title_class: Some("toc-title".into()),
+            section_numbers: false,
+            section_number_class: Some("toc-number".into()),
+            metadata_only: false,
         }
     }
 }
 
-implement_processor!(TableOfContents, TableOfContentsIter);
+implement_processor!(
+    TableOfContents,
+    TableOfContentsIter,
+    "Generates a table of contents"
+);
 
 /// The iterator implementing [`TableOfContents`].
 pub struct TableOfContentsIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
     source_iter: Option<I>,
+    builder: Option<TocBuilder<'data>>,
     iter: Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data>,
     options: Cow<'options, TableOfContents>,
 }
@@ -49,6 +101,7 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
     pub fn new<O: Into<Cow<'options, TableOfContents>>>(iterator: I, options: O) -> Self {
         Self {
             source_iter: Some(iterator),
+            builder: Some(TocBuilder::new()),
             iter: Box::new(None.into_iter()),
             options: options.into(),
         }
@@ -61,6 +114,8 @@ pub struct TocItem<'data> {
     level: usize,
     #[serde(skip_serializing_if = "Option::is_none")]
     anchor: Option<Str<'data>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    number: Option<String>,
     text: Option<Str<'data>>,
     events: Vec<AnnotatedEvent<'data>>,
     #[serde(skip_serializing_if = "Vec::is_empty")]
@@ -77,6 +132,7 @@ where
             deepest.children.push(TocItem {
                 level: deepest.level + 1,
                 anchor: None,
+                number: None,
                 text: None,
                 events: vec![],
                 children: Vec::new(),
@@ -87,7 +143,13 @@ where
     f(deepest)
 }
 
-fn dump_toc<'data>(out: &mut Vec<AnnotatedEvent<'data>>, toc: &TocItem<'data>, max_depth: usize) {
+fn dump_toc<'data>(
+    out: &mut Vec<AnnotatedEvent<'data>>,
+    options: &TableOfContents,
+    toc: &TocItem<'data>,
+    max_depth: usize,
+    nesting: usize,
+) {
     out.push(Tag::ListItem.start_tag(Attrs::default()).into());
     if !toc.events.is_empty() {
         out.push(
@@ -100,6 +162,21 @@ fn dump_toc<'data>(out: &mut Vec<AnnotatedEvent<'data>>, toc: &TocItem<'data>, m
             })
             .into(),
         );
+        if let Some(ref number) = toc.number {
+            out.push(
+                Tag::Span
+                    .start_tag(class_attrs(&[options.section_number_class.as_deref()]))
+                    .into(),
+            );
+            out.push(
+                TextEvent {
+                    text: number.clone().into(),
+                }
+                .into(),
+            );
+            out.push(Tag::Span.end_tag().into());
+            out.push(TextEvent { text: " ".into() }.into());
+        }
         out.extend(toc.events.iter().cloned());
         out.push(
             (if toc.anchor.is_some() {
@@ -112,73 +189,259 @@ fn dump_toc<'data>(out: &mut Vec<AnnotatedEvent<'data>>, toc: &TocItem<'data>, m
         );
     }
     if !toc.children.is_empty() && toc.level < max_depth {
-        out.push(Tag::UnorderedList.start_tag(Attrs::default()).into());
+        out.push(
+            options
+                .list_tag()
+                .start_tag(options.list_attrs(nesting))
+                .into(),
+        );
         for child in &toc.children {
-            dump_toc(out, child, max_depth);
+            dump_toc(out, options, child, max_depth, nesting + 1);
         }
-        out.push(Tag::UnorderedList.end_tag().into());
+        out.push(options.list_tag().end_tag().into());
     }
     out.push(Tag::ListItem.end_tag().into());
 }
 
-fn extract_toc<'data, I: Iterator<Item = AnnotatedEvent<'data>>>(
-    iter: I,
-) -> (Vec<AnnotatedEvent<'data>>, TocItem<'data>) {
-    let mut buf = Vec::with_capacity(iter.size_hint().0);
-    let mut headline = None;
-    let mut headline_buf = vec![];
-    let mut level = 0;
-    let mut toc_tree = TocItem {
-        level: 0,
-        anchor: None,
-        text: None,
-        events: Vec::new(),
-        children: Vec::new(),
-    };
-
-    for annotated_event in iter {
+fn class_attrs(classes: &[Option<&str>]) -> Attrs<'static> {
+    let class = classes.iter().filter_map(|x| *x).collect::<Vec<_>>();
+    Attrs {
+        class: if class.is_empty() {
+            None
+        } else {
+            Some(class.join(" ").into())
+        },
+        ..Attrs::default()
+    }
+}
+
+/// Assigns section numbers (`1`, `1.2`, ...) to all items below `item`.
+fn assign_numbers(item: &mut TocItem<'_>, prefix: &str) {
+    for (idx, child) in item.children.iter_mut().enumerate() {
+        let number = if prefix.is_empty() {
+            (idx + 1).to_string()
+        } else {
+            format!("{}.{}", prefix, idx + 1)
+        };
+        assign_numbers(child, &number);
+        child.number = Some(number);
+    }
+}
+
+/// Returns the indexes leading to the last item at the given level.
+fn last_path(root: &TocItem<'_>, level: usize) -> Vec<usize> {
+    let mut rv = Vec::new();
+    let mut item = root;
+    while item.level < level && !item.children.is_empty() {
+        rv.push(item.children.len() - 1);
+        item = item.children.last().unwrap();
+    }
+    rv
+}
+
+fn item_at_path<'a, 'data>(root: &'a TocItem<'data>, path: &[usize]) -> &'a TocItem<'data> {
+    path.iter().fold(root, |item, &idx| &item.children[idx])
+}
+
+fn find_anchor<'a, 'data>(item: &'a TocItem<'data>, anchor: &str) -> Option<&'a TocItem<'data>> {
+    if item.anchor.as_ref().map(|x| x.as_str()) == Some(anchor) {
+        return Some(item);
+    }
+    item.children
+        .iter()
+        .find_map(|child| find_anchor(child, anchor))
+}
+
+/// Collects the items that are shown at the top of a TOC.
+fn top_items<'a, 'data>(
+    item: &'a TocItem<'data>,
+    min_depth: usize,
+    out: &mut Vec<&'a TocItem<'data>>,
+) {
+    for child in &item.children {
+        if child.level >= min_depth {
+            out.push(child);
+        } else {
+            top_items(child, min_depth, out);
+        }
+    }
+}
+
+/// Incrementally builds the TOC tree from a stream of events.
+struct TocBuilder<'data> {
+    headline: Option<(usize, Option<Str<'data>>)>,
+    headline_buf: Vec<AnnotatedEvent<'data>>,
+    level: usize,
+    tree: TocItem<'data>,
+    /// For each TOC directive the path to the section containing it.
+    directive_sections: Vec<Option<Vec<usize>>>,
+    current_section: Option<Vec<usize>>,
+}
+
+impl<'data> TocBuilder<'data> {
+    fn new() -> TocBuilder<'data> {
+        TocBuilder {
+            headline: None,
+            headline_buf: Vec::new(),
+            level: 0,
+            tree: TocItem {
+                level: 0,
+                anchor: None,
+                number: None,
+                text: None,
+                events: Vec::new(),
+                children: Vec::new(),
+            },
+            directive_sections: Vec::new(),
+            current_section: None,
+        }
+    }
+
+    fn push(&mut self, annotated_event: &AnnotatedEvent<'data>, role_name: Option<&str>) {
         match annotated_event.event {
             Event::StartTag(StartTagEvent { tag, ref attrs }) => {
                 if let Some(header_level) = tag.header_level() {
-                    headline = Some((header_level, attrs.id.clone()));
-                } else if headline.is_some() {
-                    headline_buf.push(annotated_event.clone());
+                    self.headline = Some((header_level, attrs.id.clone()));
+                } else if self.headline.is_some() {
+                    self.headline_buf.push(annotated_event.clone());
                 }
-                if headline.is_some() {
-                    level += 1;
+                if self.headline.is_some() {
+                    self.level += 1;
                 }
             }
             Event::EndTag(..) => {
-                if headline.is_some() {
-                    level -= 1;
-                    if level == 0 {
-                        let (level, anchor) = headline.take().unwrap();
-                        let events = mem::replace(&mut headline_buf, Vec::new());
-                        with_toc_at_level(&mut toc_tree, level, move |toc_tree| {
+                if self.headline.is_some() {
+                    self.level -= 1;
+                    if self.level == 0 {
+                        let (level, anchor) = self.headline.take().unwrap();
+                        let events = std::mem::take(&mut self.headline_buf);
+                        with_toc_at_level(&mut self.tree, level, move |toc_tree| {
                             toc_tree.children.push(TocItem {
                                 level: toc_tree.level + 1,
-                                anchor: anchor.clone(),
+                                anchor,
+                                number: None,
                                 text: Some(to_plain_text(events.iter())),
-                                events: events.clone(),
+                                events,
                                 children: Vec::new(),
                             });
                         });
+                        self.current_section = Some(last_path(&self.tree, level));
                     } else {
-                        headline_buf.push(annotated_event.clone());
+                        self.headline_buf.push(annotated_event.clone());
                     }
                 }
             }
             Event::Error(..) | Event::MetaData(..) => {}
+            Event::Directive(DirectiveEvent { ref name, .. })
+                if Some(name.as_str()) == role_name =>
+            {
+                self.directive_sections.push(self.current_section.clone());
+            }
             _ => {
-                if headline.is_some() {
-                    headline_buf.push(annotated_event.clone());
+                if self.headline.is_some() {
+                    self.headline_buf.push(annotated_event.clone());
                 }
             }
         }
-        buf.push(annotated_event);
     }
 
-    (buf, toc_tree)
+    fn finish(&mut self, options: &TableOfContents) {
+        if options.section_numbers {
+            assign_numbers(&mut self.tree, "");
+        }
+    }
+
+    fn metadata(&self) -> AnnotatedEvent<'data> {
+        MetaDataEvent {
+            key: "toc".into(),
+            value: to_value(&self.tree.children).expect("bad toc tree"),
+        }
+        .into()
+    }
+}
+
+impl TableOfContents {
+    fn list_tag(&self) -> Tag {
+        if self.ordered {
+            Tag::OrderedList
+        } else {
+            Tag::UnorderedList
+        }
+    }
+
+    fn list_attrs(&self, nesting: usize) -> Attrs<'static> {
+        let level_class = self.level_classes.get(nesting).map(|x| x.as_str());
+        if nesting == 0 {
+            class_attrs(&[self.class_name.as_deref(), level_class])
+        } else {
+            class_attrs(&[level_class])
+        }
+    }
+
+    /// Renders the TOC for a directive.
+    fn render<'data>(
+        &self,
+        tree: &TocItem<'data>,
+        section: Option<&[usize]>,
+        front_matter: Option<&Value>,
+    ) -> Vec<AnnotatedEvent<'data>> {
+        let get = |key: &str| front_matter.and_then(|x| x.get(key));
+        let min_depth = get("min_depth")
+            .and_then(|x| x.as_u64())
+            .map_or(self.min_depth, |x| x as usize);
+        let max_depth = get("max_depth")
+            .and_then(|x| x.as_u64())
+            .map_or(self.max_depth, |x| x as usize);
+        let scope_to_section = get("scope_to_section")
+            .and_then(|x| x.as_bool())
+            .unwrap_or(self.scope_to_section);
+
+        let scope = match get("anchor").and_then(|x| x.as_str()) {
+            Some(anchor) => find_anchor(tree, anchor),
+            None if scope_to_section => Some(section.map_or(tree, |x| item_at_path(tree, x))),
+            None => Some(tree),
+        };
+        let mut items = Vec::new();
+        if let Some(scope) = scope {
+            top_items(scope, min_depth, &mut items);
+        }
+
+        let title = get("title")
+            .and_then(|x| x.as_str())
+            .or(self.title.as_deref());
+
+        let mut toc = Vec::new();
+        if self.nav {
+            toc.push(
+                Tag::Nav
+                    .start_tag(class_attrs(&[self.nav_class.as_deref()]))
+                    .into(),
+            );
+        }
+        if let Some(title) = title {
+            toc.push(
+                Tag::Container
+                    .start_tag(class_attrs(&[self.title_class.as_deref()]))
+                    .into(),
+            );
+            toc.push(
+                TextEvent {
+                    text: title.to_string().into(),
+                }
+                .into(),
+            );
+            toc.push(Tag::Container.end_tag().into());
+        }
+        toc.push(self.list_tag().start_tag(self.list_attrs(0)).into());
+        for item in items.into_iter().filter(|x| x.level <= max_depth) {
+            dump_toc(&mut toc, self, item, max_depth, 1);
+        }
+        toc.push(self.list_tag().end_tag().into());
+        if self.nav {
+            toc.push(Tag::Nav.end_tag().into());
+        }
+        toc
+    }
 }
 
 impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
@@ -187,25 +450,49 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
     type Item = AnnotatedEvent<'data>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.options.metadata_only {
+            let annotated_event = self.source_iter.as_mut().and_then(|x| x.next());
+            return match annotated_event {
+                Some(annotated_event) => {
+                    if let Some(ref mut builder) = self.builder {
+                        builder.push(&annotated_event, self.options.role_name.as_deref());
+                    }
+                    Some(annotated_event)
+                }
+                None => {
+                    self.source_iter = None;
+                    let mut builder = self.builder.take()?;
+                    builder.finish(&self.options);
+                    if self.options.emit_metadata {
+                        Some(builder.metadata())
+                    } else {
+                        None
+                    }
+                }
+            };
+        }
+
         if let Some(source) = self.source_iter.take() {
-            let (buf, toc_tree) = extract_toc(source);
+            let mut builder = self.builder.take().unwrap_or_else(TocBuilder::new);
+            let events = source
+                .inspect(|annotated_event| {
+                    builder.push(annotated_event, self.options.role_name.as_deref())
+                })
+                .collect::<Vec<_>>();
+            builder.finish(&self.options);
 
             let metadata = if self.options.emit_metadata {
-                Some(
-                    MetaDataEvent {
-                        key: "toc".into(),
-                        value: to_value(&toc_tree.children).expect("bad toc tree"),
-                    }
-                    .into(),
-                )
+                Some(builder.metadata())
             } else {
                 None
             };
 
-            let role_name = self.options.role_name.clone();
-            let class_name = self.options.class_name.clone();
+            let options = self.options.clone().into_owned();
+            let tree = builder.tree;
+            let mut directive_sections = builder.directive_sections.into_iter();
             self.iter = Box::new(
-                buf.into_iter()
+                events
+                    .into_iter()
                     .flat_map(move |annotated_event| {
                         if let Event::Directive(DirectiveEvent {
                             ref name,
@@ -213,37 +500,114 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
                             ..
                         }) = annotated_event.event
                         {
-                            if Some(name.as_str()) == role_name.as_deref() {
-                                let mut toc = Vec::new();
-                                let max_depth = front_matter
-                                    .as_ref()
-                                    .and_then(|x| x.get("max_depth"))
-                                    .and_then(|x| x.as_u64())
-                                    .unwrap_or(6)
-                                    as _;
-                                toc.push(
-                                    Tag::UnorderedList
-                                        .start_tag(Attrs {
-                                            class: class_name
-                                                .as_ref()
-                                                .map(|x| x.to_string().into()),
-                                            ..Attrs::default()
-                                        })
-                                        .into(),
+                            if Some(name.as_str()) == options.role_name.as_deref() {
+                                let section = directive_sections.next().flatten();
+                                return Either::Left(
+                                    options
+                                        .render(&tree, section.as_deref(), front_matter.as_ref())
+                                        .into_iter(),
                                 );
-                                for child in &toc_tree.children {
-                                    dump_toc(&mut toc, &child, max_depth);
-                                }
-                                toc.push(Tag::UnorderedList.end_tag().into());
-                                return Either::Left(toc.into_iter());
                             }
                         }
                         Either::Right(iter::once(annotated_event))
                     })
-                    .chain(metadata.into_iter()),
+                    .chain(metadata),
             ) as Box<dyn Iterator<Item = _>>;
         }
 
         self.iter.next()
     }
 }
+
+#[test]
+fn test_toc_scoping() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(TableOfContents {
+        emit_metadata: false,
+        class_name: None,
+        scope_to_section: true,
+        min_depth: 2,
+        ..Default::default()
+    });
+    let html = to_html(
+        pipeline.process("# A\n\n## A.1\n\n# B\n\n```{toc}\n```\n\n## B.1\n\n### B.1.1"),
+        &Default::default(),
+    );
+    assert!(html.starts_with(
+        "<h1>A</h1>\n<h2>A.1</h2>\n<h1>B</h1>\n<ul>\n<li><span>B.1</span><ul>\n\
+         <li><span>B.1.1</span></li>\n</ul>\n</li>\n</ul>\n"
+    ));
+}
+
+#[test]
+fn test_toc_markup() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(TableOfContents {
+        emit_metadata: false,
+        ordered: true,
+        level_classes: vec!["toc-l1".into(), "toc-l2".into()],
+        nav: true,
+        title: Some("Contents".into()),
+        ..Default::default()
+    });
+    let html = to_html(
+        pipeline.process("```{toc}\n```\n\n# A\n\n## B"),
+        &Default::default(),
+    );
+    assert!(html.starts_with(
+        "<nav>\n<div class=\"toc-title\">\nContents</div>\n\
+         <ol class=\"table-of-contents toc-l1\">\n<li><span>A</span><ol class=\"toc-l2\">\n\
+         <li><span>B</span></li>\n</ol>\n</li>\n</ol>\n</nav>\n"
+    ));
+}
+
+#[test]
+fn test_toc_metadata_only() {
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(TableOfContents {
+        metadata_only: true,
+        ..Default::default()
+    });
+    let mut iter = pipeline.process("```{toc}\n```\n\n# A\n\n## B");
+    assert!(matches!(
+        iter.next().unwrap().event,
+        Event::DocumentStart(..)
+    ));
+    assert!(matches!(iter.next().unwrap().event, Event::Directive(..)));
+    let events = iter.collect::<Vec<_>>();
+    match events.last().unwrap().event {
+        Event::MetaData(ref meta) => {
+            assert_eq!(meta.key.as_str(), "toc");
+            assert_eq!(meta.value[0]["text"], "A");
+            assert_eq!(meta.value[0]["children"][0]["text"], "B");
+        }
+        _ => panic!("expected toc metadata"),
+    }
+}
+
+#[test]
+fn test_toc_section_numbers() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(TableOfContents {
+        emit_metadata: false,
+        class_name: None,
+        section_numbers: true,
+        ..Default::default()
+    });
+    let html = to_html(
+        pipeline.process("```{toc}\n```\n\n# A\n\n## B\n\n## C"),
+        &Default::default(),
+    );
+    assert!(html.contains("<li><span><span class=\"toc-number\">1.2</span> C</span></li>"));
+}
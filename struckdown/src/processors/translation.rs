@@ -0,0 +1,592 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{
+    AnnotatedEvent, ErrorEvent, Event, Location, MetaDataEvent, Severity, TextEvent,
+};
+use crate::value::to_value;
+
+/// A translatable message.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Message {
+    /// The message text with placeholders for the inline markup.
+    pub msgid: String,
+    /// The locations of all segments with this message.
+    pub locations: Vec<Location>,
+}
+
+/// Formats messages as a gettext POT file.
+///
+/// The `filename` is used for the source references.
+pub fn messages_to_pot(messages: &[Message], filename: &str) -> String {
+    let mut rv = String::new();
+    for message in messages {
+        if !rv.is_empty() {
+            rv.push('\n');
+        }
+        for location in &message.locations {
+            writeln!(rv, "#: {}:{}", filename, location.line).unwrap();
+        }
+        writeln!(rv, "msgid \"{}\"", escape_po(&message.msgid)).unwrap();
+        rv.push_str("msgstr \"\"\n");
+    }
+    rv
+}
+
+fn escape_po(s: &str) -> String {
+    let mut rv = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => rv.push_str("\\\\"),
+            '"' => rv.push_str("\\\""),
+            '\n' => rv.push_str("\\n"),
+            '\t' => rv.push_str("\\t"),
+            c => rv.push(c),
+        }
+    }
+    rv
+}
+
+/// Returns `true` if the event is part of the inline content of a block.
+fn is_inline_event(event: &Event<'_>) -> bool {
+    match event {
+        Event::Text(..)
+        | Event::SoftBreak
+        | Event::HardBreak
+        | Event::InlineCode(..)
+        | Event::InterpretedText(..)
+        | Event::Image(..)
+        | Event::FootnoteReference(..)
+        | Event::RawHtml(..) => true,
+        Event::StartTag(tag) => tag.tag.is_inline(),
+        Event::EndTag(tag) => tag.tag.is_inline(),
+        _ => false,
+    }
+}
+
+/// The inline markup that a placeholder in a message stands for.
+pub(crate) struct Placeholder<'data> {
+    pub start: AnnotatedEvent<'data>,
+    pub end: Option<AnnotatedEvent<'data>>,
+}
+
+/// A run of inline events forming a translatable segment.
+pub(crate) struct Segment<'data> {
+    pub msgid: String,
+    pub placeholders: Vec<Placeholder<'data>>,
+}
+
+impl<'data> Segment<'data> {
+    /// Builds the segment for a run of inline events.
+    ///
+    /// Text is escaped so that `<` never starts a placeholder.  Inline tags
+    /// become numbered `<1>..</1>` pairs and everything else that is not text
+    /// (such as inline code and images) becomes a `<1/>` placeholder.  Returns
+    /// `None` if the run contains no text or unbalanced tags.
+    pub fn from_events(events: &[AnnotatedEvent<'data>]) -> Option<Segment<'data>> {
+        let mut msgid = String::new();
+        let mut placeholders: Vec<Placeholder<'data>> = Vec::new();
+        let mut stack = Vec::new();
+        let mut has_text = false;
+
+        for annotated_event in events {
+            match annotated_event.event {
+                Event::Text(TextEvent { ref text }) => {
+                    has_text = has_text || !text.as_str().trim().is_empty();
+                    for c in text.as_str().chars() {
+                        if c == '\\' || c == '<' {
+                            msgid.push('\\');
+                        }
+                        msgid.push(c);
+                    }
+                }
+                Event::SoftBreak => msgid.push('\n'),
+                Event::StartTag(..) => {
+                    placeholders.push(Placeholder {
+                        start: annotated_event.clone(),
+                        end: None,
+                    });
+                    stack.push(placeholders.len());
+                    write!(msgid, "<{}>", placeholders.len()).unwrap();
+                }
+                Event::EndTag(..) => {
+                    let idx = stack.pop()?;
+                    placeholders[idx - 1].end = Some(annotated_event.clone());
+                    write!(msgid, "</{}>", idx).unwrap();
+                }
+                _ => {
+                    placeholders.push(Placeholder {
+                        start: annotated_event.clone(),
+                        end: None,
+                    });
+                    write!(msgid, "<{}/>", placeholders.len()).unwrap();
+                }
+            }
+        }
+
+        if !has_text || !stack.is_empty() {
+            return None;
+        }
+        Some(Segment {
+            msgid: msgid.trim().to_string(),
+            placeholders,
+        })
+    }
+}
+
+/// Extracts translatable text segments.
+///
+/// Every run of inline content within a block (a paragraph, heading, table
+/// cell, tight list item etc.) is a segment.  Inline markup in a segment is
+/// replaced with numbered placeholders: `Hello *World*` becomes the message
+/// `Hello <1>World</1>` and inline code or images become self closing
+/// placeholders such as `<2/>`.  A literal `<` or `\` in the text is escaped
+/// with a backslash.  Identical messages are only recorded once.
+///
+/// The messages are emitted as a list of [`Message`]s in a meta data event
+/// at the end of the stream.  They can be formatted as a POT file with
+/// [`messages_to_pot`].
+///
+/// When applied this wraps the stream in a [`ExtractTranslationsIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ExtractTranslations {
+    /// The key of the emitted meta data.
+    pub metadata_key: String,
+}
+
+impl Default for ExtractTranslations {
+    fn default() -> ExtractTranslations {
+        ExtractTranslations {
+            metadata_key: "translations".into(),
+        }
+    }
+}
+
+implement_processor!(
+    ExtractTranslations,
+    ExtractTranslationsIter,
+    "Extracts translatable text segments",
+);
+
+/// The iterator implementing [`ExtractTranslations`].
+pub struct ExtractTranslationsIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    run: Vec<AnnotatedEvent<'data>>,
+    messages: Vec<Message>,
+    seen: HashMap<String, usize>,
+    done: bool,
+    options: Cow<'options, ExtractTranslations>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    ExtractTranslationsIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, ExtractTranslations>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            run: Vec::new(),
+            messages: Vec::new(),
+            seen: HashMap::new(),
+            done: false,
+            options: options.into(),
+        }
+    }
+
+    fn finish_run(&mut self) {
+        if self.run.is_empty() {
+            return;
+        }
+        let location = self.run[0].location;
+        if let Some(segment) = Segment::from_events(&self.run) {
+            let idx = match self.seen.get(&segment.msgid) {
+                Some(&idx) => idx,
+                None => {
+                    self.seen.insert(segment.msgid.clone(), self.messages.len());
+                    self.messages.push(Message {
+                        msgid: segment.msgid,
+                        locations: Vec::new(),
+                    });
+                    self.messages.len() - 1
+                }
+            };
+            self.messages[idx].locations.extend(location);
+        }
+        self.run.clear();
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for ExtractTranslationsIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.source.next() {
+            Some(annotated_event) => {
+                if is_inline_event(&annotated_event.event) {
+                    self.run.push(annotated_event.clone());
+                } else {
+                    self.finish_run();
+                }
+                Some(annotated_event)
+            }
+            None => {
+                self.finish_run();
+                self.done = true;
+                Some(
+                    MetaDataEvent {
+                        key: self.options.metadata_key.clone().into(),
+                        value: to_value(&self.messages).unwrap(),
+                    }
+                    .into(),
+                )
+            }
+        }
+    }
+}
+
+/// A translation in a message catalog.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Translation {
+    /// Just the translated message.
+    Plain(String),
+    /// The translated message with flags.
+    Detailed {
+        /// The translated message.
+        msgstr: String,
+        /// Marks the translation as stale (gettext's fuzzy flag).
+        #[serde(default)]
+        fuzzy: bool,
+    },
+}
+
+impl Translation {
+    /// Returns the translated message.
+    pub fn msgstr(&self) -> &str {
+        match self {
+            Translation::Plain(msgstr) => msgstr,
+            Translation::Detailed { msgstr, .. } => msgstr,
+        }
+    }
+
+    /// Returns `true` if the translation is stale.
+    pub fn is_stale(&self) -> bool {
+        matches!(self, Translation::Detailed { fuzzy: true, .. })
+    }
+}
+
+impl<'data> Segment<'data> {
+    /// Builds the events for a translation of the segment.
+    ///
+    /// Placeholders can be reordered or dropped by the translation, but tags
+    /// have to be balanced and only refer to placeholders of the segment.
+    fn translate(
+        &self,
+        msgstr: &str,
+        location: Option<Location>,
+    ) -> Result<Vec<AnnotatedEvent<'data>>, String> {
+        let mut rv = Vec::new();
+        let mut text = String::new();
+        let mut stack = Vec::new();
+        let mut rest = msgstr;
+
+        macro_rules! flush_text {
+            () => {
+                if !text.is_empty() {
+                    rv.push(AnnotatedEvent::new(
+                        TextEvent {
+                            text: std::mem::take(&mut text).into(),
+                        },
+                        location,
+                    ));
+                }
+            };
+        }
+
+        while let Some(c) = rest.chars().next() {
+            if c == '\\' {
+                let escaped = rest[1..].chars().next().unwrap_or('\\');
+                text.push(escaped);
+                rest = &rest[(1 + escaped.len_utf8()).min(rest.len())..];
+            } else if c == '\n' {
+                flush_text!();
+                rv.push(AnnotatedEvent::new(Event::SoftBreak, location));
+                rest = &rest[1..];
+            } else if c == '<' {
+                let end = rest
+                    .find('>')
+                    .ok_or_else(|| "unterminated placeholder".to_string())?;
+                let placeholder = &rest[1..end];
+                rest = &rest[end + 1..];
+                let (closing, self_closing, number) =
+                    match (placeholder.strip_prefix('/'), placeholder.strip_suffix('/')) {
+                        (Some(number), _) => (true, false, number),
+                        (_, Some(number)) => (false, true, number),
+                        _ => (false, false, placeholder),
+                    };
+                let idx = number
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&idx| idx >= 1 && idx <= self.placeholders.len())
+                    .ok_or_else(|| format!("unknown placeholder <{}>", placeholder))?;
+                let target = &self.placeholders[idx - 1];
+                flush_text!();
+                if closing {
+                    if stack.pop() != Some(idx) {
+                        return Err(format!("unexpected closing placeholder </{}>", idx));
+                    }
+                    rv.extend(target.end.clone());
+                } else if self_closing != target.end.is_none() {
+                    return Err(format!("placeholder <{}> used incorrectly", placeholder));
+                } else {
+                    if !self_closing {
+                        stack.push(idx);
+                    }
+                    rv.push(target.start.clone());
+                }
+            } else {
+                text.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+
+        if let Some(idx) = stack.pop() {
+            return Err(format!("placeholder <{}> is not closed", idx));
+        }
+        flush_text!();
+        Ok(rv)
+    }
+}
+
+/// Applies translations from a message catalog.
+///
+/// This is the counterpart to [`ExtractTranslations`].  Text segments are
+/// looked up by their message in the `catalog` and replaced with the
+/// translation.  The placeholders in the translation are mapped back to the
+/// original inline markup so formatting, links and inline code are
+/// retained.
+///
+/// Segments without a translation, with a stale (fuzzy) translation or a
+/// translation with invalid placeholders are kept as is.  Depending on the
+/// options this is reported with an error event after the segment.
+///
+/// When applied this wraps the stream in a [`ApplyTranslationsIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ApplyTranslations {
+    /// Maps messages to their translations.
+    pub catalog: HashMap<String, Translation>,
+    /// Reports segments without a translation.
+    pub report_missing: bool,
+    /// Reports segments with a stale translation.
+    pub report_stale: bool,
+}
+
+impl Default for ApplyTranslations {
+    fn default() -> ApplyTranslations {
+        ApplyTranslations {
+            catalog: HashMap::new(),
+            report_missing: true,
+            report_stale: true,
+        }
+    }
+}
+
+implement_processor!(
+    ApplyTranslations,
+    ApplyTranslationsIter,
+    "Applies translations from a message catalog",
+);
+
+/// The iterator implementing [`ApplyTranslations`].
+pub struct ApplyTranslationsIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    run: Vec<AnnotatedEvent<'data>>,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, ApplyTranslations>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    ApplyTranslationsIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, ApplyTranslations>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            run: Vec::new(),
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+
+    fn finish_run(&mut self) {
+        let run = std::mem::take(&mut self.run);
+        let location = match run.first() {
+            Some(annotated_event) => annotated_event.location,
+            None => return,
+        };
+        let segment = match Segment::from_events(&run) {
+            Some(segment) => segment,
+            None => {
+                self.buffer.extend(run);
+                return;
+            }
+        };
+
+        let error = match self.options.catalog.get(&segment.msgid) {
+            None => Some("Missing translation").filter(|_| self.options.report_missing),
+            Some(translation) if translation.is_stale() => {
+                Some("Stale translation").filter(|_| self.options.report_stale)
+            }
+            Some(translation) => match segment.translate(translation.msgstr(), location) {
+                Ok(events) => {
+                    self.buffer.extend(events);
+                    return;
+                }
+                Err(err) => {
+                    self.buffer.extend(run);
+                    self.buffer.push_back(AnnotatedEvent::new(
+                        ErrorEvent {
+                            title: "Invalid translation".into(),
+                            description: Some(format!("{}: {}", err, segment.msgid).into()),
+                            severity: Severity::Error,
+                        },
+                        location,
+                    ));
+                    return;
+                }
+            },
+        };
+
+        self.buffer.extend(run);
+        if let Some(title) = error {
+            self.buffer.push_back(AnnotatedEvent::new(
+                ErrorEvent {
+                    title: title.into(),
+                    description: Some(segment.msgid.into()),
+                    severity: Severity::Warning,
+                },
+                location,
+            ));
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for ApplyTranslationsIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(annotated_event) = self.buffer.pop_front() {
+                return Some(annotated_event);
+            }
+
+            match self.source.next() {
+                Some(annotated_event) => {
+                    if is_inline_event(&annotated_event.event) {
+                        self.run.push(annotated_event);
+                    } else {
+                        self.finish_run();
+                        self.buffer.push_back(annotated_event);
+                    }
+                }
+                None => {
+                    self.finish_run();
+                    return self.buffer.pop_front();
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_extract_translations() {
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(ExtractTranslations::default());
+    let events = pipeline
+        .process("# Hello *World*\n\nUse `a < b` or a<b\nhere.\n\n- Hello *World*\n- ![x](y.png)")
+        .collect::<Vec<_>>();
+    let messages = match events.last().map(|x| &x.event) {
+        Some(Event::MetaData(MetaDataEvent { value, .. })) => {
+            serde_json::from_value::<Vec<Message>>(value.clone()).unwrap()
+        }
+        _ => panic!("no translations emitted"),
+    };
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].msgid, "Hello <1>World</1>");
+    assert_eq!(messages[0].locations.len(), 2);
+    assert_eq!(messages[1].msgid, "Use <1/> or a\\<b\nhere.");
+    assert_eq!(
+        messages_to_pot(&messages[1..], "index.md"),
+        "#: index.md:3\nmsgid \"Use <1/> or a\\\\<b\\nhere.\"\nmsgstr \"\"\n"
+    );
+}
+
+#[test]
+fn test_apply_translations() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(ApplyTranslations {
+        catalog: vec![
+            (
+                "Hello <1>World</1>".to_string(),
+                Translation::Plain("<1>Welt</1>, hallo".into()),
+            ),
+            (
+                "Use <1/> or <2>this</2>".to_string(),
+                Translation::Plain("Nimm <2>das</2> oder <1/>".into()),
+            ),
+            (
+                "Old".to_string(),
+                Translation::Detailed {
+                    msgstr: "Alt".into(),
+                    fuzzy: true,
+                },
+            ),
+            ("Broken".to_string(), Translation::Plain("<1>Kaputt".into())),
+        ]
+        .into_iter()
+        .collect(),
+        report_missing: false,
+        ..Default::default()
+    });
+
+    let events = pipeline
+        .process("# Hello *World*\n\nUse `code` or [this](/x)\n\nOld\n\nBroken\n\nMissing")
+        .collect::<Vec<_>>();
+    let errors = events
+        .iter()
+        .filter_map(|x| match x.event {
+            Event::Error(ErrorEvent { ref title, .. }) => Some(title.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(errors, vec!["Stale translation", "Invalid translation"]);
+
+    let html = to_html(
+        events
+            .into_iter()
+            .filter(|x| !matches!(x.event, Event::Error(..))),
+        &Default::default(),
+    );
+    assert_eq!(
+        html,
+        "<h1><em>Welt</em>, hallo</h1>\n\
+         <p>Nimm <a href=\"&#x2f;x\">das</a> oder <code>code</code></p>\n\
+         <p>Old</p>\n<p>Broken</p>\n<p>Missing</p>\n"
+    );
+}
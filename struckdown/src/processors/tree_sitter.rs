@@ -0,0 +1,290 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tree_sitter::{Language, QueryError};
+use tree_sitter_highlight::{HighlightConfiguration, Highlighter, HtmlRenderer};
+use v_htmlescape::escape;
+
+use crate::error::{Error, ErrorKind};
+use crate::event::{AnnotatedEvent, CodeBlockEvent, Event, RawHtmlEvent};
+use crate::processors::BuiltinProcessor;
+
+/// The highlight names that are recognized in highlight queries.
+///
+/// Captures with other names are ignored.  More specific names such as
+/// `function.builtin` fall back to their prefix (`function`).
+pub const HIGHLIGHT_NAMES: &[&str] = &[
+    "attribute",
+    "comment",
+    "constant",
+    "constant.builtin",
+    "constructor",
+    "embedded",
+    "escape",
+    "function",
+    "function.builtin",
+    "function.macro",
+    "function.method",
+    "keyword",
+    "label",
+    "module",
+    "number",
+    "operator",
+    "property",
+    "punctuation",
+    "punctuation.bracket",
+    "punctuation.delimiter",
+    "punctuation.special",
+    "string",
+    "string.special",
+    "tag",
+    "type",
+    "type.builtin",
+    "variable",
+    "variable.builtin",
+    "variable.parameter",
+];
+
+/// A tree-sitter grammar together with its queries.
+#[derive(Clone)]
+pub struct TreeSitterLanguage(Arc<HighlightConfiguration>);
+
+impl TreeSitterLanguage {
+    /// Creates a language from a grammar and its queries.
+    ///
+    /// The queries are usually shipped with the grammar crate as
+    /// `HIGHLIGHT_QUERY`, `INJECTIONS_QUERY` and `LOCALS_QUERY`.  The latter
+    /// two can be empty.
+    pub fn new(
+        language: Language,
+        highlights_query: &str,
+        injections_query: &str,
+        locals_query: &str,
+    ) -> Result<TreeSitterLanguage, QueryError> {
+        let mut config = HighlightConfiguration::new(
+            language,
+            highlights_query,
+            injections_query,
+            locals_query,
+        )?;
+        config.configure(HIGHLIGHT_NAMES);
+        Ok(TreeSitterLanguage(Arc::new(config)))
+    }
+}
+
+impl fmt::Debug for TreeSitterLanguage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TreeSitterLanguage").finish()
+    }
+}
+
+/// Implements syntax highlighting via [`tree_sitter_highlight`].
+///
+/// Highlighted tokens are wrapped in spans with classes derived from the
+/// highlight name (`function.builtin` becomes `ts-function ts-function-builtin`
+/// with the default prefix).  Code blocks in languages without a registered
+/// grammar are passed through unchanged so that another highlighter such as
+/// `Syntect` can handle them.
+///
+/// Grammars are native code and can only be registered from code.  A
+/// highlighter without languages would not do anything and fails the
+/// pipeline's checks (eg: when configured from a config file).
+///
+/// When applied this wraps the stream in a [`TreeSitterHighlightIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct TreeSitterHighlight {
+    /// The prefix for the emitted CSS classes.
+    pub class_prefix: String,
+    /// Maps language aliases to the languages they should be highlighted as.
+    pub aliases: BTreeMap<String, String>,
+    /// The registered languages by name.
+    #[serde(skip)]
+    pub languages: BTreeMap<String, TreeSitterLanguage>,
+}
+
+impl Default for TreeSitterHighlight {
+    fn default() -> TreeSitterHighlight {
+        TreeSitterHighlight {
+            class_prefix: "ts-".into(),
+            aliases: BTreeMap::new(),
+            languages: BTreeMap::new(),
+        }
+    }
+}
+
+impl TreeSitterHighlight {
+    /// Registers a language under a name.
+    pub fn add_language<S: Into<String>>(&mut self, name: S, language: TreeSitterLanguage) {
+        self.languages.insert(name.into(), language);
+    }
+
+    fn find_language(&self, name: &str) -> Option<&HighlightConfiguration> {
+        let name = self.aliases.get(name).map_or(name, |x| x.as_str());
+        self.languages.get(name).map(|x| &*x.0)
+    }
+
+    fn class_attributes(&self) -> Vec<String> {
+        HIGHLIGHT_NAMES
+            .iter()
+            .map(|name| {
+                let mut classes = Vec::new();
+                let mut end = 0;
+                for piece in name.split('.') {
+                    end += piece.len();
+                    classes.push(format!(
+                        "{}{}",
+                        self.class_prefix,
+                        name[..end].replace('.', "-")
+                    ));
+                    end += 1;
+                }
+                format!("class=\"{}\"", escape(&classes.join(" ")))
+            })
+            .collect()
+    }
+
+    /// Verifies that languages are registered.
+    fn check(&self) -> Result<(), Error> {
+        if self.languages.is_empty() {
+            return Err(Error::new(
+                ErrorKind::ProcessorFailed,
+                "tree-sitter highlighter has no languages (they can only be registered from code)",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the configuration unless options only settable from code are used.
+    fn builtin_config(&self) -> Option<BuiltinProcessor> {
+        if !self.languages.is_empty() {
+            return None;
+        }
+        Some(self.clone().into())
+    }
+}
+
+implement_processor!(
+    TreeSitterHighlight,
+    TreeSitterHighlightIter,
+    "Highlights code blocks with tree-sitter",
+    check = TreeSitterHighlight::check,
+    to_builtin = TreeSitterHighlight::builtin_config,
+);
+
+/// The iterator implementing [`TreeSitterHighlight`].
+pub struct TreeSitterHighlightIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    highlighter: Highlighter,
+    class_attributes: Vec<String>,
+    options: Cow<'options, TreeSitterHighlight>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    TreeSitterHighlightIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, TreeSitterHighlight>>>(iterator: I, options: O) -> Self {
+        let options = options.into();
+        Self {
+            source: iterator,
+            highlighter: Highlighter::new(),
+            class_attributes: options.class_attributes(),
+            options,
+        }
+    }
+
+    fn highlight(&mut self, language: &str, code: &str) -> Option<String> {
+        let options = &self.options;
+        let config = options.find_language(language)?;
+        let events = self
+            .highlighter
+            .highlight(config, code.as_bytes(), None, |name| {
+                options.find_language(name)
+            })
+            .ok()?;
+        let class_attributes = &self.class_attributes;
+        let mut renderer = HtmlRenderer::new();
+        renderer
+            .render(events, code.as_bytes(), &|highlight| {
+                class_attributes[highlight.0].as_bytes()
+            })
+            .ok()?;
+
+        let mut rv = format!("<pre><code class=\"lang-{}\">", escape(language));
+        for line in renderer.lines() {
+            rv.push_str(line);
+        }
+        rv.push_str("</code></pre>");
+        Some(rv)
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for TreeSitterHighlightIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let annotated_event = self.source.next()?;
+        if let Event::CodeBlock(CodeBlockEvent {
+            language: Some(ref language),
+            ref code,
+            ..
+        }) = annotated_event.event
+        {
+            if let Some(html) = self.highlight(language.as_str(), code.as_str()) {
+                return Some(AnnotatedEvent::new(
+                    RawHtmlEvent { html: html.into() },
+                    annotated_event.location,
+                ));
+            }
+        }
+        Some(annotated_event)
+    }
+}
+
+#[test]
+fn test_class_attributes() {
+    let options = TreeSitterHighlight::default();
+    let attrs = options.class_attributes();
+    let idx = HIGHLIGHT_NAMES
+        .iter()
+        .position(|&x| x == "function.builtin")
+        .unwrap();
+    assert_eq!(attrs[idx], "class=\"ts-function ts-function-builtin\"");
+}
+
+#[test]
+fn test_highlight_rust() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut options = TreeSitterHighlight::default();
+    assert!(options.check().is_err());
+    options.add_language(
+        "rust",
+        TreeSitterLanguage::new(
+            tree_sitter_rust::language(),
+            tree_sitter_rust::HIGHLIGHT_QUERY,
+            tree_sitter_rust::INJECTIONS_QUERY,
+            "",
+        )
+        .unwrap(),
+    );
+    options.aliases.insert("rs".into(), "rust".into());
+    assert!(options.check().is_ok());
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(options);
+    let html = to_html(
+        pipeline.process("```rs\nfn main() {}\n```\n\n```python\npass\n```"),
+        &Default::default(),
+    );
+    assert!(html.contains("<pre><code class=\"lang-rs\"><span class=\"ts-keyword\">fn</span>"));
+    assert!(html.contains("<span class=\"ts-function\">main</span>"));
+    // languages without a grammar are left alone
+    assert!(html.contains("<code class=\"lang-python\">pass\n</code>"));
+}
@@ -0,0 +1,276 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Event, StartTagEvent, Str, Tag};
+use crate::plain::to_plain_text;
+
+/// The strategy used by [`UniqueAnchors`] to generate suffixes.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SuffixStrategy {
+    /// Appends an increasing number (`-1`, `-2`, ...).
+    Numeric,
+    /// Appends a short hash of the element's text content.
+    Hash,
+}
+
+/// Makes all `id`s in a document unique.
+///
+/// The first element with a given `id` keeps it, all further elements get a
+/// suffix attached.  Internal links (`#id`) keep pointing at the first
+/// element as that is the only unambiguous target.  For documents that link
+/// to every element in order (such as a table of contents) this can be
+/// changed with [`match_links_in_order`](Self::match_links_in_order).
+///
+/// When applied this wraps the stream in a [`UniqueAnchorsIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct UniqueAnchors {
+    /// The strategy for generating suffixes.
+    pub strategy: SuffixStrategy,
+    /// The separator between the original `id` and the suffix.
+    pub separator: String,
+    /// Matches internal fragment links up with the elements in order.
+    ///
+    /// This only applies to `id`s with exactly as many links as elements
+    /// carrying them: the n-th link then points to the n-th element.  This
+    /// is off by default as it guesses which element a link meant.
+    pub match_links_in_order: bool,
+}
+
+impl Default for UniqueAnchors {
+    fn default() -> UniqueAnchors {
+        UniqueAnchors {
+            strategy: SuffixStrategy::Numeric,
+            separator: "-".into(),
+            match_links_in_order: false,
+        }
+    }
+}
+
+implement_processor!(
+    UniqueAnchors,
+    UniqueAnchorsIter,
+    "Makes all ids in the document unique"
+);
+
+/// The iterator implementing [`UniqueAnchors`].
+pub struct UniqueAnchorsIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source_iter: Option<I>,
+    processed_iter: std::vec::IntoIter<AnnotatedEvent<'data>>,
+    options: Cow<'options, UniqueAnchors>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    UniqueAnchorsIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, UniqueAnchors>>>(iterator: I, options: O) -> Self {
+        Self {
+            source_iter: Some(iterator),
+            processed_iter: Vec::new().into_iter(),
+            options: options.into(),
+        }
+    }
+}
+
+/// A small stable hash (FNV-1a) so that anchors do not change between builds.
+fn short_hash(value: &str) -> String {
+    let mut hash: u32 = 0x811c_9dc5;
+    for byte in value.bytes() {
+        hash ^= u32::from(byte);
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    format!("{:08x}", hash)
+}
+
+/// Returns the plain text contents of the element starting at `idx`.
+fn element_text(buffer: &[AnnotatedEvent<'_>], idx: usize) -> String {
+    let mut depth = 0;
+    let mut end = idx;
+    for (offset, annotated_event) in buffer[idx..].iter().enumerate() {
+        match annotated_event.event {
+            Event::StartTag(..) => depth += 1,
+            Event::EndTag(..) => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            end = idx + offset;
+            break;
+        }
+    }
+    to_plain_text(buffer[idx..end].iter()).as_str().to_string()
+}
+
+fn make_unique(
+    options: &UniqueAnchors,
+    id: &str,
+    counter: usize,
+    text: &str,
+    taken: &HashSet<String>,
+) -> String {
+    match options.strategy {
+        SuffixStrategy::Numeric => {
+            let mut counter = counter;
+            loop {
+                let candidate = format!("{}{}{}", id, options.separator, counter);
+                if !taken.contains(&candidate) {
+                    return candidate;
+                }
+                counter += 1;
+            }
+        }
+        SuffixStrategy::Hash => {
+            let base = format!("{}{}{}", id, options.separator, short_hash(text));
+            let mut candidate = base.clone();
+            let mut extra = 1;
+            while taken.contains(&candidate) {
+                candidate = format!("{}{}{}", base, options.separator, extra);
+                extra += 1;
+            }
+            candidate
+        }
+    }
+}
+
+fn dedup_anchors(buffer: &mut [AnnotatedEvent<'_>], options: &UniqueAnchors) {
+    let mut taken = HashSet::new();
+    for annotated_event in buffer.iter() {
+        if let Event::StartTag(StartTagEvent { ref attrs, .. }) = annotated_event.event {
+            if let Some(ref id) = attrs.id {
+                taken.insert(id.as_str().to_string());
+            }
+        }
+    }
+
+    // maps original ids to the ids of all elements that carried it in order.
+    let mut occurrences = BTreeMap::<String, Vec<String>>::new();
+    for idx in 0..buffer.len() {
+        let id = match buffer[idx].event {
+            Event::StartTag(StartTagEvent { ref attrs, .. }) => match attrs.id {
+                Some(ref id) => id.as_str().to_string(),
+                None => continue,
+            },
+            _ => continue,
+        };
+        let ids = occurrences.entry(id.clone()).or_default();
+        if ids.is_empty() {
+            ids.push(id);
+            continue;
+        }
+        let text = if options.strategy == SuffixStrategy::Hash {
+            element_text(buffer, idx)
+        } else {
+            String::new()
+        };
+        let new_id = make_unique(options, &id, ids.len(), &text, &taken);
+        taken.insert(new_id.clone());
+        ids.push(new_id.clone());
+        if let Event::StartTag(StartTagEvent { ref mut attrs, .. }) = buffer[idx].event {
+            attrs.id = Some(Str::from(new_id));
+        }
+    }
+
+    if !options.match_links_in_order {
+        return;
+    }
+
+    let mut link_counts = BTreeMap::<String, usize>::new();
+    for annotated_event in buffer.iter() {
+        if let Some(fragment) = link_fragment(annotated_event) {
+            *link_counts.entry(fragment.to_string()).or_insert(0) += 1;
+        }
+    }
+
+    let mut link_indexes = BTreeMap::<String, usize>::new();
+    for annotated_event in buffer.iter_mut() {
+        let fragment = match link_fragment(annotated_event) {
+            Some(fragment) => fragment.to_string(),
+            None => continue,
+        };
+        let ids = match occurrences.get(&fragment) {
+            Some(ids) if ids.len() > 1 && link_counts.get(&fragment) == Some(&ids.len()) => ids,
+            _ => continue,
+        };
+        let index = link_indexes.entry(fragment).or_insert(0);
+        if let Event::StartTag(StartTagEvent { ref mut attrs, .. }) = annotated_event.event {
+            attrs.target = Some(format!("#{}", ids[*index]).into());
+        }
+        *index += 1;
+    }
+}
+
+fn link_fragment<'a>(annotated_event: &'a AnnotatedEvent<'_>) -> Option<&'a str> {
+    match annotated_event.event {
+        Event::StartTag(StartTagEvent {
+            tag: Tag::Link,
+            ref attrs,
+        }) => attrs
+            .target
+            .as_ref()
+            .and_then(|target| target.as_str().strip_prefix('#')),
+        _ => None,
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for UniqueAnchorsIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(source_iter) = self.source_iter.take() {
+            let mut buffer = source_iter.collect::<Vec<_>>();
+            dedup_anchors(&mut buffer, &self.options);
+            self.processed_iter = buffer.into_iter();
+        }
+        self.processed_iter.next()
+    }
+}
+
+#[test]
+fn test_unique_anchors() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(UniqueAnchors::default());
+    assert_eq!(
+        to_html(
+            pipeline.process("[a](#x) [b](#x)\n\n# A {#x}\n\n# B {#x}\n\n# C {#x-1}"),
+            &Default::default()
+        ),
+        "<p><a href=\"#x\">a</a> <a href=\"#x\">b</a></p>\n\
+         <h1 id=\"x\">A</h1>\n<h1 id=\"x-2\">B</h1>\n<h1 id=\"x-1\">C</h1>\n"
+    );
+}
+
+#[test]
+fn test_unique_anchors_match_links_in_order() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(UniqueAnchors {
+        match_links_in_order: true,
+        ..Default::default()
+    });
+    assert_eq!(
+        to_html(
+            pipeline.process("[a](#x) [b](#x)\n\n# A {#x}\n\n# B {#x}"),
+            &Default::default()
+        ),
+        "<p><a href=\"#x\">a</a> <a href=\"#x-1\">b</a></p>\n\
+         <h1 id=\"x\">A</h1>\n<h1 id=\"x-1\">B</h1>\n"
+    );
+
+    // with a different number of links they stay at the first element
+    assert_eq!(
+        to_html(
+            pipeline.process("[a](#x)\n\n# A {#x}\n\n# B {#x}"),
+            &Default::default()
+        ),
+        "<p><a href=\"#x\">a</a></p>\n<h1 id=\"x\">A</h1>\n<h1 id=\"x-1\">B</h1>\n"
+    );
+}
@@ -1,5 +1,76 @@
+use serde::Serialize;
+
+use crate::value::{to_value, Value};
+
+/// Static information about builtin processors.
+pub(crate) trait BuiltinInfo {
+    /// A short description of the processor.
+    const DESCRIPTION: &'static str;
+
+    /// Returns the JSON Schema of the options.
+    fn options_schema() -> Value;
+}
+
+/// Derives a JSON Schema from the default options of a processor.
+///
+/// Every option is listed with the type and value of its default.  Options
+/// that default to `null` accept any value.
+pub(crate) fn default_options_schema<T: Default + Serialize>() -> Value {
+    let mut properties = crate::value::Map::new();
+    if let Ok(Value::Object(defaults)) = to_value(T::default()) {
+        for (key, default) in defaults {
+            let ty = match default {
+                Value::Null => None,
+                Value::Bool(_) => Some("boolean"),
+                Value::Number(ref num) if num.is_f64() => Some("number"),
+                Value::Number(_) => Some("integer"),
+                Value::String(_) => Some("string"),
+                Value::Array(_) => Some("array"),
+                Value::Object(_) => Some("object"),
+            };
+            let mut property = crate::value::Map::new();
+            if let Some(ty) = ty {
+                property.insert("type".into(), ty.into());
+            }
+            property.insert("default".into(), default);
+            properties.insert(key, Value::Object(property));
+        }
+    }
+    crate::value::value!({
+        "type": "object",
+        "properties": properties,
+    })
+}
+
+/// Used as `to_builtin` for processors that cannot be configured.
+///
+/// These processors are not part of [`BuiltinProcessor`] as their options
+/// cannot be represented in a configuration.
+///
+/// [`BuiltinProcessor`]: crate::processors::BuiltinProcessor
+pub(crate) fn not_builtin<T>(_processor: &T) -> Option<crate::processors::BuiltinProcessor> {
+    None
+}
+
 macro_rules! implement_processor {
-    ($type:ty, $iter:tt) => {
+    (
+        $type:ty,
+        $iter:tt,
+        $description:expr
+        $(, check = $check:path)?
+        $(, schema = $schema:path)?
+        $(, to_builtin = $to_builtin:path)?
+        $(, with_path = $with_path:path)?
+        $(,)?
+    ) => {
+        impl $crate::processors::utils::BuiltinInfo for $type {
+            const DESCRIPTION: &'static str = $description;
+
+            fn options_schema() -> $crate::value::Value {
+                implement_processor!(@schema $type $(, $schema)?)
+            }
+        }
+
         impl $crate::processors::Processor for $type {
             fn apply<'data>(
                 self: Box<Self>,
@@ -14,6 +85,57 @@ macro_rules! implement_processor {
             ) -> Box<dyn Iterator<Item = $crate::event::AnnotatedEvent<'data>> + 'data> {
                 Box::new($iter::new(iter, std::borrow::Cow::Borrowed(self)))
             }
+
+            $(
+                fn apply_ref_with_path<'data, 'options: 'data>(
+                    &'options self,
+                    iter: Box<dyn Iterator<Item = $crate::event::AnnotatedEvent<'data>> + 'data>,
+                    path: Option<&'data std::path::Path>,
+                ) -> Box<dyn Iterator<Item = $crate::event::AnnotatedEvent<'data>> + 'data> {
+                    Box::new($with_path(self, iter, path))
+                }
+            )?
+
+            fn description(&self) -> &'static str {
+                <Self as $crate::processors::utils::BuiltinInfo>::DESCRIPTION
+            }
+
+            fn options_schema(&self) -> Option<$crate::value::Value> {
+                Some(<Self as $crate::processors::utils::BuiltinInfo>::options_schema())
+            }
+
+            $(
+                fn check(&self) -> Result<(), $crate::error::Error> {
+                    $check(self)
+                }
+            )?
+
+            fn to_builtin(&self) -> Option<$crate::processors::BuiltinProcessor> {
+                implement_processor!(@to_builtin self $(, $to_builtin)?)
+            }
         }
+
+        impl<'data, 'options, I> $crate::processors::StaticProcessor<'data, 'options, I> for $type
+        where
+            I: Iterator<Item = $crate::event::AnnotatedEvent<'data>>,
+        {
+            type Iter = $iter<'data, 'options, I>;
+
+            fn apply_static(&'options self, iter: I) -> Self::Iter {
+                $iter::new(iter, std::borrow::Cow::Borrowed(self))
+            }
+        }
+    };
+    (@schema $type:ty) => {
+        $crate::processors::utils::default_options_schema::<$type>()
+    };
+    (@schema $type:ty, $schema:path) => {
+        $schema()
+    };
+    (@to_builtin $self:ident) => {
+        Some($self.clone().into())
+    };
+    (@to_builtin $self:ident, $to_builtin:path) => {
+        $to_builtin($self)
     };
 }
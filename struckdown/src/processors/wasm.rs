@@ -0,0 +1,414 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use wasmtime::{Config, Engine, Error, Instance, Memory, Module, Store, TypedFunc};
+
+use crate::error::ErrorKind;
+use crate::event::{AnnotatedEvent, ErrorEvent, Severity};
+use crate::value::{value, Value};
+
+/// Passes the stream through a WebAssembly module.
+///
+/// The module is instantiated without any imports and has to export the
+/// following:
+///
+/// * `memory`: the linear memory used to exchange events
+/// * `struckdown_alloc(len: i32) -> i32`: allocates `len` bytes and returns
+///   a pointer to them
+/// * `struckdown_process(ptr: i32, len: i32) -> i64`: is invoked with a
+///   JSON serialized event for every event in the stream.  The input buffer
+///   is owned by the module after the call.  It returns a JSON array of
+///   events to emit as `ptr << 32 | len`.
+/// * `struckdown_finish() -> i64` (optional): is invoked at the end of the
+///   stream and returns a JSON array of further events to emit.
+///
+/// If the module fails an error event is emitted and the stream ends.
+///
+/// The module is compiled once on first use and then instantiated for
+/// every stream.  Clones of the processor share the compiled module.
+///
+/// When applied this wraps the stream in a [`WasmProcessorIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WasmProcessor {
+    /// The path to the `.wasm` module.
+    pub path: PathBuf,
+    /// Limits the amount of fuel (roughly instructions) the module may
+    /// consume over the entire stream.
+    pub fuel: Option<u64>,
+    #[serde(skip)]
+    compiled: ModuleCache,
+}
+
+/// A module compiled for the options it was compiled with.
+#[derive(Clone)]
+struct CompiledModule {
+    path: PathBuf,
+    fuel: bool,
+    engine: Engine,
+    module: Module,
+}
+
+/// Holds the compiled module of a [`WasmProcessor`].
+#[derive(Clone, Default)]
+struct ModuleCache(Arc<Mutex<Option<CompiledModule>>>);
+
+impl fmt::Debug for ModuleCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ModuleCache").finish()
+    }
+}
+
+impl WasmProcessor {
+    /// Creates a processor for the module at the given path.
+    pub fn new<P: Into<PathBuf>>(path: P) -> WasmProcessor {
+        WasmProcessor {
+            path: path.into(),
+            fuel: None,
+            compiled: ModuleCache::default(),
+        }
+    }
+
+    /// Returns the compiled module compiling it if necessary.
+    fn compile(&self) -> Result<CompiledModule, Error> {
+        let mut cache = self.compiled.0.lock().unwrap();
+        if let Some(ref compiled) = *cache {
+            if compiled.path == self.path && compiled.fuel == self.fuel.is_some() {
+                return Ok(compiled.clone());
+            }
+        }
+        let mut config = Config::new();
+        config.consume_fuel(self.fuel.is_some());
+        let engine = Engine::new(&config)?;
+        let module = Module::from_file(&engine, &self.path)?;
+        let compiled = CompiledModule {
+            path: self.path.clone(),
+            fuel: self.fuel.is_some(),
+            engine,
+            module,
+        };
+        *cache = Some(compiled.clone());
+        Ok(compiled)
+    }
+
+    /// Verifies that the module exists and compiles.
+    fn check(&self) -> Result<(), crate::error::Error> {
+        self.compile().map(|_| ()).map_err(|err| {
+            crate::error::Error::new(
+                ErrorKind::ProcessorFailed,
+                format!(
+                    "failed to compile wasm module {}: {:#}",
+                    self.path.display(),
+                    err
+                ),
+            )
+        })
+    }
+
+    fn options_schema() -> Value {
+        value!({
+            "type": "object",
+            "properties": {
+                "path": {"type": "string"},
+                "fuel": {"type": "integer"},
+            },
+            "required": ["path"],
+        })
+    }
+}
+
+implement_processor!(
+    WasmProcessor,
+    WasmProcessorIter,
+    "Passes the stream through a WebAssembly module",
+    check = WasmProcessor::check,
+    schema = WasmProcessor::options_schema,
+);
+
+struct Plugin {
+    store: Store<()>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    process: TypedFunc<(i32, i32), i64>,
+    finish: Option<TypedFunc<(), i64>>,
+}
+
+impl Plugin {
+    fn load(options: &WasmProcessor) -> Result<Plugin, Error> {
+        let compiled = options.compile()?;
+        let mut store = Store::new(&compiled.engine, ());
+        if let Some(fuel) = options.fuel {
+            store.add_fuel(fuel)?;
+        }
+        let instance = Instance::new(&mut store, &compiled.module, &[])?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| Error::msg("module does not export memory"))?;
+        Ok(Plugin {
+            alloc: instance.get_typed_func(&mut store, "struckdown_alloc")?,
+            process: instance.get_typed_func(&mut store, "struckdown_process")?,
+            finish: instance
+                .get_typed_func(&mut store, "struckdown_finish")
+                .ok(),
+            store,
+            memory,
+        })
+    }
+
+    fn read_events(&mut self, packed: i64) -> Result<Vec<AnnotatedEvent<'static>>, Error> {
+        let ptr = (packed as u64 >> 32) as usize;
+        let len = (packed as u64 & 0xffff_ffff) as usize;
+        let mut buf = vec![0; len];
+        self.memory.read(&self.store, ptr, &mut buf)?;
+        Ok(serde_json::from_slice(&buf)?)
+    }
+
+    fn process(&mut self, event: &AnnotatedEvent) -> Result<Vec<AnnotatedEvent<'static>>, Error> {
+        let input = serde_json::to_vec(event)?;
+        let ptr = self.alloc.call(&mut self.store, input.len() as i32)?;
+        self.memory.write(&mut self.store, ptr as usize, &input)?;
+        let packed = self
+            .process
+            .call(&mut self.store, (ptr, input.len() as i32))?;
+        self.read_events(packed)
+    }
+
+    fn finish(&mut self) -> Result<Vec<AnnotatedEvent<'static>>, Error> {
+        match self.finish {
+            Some(ref finish) => {
+                let packed = finish.call(&mut self.store, ())?;
+                self.read_events(packed)
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+/// The iterator implementing [`WasmProcessor`].
+pub struct WasmProcessorIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    plugin: Option<Plugin>,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    started: bool,
+    options: Cow<'options, WasmProcessor>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    WasmProcessorIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, WasmProcessor>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            plugin: None,
+            buffer: VecDeque::new(),
+            started: false,
+            options: options.into(),
+        }
+    }
+
+    fn fail(&mut self, err: Error) -> AnnotatedEvent<'data> {
+        self.plugin = None;
+        ErrorEvent {
+            title: format!(
+                "Failed to execute wasm processor '{}'",
+                self.options.path.display()
+            )
+            .into(),
+            // the alternate format includes the cause such as the trap code
+            description: Some(format!("{:#}", err).into()),
+            severity: Severity::Error,
+        }
+        .into()
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for WasmProcessorIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            match Plugin::load(&self.options) {
+                Ok(plugin) => self.plugin = Some(plugin),
+                Err(err) => return Some(self.fail(err)),
+            }
+        }
+
+        loop {
+            if let Some(event) = self.buffer.pop_front() {
+                return Some(event);
+            }
+            let plugin = self.plugin.as_mut()?;
+            let result = match self.source.next() {
+                Some(event) => plugin.process(&event),
+                None => {
+                    let result = plugin.finish();
+                    self.plugin = None;
+                    result
+                }
+            };
+            match result {
+                Ok(events) => self.buffer.extend(events),
+                Err(err) => return Some(self.fail(err)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::Event;
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    /// Echoes every event wrapped in an array and emits `done` at the end.
+    const ECHO_MODULE: &str = r#"(module
+        (memory (export "memory") 1)
+        (data (i32.const 0) "[{\22type\22:\22text\22,\22text\22:\22done\22}]")
+        (global $heap (mut i32) (i32.const 1024))
+        (func (export "struckdown_alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $heap))
+            (global.set $heap (i32.add (global.get $heap) (local.get $len)))
+            (local.get $ptr))
+        (func (export "struckdown_process") (param $ptr i32) (param $len i32) (result i64)
+            (local $out i32)
+            (local.set $out (global.get $heap))
+            (global.set $heap (i32.add (global.get $heap) (i32.add (local.get $len) (i32.const 2))))
+            (i32.store8 (local.get $out) (i32.const 91))
+            (memory.copy (i32.add (local.get $out) (i32.const 1)) (local.get $ptr) (local.get $len))
+            (i32.store8 (i32.add (i32.add (local.get $out) (i32.const 1)) (local.get $len)) (i32.const 93))
+            (i64.or
+                (i64.shl (i64.extend_i32_u (local.get $out)) (i64.const 32))
+                (i64.extend_i32_u (i32.add (local.get $len) (i32.const 2)))))
+        (func (export "struckdown_finish") (result i64)
+            (i64.const 31))
+    )"#;
+
+    /// Traps on the first event.
+    const TRAP_MODULE: &str = r#"(module
+        (memory (export "memory") 1)
+        (func (export "struckdown_alloc") (param $len i32) (result i32)
+            (i32.const 0))
+        (func (export "struckdown_process") (param $ptr i32) (param $len i32) (result i64)
+            unreachable)
+    )"#;
+
+    /// Never returns from processing an event.
+    const LOOP_MODULE: &str = r#"(module
+        (memory (export "memory") 1)
+        (func (export "struckdown_alloc") (param $len i32) (result i32)
+            (i32.const 0))
+        (func (export "struckdown_process") (param $ptr i32) (param $len i32) (result i64)
+            (loop $forever (br $forever))
+            (i64.const 0))
+    )"#;
+
+    fn write_module(name: &str, source: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "struckdown-wasm-{}-{}.wat",
+            name,
+            std::process::id()
+        ));
+        std::fs::write(&path, source).unwrap();
+        path
+    }
+
+    fn run(processor: WasmProcessor, source: &str) -> Vec<AnnotatedEvent<'static>> {
+        let mut pipeline = Pipeline::new();
+        pipeline.add_processor(processor);
+        pipeline
+            .process(source)
+            .map(AnnotatedEvent::into_static)
+            .collect()
+    }
+
+    fn errors(events: &[AnnotatedEvent<'_>]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|x| match x.event {
+                Event::Error(ref err) => Some(format!(
+                    "{}: {}",
+                    err.title.as_str(),
+                    err.description.as_ref().map_or("", |x| x.as_str())
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_process_and_finish() {
+        let processor = WasmProcessor::new(write_module("echo", ECHO_MODULE));
+        let events = run(processor.clone(), "Hello *World*");
+        assert!(errors(&events).is_empty());
+        assert_eq!(
+            to_html(events.into_iter(), &Default::default()),
+            "<p>Hello <em>World</em></p>\ndone"
+        );
+
+        // clones share the compiled module
+        assert!(processor.compiled.0.lock().unwrap().is_some());
+        std::fs::remove_file(&processor.path).unwrap();
+        let events = run(processor, "Again");
+        assert!(errors(&events).is_empty());
+        assert_eq!(
+            to_html(events.into_iter(), &Default::default()),
+            "<p>Again</p>\ndone"
+        );
+    }
+
+    #[test]
+    fn test_trap() {
+        let path = write_module("trap", TRAP_MODULE);
+        let events = run(WasmProcessor::new(&path), "Hello");
+        std::fs::remove_file(&path).unwrap();
+        let errors = errors(&events);
+        assert_eq!(events.len(), 1);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("Failed to execute wasm processor"));
+        assert!(errors[0].contains("unreachable"));
+    }
+
+    #[test]
+    fn test_out_of_fuel() {
+        let path = write_module("loop", LOOP_MODULE);
+        let mut processor = WasmProcessor::new(&path);
+        processor.fuel = Some(10_000);
+        let events = run(processor, "Hello");
+        std::fs::remove_file(&path).unwrap();
+        let errors = errors(&events);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("fuel"), "{}", errors[0]);
+    }
+
+    #[test]
+    fn test_missing_module() {
+        let events = run(WasmProcessor::new("/missing/module.wasm"), "Hello");
+        assert_eq!(errors(&events).len(), 1);
+    }
+
+    #[test]
+    fn test_check() {
+        let err = WasmProcessor::new("/missing/module.wasm")
+            .check()
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ProcessorFailed);
+
+        let path = write_module("invalid", "(module (func (export");
+        let result = WasmProcessor::new(&path).check();
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+
+        let path = write_module("check", ECHO_MODULE);
+        let result = WasmProcessor::new(&path).check();
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_ok());
+    }
+}
@@ -0,0 +1,802 @@
+//! Processes multiple documents that reference each other.
+//!
+//! A [`Pipeline`] only ever sees a single document.  A [`Project`] runs all
+//! documents of a project through a shared pipeline and then collects state
+//! across all documents in a [`ProjectState`]: the anchors of every document,
+//! the [`LinkGraph`] and a glossary of defined terms.  In a second pass this
+//! state is used to resolve references between documents:
+//!
+//! * interpreted text with the `ref` role (`` {ref}`setup` ``) links to the
+//!   anchor with that name in any document of the project.
+//! * interpreted text with the `term` role (`` {term}`Event` ``) links to the
+//!   definition of that term in a definition list.
+//! * links to missing anchors in documents of the project are reported.
+//! * the documents linking to a document are emitted as meta data.
+//! * if an [`OutputPathFn`] is configured, links to documents of the project
+//!   are rewritten to point to the output of these documents.
+//!
+//! ```
+//! use struckdown::pipeline::Pipeline;
+//! use struckdown::project::Project;
+//!
+//! let mut project = Project::new(Pipeline::default());
+//! project.add_document("index.md", "See {ref}`setup`.");
+//! project.add_document("guide.md", "# Setup {#setup}");
+//! let output = project.build();
+//! ```
+//!
+//! For repeated builds (eg: in watch mode) [`Project::build_incremental`]
+//! keeps the output of the pipeline in a [`BuildCache`] and only runs
+//! documents through the pipeline again if they or the pipeline changed.
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{
+    AnnotatedEvent, Attrs, DocumentStartEvent, ErrorEvent, Event, InterpretedTextEvent,
+    MetaDataEvent, Severity, StartTagEvent, Tag, TextEvent,
+};
+use crate::hash::hash_json;
+use crate::link_graph::{resolve_link, LinkGraph, LinkTarget};
+use crate::pipeline::Pipeline;
+use crate::plain::to_plain_text;
+use crate::value::to_value;
+
+/// Maps the path of a document to the path it is rendered to.
+#[derive(Clone)]
+pub struct OutputPathFn(Arc<dyn Fn(&str) -> String + Send + Sync>);
+
+impl OutputPathFn {
+    /// Wraps a function for use as output path callback.
+    pub fn new<F: Fn(&str) -> String + Send + Sync + 'static>(f: F) -> OutputPathFn {
+        OutputPathFn(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for OutputPathFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OutputPathFn").finish()
+    }
+}
+
+/// Configures the cross document pass of a [`Project`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ProjectOptions {
+    /// The role that references anchors.
+    pub ref_role: String,
+    /// The role that references glossary terms.
+    pub term_role: String,
+    /// The meta data key for backlinks.  If not set no backlinks are emitted.
+    pub backlinks_key: Option<String>,
+    /// Reports links to anchors that do not exist in documents of the project.
+    pub check_links: bool,
+    /// Maps document paths to the paths of the rendered documents (eg:
+    /// `guide.md` to `guide.html`).  If set, references and links to
+    /// documents of the project point to the rendered documents instead of
+    /// the sources.  This can only be set from code.
+    #[serde(skip)]
+    pub output_path: Option<OutputPathFn>,
+}
+
+impl Default for ProjectOptions {
+    fn default() -> ProjectOptions {
+        ProjectOptions {
+            ref_role: "ref".into(),
+            term_role: "term".into(),
+            backlinks_key: Some("backlinks".into()),
+            check_links: true,
+            output_path: None,
+        }
+    }
+}
+
+/// An anchor within a document of a project.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Anchor {
+    /// The path of the document.
+    pub document: String,
+    /// The id of the anchor.
+    pub anchor: String,
+    /// The text of the element if it is a heading or term.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// State collected across all documents of a project.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ProjectState {
+    /// The anchors of all documents by document path and id.
+    pub anchors: BTreeMap<String, BTreeMap<String, Anchor>>,
+    /// The links between documents.
+    pub links: LinkGraph,
+    /// Defined terms by lowercased term.
+    pub glossary: BTreeMap<String, Anchor>,
+}
+
+impl ProjectState {
+    /// Records the anchors, links and terms of a document.
+    pub fn add_document(&mut self, path: &str, events: &[AnnotatedEvent<'_>]) {
+        self.links.add_document(path, events.iter());
+        let anchors = self.anchors.entry(path.to_string()).or_default();
+        anchors.clear();
+        for (idx, annotated_event) in events.iter().enumerate() {
+            let (tag, attrs) = match annotated_event.event {
+                Event::StartTag(StartTagEvent { tag, ref attrs }) => (tag, attrs),
+                _ => continue,
+            };
+            let titled = tag.header_level().is_some() || tag == Tag::DefinitionTerm;
+            let title = if titled {
+                let text = events[idx + 1..]
+                    .iter()
+                    .take_while(|x| !matches!(x.event, Event::EndTag(ref end) if end.tag == tag));
+                Some(to_plain_text(text).as_str().trim().to_string())
+            } else {
+                None
+            };
+            let anchor = attrs.id.as_ref().map(|id| Anchor {
+                document: path.to_string(),
+                anchor: id.as_str().to_string(),
+                title: title.clone(),
+            });
+            if let (Tag::DefinitionTerm, Some(title)) = (tag, title) {
+                self.glossary.insert(
+                    title.to_lowercase(),
+                    Anchor {
+                        document: path.to_string(),
+                        anchor: attrs
+                            .id
+                            .as_ref()
+                            .map(|x| x.as_str().to_string())
+                            .unwrap_or_default(),
+                        title: Some(title),
+                    },
+                );
+            }
+            if let Some(anchor) = anchor {
+                anchors.insert(anchor.anchor.clone(), anchor);
+            }
+        }
+    }
+
+    /// Looks up an anchor by id.
+    ///
+    /// Anchors in the given document are preferred over anchors in other
+    /// documents.
+    pub fn find_anchor(&self, document: &str, id: &str) -> Option<&Anchor> {
+        self.anchors
+            .get(document)
+            .and_then(|anchors| anchors.get(id))
+            .or_else(|| self.anchors.values().find_map(|anchors| anchors.get(id)))
+    }
+
+    /// Checks if a link target exists.
+    ///
+    /// Targets in documents that are not part of the project are assumed to
+    /// exist.
+    pub fn target_exists(&self, target: &LinkTarget) -> bool {
+        match (self.anchors.get(&target.document), &target.anchor) {
+            (Some(anchors), Some(anchor)) => anchors.contains_key(anchor),
+            _ => true,
+        }
+    }
+}
+
+/// Returns the path of `target` relative to the directory of `document`.
+fn relative_path(document: &str, target: &str) -> String {
+    let mut base: Vec<&str> = document.split('/').collect();
+    base.pop();
+    let target: Vec<&str> = target.split('/').collect();
+    let common = base
+        .iter()
+        .zip(target.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut rv = vec![".."; base.len() - common];
+    rv.extend_from_slice(&target[common..]);
+    rv.join("/")
+}
+
+/// Builds the href of an anchor in `target` as seen from `document`.
+fn document_href(
+    options: &ProjectOptions,
+    document: &str,
+    target: &str,
+    anchor: Option<&str>,
+) -> String {
+    let mut rv = if target == document {
+        String::new()
+    } else {
+        match options.output_path {
+            Some(ref output_path) => {
+                relative_path(&(output_path.0)(document), &(output_path.0)(target))
+            }
+            None => relative_path(document, target),
+        }
+    };
+    if let Some(anchor) = anchor.filter(|x| !x.is_empty()) {
+        rv.push('#');
+        rv.push_str(anchor);
+    }
+    rv
+}
+
+/// Builds the href of an anchor as seen from `document`.
+fn anchor_href(options: &ProjectOptions, document: &str, anchor: &Anchor) -> String {
+    document_href(options, document, &anchor.document, Some(&anchor.anchor))
+}
+
+/// A document after both passes.
+#[derive(Debug, Clone)]
+pub struct ProcessedDocument {
+    /// The path of the document within the project.
+    pub path: String,
+    /// The final events of the document.
+    pub events: Vec<AnnotatedEvent<'static>>,
+    /// Indicates that the pipeline output was reused from a [`BuildCache`].
+    ///
+    /// References are resolved again on every build so the final events
+    /// can still differ from the previous build.
+    pub cached: bool,
+}
+
+/// The result of building a [`Project`].
+#[derive(Debug, Clone)]
+pub struct ProjectOutput {
+    /// The state collected across all documents.
+    pub state: ProjectState,
+    /// The processed documents in the order they were added.
+    pub documents: Vec<ProcessedDocument>,
+}
+
+/// The pipeline output of a document in a [`BuildCache`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct CachedDocument {
+    source_hash: u64,
+    events: Vec<AnnotatedEvent<'static>>,
+}
+
+/// Remembers the pipeline output of documents across builds.
+///
+/// See [`Project::build_incremental`].  The cache can be persisted with
+/// serde to speed up builds across runs.  It's invalidated entirely when the
+/// pipeline changes.  Changes to files read by processors are not detected.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct BuildCache {
+    pipeline: Option<u64>,
+    documents: BTreeMap<String, CachedDocument>,
+}
+
+impl BuildCache {
+    /// Creates an empty cache.
+    pub fn new() -> BuildCache {
+        BuildCache::default()
+    }
+
+    /// Returns the number of cached documents.
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    /// Returns `true` if no documents are cached.
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Removes all cached documents.
+    pub fn clear(&mut self) {
+        self.documents.clear();
+    }
+}
+
+/// A set of documents processed through a shared pipeline.
+#[derive(Debug)]
+pub struct Project {
+    pipeline: Pipeline,
+    options: ProjectOptions,
+    documents: Vec<(String, String)>,
+}
+
+impl Project {
+    /// Creates a project that processes documents with the given pipeline.
+    pub fn new(pipeline: Pipeline) -> Project {
+        Project::with_options(pipeline, ProjectOptions::default())
+    }
+
+    /// Creates a project with custom options for the cross document pass.
+    pub fn with_options(pipeline: Pipeline, options: ProjectOptions) -> Project {
+        Project {
+            pipeline,
+            options,
+            documents: Vec::new(),
+        }
+    }
+
+    /// Returns the pipeline of the project.
+    pub fn pipeline(&self) -> &Pipeline {
+        &self.pipeline
+    }
+
+    /// Adds a document under a path.
+    ///
+    /// Paths are `/` separated and relative to the root of the project.
+    /// Adding a document again replaces it.
+    pub fn add_document(&mut self, path: &str, source: &str) {
+        match self.documents.iter_mut().find(|(p, _)| p == path) {
+            Some(document) => document.1 = source.to_string(),
+            None => self.documents.push((path.to_string(), source.to_string())),
+        }
+    }
+
+    /// Removes the document at a path.
+    ///
+    /// Returns `true` if the document existed.
+    pub fn remove_document(&mut self, path: &str) -> bool {
+        let len = self.documents.len();
+        self.documents.retain(|(p, _)| p != path);
+        self.documents.len() != len
+    }
+
+    /// Returns the paths of all documents.
+    pub fn documents(&self) -> impl Iterator<Item = &str> {
+        self.documents.iter().map(|(path, _)| path.as_str())
+    }
+
+    /// Runs a single document through the pipeline.
+    pub(crate) fn process_document(
+        &self,
+        path: &str,
+        source: &str,
+    ) -> Vec<AnnotatedEvent<'static>> {
+        process_document(&self.pipeline, path, source)
+    }
+
+    /// Collects the cross document state and resolves references.
+    ///
+    /// The processed documents carry a flag indicating that they were
+    /// loaded from a cache.
+    pub(crate) fn link_documents(
+        &self,
+        processed: Vec<(String, Vec<AnnotatedEvent<'static>>, bool)>,
+    ) -> ProjectOutput {
+        let mut state = ProjectState::default();
+        for (path, events, _) in &processed {
+            state.add_document(path, events);
+        }
+        let documents = processed
+            .into_iter()
+            .map(|(path, events, cached)| ProcessedDocument {
+                events: resolve_references(&state, &self.options, &path, events),
+                path,
+                cached,
+            })
+            .collect();
+        ProjectOutput { state, documents }
+    }
+
+    /// Processes all documents.
+    ///
+    /// Every document is first run through the pipeline.  Afterwards the
+    /// state of all documents is collected and references are resolved.
+    pub fn build(&self) -> ProjectOutput {
+        let processed = self
+            .documents
+            .iter()
+            .map(|(path, source)| (path.clone(), self.process_document(path, source), false))
+            .collect();
+        self.link_documents(processed)
+    }
+
+    /// Processes all documents reusing the pipeline output of unchanged
+    /// documents.
+    ///
+    /// A document is only run through the pipeline if its source changed
+    /// since it was stored in the cache.  If the pipeline changed (as
+    /// detected by [`Pipeline::fingerprint`]) the cache is discarded.
+    /// Pipelines without a fingerprint always process all documents.  As
+    /// references depend on all documents they are resolved on every build.
+    ///
+    /// Files read by processors (such as the sources included by
+    /// [`CodeImport`](crate::processors::CodeImport) and
+    /// [`Snippets`](crate::processors::Snippets) or the images measured by
+    /// `ImageDimensions`) are not part of the cache key.  When these change
+    /// the cache has to be cleared with [`BuildCache::clear`].
+    ///
+    /// ```
+    /// # use struckdown::pipeline::Pipeline;
+    /// # use struckdown::project::{BuildCache, Project};
+    /// let mut project = Project::new(Pipeline::default());
+    /// project.add_document("index.md", "# Hello");
+    /// let mut cache = BuildCache::new();
+    /// project.build_incremental(&mut cache);
+    /// let output = project.build_incremental(&mut cache);
+    /// assert!(output.documents[0].cached);
+    /// ```
+    pub fn build_incremental(&self, cache: &mut BuildCache) -> ProjectOutput {
+        let fingerprint = self.pipeline.fingerprint();
+        if fingerprint.is_none() || cache.pipeline != fingerprint {
+            cache.clear();
+            cache.pipeline = fingerprint;
+        }
+        cache
+            .documents
+            .retain(|path, _| self.documents.iter().any(|(p, _)| p == path));
+
+        let processed = self
+            .documents
+            .iter()
+            .map(|(path, source)| {
+                let source_hash = hash_json(source).unwrap_or_default();
+                if let Some(document) = cache.documents.get(path) {
+                    if document.source_hash == source_hash {
+                        return (path.clone(), document.events.clone(), true);
+                    }
+                }
+                let events = self.process_document(path, source);
+                if fingerprint.is_some() {
+                    cache.documents.insert(
+                        path.clone(),
+                        CachedDocument {
+                            source_hash,
+                            events: events.clone(),
+                        },
+                    );
+                }
+                (path.clone(), events, false)
+            })
+            .collect();
+        self.link_documents(processed)
+    }
+
+    /// Processes all documents in parallel.
+    ///
+    /// Like [`build`](Self::build) but the documents are run through
+    /// pipelines on multiple threads.  As processors are not required to be
+    /// thread safe the project's pipeline is not shared.  Instead it's turned
+    /// into a config (see [`Pipeline::to_config`]) from which the pipelines
+    /// of the workers are created.  A pipeline is created for every job the
+    /// documents are split into which can be more than one per thread.
+    ///
+    /// This fails if the pipeline cannot be represented as config (eg: if
+    /// it contains custom processors).  Collecting the state and resolving
+    /// references happens afterwards in a second pass so the output is
+    /// identical to `build`.
+    #[cfg(feature = "parallel")]
+    pub fn build_parallel(&self) -> Result<ProjectOutput, crate::error::Error> {
+        use rayon::prelude::*;
+
+        let config = self.pipeline.to_config()?;
+        // report processors that cannot be created before splitting up
+        Pipeline::try_from_config(&config)?;
+        let processed = self
+            .documents
+            .par_iter()
+            .map_init(
+                || Pipeline::from_config(&config),
+                |pipeline, (path, source)| {
+                    (
+                        path.clone(),
+                        process_document(pipeline, path, source),
+                        false,
+                    )
+                },
+            )
+            .collect();
+        Ok(self.link_documents(processed))
+    }
+}
+
+/// Runs a document through a pipeline.
+fn process_document(pipeline: &Pipeline, path: &str, source: &str) -> Vec<AnnotatedEvent<'static>> {
+    pipeline
+        .process_with_path(source, std::path::Path::new(path))
+        .map(|x| x.into_static())
+        .collect()
+}
+
+/// Replaces references in a document with links.
+fn resolve_references(
+    state: &ProjectState,
+    options: &ProjectOptions,
+    path: &str,
+    events: Vec<AnnotatedEvent<'static>>,
+) -> Vec<AnnotatedEvent<'static>> {
+    let mut rv = Vec::with_capacity(events.len());
+    let mut events = events.into_iter().collect::<VecDeque<_>>();
+
+    if let Some(ref key) = options.backlinks_key {
+        let metadata = AnnotatedEvent::from(MetaDataEvent {
+            key: key.clone().into(),
+            value: to_value(state.links.backlinks(path)).unwrap(),
+        });
+        match events.front() {
+            Some(AnnotatedEvent {
+                event: Event::DocumentStart(DocumentStartEvent { .. }),
+                ..
+            }) => {
+                rv.extend(events.pop_front());
+                rv.push(metadata);
+            }
+            _ => rv.push(metadata),
+        }
+    }
+
+    for annotated_event in events {
+        let location = annotated_event.location;
+        match annotated_event.event {
+            Event::InterpretedText(InterpretedTextEvent { ref role, ref text })
+                if role.as_str() == options.ref_role || role.as_str() == options.term_role =>
+            {
+                let anchor = if role.as_str() == options.ref_role {
+                    state.find_anchor(path, text.as_str())
+                } else {
+                    state.glossary.get(&text.as_str().to_lowercase())
+                };
+                let anchor = match anchor {
+                    Some(anchor) => anchor,
+                    None => {
+                        rv.push(AnnotatedEvent::new(
+                            ErrorEvent {
+                                title: "Unresolved reference".into(),
+                                description: Some(
+                                    format!("{}: {}", role.as_str(), text.as_str()).into(),
+                                ),
+                                severity: Severity::Error,
+                            },
+                            location,
+                        ));
+                        continue;
+                    }
+                };
+                let title = match anchor.title {
+                    Some(ref title) if role.as_str() == options.ref_role => title.clone(),
+                    _ => text.as_str().to_string(),
+                };
+                rv.push(AnnotatedEvent::new(
+                    Tag::Link.start_tag(Attrs {
+                        target: Some(anchor_href(options, path, anchor).into()),
+                        ..Default::default()
+                    }),
+                    location,
+                ));
+                rv.push(AnnotatedEvent::new(
+                    TextEvent { text: title.into() },
+                    location,
+                ));
+                rv.push(AnnotatedEvent::new(Tag::Link.end_tag(), location));
+            }
+            Event::StartTag(StartTagEvent {
+                tag: Tag::Link,
+                ref attrs,
+            }) if options.check_links || options.output_path.is_some() => {
+                let target = attrs
+                    .target
+                    .as_ref()
+                    .and_then(|target| resolve_link(path, target.as_str()));
+                let broken = target
+                    .as_ref()
+                    .filter(|target| options.check_links && !state.target_exists(target));
+                if let Some(target) = broken {
+                    rv.push(AnnotatedEvent::new(
+                        ErrorEvent {
+                            title: "Broken link".into(),
+                            description: Some(
+                                format!(
+                                    "{}#{}",
+                                    target.document,
+                                    target.anchor.as_deref().unwrap_or_default()
+                                )
+                                .into(),
+                            ),
+                            severity: Severity::Warning,
+                        },
+                        location,
+                    ));
+                }
+                // links to other documents of the project point to their output
+                let href = target
+                    .filter(|target| {
+                        options.output_path.is_some()
+                            && target.document != path
+                            && state.anchors.contains_key(&target.document)
+                    })
+                    .map(|target| {
+                        document_href(options, path, &target.document, target.anchor.as_deref())
+                    });
+                let mut annotated_event = annotated_event;
+                if let Event::StartTag(StartTagEvent { ref mut attrs, .. }) = annotated_event.event
+                {
+                    if let Some(href) = href {
+                        attrs.target = Some(href.into());
+                    }
+                }
+                rv.push(annotated_event);
+            }
+            _ => rv.push(annotated_event),
+        }
+    }
+
+    rv
+}
+
+#[test]
+fn test_project() {
+    use crate::html::to_html;
+    use crate::processors::DefinitionLists;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(DefinitionLists::default());
+    let mut project = Project::new(pipeline);
+    project.add_document(
+        "index.md",
+        "See {ref}`setup`, {term}`Event` and {ref}`nowhere`.\n\n[Broken](docs/guide.md#missing)",
+    );
+    project.add_document(
+        "docs/guide.md",
+        "# Setup Guide {#setup}\n\nEvent\n: A thing that happens.\n\nBack to [index](../index.md)",
+    );
+    let output = project.build();
+
+    assert_eq!(
+        output.state.find_anchor("index.md", "setup"),
+        Some(&Anchor {
+            document: "docs/guide.md".into(),
+            anchor: "setup".into(),
+            title: Some("Setup Guide".into()),
+        })
+    );
+    assert!(output.state.glossary.contains_key("event"));
+
+    let index = &output.documents[0];
+    let html = to_html(index.events.iter().cloned(), &Default::default());
+    assert!(html.contains("<a href=\"docs&#x2f;guide.md#setup\">Setup Guide</a>"));
+    assert!(html.contains("<a href=\"docs&#x2f;guide.md\">Event</a>"));
+    assert!(html.contains("Unresolved reference"));
+    assert!(html.contains("Broken link"));
+
+    let guide = &output.documents[1];
+    let backlinks = guide.events.iter().find_map(|x| match x.event {
+        Event::MetaData(MetaDataEvent { ref value, .. }) => Some(value.clone()),
+        _ => None,
+    });
+    assert_eq!(
+        backlinks,
+        Some(crate::value::value!([{"document": "index.md", "anchor": "missing"}]))
+    );
+}
+
+#[test]
+fn test_project_output_path() {
+    use crate::html::to_html;
+
+    let mut project = Project::with_options(
+        Pipeline::default(),
+        ProjectOptions {
+            output_path: Some(OutputPathFn::new(|path| path.replace(".md", ".html"))),
+            ..Default::default()
+        },
+    );
+    project.add_document(
+        "index.md",
+        "See {ref}`setup` or [the guide](docs/guide.md), [top](#top) and [x](https://x.md/)",
+    );
+    project.add_document(
+        "docs/guide.md",
+        "# Setup {#setup}\n\nBack to [index](../index.md#top)",
+    );
+    let output = project.build();
+
+    let html = to_html(
+        output.documents[0].events.iter().cloned(),
+        &Default::default(),
+    );
+    assert!(html.contains("<a href=\"docs&#x2f;guide.html#setup\">Setup</a>"));
+    assert!(html.contains("<a href=\"docs&#x2f;guide.html\">the guide</a>"));
+    assert!(html.contains("<a href=\"#top\">top</a>"));
+    assert!(html.contains("<a href=\"https:&#x2f;&#x2f;x.md&#x2f;\">x</a>"));
+    let html = to_html(
+        output.documents[1].events.iter().cloned(),
+        &Default::default(),
+    );
+    assert!(html.contains("<a href=\"..&#x2f;index.html#top\">index</a>"));
+}
+
+#[test]
+fn test_build_incremental() {
+    use crate::processors::DefinitionLists;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(DefinitionLists::default());
+    let mut project = Project::new(pipeline);
+    project.add_document("index.md", "See {ref}`setup`.");
+    project.add_document("guide.md", "# Setup {#setup}");
+
+    let mut cache = BuildCache::new();
+    let output = project.build_incremental(&mut cache);
+    assert!(output.documents.iter().all(|x| !x.cached));
+    assert_eq!(cache.len(), 2);
+
+    project.add_document("guide.md", "# Installation {#setup}");
+    let output = project.build_incremental(&mut cache);
+    assert!(output.documents[0].cached);
+    assert!(!output.documents[1].cached);
+    assert_eq!(
+        output.state.find_anchor("index.md", "setup").unwrap().title,
+        Some("Installation".into())
+    );
+
+    let mut cache: BuildCache =
+        serde_json::from_str(&serde_json::to_string(&cache).unwrap()).unwrap();
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(DefinitionLists {
+        class_name: Some("glossary".into()),
+    });
+    let project = Project {
+        pipeline,
+        ..project
+    };
+    let output = project.build_incremental(&mut cache);
+    assert!(output.documents.iter().all(|x| !x.cached));
+}
+
+#[cfg(feature = "autoanchors-processor")]
+#[test]
+fn test_build_incremental_code_only_options() {
+    use crate::processors::{AutoAnchors, SlugifyFn};
+
+    // changes to the slugify function cannot be detected
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(AutoAnchors {
+        slugify: Some(SlugifyFn::new(|text| text.to_lowercase())),
+        ..AutoAnchors::default()
+    });
+    assert_eq!(pipeline.fingerprint(), None);
+
+    let mut project = Project::new(pipeline);
+    project.add_document("index.md", "# Hello");
+    let mut cache = BuildCache::new();
+    project.build_incremental(&mut cache);
+    let output = project.build_incremental(&mut cache);
+    assert!(!output.documents[0].cached);
+    assert!(cache.is_empty());
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn test_project_parallel() {
+    let mut project = Project::new(Pipeline::default());
+    for idx in 0..20 {
+        project.add_document(
+            &format!("doc{}.md", idx),
+            &format!(
+                "# Doc {} {{#doc{}}}\n\nSee {{ref}}`doc{}`.",
+                idx,
+                idx,
+                (idx + 1) % 20
+            ),
+        );
+    }
+    let sequential = project.build();
+    let parallel = project.build_parallel().unwrap();
+    for (a, b) in sequential.documents.iter().zip(parallel.documents.iter()) {
+        assert_eq!(a.path, b.path);
+        assert_eq!(
+            serde_json::to_string(&a.events).unwrap(),
+            serde_json::to_string(&b.events).unwrap()
+        );
+    }
+
+    // custom processors cannot be recreated on the worker threads
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(crate::processors::AutoAnchors {
+        slugify: Some(crate::processors::SlugifyFn::new(|text| {
+            text.to_lowercase()
+        })),
+        ..Default::default()
+    });
+    let mut project = Project::new(pipeline);
+    project.add_document("index.md", "# Hello");
+    assert!(project.build_parallel().is_err());
+}
@@ -0,0 +1,192 @@
+//! Maps event locations back to the source document.
+//!
+//! Events only carry a [`Location`] which is a byte span in the document
+//! they were parsed from.  A [`SourceMap`] holds on to that document and
+//! resolves locations into the authored text, the lines around it or a
+//! `data-sourcepos` style position:
+//!
+//! ```
+//! use struckdown::event::Event;
+//! use struckdown::parser::parse;
+//! use struckdown::source_map::SourceMap;
+//!
+//! let source = "# Hello\n\nSome `code` here.";
+//! let map = SourceMap::new(source);
+//! let code = parse(source, &Default::default())
+//!     .find(|x| matches!(x.event, Event::InlineCode(..)))
+//!     .unwrap();
+//! let location = code.location.unwrap();
+//! assert_eq!(map.slice(&location), Some("`code`"));
+//! assert_eq!(map.sourcepos(&location).unwrap(), "3:6-3:11");
+//! ```
+use std::ops::Range;
+
+use crate::event::Location;
+
+/// A line of the source returned by [`SourceMap::context`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceLine<'source> {
+    /// The line number (1 indexed).
+    pub line: usize,
+    /// The text of the line without the line ending.
+    pub text: &'source str,
+    /// `true` if the location covers this line.
+    pub highlighted: bool,
+}
+
+/// Resolves [`Location`]s against the document they were created from.
+#[derive(Debug, Clone)]
+pub struct SourceMap<'source> {
+    source: &'source str,
+    line_starts: Vec<usize>,
+}
+
+impl<'source> SourceMap<'source> {
+    /// Creates a source map for a document.
+    ///
+    /// This must be the full document that was given to the parser,
+    /// including front matter.
+    pub fn new(source: &'source str) -> SourceMap<'source> {
+        SourceMap {
+            source,
+            line_starts: std::iter::once(0)
+                .chain(source.match_indices('\n').map(|(idx, _)| idx + 1))
+                .collect(),
+        }
+    }
+
+    /// Returns the source document.
+    pub fn source(&self) -> &'source str {
+        self.source
+    }
+
+    /// Returns the number of lines in the document.
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Returns the byte range of a line (1 indexed) without the line ending.
+    fn line_range(&self, line: usize) -> Option<Range<usize>> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.source.len(), |&next| next - 1);
+        let end = if self.source[start..end].ends_with('\r') {
+            end - 1
+        } else {
+            end
+        };
+        Some(start..end)
+    }
+
+    /// Returns a line (1 indexed) without the line ending.
+    pub fn line(&self, line: usize) -> Option<&'source str> {
+        self.line_range(line).map(|range| &self.source[range])
+    }
+
+    /// Returns the 1 indexed line and 0 indexed byte column of an offset.
+    ///
+    /// Offsets past the end of the document resolve to the end.
+    pub fn line_and_column(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.source.len());
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(idx) => idx,
+            Err(idx) => idx - 1,
+        };
+        (line + 1, offset - self.line_starts[line])
+    }
+
+    /// Creates a location for a byte range of the document.
+    pub fn location(&self, range: Range<usize>) -> Location {
+        let (line, column) = self.line_and_column(range.start);
+        Location {
+            offset: range.start,
+            len: range.end.saturating_sub(range.start),
+            line,
+            column,
+        }
+    }
+
+    /// Returns the authored text of a location.
+    ///
+    /// Returns `None` if the location does not fit the document.
+    pub fn slice(&self, location: &Location) -> Option<&'source str> {
+        self.source
+            .get(location.offset..location.offset.checked_add(location.len)?)
+    }
+
+    /// Returns the lines covered by a location with `context` lines around
+    /// it.
+    pub fn context(&self, location: &Location, context: usize) -> Vec<SourceLine<'source>> {
+        let (first, _) = self.line_and_column(location.offset);
+        let (last, _) = self.line_and_column(
+            (location.offset + location.len.saturating_sub(1)).max(location.offset),
+        );
+        (first.saturating_sub(context).max(1)..=last + context)
+            .filter_map(|line| {
+                Some(SourceLine {
+                    line,
+                    text: self.line(line)?,
+                    highlighted: line >= first && line <= last,
+                })
+            })
+            .collect()
+    }
+
+    /// Formats a location as `data-sourcepos` value.
+    ///
+    /// The format is `start_line:start_column-end_line:end_column` with 1
+    /// indexed lines and columns and an inclusive end, as used by cmark.
+    pub fn sourcepos(&self, location: &Location) -> Option<String> {
+        self.slice(location)?;
+        let (start_line, start_column) = self.line_and_column(location.offset);
+        let end = location.offset + location.len.saturating_sub(1);
+        let (end_line, end_column) = self.line_and_column(end.max(location.offset));
+        Some(format!(
+            "{}:{}-{}:{}",
+            start_line,
+            start_column + 1,
+            end_line,
+            end_column + 1
+        ))
+    }
+}
+
+#[test]
+fn test_source_map() {
+    let source = "---\ntitle: Test\n---\nfirst\r\nsecond *line*\nthird\n";
+    let map = SourceMap::new(source);
+    assert_eq!(map.line(4), Some("first"));
+    assert_eq!(map.line(7), Some(""));
+    assert_eq!(map.line(8), None);
+
+    let events: Vec<_> = crate::parser::parse(source, &Default::default()).collect();
+    let emphasis = events
+        .iter()
+        .find(|x| {
+            matches!(
+                x.event,
+                crate::event::Event::StartTag(crate::event::StartTagEvent {
+                    tag: crate::event::Tag::Emphasis,
+                    ..
+                })
+            )
+        })
+        .unwrap()
+        .location
+        .unwrap();
+    assert_eq!(map.slice(&emphasis), Some("*line*"));
+    assert_eq!((emphasis.line, emphasis.column), (5, 7));
+    assert_eq!(map.sourcepos(&emphasis).unwrap(), "5:8-5:13");
+
+    let context = map.context(&emphasis, 1);
+    assert_eq!(
+        context.iter().map(|x| x.text).collect::<Vec<_>>(),
+        vec!["first", "second *line*", "third"]
+    );
+    assert!(context[1].highlighted && !context[0].highlighted);
+
+    let location = map.location(emphasis.offset..emphasis.offset + emphasis.len);
+    assert_eq!((location.line, location.column), (5, 7));
+}
@@ -0,0 +1,178 @@
+//! Splits event streams into multiple documents.
+//!
+//! This is useful to build chaptered or paginated output from a single
+//! source document.
+//!
+//! ```
+//! use struckdown::pipeline::Pipeline;
+//! use struckdown::split::{split, SplitOptions};
+//!
+//! let pipeline = Pipeline::default();
+//! let documents = split(
+//!     pipeline.process("# One\nFirst\n# Two\nSecond"),
+//!     &SplitOptions::default(),
+//! );
+//! assert_eq!(documents.len(), 2);
+//! ```
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, DocumentStartEvent, EndTagEvent, Event, StartTagEvent, Tag};
+use crate::plain::to_plain_text;
+use crate::value::{Map, Value};
+
+/// Configures how [`split`] splits documents.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SplitOptions {
+    /// Headings up to this level start a new document.
+    pub level: usize,
+    /// Copies the front matter of the source to all documents.
+    pub inherit_front_matter: bool,
+    /// Keeps the content before the first heading as a separate document.
+    pub keep_preamble: bool,
+}
+
+impl Default for SplitOptions {
+    fn default() -> SplitOptions {
+        SplitOptions {
+            level: 1,
+            inherit_front_matter: true,
+            keep_preamble: true,
+        }
+    }
+}
+
+/// Returns `true` if the events have content worth keeping.
+fn has_content(events: &[AnnotatedEvent<'_>]) -> bool {
+    events
+        .iter()
+        .any(|annotated_event| match annotated_event.event {
+            Event::DocumentStart(..) => false,
+            Event::Text(ref text) => !text.text.as_str().trim().is_empty(),
+            _ => true,
+        })
+}
+
+/// Returns `true` if the tag is a heading that starts a new document.
+fn starts_document(tag: Tag, options: &SplitOptions) -> bool {
+    matches!(tag.header_level(), Some(level) if level <= options.level)
+}
+
+/// Splits an event stream at headings into multiple documents.
+///
+/// Every heading with a level up to [`SplitOptions::level`] starts a new
+/// document.  Each document starts with a document start event with
+/// synthesized front matter: the front matter of the source document (if
+/// inherited) with the `title` set to the text of the heading and `anchor`
+/// set to its id if it has one.  Content before the first heading is kept
+/// as a document with the source's front matter unless it's empty or
+/// [`SplitOptions::keep_preamble`] is disabled.
+pub fn split<'data, I>(iter: I, options: &SplitOptions) -> Vec<Vec<AnnotatedEvent<'data>>>
+where
+    I: Iterator<Item = AnnotatedEvent<'data>>,
+{
+    let mut front_matter = None;
+    let mut front_matter_format = None;
+    let mut documents = Vec::new();
+    let mut current = vec![AnnotatedEvent::from(DocumentStartEvent::new(None))];
+    let mut heading = None::<Vec<AnnotatedEvent<'data>>>;
+    let mut is_preamble = true;
+
+    for annotated_event in iter {
+        match annotated_event.event {
+            Event::DocumentStart(DocumentStartEvent {
+                front_matter: ref source_front_matter,
+                front_matter_format: source_front_matter_format,
+            }) => {
+                front_matter = source_front_matter.clone();
+                front_matter_format = source_front_matter_format;
+                current[0] = annotated_event;
+                continue;
+            }
+            Event::StartTag(StartTagEvent { tag, ref attrs }) if starts_document(tag, options) => {
+                if has_content(&current) && (!is_preamble || options.keep_preamble) {
+                    documents.push(current);
+                }
+                is_preamble = false;
+
+                let mut synthesized = match front_matter {
+                    Some(Value::Object(ref map)) if options.inherit_front_matter => map.clone(),
+                    _ => Map::new(),
+                };
+                if let Some(ref id) = attrs.id {
+                    synthesized.insert("anchor".into(), Value::String(id.as_str().into()));
+                }
+                current = vec![AnnotatedEvent::new(
+                    DocumentStartEvent {
+                        front_matter: Some(Value::Object(synthesized)),
+                        front_matter_format,
+                    },
+                    annotated_event.location,
+                )];
+                heading = Some(Vec::new());
+            }
+            Event::EndTag(EndTagEvent { tag }) if tag.header_level().is_some() => {
+                if let Some(events) = heading.take() {
+                    let title = to_plain_text(events.iter());
+                    if let Event::DocumentStart(DocumentStartEvent {
+                        front_matter: Some(Value::Object(ref mut map)),
+                        ..
+                    }) = current[0].event
+                    {
+                        map.insert("title".into(), Value::String(title.as_str().into()));
+                    }
+                }
+            }
+            _ => {
+                if let Some(ref mut events) = heading {
+                    events.push(annotated_event.clone());
+                }
+            }
+        }
+        current.push(annotated_event);
+    }
+
+    if has_content(&current) && (!is_preamble || options.keep_preamble) {
+        documents.push(current);
+    }
+
+    documents
+}
+
+#[test]
+fn test_split() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+    use crate::value::value;
+
+    let pipeline = Pipeline::default();
+    let documents = split(
+        pipeline.process(
+            "---\nauthor: Me\n---\nIntro\n\n# One {#one}\nFirst\n## Sub\nMore\n# *Two*\nSecond",
+        ),
+        &SplitOptions::default(),
+    );
+    assert_eq!(documents.len(), 3);
+
+    let front_matter = documents
+        .iter()
+        .map(|document| match document[0].event {
+            Event::DocumentStart(ref start) => start.front_matter.clone(),
+            _ => panic!("document does not start with front matter"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(front_matter[0], Some(value!({"author": "Me"})));
+    assert_eq!(
+        front_matter[1],
+        Some(value!({"author": "Me", "anchor": "one", "title": "One"}))
+    );
+    assert_eq!(
+        front_matter[2],
+        Some(value!({"author": "Me", "title": "Two"}))
+    );
+
+    assert_eq!(
+        to_html(documents[1].clone().into_iter(), &Default::default()),
+        "<h1 id=\"one\">One</h1>\n<p>First</p>\n<h2>Sub</h2>\n<p>More</p>\n"
+    );
+}
@@ -0,0 +1,154 @@
+//! Gives access to [`serde_json`] value functionality.
+//!
+//! The [`Value`](crate::value::Value) type is used to represent arbitrary data in a few instances.
+//! A notable example is emitted [`front_matter`](crate::event::DocumentStartEvent::front_matter)
+//! or other [`MetaData`](crate::event::MetaDataEvent).
+//!
+//! Front matter can also be written in YAML or TOML.  The conversion functions
+//! in this module translate between those value types and [`Value`] without
+//! silently dropping information: values that have no equivalent on the other
+//! side (such as non string keys or a TOML null) produce an error instead.
+pub use serde_json::value::*;
+
+/// A re-export of the [`serde_json::json`] macro.
+pub use serde_json::json as value;
+
+pub use serde_json::{from_value, to_value};
+
+use crate::error::{Error, ErrorKind};
+
+fn unsupported(what: &str, target: &str) -> Error {
+    Error::new(
+        ErrorKind::Serialization,
+        format!("{} cannot be represented in {}", what, target),
+    )
+}
+
+fn float_to_value(f: f64) -> Result<Value, Error> {
+    Number::from_f64(f)
+        .map(Value::Number)
+        .ok_or_else(|| unsupported("non finite float", "JSON"))
+}
+
+/// Converts a YAML value into a [`Value`].
+///
+/// Mapping keys have to be strings.
+pub fn from_yaml_value(value: serde_yaml::Value) -> Result<Value, Error> {
+    Ok(match value {
+        serde_yaml::Value::Null => Value::Null,
+        serde_yaml::Value::Bool(value) => Value::Bool(value),
+        serde_yaml::Value::Number(num) => {
+            if let Some(value) = num.as_u64() {
+                Value::from(value)
+            } else if let Some(value) = num.as_i64() {
+                Value::from(value)
+            } else {
+                float_to_value(num.as_f64().unwrap_or(f64::NAN))?
+            }
+        }
+        serde_yaml::Value::String(value) => Value::String(value),
+        serde_yaml::Value::Sequence(items) => Value::Array(
+            items
+                .into_iter()
+                .map(from_yaml_value)
+                .collect::<Result<_, _>>()?,
+        ),
+        serde_yaml::Value::Mapping(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| match key {
+                    serde_yaml::Value::String(key) => Ok((key, from_yaml_value(value)?)),
+                    _ => Err(unsupported("non string key", "JSON")),
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+    })
+}
+
+/// Converts a [`Value`] into a YAML value.
+pub fn to_yaml_value(value: &Value) -> Result<serde_yaml::Value, Error> {
+    serde_yaml::to_value(value)
+        .map_err(|err| Error::from(ErrorKind::Serialization).with_source(err))
+}
+
+/// Converts a TOML value into a [`Value`].
+///
+/// As JSON has no date type, datetimes are converted into their string
+/// representation.
+pub fn from_toml_value(value: toml::Value) -> Result<Value, Error> {
+    Ok(match value {
+        toml::Value::String(value) => Value::String(value),
+        toml::Value::Integer(value) => Value::from(value),
+        toml::Value::Float(value) => float_to_value(value)?,
+        toml::Value::Boolean(value) => Value::Bool(value),
+        toml::Value::Datetime(value) => Value::String(value.to_string()),
+        toml::Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(from_toml_value)
+                .collect::<Result<_, _>>()?,
+        ),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| Ok((key, from_toml_value(value)?)))
+                .collect::<Result<_, Error>>()?,
+        ),
+    })
+}
+
+/// Converts a [`Value`] into a TOML value.
+///
+/// This fails for `null` values and integers that do not fit into an `i64`.
+pub fn to_toml_value(value: &Value) -> Result<toml::Value, Error> {
+    Ok(match *value {
+        Value::Null => return Err(unsupported("null", "TOML")),
+        Value::Bool(value) => toml::Value::Boolean(value),
+        Value::Number(ref num) => {
+            if let Some(value) = num.as_i64() {
+                toml::Value::Integer(value)
+            } else if num.is_u64() {
+                return Err(unsupported("integer out of range", "TOML"));
+            } else {
+                toml::Value::Float(num.as_f64().unwrap_or(f64::NAN))
+            }
+        }
+        Value::String(ref value) => toml::Value::String(value.clone()),
+        Value::Array(ref items) => {
+            toml::Value::Array(items.iter().map(to_toml_value).collect::<Result<_, _>>()?)
+        }
+        Value::Object(ref map) => toml::Value::Table(
+            map.iter()
+                .map(|(key, value)| Ok((key.clone(), to_toml_value(value)?)))
+                .collect::<Result<_, Error>>()?,
+        ),
+    })
+}
+
+#[test]
+fn test_value_conversions() {
+    let value = value!({
+        "title": "Hello",
+        "tags": ["a", "b"],
+        "draft": false,
+        "weight": -1,
+        "ratio": 0.5,
+        "author": {"name": "Peter"},
+    });
+    assert_eq!(
+        from_yaml_value(to_yaml_value(&value).unwrap()).unwrap(),
+        value
+    );
+    assert_eq!(
+        from_toml_value(to_toml_value(&value).unwrap()).unwrap(),
+        value
+    );
+
+    assert!(to_toml_value(&value!({ "missing": null })).is_err());
+    let yaml: serde_yaml::Value = serde_yaml::from_str("1: one").unwrap();
+    assert!(from_yaml_value(yaml).is_err());
+    let toml: toml::Value = toml::from_str("date = 1979-05-27").unwrap();
+    assert_eq!(
+        from_toml_value(toml).unwrap(),
+        value!({ "date": "1979-05-27" })
+    );
+}
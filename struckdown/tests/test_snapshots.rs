@@ -30,6 +30,7 @@ fn apply_processors<'data, I: 'data + Iterator<Item = AnnotatedEvent<'data>>>(
     if let Some(document_start) = iter.peek() {
         if let Event::DocumentStart(DocumentStartEvent {
             front_matter: Some(ref front_matter),
+            ..
         }) = document_start.event
         {
             if let Some(processors) = front_matter.get("processors").and_then(|x| x.as_array()) {